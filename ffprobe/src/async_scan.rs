@@ -0,0 +1,51 @@
+//! Probes awaited on an event loop instead of blocking a thread, with the `async` feature.
+
+use std::io;
+use std::path::Path;
+
+use futures::Future;
+use tokio_process::CommandExt;
+
+use error::{Error, Result};
+use ffprobe::{parse_output, Ffprobe, MediaInfo};
+
+impl Ffprobe {
+    /// Probe the file like `scan`, on the reactor of the current tokio runtime. Dropping the
+    /// future kills the program, which cancels the probe. The timeout is not applied, wrap the
+    /// future in a `tokio::timer::Timeout` instead.
+    pub fn scan_async(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> impl Future<Item = MediaInfo, Error = Error> {
+        let program = self.program().to_owned();
+        self.scan_command(path.as_ref())
+            .output_async()
+            .then(move |output| -> Result<MediaInfo> {
+                let output = output.map_err(|err| match err.kind() {
+                    io::ErrorKind::NotFound => Error::NotFound(program),
+                    _ => Error::Io(err),
+                })?;
+                if !output.status.success() {
+                    return Err(Error::probe_failed(output.status, &output.stderr));
+                }
+                parse_output(&String::from_utf8_lossy(&output.stdout))
+            })
+    }
+}
+
+/// Probe the file with the default ffprobe like `scan`, without falling back to mediainfo.
+pub fn scan_async(path: impl AsRef<Path>) -> impl Future<Item = MediaInfo, Error = Error> {
+    Ffprobe::default().scan_async(path)
+}
+
+#[test]
+fn test_scan_async() {
+    match Ffprobe::new("/nonexistent/ffprobe").scan_async("Heat.mkv").wait() {
+        Err(Error::NotFound(program)) => assert_eq!(program, Path::new("/nonexistent/ffprobe")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match Ffprobe::new("false").scan_async("Heat.mkv").wait() {
+        Err(Error::ProbeFailed { code, .. }) => assert_eq!(code, Some(1)),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
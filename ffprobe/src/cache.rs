@@ -0,0 +1,135 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde_json;
+
+use error::Result;
+use ffprobe::MediaInfo;
+
+/// The probe of a file, valid as long as the file keeps its size and modification time.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Entry {
+    size: u64,
+    modified: SystemTime,
+    info: MediaInfo,
+}
+
+/// Probes of the files kept between runs, so that the scans of an unchanged library do not run
+/// ffprobe again. A file is probed again once its size or its modification time changes. The
+/// cache is shared by reference, the probes are added through `&self`.
+#[derive(Debug, Default)]
+pub struct ProbeCache {
+    entries: RefCell<HashMap<PathBuf, Entry>>,
+    changed: Cell<bool>,
+}
+
+impl ProbeCache {
+    /// Load the cache saved at the path. A missing or unreadable cache is an empty one, the files
+    /// are probed again.
+    pub fn load(path: impl AsRef<Path>) -> Result<ProbeCache> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(ProbeCache::default())
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let entries = serde_json::from_str(&content).unwrap_or_default();
+        Ok(ProbeCache {
+            entries: RefCell::new(entries),
+            changed: Cell::new(false),
+        })
+    }
+
+    /// Save the cache at the path, unless no probe was added since it was loaded.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        if !self.changed.get() {
+            return Ok(());
+        }
+        let file = File::create(path)?;
+        serde_json::to_writer(file, &*self.entries.borrow())?;
+        self.changed.set(false);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+
+    /// Get the probe of the file from the cache, or probe it with the function and keep the probe
+    /// when it succeeds. Files whose metadata cannot be read, such as those of a remote machine,
+    /// are always probed.
+    pub fn probe(
+        &self,
+        path: &Path,
+        probe: impl FnOnce(&Path) -> Result<MediaInfo>,
+    ) -> Result<MediaInfo> {
+        let metadata = fs::metadata(path).and_then(|m| Ok((m.len(), m.modified()?)));
+        let (size, modified) = match metadata {
+            Ok(metadata) => metadata,
+            Err(_) => return probe(path),
+        };
+        if let Some(entry) = self.entries.borrow().get(path) {
+            if entry.size == size && entry.modified == modified {
+                return Ok(entry.info.clone());
+            }
+        }
+        let info = probe(path)?;
+        self.entries.borrow_mut().insert(
+            path.to_owned(),
+            Entry {
+                size,
+                modified,
+                info: info.clone(),
+            },
+        );
+        self.changed.set(true);
+        Ok(info)
+    }
+}
+
+#[test]
+fn test_probe_cache() {
+    use std::env;
+
+    use error::Error;
+    use ffprobe::parse_output;
+
+    let dir = env::temp_dir().join(format!("ffprobe-cache-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let movie = dir.join("Heat.mkv");
+    fs::write(&movie, b"movie").unwrap();
+    let failed = dir.join("Ronin.mkv");
+    fs::write(&failed, b"").unwrap();
+    let json = r#"{"streams": [], "format": {"duration": "10227.0", "format_name": "matroska"}}"#;
+
+    let cache = ProbeCache::default();
+    let info = cache.probe(&movie, |_| parse_output(json)).unwrap();
+    assert_eq!(info.duration, Some(10227.0));
+    // The unchanged file is not probed again, and the failures are not kept.
+    let info = cache.probe(&movie, |_| panic!("probed again")).unwrap();
+    assert_eq!(info.format_name.as_ref().map(|s| s.as_str()), Some("matroska"));
+    assert!(cache.probe(&failed, |_| Err(Error::SpawnError("failed".into()))).is_err());
+    assert_eq!(cache.len(), 1);
+
+    let cache_path = dir.join("probes.json");
+    cache.save(&cache_path).unwrap();
+    let cache = ProbeCache::load(&cache_path).unwrap();
+    let info = cache.probe(&movie, |_| panic!("probed again")).unwrap();
+    assert_eq!(info.duration, Some(10227.0));
+
+    // A file whose size changed is probed again.
+    fs::write(&movie, b"another movie").unwrap();
+    let info = cache.probe(&movie, |_| parse_output(r#"{"streams": []}"#)).unwrap();
+    assert_eq!(info.duration, None);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,7 +1,10 @@
 use std::error;
 use std::fmt;
 use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
 use std::result;
+use std::time::Duration;
 
 use serde_json;
 
@@ -9,17 +12,45 @@ use serde_json;
 pub enum Error {
     Io(io::Error),
     Json(serde_json::Error),
+    /// The ffprobe program was not found at its path or on the PATH.
+    NotFound(PathBuf),
     SpawnError(String),
+    /// The program exited with an error, with its exit code, `None` when it was killed by a
+    /// signal, and what it wrote to stderr such as `moov atom not found`.
+    ProbeFailed { code: Option<i32>, stderr: String },
+    /// The program was killed after running longer than the timeout.
+    Timeout(Duration),
 }
 
 pub type Result<T> = result::Result<T, Error>;
 
+impl Error {
+    pub(crate) fn probe_failed(status: ExitStatus, stderr: &[u8]) -> Error {
+        Error::ProbeFailed {
+            code: status.code(),
+            stderr: String::from_utf8_lossy(stderr).trim().to_string(),
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Io(e) => write!(w, "ProbeError({})", e),
             Error::Json(e) => write!(w, "ProbeError({})", e),
+            Error::NotFound(p) => write!(w, "ProbeError({} not found)", p.display()),
             Error::SpawnError(e) => write!(w, "ProbeError({})", e),
+            Error::ProbeFailed { code, stderr } => {
+                match code {
+                    Some(code) => write!(w, "ProbeError(exit code {}", code)?,
+                    None => write!(w, "ProbeError(killed by a signal")?,
+                }
+                if !stderr.is_empty() {
+                    write!(w, ": {}", stderr)?;
+                }
+                write!(w, ")")
+            }
+            Error::Timeout(t) => write!(w, "ProbeError(timed out after {}s)", t.as_secs()),
         }
     }
 }
@@ -29,7 +60,10 @@ impl error::Error for Error {
         match self {
             Error::Io(e) => e.description(),
             Error::Json(e) => e.description(),
+            Error::NotFound(_) => "ffprobe not found",
             Error::SpawnError(_) => "spawn error",
+            Error::ProbeFailed { .. } => "probe failed",
+            Error::Timeout(_) => "timed out",
         }
     }
 
@@ -37,7 +71,10 @@ impl error::Error for Error {
         match self {
             Error::Io(e) => e.cause(),
             Error::Json(e) => e.cause(),
+            Error::NotFound(_) => None,
             Error::SpawnError(_) => None,
+            Error::ProbeFailed { .. } => None,
+            Error::Timeout(_) => None,
         }
     }
 }
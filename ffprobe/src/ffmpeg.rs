@@ -0,0 +1,86 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use error::Result;
+use ffprobe::output_with_stderr;
+
+/// The ffmpeg program, used to decode whole movies. The default one is found at the path of the
+/// FFMPEG_PATH environment variable, or on the PATH. It has no timeout, decoding a movie takes
+/// about as long as reading it.
+#[derive(Clone, Debug)]
+pub struct Ffmpeg {
+    program: PathBuf,
+    timeout: Option<Duration>,
+}
+
+impl Default for Ffmpeg {
+    fn default() -> Ffmpeg {
+        Ffmpeg::new(env::var_os("FFMPEG_PATH").unwrap_or_else(|| "ffmpeg".into()))
+    }
+}
+
+impl Ffmpeg {
+    /// Use the program at this path, or with this name on the PATH, without a timeout.
+    pub fn new(program: impl Into<PathBuf>) -> Ffmpeg {
+        Ffmpeg {
+            program: program.into(),
+            timeout: None,
+        }
+    }
+
+    /// Kill the program when it runs longer than the timeout, and fail with `Error::Timeout`.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Ffmpeg {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn program(&self) -> &Path {
+        &self.program
+    }
+
+    /// Decode every stream of the file and throw the frames away, and get the decode errors, such
+    /// as `[h264 @ 0x55d5] error while decoding MB 12 34`. An intact file has none. A file ffmpeg
+    /// cannot open at all fails with `Error::ProbeFailed`.
+    pub fn verify(&self, path: impl AsRef<Path>) -> Result<Vec<String>> {
+        // ffmpeg -nostdin -v error -i <path> -f null -
+        let mut command = Command::new(&self.program);
+        command
+            .args(&["-nostdin", "-v", "error", "-i"])
+            .arg(path.as_ref())
+            .args(&["-f", "null", "-"]);
+        let (_, stderr) = output_with_stderr(&self.program, self.timeout, &mut command)?;
+        Ok(decode_errors(&String::from_utf8_lossy(&stderr)))
+    }
+}
+
+/// Get the errors written by ffmpeg, one per line.
+fn decode_errors(stderr: &str) -> Vec<String> {
+    stderr
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[test]
+fn test_verify() {
+    use error::Error;
+
+    let stderr = "[h264 @ 0x55d5] error while decoding MB 12 34, bytestream -5\n\n\
+                  [aac @ 0x55e0] Input buffer exhausted before END element found\n";
+    assert_eq!(
+        decode_errors(stderr),
+        vec![
+            "[h264 @ 0x55d5] error while decoding MB 12 34, bytestream -5",
+            "[aac @ 0x55e0] Input buffer exhausted before END element found",
+        ]
+    );
+
+    match Ffmpeg::new("/nonexistent/ffmpeg").verify("Heat.mkv") {
+        Err(Error::NotFound(program)) => assert_eq!(program, Path::new("/nonexistent/ffmpeg")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
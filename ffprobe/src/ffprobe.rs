@@ -1,88 +1,1042 @@
 use std::collections::HashMap;
-use std::path::Path;
-use std::process::Command;
+use std::env;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use serde_json;
+use serde_json::{self, Value};
 
 use error::{Error, Result};
+use mediainfo::Mediainfo;
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct MediaInfo {
     pub video: Vec<VideoStream>,
     pub audio: Vec<AudioStream>,
     pub subtitle: Vec<SubtitleStream>,
+    /// Duration of the container in seconds, `None` when unknown.
+    pub duration: Option<f64>,
+    /// Formats the container was detected as, separated by commas such as `matroska,webm`.
+    #[serde(default)]
+    pub format_name: Option<String>,
+    /// Bit rate of the whole file in bits per second, `None` when unknown.
+    #[serde(default)]
+    pub bit_rate: Option<u64>,
+    #[serde(default)]
+    pub chapters: Vec<Chapter>,
+    #[serde(default)]
+    pub(crate) raw: Value,
 }
 
-pub fn scan(path: impl AsRef<Path>) -> Result<MediaInfo> {
-    // ffprobe -v quiet -print_format json -show_streams <path>
-    let output = Command::new("ffprobe")
-        .args(&["-v", "quiet", "-print_format", "json", "-show_streams"])
-        .arg(path.as_ref())
-        .output()?;
+impl MediaInfo {
+    /// The whole output of the program the file was probed with, for the fields that are not
+    /// typed yet, such as the attachments or the side data of the streams. It is the output of
+    /// ffprobe, or the report of mediainfo when it was probed with mediainfo instead.
+    pub fn raw(&self) -> &Value {
+        &self.raw
+    }
+
+    /// The channels of the first audio stream, see `AudioStream::audio_channels`. The first stream
+    /// is the default one in most releases.
+    pub fn audio_channels(&self) -> Option<String> {
+        self.audio.first().map(AudioStream::audio_channels)
+    }
+
+    /// The languages of the audio streams in the order of the streams, without the duplicates
+    /// and the undetermined languages.
+    pub fn audio_languages(&self) -> Vec<&str> {
+        let mut languages = vec![];
+        for language in self.audio.iter().filter_map(AudioStream::language) {
+            if !languages.contains(&language) {
+                languages.push(language);
+            }
+        }
+        languages
+    }
+
+    /// Bit rate of the first video stream in bits per second. When the container does not report
+    /// it, it is estimated from the bit rate of the file minus the bit rates of the audio streams.
+    pub fn video_bit_rate(&self) -> Option<u64> {
+        let video = self.video.first()?;
+        if let Some(bit_rate) = video.bits_per_second() {
+            return Some(bit_rate);
+        }
+        let mut audio = 0;
+        for stream in self.audio.iter() {
+            audio += stream.bits_per_second()?;
+        }
+        self.bit_rate?.checked_sub(audio).filter(|&bit_rate| bit_rate > 0)
+    }
+
+    /// Tell if the container detected is the one the extension of the file stands for, such as
+    /// `matroska` for `mkv`. `None` when the container or the extension is unknown.
+    pub fn matches_extension(&self, extension: &str) -> Option<bool> {
+        let formats = container_formats(&extension.to_lowercase())?;
+        let format_name = self.format_name.as_ref()?;
+        Some(format_name.split(',').any(|name| formats.contains(&name)))
+    }
+}
+
+/// The names ffprobe gives to the containers the extension stands for.
+fn container_formats(extension: &str) -> Option<&'static [&'static str]> {
+    Some(match extension {
+        "mkv" | "webm" => &["matroska", "webm"],
+        "mp4" | "m4v" | "mov" => &["mov", "mp4"],
+        "avi" => &["avi"],
+        "ts" | "m2ts" | "mts" => &["mpegts"],
+        "mpg" | "mpeg" | "vob" => &["mpeg", "mpegvideo"],
+        "wmv" => &["asf"],
+        "flv" => &["flv"],
+        "ogv" | "ogg" => &["ogg"],
+        _ => return None,
+    })
+}
+
+/// Time after which a probe is killed, unless the FFPROBE_TIMEOUT environment variable sets
+/// another number of seconds.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The ffprobe program. The default one is found at the path of the FFPROBE_PATH environment
+/// variable, or on the PATH.
+#[derive(Clone, Debug)]
+pub struct Ffprobe {
+    program: PathBuf,
+    timeout: Option<Duration>,
+}
+
+/// The timeout of the FFPROBE_TIMEOUT environment variable, or the default one.
+pub(crate) fn default_timeout() -> Duration {
+    env::var("FFPROBE_TIMEOUT")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// A program reading the streams of media files.
+pub trait Probe {
+    fn probe(&self, path: &Path) -> Result<MediaInfo>;
+}
+
+impl Default for Ffprobe {
+    fn default() -> Ffprobe {
+        Ffprobe::new(env::var_os("FFPROBE_PATH").unwrap_or_else(|| "ffprobe".into()))
+            .timeout(Some(default_timeout()))
+    }
+}
+
+impl Ffprobe {
+    /// Use the program at this path, or with this name on the PATH, without a timeout.
+    pub fn new(program: impl Into<PathBuf>) -> Ffprobe {
+        Ffprobe {
+            program: program.into(),
+            timeout: None,
+        }
+    }
+
+    /// Kill the program when it runs longer than the timeout, such as on a corrupt file or a
+    /// network share that hangs, and fail with `Error::Timeout`.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Ffprobe {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn program(&self) -> &Path {
+        &self.program
+    }
+
+    fn command(&self) -> Command {
+        Command::new(&self.program)
+    }
+
+    /// Get the version of the program, such as `4.0.2`.
+    pub fn version(&self) -> Result<String> {
+        let output = output(&self.program, self.timeout, self.command().arg("-version"))?;
+        let stdout = String::from_utf8_lossy(&output);
+        parse_version(&stdout)
+            .map(|version| version.to_string())
+            .ok_or_else(|| Error::SpawnError(format!("unknown version: {}", stdout.trim())))
+    }
+
+    pub fn scan(&self, path: impl AsRef<Path>) -> Result<MediaInfo> {
+        let mut command = self.scan_command(path.as_ref());
+        let output = output(&self.program, self.timeout, &mut command)?;
+        parse_output(&String::from_utf8_lossy(&output))
+    }
+
+    pub(crate) fn scan_command(&self, path: &Path) -> Command {
+        // ffprobe -v error -print_format json -show_streams -show_format -show_chapters <path>
+        // Only the errors are written to stderr, they are kept in `Error::ProbeFailed`.
+        let mut command = self.command();
+        command
+            .args(&["-v", "error", "-print_format", "json"])
+            .args(&["-show_streams", "-show_format", "-show_chapters"])
+            .arg(path);
+        command
+    }
+}
+
+impl Probe for Ffprobe {
+    fn probe(&self, path: &Path) -> Result<MediaInfo> {
+        self.scan(path)
+    }
+}
 
-    if !output.status.success() {
-        return Err(Error::SpawnError(
-            String::from_utf8_lossy(&output.stderr).into_owned(),
-        ));
+/// Run the command of the program and get its output, killing it after the timeout. A missing
+/// program is reported as `Error::NotFound`.
+pub(crate) fn output(
+    program: &Path,
+    timeout: Option<Duration>,
+    command: &mut Command,
+) -> Result<Vec<u8>> {
+    output_with_stderr(program, timeout, command).map(|(stdout, _)| stdout)
+}
+
+/// Run the command like `output`, and also get what the program wrote to stderr.
+pub(crate) fn output_with_stderr(
+    program: &Path,
+    timeout: Option<Duration>,
+    command: &mut Command,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => Error::NotFound(program.to_owned()),
+            _ => Error::Io(err),
+        })?;
+    // The pipes are read while waiting, a child filling a pipe would never exit.
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let status = match timeout {
+        Some(timeout) => match wait_timeout(&mut child, timeout)? {
+            Some(status) => status,
+            None => {
+                child.kill()?;
+                child.wait()?;
+                return Err(Error::Timeout(timeout));
+            }
+        },
+        None => child.wait()?,
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        return Err(Error::probe_failed(status, &stderr));
+    }
+    Ok((stdout, stderr))
+}
+
+/// Read a pipe of a child to the end on another thread.
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Wait for the child to exit, `None` when it is still running after the timeout.
+fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let start = Instant::now();
+    let mut delay = Duration::from_millis(1);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            return Ok(None);
+        }
+        thread::sleep(delay.min(timeout - elapsed));
+        delay = (delay * 2).min(Duration::from_millis(100));
     }
+}
+
+/// Get the version from the first line printed by `ffprobe -version`, such as
+/// `ffprobe version 4.0.2 Copyright (c) 2007-2018 the FFmpeg developers`.
+fn parse_version(output: &str) -> Option<&str> {
+    let mut words = output.lines().next()?.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("ffprobe"), Some("version"), Some(version)) => Some(version),
+        _ => None,
+    }
+}
+
+/// Probe the file with the default ffprobe program, or with the default mediainfo program when
+/// ffprobe is not installed. The program is killed after the default timeout.
+pub fn scan(path: impl AsRef<Path>) -> Result<MediaInfo> {
+    let path = path.as_ref();
+    match Ffprobe::default().scan(path) {
+        Err(Error::NotFound(program)) => match Mediainfo::default().probe(path) {
+            Err(Error::NotFound(_)) => Err(Error::NotFound(program)),
+            res => res,
+        },
+        res => res,
+    }
+}
+
+/// Run ffprobe on another machine through `ssh`, the path is the path on the remote machine. The
+/// `ssh` program is killed after the timeout of the FFPROBE_TIMEOUT environment variable, or the
+/// default one, like a local probe.
+pub fn scan_remote(host: &str, path: impl AsRef<Path>) -> Result<MediaInfo> {
+    // Quote the path for the remote shell.
+    let path = path.as_ref().to_string_lossy().replace('\'', "'\\''");
+    let mut command = Command::new("ssh");
+    command.args(&["-o", "BatchMode=yes", host]).arg(format!(
+        "ffprobe -v error -print_format json -show_streams -show_format -show_chapters '{}'",
+        path
+    ));
+    let output = output(Path::new("ssh"), Some(default_timeout()), &mut command)?;
+    parse_output(&String::from_utf8_lossy(&output))
+}
 
-    let probe: Probe = serde_json::from_reader(&output.stdout[..])?;
+/// Parse the JSON output of `ffprobe -print_format json -show_streams -show_format -show_chapters`
+/// captured elsewhere. The format and the chapters are optional, the duration is unknown without
+/// the format.
+pub fn parse_output(json: &str) -> Result<MediaInfo> {
+    let raw: Value = serde_json::from_str(json)?;
+    let probe: ProbeOutput = serde_json::from_value(raw.clone())?;
     let mut video = vec![];
     let mut audio = vec![];
     let mut subtitle = vec![];
 
     for stream in probe.streams.into_iter() {
-        match stream {
-            Stream::Video(inner) => video.push(inner),
-            Stream::Audio(inner) => audio.push(inner),
-            Stream::Subtitle(inner) => subtitle.push(inner),
+        // Data and attachment streams, such as the fonts of a Matroska file, are skipped.
+        match stream["codec_type"].as_str() {
+            Some("video") => video.push(serde_json::from_value(stream)?),
+            Some("audio") => audio.push(serde_json::from_value(stream)?),
+            Some("subtitle") => subtitle.push(serde_json::from_value(stream)?),
+            _ => {}
         }
     }
 
+    let (duration, format_name, bit_rate) = match probe.format {
+        Some(format) => (
+            format.duration.and_then(|duration| duration.parse().ok()),
+            format.format_name,
+            format.bit_rate.and_then(|bit_rate| bit_rate.parse().ok()),
+        ),
+        None => (None, None, None),
+    };
+    let chapters = probe
+        .chapters
+        .into_iter()
+        .filter_map(|chapter| {
+            Some(Chapter {
+                start: chapter.start_time.parse().ok()?,
+                end: chapter.end_time.parse().ok()?,
+                title: chapter.tags.get("title").cloned(),
+            })
+        })
+        .collect();
+
     Ok(MediaInfo {
         video,
         audio,
         subtitle,
+        duration,
+        format_name,
+        bit_rate,
+        chapters,
+        raw,
     })
 }
 
 #[derive(Debug, Deserialize)]
-struct Probe {
-    streams: Vec<Stream>,
+struct ProbeOutput {
+    streams: Vec<Value>,
+    #[serde(default)]
+    format: Option<Format>,
+    #[serde(default)]
+    chapters: Vec<RawChapter>,
 }
 
+/// A chapter as printed by ffprobe, its times are decimal numbers of seconds such as `512.345000`.
 #[derive(Debug, Deserialize)]
-#[serde(tag = "codec_type")]
-enum Stream {
-    #[serde(rename = "video")]
-    Video(VideoStream),
-    #[serde(rename = "audio")]
-    Audio(AudioStream),
-    #[serde(rename = "subtitle")]
-    Subtitle(SubtitleStream),
+struct RawChapter {
+    start_time: String,
+    end_time: String,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+/// A chapter of the movie, its start and end in seconds.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Chapter {
+    pub start: f64,
+    pub end: f64,
+    pub title: Option<String>,
+}
+
+impl Chapter {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
 }
 
 #[derive(Debug, Deserialize)]
+struct Format {
+    /// Duration in seconds written as a decimal number, such as `6135.424000`.
+    #[serde(default)]
+    duration: Option<String>,
+    #[serde(default)]
+    format_name: Option<String>,
+    /// Bit rate in bits per second written as a number, such as `10905473`.
+    #[serde(default)]
+    bit_rate: Option<String>,
+}
+
+/// Get the language of the stream from its tags, `None` when undetermined.
+fn language(tags: &HashMap<String, String>) -> Option<&str> {
+    tags.get("language")
+        .map(|s| s.as_str())
+        .filter(|&lang| !lang.is_empty() && lang != "und")
+}
+
+/// Get the bit rate of a stream in bits per second, or the one in its tags when the container
+/// does not report it, such as the `BPS` statistics mkvmerge writes in Matroska files.
+fn bits_per_second(bit_rate: Option<&str>, tags: &HashMap<String, String>) -> Option<u64> {
+    bit_rate
+        .and_then(|bit_rate| bit_rate.parse().ok())
+        .or_else(|| tags.get("BPS").or_else(|| tags.get("BPS-eng"))?.parse().ok())
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct VideoStream {
     pub index: u32,
     pub codec_name: String,
     pub width: u32,
     pub height: u32,
+    /// Order of the fields, `progressive` or one of `tt`, `bb`, `tb` and `bt` for interlaced
+    /// video. Not reported by every container.
+    #[serde(default)]
+    pub field_order: Option<String>,
+    /// Average frame rate as a fraction, such as `24000/1001`, or `0/0` when unknown.
+    #[serde(default)]
+    pub avg_frame_rate: String,
+    /// Lowest frame rate all the timestamps can be represented with, as a fraction. It differs
+    /// from the average frame rate for variable frame rate video.
+    #[serde(default)]
+    pub r_frame_rate: String,
+    /// Display aspect ratio, such as `16:9`, or `0:1` when unknown.
+    #[serde(default)]
+    pub display_aspect_ratio: Option<String>,
+    /// Aspect ratio of the pixels, such as `1:1` or `64:45` for anamorphic PAL.
+    #[serde(default)]
+    pub sample_aspect_ratio: Option<String>,
+    /// Bit rate in bits per second written as a number, not reported by every container.
+    #[serde(default)]
+    pub bit_rate: Option<String>,
+    /// Pixel format, such as `yuv420p` or `yuv420p10le`.
+    #[serde(default)]
+    pub pix_fmt: Option<String>,
+    /// Bits per sample written as a number, such as `10`. Not reported by every codec.
+    #[serde(default)]
+    pub bits_per_raw_sample: Option<String>,
+    /// Transfer characteristics, `smpte2084` for the PQ curve of HDR10 and `arib-std-b67` for HLG.
+    #[serde(default)]
+    pub color_transfer: Option<String>,
+    /// Color primaries, such as `bt709` or `bt2020`.
+    #[serde(default)]
+    pub color_primaries: Option<String>,
+    /// Side data of the stream, such as the Dolby Vision configuration record.
+    #[serde(default)]
+    pub side_data_list: Vec<SideData>,
+    #[serde(default)]
     pub tags: HashMap<String, String>,
 }
 
-#[derive(Debug, Deserialize)]
+impl VideoStream {
+    /// Language of the stream, such as `eng`, `None` when undetermined.
+    pub fn language(&self) -> Option<&str> {
+        language(&self.tags)
+    }
+
+    /// Bit rate of the stream in bits per second, `None` when unknown.
+    pub fn bits_per_second(&self) -> Option<u64> {
+        bits_per_second(self.bit_rate.as_ref().map(|s| s.as_str()), &self.tags)
+    }
+
+    /// Average frame rate in frames per second, `None` when unknown.
+    pub fn frame_rate(&self) -> Option<f64> {
+        parse_ratio(&self.avg_frame_rate, '/')
+    }
+
+    /// Tell if the frame rate of the video varies, from the average frame rate differing from the
+    /// base frame rate. The base frame rate of interlaced video is its field rate, twice its frame
+    /// rate.
+    pub fn is_variable_frame_rate(&self) -> bool {
+        let (avg, base) = match (self.frame_rate(), parse_ratio(&self.r_frame_rate, '/')) {
+            (Some(avg), Some(base)) => (avg, base),
+            _ => return false,
+        };
+        (avg - base).abs() >= FRAME_RATE_TOLERANCE
+            && (avg * 2.0 - base).abs() >= FRAME_RATE_TOLERANCE
+    }
+
+    /// Get the standard of the frame rate, `None` for the other frame rates.
+    pub fn standard(&self) -> Option<Standard> {
+        let fps = self.frame_rate()?;
+        let near = |rate: f64| (rate - fps).abs() < FRAME_RATE_TOLERANCE;
+        if near(24.0) {
+            Some(Standard::Film)
+        } else if near(25.0) || near(50.0) {
+            Some(Standard::Pal)
+        } else if near(24000.0 / 1001.0) || near(30000.0 / 1001.0) || near(60000.0 / 1001.0) {
+            Some(Standard::Ntsc)
+        } else {
+            None
+        }
+    }
+
+    /// Display aspect ratio as a number, such as 1.78 for 16:9. Computed from the dimensions and
+    /// the aspect ratio of the pixels when the display aspect ratio is not reported.
+    pub fn aspect_ratio(&self) -> Option<f64> {
+        if let Some(ratio) = self.display_aspect_ratio.as_ref().and_then(|r| parse_ratio(r, ':')) {
+            return Some(ratio);
+        }
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+        let sample = self
+            .sample_aspect_ratio
+            .as_ref()
+            .and_then(|r| parse_ratio(r, ':'))
+            .unwrap_or(1.0);
+        Some(f64::from(self.width) / f64::from(self.height) * sample)
+    }
+
+    /// Bits per sample of the video, from the bits per sample reported or the pixel format, such
+    /// as 10 for `yuv420p10le`. Pixel formats without a depth are 8 bits.
+    pub fn bit_depth(&self) -> Option<u32> {
+        if let Some(bits) = self.bits_per_raw_sample.as_ref().and_then(|b| b.parse().ok()) {
+            return Some(bits);
+        }
+        let pix_fmt = self.pix_fmt.as_ref()?;
+        let format = pix_fmt.trim_right_matches("le").trim_right_matches("be");
+        let digits = format.len() - format.trim_right_matches(|c: char| c.is_ascii_digit()).len();
+        match format[format.len() - digits..].parse() {
+            Ok(bits) if bits > 8 && format[..format.len() - digits].ends_with('p') => Some(bits),
+            _ => Some(8),
+        }
+    }
+
+    /// Get the kind of HDR of the video from its side data and transfer characteristics, `None`
+    /// for standard dynamic range.
+    pub fn hdr(&self) -> Option<Hdr> {
+        let has_side_data = |kind: &str| {
+            self.side_data_list
+                .iter()
+                .any(|data| data.side_data_type.contains(kind))
+        };
+        if has_side_data("DOVI configuration record") {
+            return Some(Hdr::DolbyVision);
+        }
+        match self.color_transfer.as_ref().map(|s| s.as_str()) {
+            Some("smpte2084") if has_side_data("HDR10+") => Some(Hdr::Hdr10Plus),
+            Some("smpte2084") => Some(Hdr::Hdr10),
+            Some("arib-std-b67") => Some(Hdr::Hlg),
+            _ => None,
+        }
+    }
+
+    /// Tell if the video is interlaced according to its field order.
+    pub fn is_interlaced(&self) -> bool {
+        match self.field_order.as_ref().map(|s| s.as_str()) {
+            Some("tt") | Some("bb") | Some("tb") | Some("bt") => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SideData {
+    /// Kind of side data, such as `DOVI configuration record`.
+    #[serde(default)]
+    pub side_data_type: String,
+    /// Profile of Dolby Vision, such as 5 or 8, in its configuration record.
+    #[serde(default)]
+    pub dv_profile: Option<u32>,
+}
+
+/// Difference in frames per second under which two frame rates are the same.
+const FRAME_RATE_TOLERANCE: f64 = 0.01;
+
+/// Parse a ratio such as `24000/1001` or `16:9`, `None` when a part is zero.
+fn parse_ratio(ratio: &str, separator: char) -> Option<f64> {
+    let mut parts = ratio.splitn(2, separator);
+    let num: f64 = parts.next()?.parse().ok()?;
+    let den: f64 = parts.next().unwrap_or("1").parse().ok()?;
+    if num == 0.0 || den == 0.0 {
+        return None;
+    }
+    Some(num / den)
+}
+
+/// Standard of a frame rate. The same movie runs 4% faster in PAL than on film or in NTSC.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Standard {
+    /// 24 frames per second.
+    Film,
+    /// 25 or 50 frames per second.
+    Pal,
+    /// 23.976, 29.97 or 59.94 frames per second.
+    Ntsc,
+}
+
+impl Standard {
+    pub fn name(self) -> &'static str {
+        match self {
+            Standard::Film => "film",
+            Standard::Pal => "PAL",
+            Standard::Ntsc => "NTSC",
+        }
+    }
+}
+
+impl fmt::Display for Standard {
+    fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
+        w.write_str(self.name())
+    }
+}
+
+/// Kind of high dynamic range video.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Hdr {
+    DolbyVision,
+    Hdr10Plus,
+    Hdr10,
+    Hlg,
+}
+
+impl Hdr {
+    /// Name of the kind of HDR the way release names write it, such as `DV` or `HDR10`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Hdr::DolbyVision => "DV",
+            Hdr::Hdr10Plus => "HDR10+",
+            Hdr::Hdr10 => "HDR10",
+            Hdr::Hlg => "HLG",
+        }
+    }
+}
+
+impl fmt::Display for Hdr {
+    fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
+        w.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AudioStream {
     pub index: u32,
     pub codec_name: String,
+    /// Bit rate in bits per second, empty when the container does not report it.
+    #[serde(default)]
     pub bit_rate: String,
     pub channels: u32,
+    /// Layout of the channels, such as `stereo` or `5.1(side)`.
+    #[serde(default)]
+    pub channel_layout: Option<String>,
+    /// Profile of the codec, which tells Dolby Atmos tracks apart, such as
+    /// `Dolby TrueHD + Dolby Atmos`.
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
     pub tags: HashMap<String, String>,
 }
 
-#[derive(Debug, Deserialize)]
+impl AudioStream {
+    /// Language of the stream, such as `eng`, `None` when undetermined.
+    pub fn language(&self) -> Option<&str> {
+        language(&self.tags)
+    }
+
+    /// Bit rate of the stream in bits per second, `None` when unknown.
+    pub fn bits_per_second(&self) -> Option<u64> {
+        bits_per_second(Some(&self.bit_rate), &self.tags)
+    }
+
+    /// Title of the track, such as `Commentary`.
+    pub fn title(&self) -> Option<&str> {
+        self.tags.get("title").map(|s| s.as_str())
+    }
+
+    /// The channels written the way release names write them, such as `2.0`, `5.1` or `7.1`.
+    pub fn channels_label(&self) -> String {
+        let layout = self.channel_layout.as_ref().map(|s| s.as_str()).unwrap_or("");
+        match layout {
+            "mono" => return "1.0".to_string(),
+            "stereo" => return "2.0".to_string(),
+            _ => {}
+        }
+        // Layouts such as `5.1(side)` or `7.1(wide)` start with the label.
+        let label = layout.split('(').next().unwrap_or("");
+        if label.contains('.') && label.chars().all(|c| c.is_ascii_digit() || c == '.') {
+            return label.to_string();
+        }
+        match self.channels {
+            1 => "1.0".to_string(),
+            2 => "2.0".to_string(),
+            6 => "5.1".to_string(),
+            8 => "7.1".to_string(),
+            channels => format!("{}ch", channels),
+        }
+    }
+
+    /// Tell if the track carries Dolby Atmos metadata, according to its profile or title.
+    pub fn is_atmos(&self) -> bool {
+        let title = self.title().unwrap_or("");
+        let profile = self.profile.as_ref().map(|s| s.as_str()).unwrap_or("");
+        profile.contains("Atmos") || title.contains("Atmos")
+    }
+
+    /// The channels followed by `Atmos` for Atmos tracks, such as `7.1 Atmos`.
+    pub fn audio_channels(&self) -> String {
+        if self.is_atmos() {
+            format!("{} Atmos", self.channels_label())
+        } else {
+            self.channels_label()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct SubtitleStream {
     pub index: u32,
     pub codec_name: String,
+    #[serde(default)]
+    pub disposition: Disposition,
+    #[serde(default)]
     pub tags: HashMap<String, String>,
 }
+
+/// Flags of a stream, each set to 1 or 0.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Disposition {
+    #[serde(default)]
+    pub default: u8,
+    #[serde(default)]
+    pub forced: u8,
+    #[serde(default)]
+    pub hearing_impaired: u8,
+}
+
+impl SubtitleStream {
+    /// Language of the stream, such as `eng`, `None` when undetermined.
+    pub fn language(&self) -> Option<&str> {
+        language(&self.tags)
+    }
+
+    /// Title of the track, such as `Forced`.
+    pub fn title(&self) -> Option<&str> {
+        self.tags.get("title").map(|s| s.as_str())
+    }
+
+    /// Tell if the subtitles are only shown for the foreign dialogues, according to the flags or
+    /// the title of the track.
+    pub fn is_forced(&self) -> bool {
+        self.disposition.forced != 0 || self.title_has("forced")
+    }
+
+    /// Tell if the subtitles are for the deaf and hard of hearing, according to the flags or the
+    /// title of the track.
+    pub fn is_sdh(&self) -> bool {
+        self.disposition.hearing_impaired != 0 || self.title_has("sdh")
+    }
+
+    fn title_has(&self, word: &str) -> bool {
+        self.title().map_or(false, |title| {
+            title
+                .split(|c: char| !c.is_alphanumeric())
+                .any(|w| w.eq_ignore_ascii_case(word))
+        })
+    }
+}
+
+#[test]
+fn test_parse_output() {
+    let json = r#"{
+        "streams": [
+            {
+                "index": 0,
+                "codec_type": "video",
+                "codec_name": "h264",
+                "width": 1920,
+                "height": 1080,
+                "field_order": "tt",
+                "avg_frame_rate": "30000/1001",
+                "tags": {"BPS": "8000000"}
+            },
+            {
+                "index": 1,
+                "codec_type": "audio",
+                "codec_name": "ac3",
+                "bit_rate": "640000",
+                "channels": 6,
+                "channel_layout": "5.1(side)",
+                "tags": {"language": "eng"}
+            },
+            {
+                "index": 2,
+                "codec_type": "subtitle",
+                "codec_name": "subrip",
+                "tags": {"language": "fre"}
+            },
+            {
+                "index": 5,
+                "codec_type": "subtitle",
+                "codec_name": "hdmv_pgs_subtitle",
+                "disposition": {"default": 0, "forced": 1, "hearing_impaired": 0},
+                "tags": {"language": "eng"}
+            },
+            {
+                "index": 6,
+                "codec_type": "subtitle",
+                "codec_name": "subrip",
+                "tags": {"language": "eng", "title": "English (SDH)"}
+            },
+            {
+                "index": 3,
+                "codec_type": "attachment",
+                "codec_name": "ttf"
+            },
+            {
+                "index": 4,
+                "codec_type": "audio",
+                "codec_name": "aac",
+                "channels": 2,
+                "tags": {"language": "und", "title": "Commentary"}
+            }
+        ],
+        "chapters": [
+            {
+                "id": 0,
+                "time_base": "1/1000000000",
+                "start_time": "0.000000",
+                "end_time": "512.345000",
+                "tags": {"title": "Opening"}
+            },
+            {
+                "id": 1,
+                "time_base": "1/1000000000",
+                "start_time": "512.345000",
+                "end_time": "6135.424000"
+            }
+        ],
+        "format": {
+            "format_name": "matroska,webm",
+            "duration": "6135.424000",
+            "bit_rate": "8832000"
+        }
+    }"#;
+
+    let info = parse_output(json).unwrap();
+    assert_eq!(info.video[0].width, 1920);
+    assert!(info.video[0].is_interlaced());
+    assert_eq!(info.video[0].frame_rate().map(|r| (r * 100.0).round()), Some(2997.0));
+    assert_eq!(info.audio[0].channels, 6);
+    assert_eq!(info.audio_channels(), Some("5.1".to_string()));
+    assert_eq!(info.video[0].language(), None);
+    assert_eq!(info.video[0].bit_depth(), None);
+    assert_eq!(info.video[0].hdr(), None);
+    assert_eq!(info.audio[0].language(), Some("eng"));
+    assert_eq!(info.audio[1].language(), None);
+    assert_eq!(info.audio[1].title(), Some("Commentary"));
+    assert_eq!(info.audio_languages(), vec!["eng"]);
+    assert_eq!(info.subtitle[0].language(), Some("fre"));
+    assert!(!info.subtitle[0].is_forced() && !info.subtitle[0].is_sdh());
+    assert!(info.subtitle[1].is_forced() && !info.subtitle[1].is_sdh());
+    assert!(!info.subtitle[2].is_forced() && info.subtitle[2].is_sdh());
+    assert_eq!(info.duration, Some(6135.424));
+    assert_eq!(info.chapters.len(), 2);
+    assert_eq!(info.chapters[0].title, Some("Opening".to_string()));
+    assert_eq!(info.chapters[0].duration(), 512.345);
+    assert_eq!(info.chapters[1].title, None);
+    assert_eq!(info.matches_extension("mkv"), Some(true));
+    assert_eq!(info.matches_extension("WEBM"), Some(true));
+    assert_eq!(info.matches_extension("avi"), Some(false));
+    assert_eq!(info.matches_extension("rmvb"), None);
+    assert_eq!(info.raw()["streams"][5]["codec_name"], "ttf");
+    assert_eq!(info.bit_rate, Some(8832000));
+    assert_eq!(info.video[0].bits_per_second(), Some(8000000));
+    assert_eq!(info.audio[0].bits_per_second(), Some(640000));
+    assert_eq!(info.video_bit_rate(), Some(8000000));
+}
+
+#[test]
+fn test_version() {
+    let output = "ffprobe version 4.0.2 Copyright (c) 2007-2018 the FFmpeg developers\n\
+                  built with gcc 8.2.1 (GCC) 20180831\n";
+    assert_eq!(parse_version(output), Some("4.0.2"));
+    assert_eq!(parse_version("ffprobe version n4.4-78-g031c0cb0b4"), Some("n4.4-78-g031c0cb0b4"));
+    assert_eq!(parse_version("ffmpeg version 4.0.2"), None);
+    assert_eq!(parse_version(""), None);
+
+    match Ffprobe::new("/nonexistent/ffprobe").version() {
+        Err(Error::NotFound(program)) => assert_eq!(program, Path::new("/nonexistent/ffprobe")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_timeout() {
+    let timeout = Duration::from_millis(100);
+    let sh = Path::new("sh");
+    let start = Instant::now();
+    match output(sh, Some(timeout), Command::new("sh").args(&["-c", "exec sleep 10"])) {
+        Err(Error::Timeout(t)) => assert_eq!(t, timeout),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+    let stdout = output(sh, Some(timeout), Command::new("sh").args(&["-c", "echo ok"])).unwrap();
+    assert_eq!(stdout, b"ok\n");
+}
+
+#[test]
+fn test_probe_failed() {
+    let script = "echo 'Heat.mp4: moov atom not found' >&2; exit 1";
+    match output(Path::new("sh"), None, Command::new("sh").args(&["-c", script])) {
+        Err(err @ Error::ProbeFailed { .. }) => {
+            assert_eq!(err.to_string(), "ProbeError(exit code 1: Heat.mp4: moov atom not found)")
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_hdr() {
+    let json = r#"{
+        "streams": [
+            {
+                "index": 0,
+                "codec_type": "video",
+                "codec_name": "hevc",
+                "width": 3840,
+                "height": 2160,
+                "pix_fmt": "yuv420p10le",
+                "color_transfer": "smpte2084",
+                "color_primaries": "bt2020",
+                "side_data_list": [
+                    {"side_data_type": "DOVI configuration record", "dv_profile": 8}
+                ]
+            },
+            {
+                "index": 1,
+                "codec_type": "video",
+                "codec_name": "hevc",
+                "width": 3840,
+                "height": 2160,
+                "pix_fmt": "yuv420p10le",
+                "color_transfer": "smpte2084"
+            },
+            {
+                "index": 2,
+                "codec_type": "video",
+                "codec_name": "hevc",
+                "width": 1920,
+                "height": 1080,
+                "pix_fmt": "yuv420p10le",
+                "color_transfer": "arib-std-b67"
+            },
+            {
+                "index": 3,
+                "codec_type": "video",
+                "codec_name": "h264",
+                "width": 1920,
+                "height": 1080,
+                "pix_fmt": "yuv420p",
+                "bits_per_raw_sample": "8",
+                "color_transfer": "bt709"
+            }
+        ]
+    }"#;
+
+    let info = parse_output(json).unwrap();
+    assert_eq!(info.video[0].hdr(), Some(Hdr::DolbyVision));
+    assert_eq!(info.video[0].side_data_list[0].dv_profile, Some(8));
+    assert_eq!(info.video[0].bit_depth(), Some(10));
+    assert_eq!(info.video[1].hdr(), Some(Hdr::Hdr10));
+    assert_eq!(info.video[2].hdr(), Some(Hdr::Hlg));
+    assert_eq!(info.video[3].hdr(), None);
+    assert_eq!(info.video[3].bit_depth(), Some(8));
+    assert_eq!(Hdr::Hdr10Plus.to_string(), "HDR10+");
+}
+
+#[test]
+fn test_frame_rate() {
+    let json = r#"{
+        "streams": [
+            {
+                "index": 0,
+                "codec_type": "video",
+                "codec_name": "mpeg2video",
+                "width": 720,
+                "height": 576,
+                "field_order": "tt",
+                "avg_frame_rate": "25/1",
+                "r_frame_rate": "50/1",
+                "display_aspect_ratio": "16:9",
+                "sample_aspect_ratio": "64:45"
+            },
+            {
+                "index": 1,
+                "codec_type": "video",
+                "codec_name": "h264",
+                "width": 1920,
+                "height": 1080,
+                "avg_frame_rate": "24000/1001",
+                "r_frame_rate": "24000/1001"
+            },
+            {
+                "index": 2,
+                "codec_type": "video",
+                "codec_name": "h264",
+                "width": 1280,
+                "height": 720,
+                "avg_frame_rate": "24/1",
+                "r_frame_rate": "120/1",
+                "display_aspect_ratio": "0:1",
+                "sample_aspect_ratio": "0:1"
+            }
+        ]
+    }"#;
+
+    let info = parse_output(json).unwrap();
+    assert_eq!(info.video[0].standard(), Some(Standard::Pal));
+    assert!(info.video[0].is_interlaced());
+    assert!(!info.video[0].is_variable_frame_rate());
+    assert_eq!(info.video[0].aspect_ratio(), Some(16.0 / 9.0));
+    assert_eq!(info.video[1].standard(), Some(Standard::Ntsc));
+    assert!(!info.video[1].is_variable_frame_rate());
+    assert_eq!(info.video[1].aspect_ratio(), Some(16.0 / 9.0));
+    assert_eq!(info.video[2].standard(), Some(Standard::Film));
+    assert!(info.video[2].is_variable_frame_rate());
+    assert_eq!(info.video[2].aspect_ratio(), Some(1280.0 / 720.0));
+}
+
+#[test]
+fn test_audio_channels() {
+    let mut stream = AudioStream {
+        index: 1,
+        codec_name: "truehd".to_string(),
+        bit_rate: String::new(),
+        channels: 8,
+        channel_layout: None,
+        profile: Some("Dolby TrueHD + Dolby Atmos".to_string()),
+        tags: HashMap::new(),
+    };
+    assert_eq!(stream.audio_channels(), "7.1 Atmos");
+
+    stream.profile = None;
+    stream.channel_layout = Some("stereo".to_string());
+    assert_eq!(stream.audio_channels(), "2.0");
+}
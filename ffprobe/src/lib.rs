@@ -1,10 +1,27 @@
+#[cfg(feature = "async")]
+extern crate futures;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+#[cfg(feature = "async")]
+extern crate tokio_process;
 
+#[cfg(feature = "async")]
+mod async_scan;
+mod cache;
 mod error;
+mod ffmpeg;
 mod ffprobe;
+mod mediainfo;
 
+#[cfg(feature = "async")]
+pub use async_scan::scan_async;
+pub use cache::ProbeCache;
 pub use error::{Error, Result};
-pub use ffprobe::scan;
+pub use ffmpeg::Ffmpeg;
+pub use ffprobe::{
+    parse_output, scan, scan_remote, AudioStream, Chapter, Disposition, Ffprobe, Hdr, MediaInfo,
+    Probe, SideData, Standard, SubtitleStream, VideoStream, DEFAULT_TIMEOUT,
+};
+pub use mediainfo::{parse_mediainfo, Mediainfo};
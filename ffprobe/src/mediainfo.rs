@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use serde_json::{self, Value};
+
+use error::{Error, Result};
+use ffprobe::{
+    default_timeout, output, AudioStream, Chapter, Disposition, MediaInfo, Probe, SideData,
+    SubtitleStream, VideoStream,
+};
+
+/// The mediainfo program, used instead of ffprobe where ffmpeg is not installed. The default one
+/// is found at the path of the MEDIAINFO_PATH environment variable, or on the PATH. Its report is
+/// converted to the streams ffprobe reports.
+#[derive(Clone, Debug)]
+pub struct Mediainfo {
+    program: PathBuf,
+    timeout: Option<Duration>,
+}
+
+impl Default for Mediainfo {
+    fn default() -> Mediainfo {
+        Mediainfo::new(env::var_os("MEDIAINFO_PATH").unwrap_or_else(|| "mediainfo".into()))
+            .timeout(Some(default_timeout()))
+    }
+}
+
+impl Mediainfo {
+    /// Use the program at this path, or with this name on the PATH, without a timeout.
+    pub fn new(program: impl Into<PathBuf>) -> Mediainfo {
+        Mediainfo {
+            program: program.into(),
+            timeout: None,
+        }
+    }
+
+    /// Kill the program when it runs longer than the timeout, and fail with `Error::Timeout`.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Mediainfo {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn program(&self) -> &Path {
+        &self.program
+    }
+
+    /// Get the version of the program, such as `21.09`.
+    pub fn version(&self) -> Result<String> {
+        let mut command = Command::new(&self.program);
+        command.arg("--Version");
+        let stdout = output(&self.program, self.timeout, &mut command)?;
+        let stdout = String::from_utf8_lossy(&stdout);
+        parse_version(&stdout)
+            .map(|version| version.to_string())
+            .ok_or_else(|| Error::SpawnError(format!("unknown version: {}", stdout.trim())))
+    }
+}
+
+impl Probe for Mediainfo {
+    fn probe(&self, path: &Path) -> Result<MediaInfo> {
+        // mediainfo --Output=JSON <path>
+        let mut command = Command::new(&self.program);
+        command.arg("--Output=JSON").arg(path);
+        let stdout = output(&self.program, self.timeout, &mut command)?;
+        parse_mediainfo(&String::from_utf8_lossy(&stdout))
+    }
+}
+
+/// Get the version from the output of `mediainfo --Version`, such as
+/// `MediaInfo Command line,\nMediaInfoLib - v21.09`.
+fn parse_version(output: &str) -> Option<&str> {
+    output
+        .lines()
+        .filter_map(|line| line.split(" - v").nth(1))
+        .map(|version| version.trim())
+        .next()
+}
+
+/// Parse the JSON report of `mediainfo --Output=JSON`, whose values are all strings, into the
+/// streams ffprobe reports. The names of the formats are translated to the names of the codecs of
+/// ffprobe when they are known.
+pub fn parse_mediainfo(json: &str) -> Result<MediaInfo> {
+    let report: Value = serde_json::from_str(json)?;
+    let tracks = report["media"]["track"].as_array().cloned().unwrap_or_default();
+
+    let mut info = MediaInfo {
+        video: vec![],
+        audio: vec![],
+        subtitle: vec![],
+        duration: None,
+        format_name: None,
+        bit_rate: None,
+        chapters: vec![],
+        raw: Value::Null,
+    };
+
+    for (position, track) in tracks.iter().enumerate() {
+        let field = |name: &str| track[name].as_str();
+        let index = field("StreamOrder")
+            .and_then(|order| order.parse().ok())
+            .unwrap_or(position as u32);
+        let codec_name = codec_name(field("Format").unwrap_or(""));
+        let mut tags = HashMap::new();
+        if let Some(language) = field("Language") {
+            tags.insert("language".to_string(), language.to_string());
+        }
+        if let Some(title) = field("Title") {
+            tags.insert("title".to_string(), title.to_string());
+        }
+        let flag = |name: &str| if field(name) == Some("Yes") { 1 } else { 0 };
+
+        match field("@type") {
+            Some("General") => {
+                info.duration = field("Duration").and_then(|d| d.parse().ok());
+                info.format_name = field("Format").map(format_name);
+                info.bit_rate = field("OverallBitRate").and_then(|b| b.parse().ok());
+            }
+            Some("Video") => {
+                let frame_rate = match (field("FrameRate_Num"), field("FrameRate_Den")) {
+                    (Some(num), Some(den)) => format!("{}/{}", num, den),
+                    _ => field("FrameRate").unwrap_or("0").to_string(),
+                };
+                let base_frame_rate = match field("FrameRate_Mode") {
+                    Some("VFR") => field("FrameRate_Maximum").unwrap_or("0").to_string(),
+                    _ => frame_rate.clone(),
+                };
+                let field_order = match (field("ScanType"), field("ScanOrder")) {
+                    (Some("Progressive"), _) => Some("progressive"),
+                    (_, Some("TFF")) => Some("tt"),
+                    (_, Some("BFF")) => Some("bb"),
+                    _ => None,
+                };
+                let hdr_format = field("HDR_Format").unwrap_or("");
+                let mut side_data_list = vec![];
+                if hdr_format.contains("Dolby Vision") {
+                    side_data_list.push(SideData {
+                        side_data_type: "DOVI configuration record".to_string(),
+                        dv_profile: field("HDR_Format_Profile")
+                            .and_then(|p| p.split('.').nth(1))
+                            .map(|p| p.trim_right_matches(|c: char| !c.is_ascii_digit()))
+                            .and_then(|p| p.parse().ok()),
+                    });
+                }
+                if hdr_format.contains("SMPTE ST 2094 App 4") {
+                    side_data_list.push(SideData {
+                        side_data_type: "HDR10+".to_string(),
+                        dv_profile: None,
+                    });
+                }
+                info.video.push(VideoStream {
+                    index,
+                    codec_name,
+                    width: field("Width").and_then(|w| w.parse().ok()).unwrap_or(0),
+                    height: field("Height").and_then(|h| h.parse().ok()).unwrap_or(0),
+                    field_order: field_order.map(|s| s.to_string()),
+                    avg_frame_rate: frame_rate,
+                    r_frame_rate: base_frame_rate,
+                    display_aspect_ratio: field("DisplayAspectRatio")
+                        .map(|ratio| format!("{}:1", ratio)),
+                    sample_aspect_ratio: field("PixelAspectRatio")
+                        .map(|ratio| format!("{}:1", ratio)),
+                    bit_rate: field("BitRate").map(|s| s.to_string()),
+                    pix_fmt: None,
+                    bits_per_raw_sample: field("BitDepth").map(|s| s.to_string()),
+                    color_transfer: field("transfer_characteristics").map(|t| match t {
+                        "PQ" => "smpte2084".to_string(),
+                        "HLG" => "arib-std-b67".to_string(),
+                        _ => t.to_lowercase().replace('.', ""),
+                    }),
+                    color_primaries: field("colour_primaries")
+                        .map(|p| p.to_lowercase().replace('.', "")),
+                    side_data_list,
+                    tags,
+                });
+            }
+            Some("Audio") => info.audio.push(AudioStream {
+                index,
+                codec_name,
+                bit_rate: field("BitRate").unwrap_or("").to_string(),
+                channels: field("Channels").and_then(|c| c.parse().ok()).unwrap_or(0),
+                channel_layout: None,
+                profile: field("Format_Commercial_IfAny").map(|s| s.to_string()),
+                tags,
+            }),
+            Some("Text") => info.subtitle.push(SubtitleStream {
+                index,
+                codec_name,
+                disposition: Disposition {
+                    default: flag("Default"),
+                    forced: flag("Forced"),
+                    hearing_impaired: 0,
+                },
+                tags,
+            }),
+            Some("Menu") => info.chapters = chapters(&track["extra"], info.duration),
+            _ => {}
+        }
+    }
+
+    info.raw = report;
+    Ok(info)
+}
+
+/// Translate the name of a format to the name ffprobe gives to its codec.
+fn codec_name(format: &str) -> String {
+    match format {
+        "AVC" => "h264",
+        "HEVC" => "hevc",
+        "MPEG Video" => "mpeg2video",
+        "MPEG-4 Visual" => "mpeg4",
+        "AC-3" => "ac3",
+        "E-AC-3" => "eac3",
+        "MLP FBA" => "truehd",
+        "MPEG Audio" => "mp3",
+        "UTF-8" => "subrip",
+        "PGS" => "hdmv_pgs_subtitle",
+        "VobSub" => "dvd_subtitle",
+        format => return format.to_lowercase(),
+    }.to_string()
+}
+
+/// Translate the name of a container to the names ffprobe gives to it.
+fn format_name(format: &str) -> String {
+    match format {
+        "Matroska" => "matroska,webm",
+        "WebM" => "matroska,webm",
+        "MPEG-4" | "QuickTime" => "mov,mp4,m4a,3gp,3g2,mj2",
+        "MPEG-TS" | "BDAV" => "mpegts",
+        "MPEG-PS" => "mpeg",
+        "Windows Media" => "asf",
+        "Flash Video" => "flv",
+        format => return format.to_lowercase(),
+    }.to_string()
+}
+
+/// Read the chapters of the menu, written as an object of their start such as `_00_08_32_345` to
+/// their title such as `en:Chapter 2`. A chapter ends where the next one starts.
+fn chapters(extra: &Value, duration: Option<f64>) -> Vec<Chapter> {
+    let entries = match extra.as_object() {
+        Some(entries) => entries,
+        None => return vec![],
+    };
+    let mut starts: Vec<(f64, Option<String>)> = entries
+        .iter()
+        .filter_map(|(key, title)| {
+            let parts: Vec<f64> = key
+                .trim_left_matches('_')
+                .split('_')
+                .map(|part| part.parse().ok())
+                .collect::<Option<_>>()?;
+            if parts.len() != 4 {
+                return None;
+            }
+            let start = parts[0] * 3600.0 + parts[1] * 60.0 + parts[2] + parts[3] / 1000.0;
+            // The title is prefixed by its language, if any.
+            let title = title.as_str().map(|title| match title.find(':') {
+                Some(i) if i <= 3 && title[..i].chars().all(|c| c.is_ascii_lowercase()) => {
+                    title[i + 1..].to_string()
+                }
+                _ => title.to_string(),
+            });
+            Some((start, title.filter(|t| !t.is_empty())))
+        })
+        .collect();
+    starts.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    let ends: Vec<f64> = starts
+        .iter()
+        .skip(1)
+        .map(|&(start, _)| start)
+        .chain(duration)
+        .collect();
+    starts
+        .into_iter()
+        .zip(ends)
+        .map(|((start, title), end)| Chapter { start, end, title })
+        .collect()
+}
+
+#[test]
+fn test_parse_mediainfo() {
+    let json = r#"{
+        "media": {
+            "@ref": "Heat.1995.mkv",
+            "track": [
+                {
+                    "@type": "General",
+                    "Format": "Matroska",
+                    "Duration": "6135.424",
+                    "OverallBitRate": "52000000"
+                },
+                {
+                    "@type": "Video",
+                    "StreamOrder": "0",
+                    "Format": "HEVC",
+                    "Width": "3840",
+                    "Height": "2160",
+                    "BitRate": "48000000",
+                    "DisplayAspectRatio": "1.778",
+                    "FrameRate_Mode": "CFR",
+                    "FrameRate": "23.976",
+                    "FrameRate_Num": "24000",
+                    "FrameRate_Den": "1001",
+                    "ScanType": "Progressive",
+                    "BitDepth": "10",
+                    "HDR_Format": "Dolby Vision / SMPTE ST 2086",
+                    "HDR_Format_Profile": "dvhe.08 / ",
+                    "transfer_characteristics": "PQ",
+                    "colour_primaries": "BT.2020"
+                },
+                {
+                    "@type": "Audio",
+                    "StreamOrder": "1",
+                    "Format": "MLP FBA",
+                    "Format_Commercial_IfAny": "Dolby TrueHD with Dolby Atmos",
+                    "Channels": "8",
+                    "Language": "en"
+                },
+                {
+                    "@type": "Text",
+                    "StreamOrder": "2",
+                    "Format": "UTF-8",
+                    "Language": "fr",
+                    "Forced": "Yes"
+                },
+                {
+                    "@type": "Menu",
+                    "extra": {
+                        "_00_00_00_000": "en:Opening",
+                        "_00_08_32_345": ""
+                    }
+                }
+            ]
+        }
+    }"#;
+
+    let info = parse_mediainfo(json).unwrap();
+    assert_eq!(info.duration, Some(6135.424));
+    assert_eq!(info.matches_extension("mkv"), Some(true));
+    let video = &info.video[0];
+    assert_eq!((video.codec_name.as_str(), video.width, video.height), ("hevc", 3840, 2160));
+    assert_eq!(video.frame_rate(), Some(24000.0 / 1001.0));
+    assert!(!video.is_variable_frame_rate() && !video.is_interlaced());
+    assert_eq!(video.bit_depth(), Some(10));
+    assert_eq!(video.hdr(), Some(::Hdr::DolbyVision));
+    assert_eq!(video.side_data_list[0].dv_profile, Some(8));
+    assert_eq!(video.aspect_ratio(), Some(1.778));
+    assert_eq!(info.audio_channels(), Some("7.1 Atmos".to_string()));
+    assert_eq!(info.audio_languages(), vec!["en"]);
+    assert!(info.subtitle[0].is_forced());
+    assert_eq!(info.subtitle[0].codec_name, "subrip");
+    assert_eq!(
+        info.chapters,
+        vec![
+            Chapter {
+                start: 0.0,
+                end: 512.345,
+                title: Some("Opening".to_string()),
+            },
+            Chapter {
+                start: 512.345,
+                end: 6135.424,
+                title: None,
+            },
+        ]
+    );
+    assert_eq!(info.raw()["media"]["@ref"], "Heat.1995.mkv");
+    assert_eq!(info.bit_rate, Some(52000000));
+    assert_eq!(info.video_bit_rate(), Some(48000000));
+
+    assert_eq!(parse_version("MediaInfo Command line,\nMediaInfoLib - v21.09\n"), Some("21.09"));
+    assert_eq!(parse_version("ffprobe version 4.0.2"), None);
+}
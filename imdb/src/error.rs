@@ -1,11 +1,12 @@
 use std::error;
 use std::fmt;
 use std::io;
-use std::num::ParseIntError;
+use std::num::{ParseFloatError, ParseIntError};
 use std::result;
 
 use bincode;
 use csv;
+#[cfg(feature = "download")]
 use reqwest;
 
 #[derive(Debug)]
@@ -14,7 +15,16 @@ pub enum Error {
     Csv(csv::Error),
     Io(io::Error),
     ParseIntError(ParseIntError),
+    ParseFloatError(ParseFloatError),
+    #[cfg(feature = "download")]
     Reqwest(reqwest::Error),
+    /// The index was saved with another version of its layout, it must be created again.
+    Version { found: u32, expected: u32 },
+    /// The index cannot be used for the reason and cannot be created again without downloading
+    /// the datasets, which offline mode forbids.
+    Offline { reason: String },
+    /// A dataset is truncated or corrupted, for the reason given.
+    Dataset { file: String, reason: String },
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -26,7 +36,22 @@ impl fmt::Display for Error {
             Error::Csv(e) => write!(w, "ImdbError({})", e),
             Error::Io(e) => write!(w, "ImdbError({})", e),
             Error::ParseIntError(e) => write!(w, "ImdbError({})", e),
+            Error::ParseFloatError(e) => write!(w, "ImdbError({})", e),
+            #[cfg(feature = "download")]
             Error::Reqwest(e) => write!(w, "ImdbError({})", e),
+            Error::Version { found, expected } => write!(
+                w,
+                "ImdbError(index saved with version {} of the layout, version {} expected)",
+                found, expected
+            ),
+            Error::Offline { reason } => write!(
+                w,
+                "ImdbError(the index cannot be used offline: {}, and the datasets are missing)",
+                reason
+            ),
+            Error::Dataset { file, reason } => {
+                write!(w, "ImdbError(dataset {} is corrupted: {})", file, reason)
+            }
         }
     }
 }
@@ -38,7 +63,12 @@ impl error::Error for Error {
             Error::Csv(e) => e.description(),
             Error::Io(e) => e.description(),
             Error::ParseIntError(e) => e.description(),
+            Error::ParseFloatError(e) => e.description(),
+            #[cfg(feature = "download")]
             Error::Reqwest(e) => e.description(),
+            Error::Version { .. } => "index saved with another version of the layout",
+            Error::Offline { .. } => "index unusable offline",
+            Error::Dataset { .. } => "corrupted dataset",
         }
     }
 
@@ -48,7 +78,12 @@ impl error::Error for Error {
             Error::Csv(e) => e.cause(),
             Error::Io(e) => e.cause(),
             Error::ParseIntError(e) => e.cause(),
+            Error::ParseFloatError(e) => e.cause(),
+            #[cfg(feature = "download")]
             Error::Reqwest(e) => e.cause(),
+            Error::Version { .. } => None,
+            Error::Offline { .. } => None,
+            Error::Dataset { .. } => None,
         }
     }
 }
@@ -77,6 +112,13 @@ impl From<ParseIntError> for Error {
     }
 }
 
+impl From<ParseFloatError> for Error {
+    fn from(err: ParseFloatError) -> Error {
+        Error::ParseFloatError(err)
+    }
+}
+
+#[cfg(feature = "download")]
 impl From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Error {
         Error::Reqwest(err)
@@ -0,0 +1,81 @@
+//! The header written before the saved indexes, so that an index saved by another version of the
+//! crate is told apart from a corrupt one.
+//!
+//! ```text
+//! header: magic (4 bytes), version (u32, little endian)
+//! ```
+//!
+//! The version of a format is bumped whenever the saved structures change.
+
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use error::{Error, Result};
+
+/// The indexes saved before the header was added start directly with the gzip stream.
+const GZIP_MAGIC: &[u8; 2] = b"\x1f\x8b";
+
+/// A kind of saved index and the version of its layout.
+pub(crate) struct Format {
+    magic: &'static [u8; 4],
+    version: u32,
+}
+
+pub(crate) const IMDB_FORMAT: Format = Format {
+    magic: b"MERI",
+    version: 15,
+};
+
+pub(crate) const TV_FORMAT: Format = Format {
+    magic: b"MERT",
+    version: 3,
+};
+
+impl Format {
+    pub(crate) fn write_header(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(self.magic)?;
+        w.write_u32::<LittleEndian>(self.version)
+    }
+
+    /// Read the header and check that the index is of this kind and version. The indexes saved
+    /// without a header have the version 0.
+    pub(crate) fn read_header(&self, r: &mut impl Read) -> Result<()> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if magic[..2] == GZIP_MAGIC[..] {
+            return Err(self.mismatch(0));
+        }
+        if &magic != self.magic {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not an index file").into());
+        }
+        let version = r.read_u32::<LittleEndian>()?;
+        if version != self.version {
+            return Err(self.mismatch(version));
+        }
+        Ok(())
+    }
+
+    fn mismatch(&self, found: u32) -> Error {
+        Error::Version {
+            found,
+            expected: self.version,
+        }
+    }
+}
+
+#[test]
+fn test_header() {
+    let mut buf = vec![];
+    IMDB_FORMAT.write_header(&mut buf).unwrap();
+    assert!(IMDB_FORMAT.read_header(&mut &buf[..]).is_ok());
+
+    match TV_FORMAT.read_header(&mut &buf[..]) {
+        Err(Error::Io(ref err)) if err.kind() == io::ErrorKind::InvalidData => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    match IMDB_FORMAT.read_header(&mut &b"\x1f\x8b\x08\x00\x00\x00\x00\x00"[..]) {
+        Err(Error::Version { found: 0, .. }) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+}
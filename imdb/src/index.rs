@@ -1,20 +1,44 @@
-use std::cmp::Reverse;
+use std::borrow::{Borrow, Cow};
+use std::cmp::{Ordering, Reverse};
 use std::collections::{HashMap, HashSet};
-use std::fs::{DirBuilder, File};
+use std::fs::{self, DirBuilder, File};
+use std::io::{self, BufReader};
 use std::path::Path;
+#[cfg(feature = "download")]
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
+#[cfg(feature = "download")]
+use std::time::SystemTime;
 
 use bincode;
-use csv::ReaderBuilder;
+use csv::{Reader, ReaderBuilder};
 use flate2::{read::GzDecoder, write::GzEncoder};
-use reqwest::Client;
+#[cfg(feature = "download")]
+use reqwest::header::{ContentLength, ETag, HttpDate, IfModifiedSince, IfNoneMatch};
+#[cfg(feature = "download")]
+use reqwest::{Client, Proxy, Response, StatusCode};
 use strsim;
 
-use error::Result;
-use title::{Title, TitleKind};
-use util::{Counter, NonNan};
+use error::{Error, Result};
+use format::IMDB_FORMAT;
+use mapped::{self, LazyTitles, MappedImdb};
+use options::IndexOptions;
+#[cfg(feature = "download")]
+use progress::copy_download;
+use progress::{report_rows, Phase, Progress};
+use search::{Rank, ScoredTitle, SearchQuery, SearchResult};
+use tags::{TagIndex, TagIndexBuilder};
+use title::{Genre, RegionalTitle, Title, TitleKind};
+use tokenize::{fold, tag_splitter, uncatalog, within_one_edit, Tokenizer, FUZZY_MIN_LEN};
+use util::{current_year, Counter, NonNan};
+use verify;
 
-fn parse_none<T: FromStr>(record: &str) -> Option<T> {
+/// Name of the index in the mapped layout, see `Imdb::save_mapped`. When the index directory
+/// holds it, `Imdb::load_or_refresh_index` maps it instead of loading `index.gz`.
+pub const MAPPED_INDEX: &str = "index.map";
+
+pub(crate) fn parse_none<T: FromStr>(record: &str) -> Option<T> {
     match record {
         "\\N" => None,
         s => s.parse().ok(),
@@ -30,64 +54,57 @@ macro_rules! some_or_continue {
     };
 }
 
-fn read_votes(path: impl AsRef<Path>) -> Result<HashMap<u32, u32>> {
+pub(crate) fn open_dataset(path: impl AsRef<Path>) -> Result<Reader<GzDecoder<File>>> {
     let file = File::open(path)?;
     let decompressor = GzDecoder::new(file);
-    let mut reader = ReaderBuilder::new()
+    Ok(ReaderBuilder::new()
         .flexible(true)
         .delimiter(b'\t')
         .quoting(false)
-        .from_reader(decompressor);
+        .from_reader(decompressor))
+}
+
+pub(crate) fn read_ratings(
+    path: impl AsRef<Path>,
+    min_votes: u32,
+    progress: &mut dyn Progress,
+) -> Result<HashMap<u32, (f32, u32)>> {
+    let mut reader = open_dataset(path)?;
 
-    let mut votes_table = HashMap::new();
+    let mut ratings_table = HashMap::new();
 
-    for record in reader.records() {
+    for (row, record) in reader.records().enumerate() {
+        report_rows(progress, row);
         let record = record?;
 
         let id: u32 = record[0][2..].parse()?;
-        //let score = record[1].parse()?;
+        let rating = record[1].parse()?;
         let votes = record[2].parse()?;
 
-        // 50 is a totally arbitrary cutoff for the number of votes
-        if votes >= 50 {
-            votes_table.insert(id, votes);
+        if votes >= min_votes {
+            ratings_table.insert(id, (rating, votes));
         }
     }
 
-    Ok(votes_table)
+    Ok(ratings_table)
 }
 
 fn read_titles(
     path: impl AsRef<Path>,
-    votes_table: &HashMap<u32, u32>,
+    ratings_table: &HashMap<u32, (f32, u32)>,
+    options: &IndexOptions,
+    progress: &mut dyn Progress,
 ) -> Result<HashMap<u32, Title>> {
-    let file = File::open(path)?;
-    let decompressor = GzDecoder::new(file);
-    let mut reader = ReaderBuilder::new()
-        .flexible(true)
-        .delimiter(b'\t')
-        .quoting(false)
-        .from_reader(decompressor);
+    let mut reader = open_dataset(path)?;
 
     let mut titles = HashMap::new();
 
-    for record in reader.records() {
+    for (row, record) in reader.records().enumerate() {
+        report_rows(progress, row);
         let record = record?;
 
         let adult: u8 = some_or_continue!(parse_none(&record[4]));
-        if adult == 1 {
-            continue;
-        }
-
-        let kind = &record[1];
-
-        let kind = match kind {
-            "movie" => TitleKind::Movie,
-            "tvMovie" => TitleKind::TvMovie,
-            "video" => TitleKind::Video,
-            "short" => TitleKind::Short,
-            _ => continue,
-        };
+        let kind = some_or_continue!(TitleKind::parse(&record[1]));
 
         let year = some_or_continue!(parse_none(&record[5]));
         let runtime = some_or_continue!(parse_none(&record[7]));
@@ -97,8 +114,19 @@ fn read_titles(
         }
 
         let id = record[0][2..].parse()?;
+        // skip titles with no votes
+        let (rating, votes) = some_or_continue!(ratings_table.get(&id).cloned());
+        if !options.accepts(kind, votes, adult == 1) {
+            continue;
+        }
         let primary_title = &record[2];
         let original_title = &record[3];
+        let genres = record
+            .get(8)
+            .unwrap_or("")
+            .split(',')
+            .filter_map(Genre::parse)
+            .collect();
 
         let title = Title {
             id,
@@ -111,11 +139,15 @@ fn read_titles(
                 None
             },
             kind,
-            // skip titles with no votes
-            votes: match votes_table.get(&id) {
-                None => continue,
-                Some(votes) => *votes,
-            },
+            akas: vec![],
+            votes,
+            rating,
+            genres,
+            directors: vec![],
+            folded_names: vec![],
+            uncataloged_names: vec![],
+            regional_titles: vec![],
+            display: 0,
         };
 
         titles.insert(id, title);
@@ -125,53 +157,123 @@ fn read_titles(
     Ok(titles)
 }
 
-// Tag splitter must be a superset of the filter_path function
-fn tag_splitter(c: char) -> bool {
-    match c {
-        c if c.is_whitespace() => true,
-        c if c.is_ascii_control() => true,
-        '/' | '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*' => true, // from filter_path
-        '_' => true,
-        '-' => true,
-        '.' => true,
-        ',' => true,
-        '\'' => true,
-        '(' => true,
-        ')' => true,
-        _ => false,
+fn read_akas(
+    path: impl AsRef<Path>,
+    titles: &mut HashMap<u32, Title>,
+    progress: &mut dyn Progress,
+) -> Result<()> {
+    let mut reader = open_dataset(path)?;
+
+    for (row, record) in reader.records().enumerate() {
+        report_rows(progress, row);
+        let record = record?;
+
+        let id: u32 = record[0][2..].parse()?;
+        // only keep the akas of the titles we indexed
+        let title = some_or_continue!(titles.get_mut(&id));
+        let aka = &record[2];
+
+        if aka != title.primary_title()
+            && Some(aka) != title.original_title()
+            && !title.akas.iter().any(|a| a == aka)
+        {
+            title.akas.push(aka.to_string());
+        }
+
+        // Only the first name of a region and language is kept, the working and alternative
+        // titles are not the names the title is known by.
+        let region = parse_none::<String>(&record[3]);
+        let language = parse_none::<String>(&record[4]);
+        let types = record.get(5).unwrap_or("");
+        if (region.is_some() || language.is_some())
+            && types != "working"
+            && types != "alternative"
+            && !title
+                .regional_titles
+                .iter()
+                .any(|r| r.region == region && r.language == language)
+        {
+            let name = title.names().position(|name| name == aka).unwrap_or(0);
+            title.regional_titles.push(RegionalTitle {
+                region,
+                language,
+                name: name as u16,
+            });
+        }
     }
-}
 
-fn ignored(tag: &str) -> bool {
-    match tag {
-        "a" | "an" | "the" | "of" | "in" | "on" | "to" | "t" | "s" => true,
-        _ => false,
+    for title in titles.values_mut() {
+        title.akas.shrink_to_fit();
+        title.regional_titles.shrink_to_fit();
+        title.fold_names();
     }
+    Ok(())
 }
 
-fn text_to_tags(text: &str, tags: &mut Vec<String>) {
-    let text = text.to_lowercase();
-    tags.clear();
-    for tag in text.split(tag_splitter) {
-        if !tag.is_empty() && !ignored(tag) {
-            tags.push(tag.to_string());
+fn read_directors(
+    crew_path: impl AsRef<Path>,
+    names_path: impl AsRef<Path>,
+    titles: &mut HashMap<u32, Title>,
+    progress: &mut dyn Progress,
+) -> Result<()> {
+    let mut reader = open_dataset(crew_path)?;
+    let mut directors_table: HashMap<u32, Vec<u32>> = HashMap::new();
+
+    for (row, record) in reader.records().enumerate() {
+        report_rows(progress, row);
+        let record = record?;
+
+        let id: u32 = record[0][2..].parse()?;
+        if !titles.contains_key(&id) || &record[1] == "\\N" {
+            continue;
         }
+
+        let mut directors = vec![];
+        for director in record[1].split(',') {
+            directors.push(director[2..].parse()?);
+        }
+        directors_table.insert(id, directors);
     }
-    tags.dedup();
+
+    // only keep the names of the directors we need, the names dataset is huge
+    let wanted: HashSet<u32> = directors_table
+        .values()
+        .flat_map(|ids| ids.iter().cloned())
+        .collect();
+    let mut names = HashMap::new();
+    let mut reader = open_dataset(names_path)?;
+
+    for (row, record) in reader.records().enumerate() {
+        report_rows(progress, row);
+        let record = record?;
+
+        let id: u32 = record[0][2..].parse()?;
+        if wanted.contains(&id) {
+            names.insert(id, record[1].to_string());
+        }
+    }
+
+    for (id, directors) in directors_table {
+        if let Some(title) = titles.get_mut(&id) {
+            title.directors = directors
+                .iter()
+                .filter_map(|director| names.get(director).cloned())
+                .collect();
+        }
+    }
+
+    Ok(())
 }
 
-fn build_reverse_index(titles: &HashMap<u32, Title>) -> HashMap<String, HashSet<u32>> {
-    let mut index = HashMap::new();
+fn build_reverse_index(titles: &HashMap<u32, Title>, tokenizer: &Tokenizer) -> TagIndex {
+    let mut index = TagIndexBuilder::new();
     let mut tags = Vec::new();
 
     for title in titles.values() {
         let mut index_title = |text: &str| {
-            text_to_tags(&text, &mut tags);
+            tokenizer.tags(&text, &mut tags);
             for tag in tags.drain(..) {
-                index
-                    .entry(tag)
-                    .or_insert_with(|| HashSet::new())
-                    .insert(title.id());
+                index.add(tag, title.id());
             }
         };
 
@@ -181,178 +283,1344 @@ fn build_reverse_index(titles: &HashMap<u32, Title>) -> HashMap<String, HashSet<
                 index_title(&original_title);
             }
         }
+        for aka in title.akas() {
+            index_title(aka);
+        }
     }
 
-    index.shrink_to_fit();
-    index.values_mut().for_each(|bucket| bucket.shrink_to_fit());
+    index.build()
+}
 
-    index
+#[cfg(feature = "download")]
+fn download_file(
+    client: &Client,
+    url: &str,
+    dest: impl AsRef<Path>,
+    progress: &mut dyn Progress,
+) -> Result<()> {
+    let mut resp = client.get(url).send()?.error_for_status()?;
+    save_download(&mut resp, dest.as_ref(), progress)
 }
 
-fn download_file(client: &Client, url: &str, dest: impl AsRef<Path>) -> Result<()> {
-    let mut file = File::create(dest)?;
-    let mut resp = client.get(url).send()?;
-    resp.copy_to(&mut file)?;
-    Ok(())
+/// Download to a temporary file so that an interrupted download doesn't replace the dataset. The
+/// download is verified before it replaces the dataset, and its checksum saved.
+#[cfg(feature = "download")]
+fn save_download(resp: &mut Response, dest: &Path, progress: &mut dyn Progress) -> Result<()> {
+    let tmp_path = dest.with_extension("part");
+    {
+        let mut file = File::create(&tmp_path)?;
+        let total = resp.headers().get::<ContentLength>().map(|len| len.0);
+        copy_download(resp, &mut file, file_name(dest), total, progress)?;
+    }
+    let digest = match verify::verify(&tmp_path) {
+        Ok(digest) => digest,
+        Err(err) => {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+    };
+    fs::rename(&tmp_path, dest)?;
+    verify::write_sum(dest, &digest)
 }
 
-fn download_file_if_missing(client: &Client, url: &str, dest: impl AsRef<Path>) -> Result<()> {
+#[cfg(feature = "download")]
+fn file_name(path: &Path) -> &str {
+    path.file_name().and_then(|name| name.to_str()).unwrap_or("")
+}
+
+#[cfg(feature = "download")]
+pub(crate) fn download_file_if_missing(
+    client: &Client,
+    url: &str,
+    dest: impl AsRef<Path>,
+) -> Result<()> {
     if !dest.as_ref().exists() {
-        download_file(client, url, dest)?;
+        download_file(client, url, dest, &mut ())?;
     }
     Ok(())
 }
 
-const SRC_FILE_BASICS: &str = "title.basics.tsv.gz";
-const SRC_FILE_RATINGS: &str = "title.ratings.tsv.gz";
+/// Path of the file remembering the ETag of a dataset. Its modification time is the last time the
+/// dataset was found to be fresh.
+#[cfg(feature = "download")]
+fn etag_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_owned();
+    name.push(".etag");
+    dest.with_file_name(name)
+}
 
-fn check_source_files(index_dir: &Path) -> Result<()> {
-    let client = Client::new();
+/// Download a dataset if it's missing, or if it was last checked more than `max_age` ago and the
+/// server has a newer copy. Returns true if a new copy was downloaded.
+#[cfg(feature = "download")]
+pub(crate) fn refresh_file(
+    client: &Client,
+    url: &str,
+    dest: impl AsRef<Path>,
+    max_age: Option<Duration>,
+    progress: &mut dyn Progress,
+) -> Result<bool> {
+    let dest = dest.as_ref();
+    let etag_path = etag_path(dest);
 
-    download_file_if_missing(
-        &client,
-        "https://datasets.imdbws.com/title.basics.tsv.gz",
-        index_dir.join(SRC_FILE_BASICS),
-    )?;
+    let modified = match dest.metadata() {
+        Ok(metadata) => Some(metadata.modified()?),
+        Err(_) => None,
+    };
 
-    download_file_if_missing(
-        &client,
-        "https://datasets.imdbws.com/title.ratings.tsv.gz",
-        index_dir.join(SRC_FILE_RATINGS),
-    )?;
+    let mut request = client.get(url);
+    if let Some(modified) = modified {
+        let max_age = match max_age {
+            Some(max_age) => max_age,
+            None => return Ok(false),
+        };
+        let checked = etag_path
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(modified);
+        let age = SystemTime::now()
+            .duration_since(checked)
+            .unwrap_or_default();
+        if age < max_age {
+            return Ok(false);
+        }
 
-    Ok(())
+        request.header(IfModifiedSince(HttpDate::from(modified)));
+        if let Ok(text) = fs::read_to_string(&etag_path) {
+            if let Ok(etag) = text.trim().parse() {
+                request.header(IfNoneMatch::Items(vec![etag]));
+            }
+        }
+    }
+
+    let resp = request.send()?;
+    if resp.status() == StatusCode::NotModified {
+        // Touch the ETag file to remember that the dataset is fresh.
+        let etag = fs::read_to_string(&etag_path).unwrap_or_default();
+        fs::write(&etag_path, etag)?;
+        return Ok(false);
+    }
+    let mut resp = resp.error_for_status()?;
+    save_download(&mut resp, dest, progress)?;
+
+    let etag = resp
+        .headers()
+        .get::<ETag>()
+        .map(|etag| etag.0.to_string())
+        .unwrap_or_default();
+    fs::write(&etag_path, etag)?;
+
+    Ok(true)
+}
+
+pub(crate) const SRC_FILE_BASICS: &str = "title.basics.tsv.gz";
+pub(crate) const SRC_FILE_RATINGS: &str = "title.ratings.tsv.gz";
+const SRC_FILE_AKAS: &str = "title.akas.tsv.gz";
+const SRC_FILE_CREW: &str = "title.crew.tsv.gz";
+const SRC_FILE_NAMES: &str = "name.basics.tsv.gz";
+
+/// The datasets needed to create the index.
+const SRC_FILES: [&str; 3] = [SRC_FILE_BASICS, SRC_FILE_RATINGS, SRC_FILE_AKAS];
+/// The datasets of the directors of the titles, the index is created without them when missing.
+const CREW_FILES: [&str; 2] = [SRC_FILE_CREW, SRC_FILE_NAMES];
+
+/// Tell if the datasets needed to create the index are in the index directory.
+fn has_source_files(index_dir: &Path) -> bool {
+    SRC_FILES.iter().all(|name| index_dir.join(name).exists())
 }
 
-struct Match<'t> {
-    score: NonNan,
-    title: &'t Title,
+/// Create the client downloading the datasets, going through the proxy of the options.
+#[cfg(feature = "download")]
+pub(crate) fn dataset_client(options: &IndexOptions) -> Result<Client> {
+    let mut builder = Client::builder();
+    if let Some(proxy) = options.get_proxy() {
+        builder.proxy(Proxy::all(proxy)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Make sure the source files are present, along with the crew datasets when the options ask for
+/// them, and refresh those older than `max_age`. Returns true if any of them was downloaded. The downloads are verified before they
+/// replace the datasets, the datasets already downloaded are checked when the index is created.
+#[cfg(feature = "download")]
+fn check_source_files(
+    client: &Client,
+    index_dir: &Path,
+    options: &IndexOptions,
+    max_age: Option<Duration>,
+    progress: &mut dyn Progress,
+) -> Result<bool> {
+    let mut changed = false;
+    let crew: &[&str] = if options.get_crew() { &CREW_FILES } else { &[] };
+    for name in SRC_FILES.iter().chain(crew) {
+        let dest = index_dir.join(name);
+        let url = options.dataset_file_url(name);
+        // A download that fails its verification is attempted once more.
+        changed |= match refresh_file(client, &url, &dest, max_age, progress) {
+            Err(Error::Dataset { .. }) => refresh_file(client, &url, &dest, max_age, progress)?,
+            result => result?,
+        };
+    }
+    Ok(changed)
 }
 
+// Penalty applied for every trailing query token left out of a prefix alignment. This keeps a full
+// match ahead of a prefix match, e.g. "blade runner 2049" should prefer "Blade Runner 2049" over
+// "Blade Runner".
+const PREFIX_TOKEN_PENALTY: f64 = 0.02;
+/// Penalty of a title made only of words missing from the query, see `word_count_prior`.
+const WORD_COUNT_PENALTY: f64 = 0.2;
+
+/// Score the title against the query, tolerating extra words at the end of the query.
+///
+/// Filenames often contain an edition or alternate title after the actual title, such as
+/// "blade runner the final cut". Every token prefix of the query is compared to the title and the
+/// best alignment is kept, with a small penalty for each token left out.
+pub(crate) fn prefix_jaro(title: &str, text: &str) -> f64 {
+    let mut best = strsim::jaro(title, text);
+
+    let ends: Vec<usize> = text
+        .char_indices()
+        .filter(|&(idx, c)| c.is_whitespace() && idx > 0)
+        .map(|(idx, _)| idx)
+        .collect();
+
+    for (pos, &end) in ends.iter().enumerate() {
+        let prefix = text[..end].trim_right();
+        if prefix.is_empty() {
+            continue;
+        }
+        let dropped = (ends.len() - pos) as f64;
+        let score = strsim::jaro(title, prefix) * (1.0 - PREFIX_TOKEN_PENALTY * dropped);
+        if score > best {
+            best = score;
+        }
+    }
+
+    best
+}
+
+/// Penalize the titles with more words than the query, in proportion of the words the query lacks.
+///
+/// A short query such as "heat" shares its only tag with dozens of titles, this keeps "Heat and
+/// Dust" from scoring as high as "Heat". Titles shorter than the query are left to `prefix_jaro`.
+fn word_count_prior(title: &str, text: &str) -> f64 {
+    let words = |s: &str| s.split(tag_splitter).filter(|w| !w.is_empty()).count() as f64;
+    let (title_words, text_words) = (words(title), words(text));
+    if title_words <= text_words {
+        return 1.0;
+    }
+    1.0 - WORD_COUNT_PENALTY * (1.0 - text_words / title_words)
+}
+
+pub(crate) struct Match<T> {
+    pub(crate) score: NonNan,
+    pub(crate) title: T,
+}
+
+/// The text of a lookup, folded once for all the titles it is compared with.
+pub(crate) struct FoldedText {
+    text: String,
+    /// The text moved out of the catalog form, when the options accept catalog articles.
+    uncataloged: Option<String>,
+}
+
+impl FoldedText {
+    pub(crate) fn new(text: &str, options: &IndexOptions) -> FoldedText {
+        let text = fold(text);
+        let uncataloged = if options.get_catalog_articles() {
+            uncatalog(&text)
+        } else {
+            None
+        };
+        FoldedText { text, uncataloged }
+    }
+}
+
+/// Score how well the title matches the text and the year parsed from a file name.
+pub(crate) fn score_title(
+    title: &Title,
+    text: &FoldedText,
+    year: Option<i32>,
+    options: &IndexOptions,
+) -> NonNan {
+    let compare = |name: &str, text: &str| prefix_jaro(name, text) * word_count_prior(name, text);
+    // Both forms of the text are compared with both forms of the title.
+    let mut score: f64 = 0.0;
+    for name in title.folded_names() {
+        score = score.max(compare(name, &text.text));
+        if let Some(ref uncataloged) = text.uncataloged {
+            score = score.max(compare(name, uncataloged));
+        }
+    }
+    if options.get_catalog_articles() {
+        for name in title.uncataloged_names() {
+            score = score.max(compare(name, &text.text));
+        }
+    }
+
+    if let Some(year) = year {
+        if title.year() != year {
+            score *= 0.85;
+        }
+    }
+
+    score *= options.get_kind_weight(title.kind());
+
+    NonNan::new(score)
+}
+
+/// If we have year information, only keep titles whose year is within one of the target year.
+#[inline]
+pub(crate) fn year_within(title_year: i32, year: Option<i32>) -> bool {
+    match year {
+        Some(year) => (year - title_year).abs() <= 1,
+        None => true,
+    }
+}
+
+/// Keep the `n` best matches, best match first.
+pub(crate) fn rank_matches<T: Borrow<Title>>(
+    matches: Vec<Match<T>>,
+    year: Option<i32>,
+    n: usize,
+) -> Vec<T> {
+    rank_scored(matches, year, n).0.into_iter().map(|m| m.title).collect()
+}
+
+/// Like `rank_matches`, but the scores are kept. Also returns the number of matches within 1% of
+/// the best score, which were ranked by tie-break instead of by score: the titles of the year
+/// first, then the most voted, then the best rated.
+pub(crate) fn rank_scored<T: Borrow<Title>>(
+    mut matches: Vec<Match<T>>,
+    year: Option<i32>,
+    n: usize,
+) -> (Vec<Match<T>>, usize) {
+    // sort by score descending
+    matches.sort_by_key(|m| Reverse(m.score));
+    let mut matches = matches.into_iter().peekable();
+
+    // the best matches with 1% error margin are sorted by year agreement, then by popularity
+    let best = match matches.next() {
+        Some(best) => best,
+        None => return (vec![], 0),
+    };
+    let best_score = best.score;
+    let mut candidates = vec![best];
+    while matches
+        .peek()
+        .map(|m| (*best_score - *m.score).abs() <= 0.01)
+        .unwrap_or(false)
+    {
+        candidates.extend(matches.next());
+    }
+    candidates.sort_by(|a, b| {
+        let (a, b) = (a.title.borrow(), b.title.borrow());
+        let exact = |title: &Title| Some(title.year()) == year;
+        exact(b)
+            .cmp(&exact(a))
+            .then(b.votes().cmp(&a.votes()))
+            .then(b.rating().partial_cmp(&a.rating()).unwrap_or(Ordering::Equal))
+    });
+    let close = candidates.len();
+
+    let ranked = candidates.into_iter().chain(matches).take(n).collect();
+    (ranked, close)
+}
+
+/// Buffers of a lookup, reused between the lookups of a batch.
+pub(crate) struct Scratch {
+    tags: Vec<String>,
+    counter: Counter<u32>,
+}
+
+impl Scratch {
+    pub(crate) fn new() -> Scratch {
+        Scratch {
+            tags: Vec::new(),
+            counter: Counter::new(),
+        }
+    }
+}
+
+/// The fields of a title the lookups filter the candidates on, read from the record of a mapped
+/// index without decoding the title.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Summary {
+    pub(crate) year: i32,
+    pub(crate) kind: TitleKind,
+    pub(crate) votes: u32,
+}
+
+impl Summary {
+    pub(crate) fn of(title: &Title) -> Summary {
+        Summary {
+            year: title.year(),
+            kind: title.kind(),
+            votes: title.votes(),
+        }
+    }
+}
+
+/// Where the lookups find the tags and the titles: the titles of a loaded index, or the records of
+/// a mapped index, decoded only when they are scored.
+pub(crate) trait Source {
+    /// Get the sorted ids of the titles indexed under the tag.
+    fn tag_ids<'s>(&'s self, tag: &str) -> Option<Cow<'s, [u32]>>;
+
+    /// Get the tags kept by the filter with the sorted ids of their titles, scanning all the tags.
+    fn tags_where<'s>(&'s self, filter: &dyn Fn(&str) -> bool) -> Vec<(&'s str, Cow<'s, [u32]>)>;
+
+    fn summary(&self, id: u32) -> Option<Summary>;
+
+    /// Get the title, decoded with the names preferred by the options when it is mapped.
+    fn decode<'s>(&'s self, id: u32, options: &IndexOptions) -> Option<Cow<'s, Title>>;
+}
+
+/// Score the titles sharing the most tags with the text, among the titles whose summary is kept
+/// by the filter. Only the titles scored are decoded, and they are dropped by the caller unless
+/// they are returned.
+pub(crate) fn matches_where<'s, S: Source + ?Sized>(
+    source: &'s S,
+    options: &IndexOptions,
+    scratch: &mut Scratch,
+    text: &str,
+    year: Option<i32>,
+    filter: impl Fn(Summary) -> bool,
+) -> Vec<Match<Cow<'s, Title>>> {
+    let Scratch {
+        ref mut tags,
+        ref mut counter,
+    } = *scratch;
+    options.get_tokenizer().tags(&text, tags);
+    counter.clear();
+
+    for tag in tags.iter() {
+        let title_ids = match source.tag_ids(tag) {
+            Some(title_ids) => title_ids,
+            None if options.get_fuzzy() && tag.chars().count() >= FUZZY_MIN_LEN => {
+                let mut fuzzy_ids: Vec<u32> = source
+                    .tags_where(&|other| within_one_edit(tag, other))
+                    .into_iter()
+                    .flat_map(|(_, title_ids)| title_ids.into_owned())
+                    .collect();
+                fuzzy_ids.sort();
+                fuzzy_ids.dedup();
+                Cow::Owned(fuzzy_ids)
+            }
+            None => continue,
+        };
+        for &title_id in title_ids.iter() {
+            match source.summary(title_id) {
+                Some(summary) if filter(summary) => counter.add(title_id),
+                _ => {}
+            }
+        }
+    }
+
+    let folded = FoldedText::new(text, options);
+    counter
+        .most_common()
+        .into_iter()
+        .filter_map(|&title_id| source.decode(title_id, options))
+        .map(|title| Match {
+            score: score_title(&title, &folded, year, options),
+            title,
+        }).collect()
+}
+
+/// Like `matches_where`, among the titles the options allow to match. The titles scoring less than
+/// the minimum score of the options are left out.
+pub(crate) fn matches<'s, S: Source + ?Sized>(
+    source: &'s S,
+    options: &IndexOptions,
+    scratch: &mut Scratch,
+    text: &str,
+    year: Option<i32>,
+) -> Vec<Match<Cow<'s, Title>>> {
+    let current_year = current_year();
+    let mut matches = matches_where(source, options, scratch, text, year, |summary| {
+        options.matches_year(summary.year, current_year)
+            && options.matches_title(summary.kind, summary.votes)
+            && year_within(summary.year, year)
+    });
+    matches.retain(|m| *m.score >= options.get_min_score());
+    matches
+}
+
+/// An index of the IMDB titles, searchable by name.
+///
+/// `Imdb` is `Send` and `Sync`, so a single index can be loaded once and shared between threads.
+/// The index is never modified by lookups, their buffers belong to each call, so no lock is taken
+/// and the threads can split the lookups of a library between them. Only a mapped index, see
+/// `load_index`, takes a lock to keep the titles it returns.
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// use imdb::Imdb;
+///
+/// let imdb = Arc::new(Imdb::load_index(".merovingian/index.gz").unwrap());
+///
+/// let handles: Vec<_> = vec!["the matrix", "blade runner"]
+///     .into_iter()
+///     .map(|name| {
+///         let imdb = imdb.clone();
+///         thread::spawn(move || imdb.lookup(name, None).map(|title| title.id()))
+///     }).collect();
+///
+/// for handle in handles {
+///     println!("{:?}", handle.join().unwrap());
+/// }
+/// ```
 #[derive(Deserialize, Serialize)]
 pub struct Imdb {
-    titles: HashMap<u32, Title>,
-    index: HashMap<String, HashSet<u32>>,
+    pub(crate) options: IndexOptions,
+    pub(crate) titles: HashMap<u32, Title>,
+    pub(crate) index: TagIndex,
+    /// The titles and the tags of an index saved by `save_mapped` and mapped by `load_index`,
+    /// instead of the two fields above which are then empty.
+    #[serde(skip)]
+    pub(crate) mapped: Option<LazyTitles>,
 }
 
 impl Imdb {
-    pub fn create_index(index_dir: &Path) -> Result<Imdb> {
-        let votes_table = read_votes(index_dir.join(SRC_FILE_RATINGS))?;
-        let titles = read_titles(index_dir.join(SRC_FILE_BASICS), &votes_table)?;
+    pub fn create_index(index_dir: &Path, options: &IndexOptions) -> Result<Imdb> {
+        Imdb::create_index_with_progress(index_dir, options, &mut ())
+    }
+
+    /// Like `create_index`, reporting the progress of the index creation.
+    pub fn create_index_with_progress(
+        index_dir: &Path,
+        options: &IndexOptions,
+        progress: &mut dyn Progress,
+    ) -> Result<Imdb> {
+        for name in SRC_FILES.iter() {
+            verify::check_dataset(&index_dir.join(name))?;
+        }
+        progress.phase(Phase::Ratings);
+        let ratings_table = read_ratings(
+            index_dir.join(SRC_FILE_RATINGS),
+            options.get_min_votes(),
+            progress,
+        )?;
+        progress.phase(Phase::Titles);
+        let mut titles = read_titles(
+            index_dir.join(SRC_FILE_BASICS),
+            &ratings_table,
+            options,
+            progress,
+        )?;
+        progress.phase(Phase::Akas);
+        read_akas(index_dir.join(SRC_FILE_AKAS), &mut titles, progress)?;
+
+        // The crew datasets are optional, see `IndexOptions::crew`.
+        if CREW_FILES.iter().all(|name| index_dir.join(name).exists()) {
+            for name in CREW_FILES.iter() {
+                verify::check_dataset(&index_dir.join(name))?;
+            }
+            progress.phase(Phase::Directors);
+            let crew_path = index_dir.join(SRC_FILE_CREW);
+            read_directors(crew_path, index_dir.join(SRC_FILE_NAMES), &mut titles, progress)?;
+        }
+
+        progress.phase(Phase::Index);
+        let index = build_reverse_index(&titles, options.get_tokenizer());
+        let mut imdb = Imdb {
+            options: options.clone(),
+            titles,
+            index,
+            mapped: None,
+        };
+        imdb.prefer_region();
+        Ok(imdb)
+    }
+
+    /// Download the optional crew datasets used to get the directors of the titles, from the
+    /// mirror and through the proxy of the options. The index must be created again for the
+    /// directors to be available. With `IndexOptions::crew`, `load_or_refresh_index` downloads
+    /// them along with the other datasets and creates the index again once they are.
+    #[cfg(feature = "download")]
+    pub fn download_crew(index_dir: impl AsRef<Path>, options: &IndexOptions) -> Result<()> {
+        let index_dir = index_dir.as_ref();
+        if options.get_offline() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the crew datasets cannot be downloaded offline",
+            ).into());
+        }
+        let client = dataset_client(options)?;
+
+        for name in CREW_FILES.iter() {
+            download_file_if_missing(
+                &client,
+                &options.dataset_file_url(name),
+                index_dir.join(name),
+            )?;
+        }
 
-        let index = build_reverse_index(&titles);
-        Ok(Imdb { titles, index })
+        Ok(())
     }
 
+    /// Build an index from in-memory titles instead of the IMDB datasets, so that tests can run
+    /// without downloading them.
+    pub fn from_titles(titles: Vec<Title>) -> Imdb {
+        Imdb::from_titles_with_options(titles, &IndexOptions::new())
+    }
+
+    /// Like `from_titles`, but the titles are indexed and matched with the options. The options
+    /// deciding which titles are indexed are not applied.
+    pub fn from_titles_with_options(titles: Vec<Title>, options: &IndexOptions) -> Imdb {
+        let titles: HashMap<u32, Title> = titles
+            .into_iter()
+            .map(|mut t| {
+                t.fold_names();
+                (t.id(), t)
+            }).collect();
+        let index = build_reverse_index(&titles, options.get_tokenizer());
+        let mut imdb = Imdb {
+            options: options.clone(),
+            titles,
+            index,
+            mapped: None,
+        };
+        imdb.prefer_region();
+        imdb
+    }
+
+    /// Choose the names displayed by the titles with the preferred region and language.
+    fn prefer_region(&mut self) {
+        if let Some(ref mut mapped) = self.mapped {
+            mapped.clear();
+        }
+        let region = self.options.get_preferred_region();
+        let language = self.options.get_preferred_language();
+        for title in self.titles.values_mut() {
+            title.prefer_region(region, language);
+        }
+    }
+
+    /// Load an index saved by `save`, or map an index saved by `save_mapped`, whose titles are
+    /// only decoded once they are matched. An index saved by another version of the crate is
+    /// reported with `Error::Version`.
     pub fn load_index(path: impl AsRef<Path>) -> Result<Imdb> {
-        let file = File::open(path)?;
+        let path = path.as_ref();
+        if mapped::is_mapped(path)? {
+            let mapped = MappedImdb::open(path)?;
+            return Ok(Imdb {
+                options: mapped.options().clone(),
+                titles: HashMap::new(),
+                index: TagIndex::default(),
+                mapped: Some(LazyTitles::new(mapped)),
+            });
+        }
+        let mut file = BufReader::new(File::open(path)?);
+        IMDB_FORMAT.read_header(&mut file)?;
         let decompressor = GzDecoder::new(file);
         let mut imdb: Imdb = bincode::deserialize_from(decompressor)?;
 
         imdb.titles.shrink_to_fit();
         imdb.index.shrink_to_fit();
-        imdb.index
-            .values_mut()
-            .for_each(|bucket| bucket.shrink_to_fit());
+        imdb.prefer_region();
 
         Ok(imdb)
     }
 
+    /// Load the index, downloading the datasets and creating the index first if needed.
     pub fn load_or_create_index(index_dir: impl AsRef<Path>) -> Result<Imdb> {
+        Imdb::load_or_refresh_index(index_dir, &IndexOptions::new(), None, &mut ())
+    }
+
+    /// Like `load_or_create_index`, but the datasets last checked more than `max_age` ago are
+    /// downloaded again if the server has newer copies, in which case the index is rebuilt.
+    /// Without the download feature or with `IndexOptions::offline`, the datasets are never
+    /// refreshed, and a missing or outdated index is created again only if the datasets were
+    /// already downloaded, `Error::Offline` is returned otherwise. The progress of the
+    /// downloads and of the index creation is reported to `progress`.
+    ///
+    /// The datasets are downloaded through the proxy of the options, see `IndexOptions::proxy`.
+    pub fn load_or_refresh_index(
+        index_dir: impl AsRef<Path>,
+        options: &IndexOptions,
+        max_age: Option<Duration>,
+        progress: &mut dyn Progress,
+    ) -> Result<Imdb> {
         let index_dir = index_dir.as_ref();
-        let index_path = index_dir.join("index.gz");
+        // Without the download feature, the source files must already be in the index directory.
+        #[cfg(feature = "download")]
+        {
+            if !options.get_offline() {
+                let client = dataset_client(options)?;
+                return Imdb::load_or_refresh_index_with_client(
+                    &client, index_dir, options, max_age, progress,
+                );
+            }
+        }
+        let _ = max_age;
+        Imdb::load_or_rebuild_index(index_dir, options, false, progress)
+    }
+
+    /// Like `load_or_refresh_index`, but the datasets are downloaded with the client, configured
+    /// by the caller with its own proxy, timeouts or certificates.
+    #[cfg(feature = "download")]
+    pub fn load_or_refresh_index_with_client(
+        client: &Client,
+        index_dir: impl AsRef<Path>,
+        options: &IndexOptions,
+        max_age: Option<Duration>,
+        progress: &mut dyn Progress,
+    ) -> Result<Imdb> {
+        let index_dir = index_dir.as_ref();
+        DirBuilder::new().recursive(true).create(index_dir)?;
+        if options.get_offline() {
+            return Imdb::load_or_rebuild_index(index_dir, options, false, progress);
+        }
+        let changed = check_source_files(client, index_dir, options, max_age, progress)?;
+        match Imdb::load_or_rebuild_index(index_dir, options, changed, progress) {
+            // A dataset corrupted since it was downloaded is removed and downloaded again.
+            Err(Error::Dataset { ref file, .. }) => {
+                verify::remove_dataset(Path::new(file))?;
+                check_source_files(client, index_dir, options, max_age, progress)?;
+                Imdb::load_or_rebuild_index(index_dir, options, true, progress)
+            }
+            result => result,
+        }
+    }
 
+    /// Load the index unless the datasets `changed`, and create it again when it cannot be used.
+    fn load_or_rebuild_index(
+        index_dir: &Path,
+        options: &IndexOptions,
+        changed: bool,
+        progress: &mut dyn Progress,
+    ) -> Result<Imdb> {
+        // An index converted to the mapped layout stays mapped when it is created again.
+        let mapped = index_dir.join(MAPPED_INDEX).exists();
+        let index_path = index_dir.join(if mapped { MAPPED_INDEX } else { "index.gz" });
         DirBuilder::new().recursive(true).create(index_dir)?;
-        check_source_files(index_dir)?;
 
-        Ok(match Imdb::load_index(&index_path) {
+        // The reason the index must be created again, none when it was never created.
+        let loaded = if changed {
+            Err(Some("the datasets changed".to_string()))
+        } else {
+            match Imdb::load_index(&index_path) {
+                // The index is created again when the options change which titles are indexed.
+                Ok(ref imdb) if !imdb.options.same_titles(options) => Err(Some(
+                    "the options changed which titles and tags are indexed".to_string(),
+                )),
+                Ok(mut imdb) => {
+                    imdb.options = options.clone();
+                    imdb.prefer_region();
+                    Ok(imdb)
+                }
+                Err(Error::Io(ref err)) if err.kind() == io::ErrorKind::NotFound => Err(None),
+                Err(Error::Version { found, expected }) => Err(Some(format!(
+                    "it was saved with version {} of the layout, version {} is expected",
+                    found, expected
+                ))),
+                Err(err) => Err(Some(format!("it cannot be read, {}", err))),
+            }
+        };
+
+        Ok(match loaded {
             Ok(imdb) => imdb,
-            Err(_) => {
-                let imdb = Imdb::create_index(index_dir)?;
-                imdb.save(&index_path)?;
+            Err(reason) => {
+                // Offline, the index can only be created again from datasets already downloaded.
+                if options.get_offline() && !has_source_files(index_dir) {
+                    return Err(Error::Offline {
+                        reason: reason.unwrap_or_else(|| "it was never created".to_string()),
+                    });
+                }
+                if let Some(reason) = reason {
+                    progress.rebuild(&reason);
+                }
+                let imdb = Imdb::create_index_with_progress(index_dir, options, progress)?;
+                if mapped {
+                    imdb.save_mapped(&index_path)?;
+                    // The other index is outdated, it is not loaded once the mapped one is removed.
+                    let _ = fs::remove_file(index_dir.join("index.gz"));
+                } else {
+                    imdb.save(&index_path)?;
+                }
                 imdb
             }
         })
     }
 
+    /// Save the index, see `load_index`. A mapped index is already saved in the file it maps, and
+    /// cannot be saved again.
     pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
-        let file = File::create(path)?;
+        self.check_not_mapped()?;
+        let mut file = File::create(path)?;
+        IMDB_FORMAT.write_header(&mut file)?;
         let compressor = GzEncoder::new(file, Default::default());
         bincode::serialize_into(compressor, self)?;
         Ok(())
     }
 
+    /// Save the index in a layout that `MappedImdb::open` maps in memory instead of reading it.
+    pub fn save_mapped(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.check_not_mapped()?;
+        mapped::write(self, path.as_ref())
+    }
+
+    fn check_not_mapped(&self) -> Result<()> {
+        match self.mapped {
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a mapped index cannot be saved again",
+            ).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Tell if the index is mapped from a file saved by `save_mapped`.
+    pub fn is_mapped(&self) -> bool {
+        self.mapped.is_some()
+    }
+
+    /// Get the title, decoded the first time from a mapped index.
+    #[inline]
+    fn title(&self, id: u32) -> Option<&Title> {
+        match self.mapped {
+            Some(ref mapped) => mapped.get(id, &self.options),
+            None => self.titles.get(&id),
+        }
+    }
+
+    /// Get a title scored by a lookup, kept when it was decoded from a mapped index so that it
+    /// can be returned.
+    fn keep<'t>(&'t self, title: Cow<'t, Title>) -> &'t Title {
+        match title {
+            Cow::Borrowed(title) => title,
+            Cow::Owned(title) => self
+                .mapped
+                .as_ref()
+                .expect("only a mapped index decodes its titles")
+                .keep(title),
+        }
+    }
+
+    /// Get the ids of the titles indexed under the tags starting with the prefix, sorted.
+    fn prefixed_ids(&self, prefix: &str) -> Vec<u32> {
+        let mut ids: Vec<u32> = match self.mapped {
+            Some(ref mapped) => mapped
+                .mapped
+                .prefixed(prefix)
+                .flat_map(|(_, postings)| mapped.mapped.ids(postings))
+                .collect(),
+            None => self
+                .index
+                .prefixed(prefix)
+                .flat_map(|(_, ids)| ids.iter().cloned())
+                .collect(),
+        };
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
     pub fn lookup(&self, text: &str, year: Option<i32>) -> Option<&Title> {
+        self.lookup_n(text, year, 1).into_iter().next()
+    }
+
+    /// Find the `n` best matches for the text, best match first.
+    pub fn lookup_n(&self, text: &str, year: Option<i32>, n: usize) -> Vec<&Title> {
+        let matches = matches(self, &self.options, &mut Scratch::new(), text, year);
+        rank_matches(matches, year, n)
+            .into_iter()
+            .map(|title| self.keep(title))
+            .collect()
+    }
+
+    /// Like `lookup`, for many texts and their years at once, such as the names of all the files
+    /// of a library. The matches are returned in the order of the queries. The buffers used to tag
+    /// the texts and count the titles are allocated once for the whole batch.
+    pub fn lookup_batch<S>(&self, queries: &[(S, Option<i32>)]) -> Vec<Option<&Title>>
+    where
+        S: AsRef<str>,
+    {
+        let mut scratch = Scratch::new();
+        queries
+            .iter()
+            .map(|&(ref text, year)| {
+                let matches = matches(self, &self.options, &mut scratch, text.as_ref(), year);
+                let best = rank_matches(matches, year, 1).into_iter().next();
+                best.map(|title| self.keep(title))
+            }).collect()
+    }
+
+    /// Complete the text typed so far in an interactive picker, such as "the mat": find the titles
+    /// with all the words of the text, the last word being only the start of a word unless the
+    /// text ends with a space. At most `limit` titles are returned, the most voted first.
+    pub fn complete(&self, prefix: &str, limit: usize) -> Vec<&Title> {
         let mut tags = Vec::new();
-        text_to_tags(&text, &mut tags);
-
-        let scoring_func = |title: &Title| -> NonNan {
-            let mut score = match title.original_title() {
-                None => strsim::jaro(&title.primary_title().to_lowercase(), text),
-                Some(original_title) => f64::max(
-                    strsim::jaro(&title.primary_title().to_lowercase(), text),
-                    strsim::jaro(&original_title.to_lowercase(), text),
-                ),
-            };
+        self.options.get_tokenizer().tags(prefix, &mut tags);
+        let partial = if prefix.ends_with(char::is_whitespace) {
+            None
+        } else {
+            tags.pop()
+        };
 
-            if let Some(year) = year {
-                if title.year() != year {
-                    score *= 0.85;
+        let mut title_ids: Option<Vec<u32>> = partial.map(|partial| self.prefixed_ids(&partial));
+        for tag in tags.iter() {
+            let ids = match self.tag_ids(tag) {
+                Some(ids) => ids,
+                None => return vec![],
+            };
+            title_ids = Some(match title_ids {
+                Some(mut title_ids) => {
+                    title_ids.retain(|id| ids.binary_search(id).is_ok());
+                    title_ids
                 }
-            }
+                None => ids.to_vec(),
+            });
+        }
 
-            score *= match title.kind() {
-                TitleKind::Movie => 1.0,
-                _ => 0.80,
-            };
+        let current_year = current_year();
+        let mut summaries: Vec<(u32, Summary)> = title_ids
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|id| Some((id, self.summary(id)?)))
+            .filter(|&(_, summary)| {
+                self.options.matches_year(summary.year, current_year)
+                    && self.options.matches_title(summary.kind, summary.votes)
+            }).collect();
+        summaries.sort_by_key(|&(_, summary)| Reverse(summary.votes));
+        summaries
+            .into_iter()
+            .take(limit)
+            .filter_map(|(id, _)| self.title(id))
+            .collect()
+    }
 
-            NonNan::new(score)
-        };
+    /// Get the titles indexed under any of the tags, without scoring them. Each title is returned
+    /// once, in no particular order. Tags should be produced by the tokenizer of the index, see
+    /// `IndexOptions::get_tokenizer`.
+    pub fn candidates<'a, S>(&'a self, tags: &'a [S]) -> impl Iterator<Item = &'a Title> + 'a
+    where
+        S: AsRef<str>,
+    {
+        let current_year = current_year();
+        let mut seen = HashSet::new();
+        tags.iter()
+            .filter_map(move |tag| self.tag_ids(tag.as_ref()))
+            .flat_map(|title_ids| title_ids.into_owned())
+            .filter(move |&title_id| seen.insert(title_id))
+            .filter(move |&title_id| match self.summary(title_id) {
+                Some(summary) => {
+                    self.options.matches_year(summary.year, current_year)
+                        && self.options.matches_title(summary.kind, summary.votes)
+                }
+                None => false,
+            }).filter_map(move |title_id| self.title(title_id))
+    }
 
-        let mut counter = Counter::new();
+    /// Like `lookup_n`, but the matches carry their score and the reason of their rank, to tell
+    /// why a title was matched.
+    pub fn explain<'t>(&'t self, text: &str, year: Option<i32>, n: usize) -> Vec<ScoredTitle<'t>> {
+        let mut tags = Vec::new();
+        self.options.get_tokenizer().tags(&text, &mut tags);
 
-        for tag in tags.into_iter() {
-            if let Some(title_ids) = self.index.get(&tag) {
-                for title_id in title_ids.iter() {
-                    let title = &self.titles[title_id];
+        let matches = matches(self, &self.options, &mut Scratch::new(), text, year);
+        let (ranked, close) = rank_scored(matches, year, n);
+        ranked
+            .into_iter()
+            .enumerate()
+            .map(|(pos, m)| ScoredTitle {
+                tags: self.hit_tags(&tags, m.title.id()),
+                title: self.keep(m.title),
+                score: *m.score,
+                rank: if pos < close && close > 1 {
+                    Rank::TieBreak { close }
+                } else {
+                    Rank::Score
+                },
+            }).collect()
+    }
 
-                    // If we have year information, only keep titles whose year is within one of the target year.
-                    if let Some(year) = year {
-                        if (year - title.year()).abs() > 1 {
-                            continue;
-                        }
+    /// Get the tags of the text under which the title is indexed. A tag matched with a typo is
+    /// followed by the indexed tag, such as `gladiattor~gladiator`.
+    fn hit_tags(&self, tags: &[String], title_id: u32) -> Vec<String> {
+        let mut hits = vec![];
+        for tag in tags.iter() {
+            match self.tag_ids(tag) {
+                Some(title_ids) => {
+                    if title_ids.binary_search(&title_id).is_ok() {
+                        hits.push(tag.clone());
                     }
-
-                    counter.add(title);
                 }
+                None if self.options.get_fuzzy() && tag.chars().count() >= FUZZY_MIN_LEN => {
+                    hits.extend(
+                        self.tags_where(&|other| within_one_edit(tag, other))
+                            .into_iter()
+                            .filter(|&(_, ref ids)| ids.binary_search(&title_id).is_ok())
+                            .map(|(other, _)| format!("{}~{}", tag, other)),
+                    );
+                }
+                None => {}
             }
         }
+        hits
+    }
 
-        let mut matches: Vec<_> = counter
-            .most_common()
+    /// Find the titles matching the query, best match first. Unlike `lookup_n`, titles of any
+    /// year can match and the results carry their score.
+    pub fn search<'t>(&'t self, query: &SearchQuery) -> Vec<SearchResult<'t>> {
+        let current_year = current_year();
+        let mut scratch = Scratch::new();
+        let matches = matches_where(self, &self.options, &mut scratch, query.text(), None, |s| {
+            self.options.matches_year(s.year, current_year) && query.accepts_summary(s)
+        });
+        rank_scored(matches, None, query.get_limit())
+            .0
             .into_iter()
-            .map(|title| Match {
-                score: scoring_func(title),
-                title,
-            }).collect();
+            .map(|m| SearchResult {
+                title: self.keep(m.title),
+                score: *m.score,
+            }).collect()
+    }
 
-        // sort by score descending
-        matches.sort_by_key(|m| Reverse(m.score));
-
-        // this step uses popularity, the best matches with 1% error margin are sorted by popularity
-        let mut iterator = matches.into_iter();
-        if let Some(best) = iterator.next() {
-            let mut candidates = vec![];
-            candidates.extend(iterator.take_while(|m| (*best.score - *m.score).abs() <= 0.01));
-            candidates.push(best);
-            candidates.sort_by_key(|m| Reverse(m.title.votes()));
-            candidates.into_iter().map(|m| m.title).next()
-        } else {
-            None
-        }
+    /// Get the title with the given IMDB id, see `parse_id` to parse `tt0133093` style ids.
+    #[inline]
+    pub fn by_id(&self, id: u32) -> Option<&Title> {
+        self.title(id)
+    }
+
+    /// Iterate over all the indexed titles, in no particular order. The titles of a mapped index
+    /// are all decoded.
+    pub fn titles<'t>(&'t self) -> impl Iterator<Item = &'t Title> + 't {
+        let mapped_ids = self.mapped.iter().flat_map(|mapped| mapped.mapped.title_ids());
+        self.titles
+            .values()
+            .chain(mapped_ids.filter_map(move |id| self.title(id)))
+    }
+
+    /// Iterate over the indexed titles of the kind, in no particular order.
+    pub fn iter_by_kind<'t>(&'t self, kind: TitleKind) -> impl Iterator<Item = &'t Title> + 't {
+        self.titles().filter(move |title| title.kind() == kind)
     }
 
     pub fn len(&self) -> usize {
-        self.titles.len()
+        match self.mapped {
+            Some(ref mapped) => mapped.mapped.len(),
+            None => self.titles.len(),
+        }
+    }
+}
+
+impl Source for Imdb {
+    fn tag_ids<'s>(&'s self, tag: &str) -> Option<Cow<'s, [u32]>> {
+        match self.mapped {
+            Some(ref mapped) => mapped.mapped.tag_ids(tag),
+            None => self.index.get(tag).map(Cow::Borrowed),
+        }
+    }
+
+    fn tags_where<'s>(&'s self, filter: &dyn Fn(&str) -> bool) -> Vec<(&'s str, Cow<'s, [u32]>)> {
+        match self.mapped {
+            Some(ref mapped) => mapped.mapped.tags_where(filter),
+            None => self
+                .index
+                .iter()
+                .filter(|&(tag, _)| filter(tag))
+                .map(|(tag, ids)| (tag, Cow::Borrowed(ids)))
+                .collect(),
+        }
+    }
+
+    fn summary(&self, id: u32) -> Option<Summary> {
+        match self.mapped {
+            Some(ref mapped) => mapped.mapped.summary(id),
+            None => self.titles.get(&id).map(Summary::of),
+        }
+    }
+
+    fn decode<'s>(&'s self, id: u32, options: &IndexOptions) -> Option<Cow<'s, Title>> {
+        match self.mapped {
+            Some(ref mapped) => mapped.mapped.decode(id, options),
+            None => self.titles.get(&id).map(Cow::Borrowed),
+        }
+    }
+}
+
+// Fails to compile if the indexes or what their lookups return can no longer be shared between
+// threads.
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn assert<T: Send + Sync>() {}
+    assert::<Imdb>();
+    assert::<::mapped::MappedImdb>();
+    assert::<::tv::Tv>();
+    assert::<IndexOptions>();
+    assert::<Title>();
+    assert::<ScoredTitle>();
+    assert::<SearchResult>();
+    assert::<::tv::Series>();
+    assert::<::tv::Episode>();
+}
+
+#[test]
+fn test_shared_between_threads() {
+    use std::sync::Arc;
+    use std::thread;
+
+    let imdb = Arc::new(Imdb::from_titles(vec![
+        Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000),
+        Title::new(133093, "The Matrix", 1999, TitleKind::Movie, 1700000),
+    ]));
+    let handles: Vec<_> = vec![("heat", 113277), ("the matrix", 133093)]
+        .into_iter()
+        .map(|(name, id)| {
+            let imdb = imdb.clone();
+            thread::spawn(move || imdb.lookup(name, None).map(|title| title.id()) == Some(id))
+        }).collect();
+    for handle in handles {
+        assert!(handle.join().unwrap());
+    }
+}
+
+#[test]
+fn test_prefix_jaro() {
+    assert_eq!(prefix_jaro("blade runner", "blade runner"), 1.0);
+    assert!(prefix_jaro("blade runner", "blade runner the final cut") > 0.9);
+    assert!(
+        prefix_jaro("blade runner", "blade runner the final cut")
+            > strsim::jaro("blade runner", "blade runner the final cut")
+    );
+    assert!(
+        prefix_jaro("blade runner 2049", "blade runner 2049")
+            > prefix_jaro("blade runner", "blade runner 2049")
+    );
+}
+
+#[test]
+fn test_from_titles() {
+    let imdb = Imdb::from_titles(vec![
+        Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000),
+        Title::new(83987, "Heat", 1986, TitleKind::Movie, 5000),
+        Title::new(133093, "The Matrix", 1999, TitleKind::Movie, 1700000),
+    ]);
+    assert_eq!(imdb.len(), 3);
+    assert_eq!(imdb.lookup("heat", None).map(|t| t.id()), Some(113277));
+    assert_eq!(imdb.lookup("heat", Some(1986)).map(|t| t.id()), Some(83987));
+    assert_eq!(imdb.lookup("matrix", Some(1999)).map(|t| t.id()), Some(133093));
+}
+
+#[test]
+fn test_kind_weight() {
+    let titles = vec![
+        Title::new(1, "Stop Making Sense", 1984, TitleKind::Movie, 20000),
+        Title::new(2, "Stop Making Sense", 1984, TitleKind::Video, 30000),
+    ];
+    let imdb = Imdb::from_titles(titles.clone());
+    assert_eq!(imdb.lookup("stop making sense", None).map(|t| t.id()), Some(1));
+
+    let mut imdb = Imdb::from_titles(titles);
+    imdb.options = IndexOptions::new().kind_weight(TitleKind::Video, 1.0);
+    assert_eq!(imdb.lookup("stop making sense", None).map(|t| t.id()), Some(2));
+}
+
+#[test]
+fn test_fuzzy_tags() {
+    let titles = vec![Title::new(172495, "Gladiator", 2000, TitleKind::Movie, 1300000)];
+    let imdb = Imdb::from_titles(titles.clone());
+    assert!(imdb.lookup("gladiattor", Some(2000)).is_none());
+
+    let imdb = Imdb::from_titles_with_options(titles, &IndexOptions::new().fuzzy(true));
+    assert_eq!(imdb.lookup("gladiattor", Some(2000)).map(|t| t.id()), Some(172495));
+}
+
+#[test]
+fn test_catalog_articles() {
+    let imdb = Imdb::from_titles(vec![
+        Title::new(133093, "The Matrix", 1999, TitleKind::Movie, 1500000),
+        Title::new(1, "Matrix", 1999, TitleKind::Movie, 100),
+    ]);
+    let explained = imdb.explain("Matrix The", Some(1999), 2);
+    assert_eq!(explained[0].title.id(), 133093);
+    assert_eq!(explained[0].score, 1.0);
+}
+
+#[test]
+fn test_display_title() {
+    let titles = vec![
+        Title::new(211915, "Amélie", 2001, TitleKind::Movie, 700000)
+            .with_regional_title("Le fabuleux destin d'Amélie Poulain", Some("FR"), None)
+            .with_regional_title("Die fabelhafte Welt der Amélie", Some("DE"), Some("de")),
+    ];
+    let imdb = Imdb::from_titles(titles.clone());
+    assert_eq!(imdb.by_id(211915).unwrap().display_title(), "Amélie");
+
+    let options = IndexOptions::new().preferred_region("FR").preferred_language("fr");
+    let imdb = Imdb::from_titles_with_options(titles.clone(), &options);
+    let title = imdb.lookup("le fabuleux destin d amelie poulain", None).unwrap();
+    assert_eq!(title.display_title(), "Le fabuleux destin d'Amélie Poulain");
+
+    let options = IndexOptions::new().preferred_language("de");
+    let imdb = Imdb::from_titles_with_options(titles.clone(), &options);
+    assert_eq!(imdb.by_id(211915).unwrap().display_title(), "Die fabelhafte Welt der Amélie");
+
+    let options = IndexOptions::new().preferred_region("IT");
+    let imdb = Imdb::from_titles_with_options(titles, &options);
+    assert_eq!(imdb.by_id(211915).unwrap().display_title(), "Amélie");
+}
+
+#[test]
+fn test_min_score() {
+    let titles = vec![Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000)];
+    let imdb = Imdb::from_titles(titles.clone());
+    assert!(imdb.lookup("miss saigon heat", None).is_some());
+
+    let options = IndexOptions::new().min_score(0.9);
+    let imdb = Imdb::from_titles_with_options(titles, &options);
+    assert!(imdb.lookup("miss saigon heat", None).is_none());
+    assert!(imdb.lookup("heat", Some(1995)).is_some());
+}
+
+#[test]
+fn test_match_constraints() {
+    let titles = vec![
+        Title::new(1, "Stop Making Sense", 1984, TitleKind::Movie, 800),
+        Title::new(2, "Stop Making Sense", 1984, TitleKind::Short, 30000),
+    ];
+    let options = IndexOptions::new().exclude_kinds(&[TitleKind::Short]);
+    let imdb = Imdb::from_titles_with_options(titles.clone(), &options);
+    assert_eq!(imdb.lookup("stop making sense", None).map(|t| t.id()), Some(1));
+
+    let options = options.match_min_votes(1000);
+    let imdb = Imdb::from_titles_with_options(titles, &options);
+    assert!(imdb.lookup("stop making sense", None).is_none());
+    assert_eq!(imdb.len(), 2);
+}
+
+#[test]
+fn test_lookup_batch() {
+    let imdb = Imdb::from_titles(vec![
+        Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000),
+        Title::new(83987, "Heat", 1986, TitleKind::Movie, 5000),
+        Title::new(133093, "The Matrix", 1999, TitleKind::Movie, 1700000),
+    ]);
+    let found = imdb.lookup_batch(&[("heat", Some(1986)), ("the matrix", None), ("zzz", None)]);
+    let ids: Vec<_> = found.iter().map(|title| title.map(|t| t.id())).collect();
+    assert_eq!(ids, vec![Some(83987), Some(133093), None]);
+}
+
+#[test]
+fn test_complete() {
+    let imdb = Imdb::from_titles(vec![
+        Title::new(133093, "The Matrix", 1999, TitleKind::Movie, 1700000),
+        Title::new(234215, "The Matrix Reloaded", 2003, TitleKind::Movie, 500000),
+        Title::new(1, "Matilda", 1996, TitleKind::Movie, 150000),
+        Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000),
+    ]);
+    let ids = |prefix, limit| -> Vec<u32> {
+        imdb.complete(prefix, limit).iter().map(|t| t.id()).collect()
+    };
+    assert_eq!(ids("the mat", 10), vec![133093, 234215, 1]);
+    assert_eq!(ids("mat", 1), vec![133093]);
+    assert_eq!(ids("matrix rel", 10), vec![234215]);
+    assert_eq!(ids("mat ", 10), Vec::<u32>::new());
+    assert_eq!(ids("", 10), Vec::<u32>::new());
+}
+
+#[test]
+fn test_word_count_prior() {
+    assert_eq!(word_count_prior("heat", "heat"), 1.0);
+    assert_eq!(word_count_prior("heat", "heat 1995"), 1.0);
+    assert_eq!(word_count_prior("heat and dust", "heat"), 1.0 - WORD_COUNT_PENALTY * 2.0 / 3.0);
+
+    let imdb = Imdb::from_titles(vec![
+        Title::new(85672, "Heat and Dust", 1983, TitleKind::Movie, 5000),
+        Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000),
+    ]);
+    let explained = imdb.explain("heat", None, 2);
+    assert_eq!(explained[0].title.id(), 113277);
+    assert!(explained[1].score < 0.7);
+}
+
+#[test]
+fn test_offline() {
+    let dir = ::std::env::temp_dir().join(format!("imdb-offline-{}", ::std::process::id()));
+    let options = IndexOptions::new().offline(true);
+    let result = Imdb::load_or_refresh_index(&dir, &options, None, &mut ());
+    let _ = fs::remove_dir_all(&dir);
+    match result {
+        Err(Error::Offline { ref reason }) => assert_eq!(reason, "it was never created"),
+        _ => panic!("the index was loaded offline without datasets"),
     }
 }
+
+#[test]
+fn test_tie_break() {
+    let original = Title::new(1, "Solaris", 1972, TitleKind::Movie, 90000);
+    let remake = Title::new(2, "Solaris", 2002, TitleKind::Movie, 100000);
+    let matches = || {
+        vec![
+            Match {
+                score: NonNan::new(0.9),
+                title: &remake,
+            },
+            Match {
+                score: NonNan::new(0.9),
+                title: &original,
+            },
+        ]
+    };
+    assert_eq!(rank_matches(matches(), Some(1972), 1)[0].id(), 1);
+    assert_eq!(rank_matches(matches(), None, 1)[0].id(), 2);
+}
+
+#[test]
+fn test_search() {
+    let imdb = Imdb::from_titles(vec![
+        Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000),
+        Title::new(83987, "Heat", 1986, TitleKind::Movie, 5000),
+        Title::new(1, "Heat", 2013, TitleKind::Short, 100),
+    ]);
+
+    let results = imdb.search(&SearchQuery::new("heat"));
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].title.id(), 113277);
+    assert!(results[0].score > 0.9);
+
+    let results = imdb.search(&SearchQuery::new("heat").years(1980, 1989));
+    assert_eq!(results.iter().map(|r| r.title.id()).collect::<Vec<_>>(), vec![83987]);
+
+    let results = imdb.search(&SearchQuery::new("heat").kinds(&[TitleKind::Short]));
+    assert_eq!(results.iter().map(|r| r.title.id()).collect::<Vec<_>>(), vec![1]);
+
+    let results = imdb.search(&SearchQuery::new("heat").min_votes(1000).limit(1));
+    assert_eq!(results.iter().map(|r| r.title.id()).collect::<Vec<_>>(), vec![113277]);
+}
+
+#[test]
+fn test_titles() {
+    let imdb = Imdb::from_titles(vec![
+        Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000),
+        Title::new(1, "Heat", 2013, TitleKind::Short, 100),
+    ]);
+    let mut ids: Vec<u32> = imdb.titles().map(|t| t.id()).collect();
+    ids.sort();
+    assert_eq!(ids, vec![1, 113277]);
+    let shorts: Vec<u32> = imdb.iter_by_kind(TitleKind::Short).map(|t| t.id()).collect();
+    assert_eq!(shorts, vec![1]);
+}
+
+#[test]
+fn test_explain() {
+    let titles = vec![
+        Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000),
+        Title::new(83987, "Heat", 1986, TitleKind::Movie, 5000),
+    ];
+    let imdb = Imdb::from_titles_with_options(titles, &IndexOptions::new().fuzzy(true));
+
+    let explained = imdb.explain("heat", None, 2);
+    assert_eq!(explained[0].title.id(), 113277);
+    assert_eq!(explained[0].tags, vec!["heat"]);
+    assert_eq!(explained[0].rank, Rank::TieBreak { close: 2 });
+
+    let explained = imdb.explain("heaat", Some(1995), 1);
+    assert_eq!(explained[0].tags, vec!["heaat~heat"]);
+    assert_eq!(explained[0].rank, Rank::Score);
+}
@@ -1,19 +1,40 @@
 #![feature(nll)]
 
 extern crate bincode;
+extern crate byteorder;
 extern crate csv;
 extern crate flate2;
+#[cfg(unix)]
+extern crate libc;
+#[cfg(feature = "download")]
 extern crate reqwest;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate strsim;
+extern crate unicode_normalization;
 
 mod error;
+mod format;
 mod index;
+mod mapped;
+mod options;
+mod progress;
+mod search;
+mod tags;
 mod title;
+pub mod tokenize;
+mod tv;
 mod util;
+mod verify;
 
 pub use error::{Error, Result};
-pub use index::Imdb;
-pub use title::{Title, TitleKind};
+pub use index::{Imdb, MAPPED_INDEX};
+pub use mapped::MappedImdb;
+pub use options::{IndexOptions, DEFAULT_DATASET_URL};
+pub use progress::{Phase, Progress};
+pub use search::{Rank, ScoredTitle, SearchQuery, SearchResult};
+pub use title::{parse_id, Genre, Title, TitleKind};
+pub use tokenize::{Language, Tokenizer};
+pub use tv::{Episode, Series, Tv};
+pub use util::current_year;
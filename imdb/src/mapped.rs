@@ -0,0 +1,554 @@
+//! An on-disk layout of the index that is memory mapped instead of deserialized, so that opening
+//! it is instant and only the titles matched are decoded.
+//!
+//! All the integers are little endian. The file starts with a header, followed by the fixed-size
+//! records of the titles sorted by id, the fixed-size records of the tags sorted by their bytes,
+//! the postings of the tags and finally the variable-size data.
+//!
+//! ```text
+//! header:  magic (8 bytes), title count, tag count, titles offset, tags offset,
+//!          postings offset, options offset, options length
+//! title:   id, year (u16), kind (u8), padding (u8), votes, data offset, data length
+//! tag:     tag offset, tag length, postings offset, postings count
+//! posting: index of a title record
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use bincode;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use error::Result;
+use index::{matches, rank_matches, Imdb, Scratch, Source, Summary};
+use options::IndexOptions;
+use title::{Title, TitleKind};
+
+const MAGIC: &[u8; 8] = b"MEROMP14";
+const HEADER_LEN: usize = 8 + 7 * 4;
+const TITLE_RECORD_LEN: usize = 20;
+const TAG_RECORD_LEN: usize = 16;
+
+#[cfg(unix)]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use std::ptr;
+    use std::slice;
+
+    use libc;
+
+    /// A read-only memory mapping of a whole file.
+    pub struct Bytes {
+        ptr: *mut libc::c_void,
+        len: usize,
+    }
+
+    // The mapping is never written to.
+    unsafe impl Send for Bytes {}
+    unsafe impl Sync for Bytes {}
+
+    impl Bytes {
+        pub fn open(file: &File) -> io::Result<Bytes> {
+            let len = file.metadata()?.len() as usize;
+            if len == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "empty index"));
+            }
+            let ptr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_READ,
+                    libc::MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Bytes { ptr, len })
+        }
+
+        #[inline]
+        pub fn as_slice(&self) -> &[u8] {
+            unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+        }
+    }
+
+    impl Drop for Bytes {
+        fn drop(&mut self) {
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use std::fs::File;
+    use std::io::{self, Read};
+
+    /// The whole file read in memory, where memory mapping is not supported.
+    pub struct Bytes(Vec<u8>);
+
+    impl Bytes {
+        pub fn open(mut file: &File) -> io::Result<Bytes> {
+            let mut buf = vec![];
+            file.read_to_end(&mut buf)?;
+            Ok(Bytes(buf))
+        }
+
+        #[inline]
+        pub fn as_slice(&self) -> &[u8] {
+            &self.0
+        }
+    }
+}
+
+/// Tell if the file starts like a mapped index, so that `Imdb::load_index` maps it.
+pub(crate) fn is_mapped(path: &Path) -> io::Result<bool> {
+    let mut magic = [0; 8];
+    match File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(&magic == MAGIC),
+        Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Offset and count of the postings of a tag.
+pub(crate) type Postings = (usize, usize);
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid mapped index: {}", reason))
+}
+
+/// Write the index in the mapped layout. The file is written next to the destination and renamed
+/// over it, a mapped file must never be modified in place.
+pub(crate) fn write(imdb: &Imdb, path: &Path) -> Result<()> {
+    let mut titles: Vec<&Title> = imdb.titles.values().collect();
+    titles.sort_by_key(|title| title.id());
+    let positions: HashMap<u32, u32> = titles
+        .iter()
+        .enumerate()
+        .map(|(idx, title)| (title.id(), idx as u32))
+        .collect();
+
+    // The tags of the index are already sorted by their bytes.
+    let tags: Vec<(&str, Vec<u32>)> = imdb
+        .index
+        .iter()
+        .map(|(tag, ids)| {
+            let mut postings: Vec<u32> = ids.iter().map(|id| positions[id]).collect();
+            postings.sort();
+            (tag, postings)
+        }).collect();
+
+    let postings_len: usize = tags.iter().map(|&(_, ref postings)| postings.len()).sum();
+    let titles_off = HEADER_LEN;
+    let tags_off = titles_off + titles.len() * TITLE_RECORD_LEN;
+    let postings_off = tags_off + tags.len() * TAG_RECORD_LEN;
+    let data_off = postings_off + postings_len * 4;
+
+    // The variable-size data: options, tags and titles.
+    let mut data = bincode::serialize(&imdb.options)?;
+    let options_len = data.len();
+
+    let tmp_path = path.with_extension("part");
+    {
+        let mut w = BufWriter::new(File::create(&tmp_path)?);
+
+        w.write_all(MAGIC)?;
+        w.write_u32::<LittleEndian>(titles.len() as u32)?;
+        w.write_u32::<LittleEndian>(tags.len() as u32)?;
+        w.write_u32::<LittleEndian>(titles_off as u32)?;
+        w.write_u32::<LittleEndian>(tags_off as u32)?;
+        w.write_u32::<LittleEndian>(postings_off as u32)?;
+        w.write_u32::<LittleEndian>(data_off as u32)?;
+        w.write_u32::<LittleEndian>(options_len as u32)?;
+
+        for title in titles.iter() {
+            let encoded = bincode::serialize(title)?;
+            w.write_u32::<LittleEndian>(title.id())?;
+            w.write_u16::<LittleEndian>(title.year() as u16)?;
+            w.write_u8(title.kind() as u8)?;
+            w.write_u8(0)?;
+            w.write_u32::<LittleEndian>(title.votes())?;
+            w.write_u32::<LittleEndian>((data_off + data.len()) as u32)?;
+            w.write_u32::<LittleEndian>(encoded.len() as u32)?;
+            data.extend(encoded);
+        }
+
+        let mut postings_pos = postings_off;
+        for &(tag, ref postings) in tags.iter() {
+            w.write_u32::<LittleEndian>((data_off + data.len()) as u32)?;
+            w.write_u32::<LittleEndian>(tag.len() as u32)?;
+            w.write_u32::<LittleEndian>(postings_pos as u32)?;
+            w.write_u32::<LittleEndian>(postings.len() as u32)?;
+            data.extend(tag.as_bytes());
+            postings_pos += postings.len() * 4;
+        }
+
+        for &(_, ref postings) in tags.iter() {
+            for &posting in postings.iter() {
+                w.write_u32::<LittleEndian>(posting)?;
+            }
+        }
+
+        w.write_all(&data)?;
+        w.flush()?;
+    }
+    fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
+
+/// An index of the IMDB titles in the mapped layout, see `Imdb::save_mapped`.
+///
+/// The titles are decoded when they are matched, so the lookups return owned titles.
+pub struct MappedImdb {
+    bytes: sys::Bytes,
+    title_count: usize,
+    tag_count: usize,
+    titles_off: usize,
+    tags_off: usize,
+    options: IndexOptions,
+}
+
+impl MappedImdb {
+    pub fn open(path: impl AsRef<Path>) -> Result<MappedImdb> {
+        let file = File::open(path)?;
+        let bytes = sys::Bytes::open(&file)?;
+
+        let (title_count, tag_count, titles_off, tags_off, postings_off, data_off, options_len) = {
+            let buf = bytes.as_slice();
+            if buf.len() < HEADER_LEN || &buf[..8] != MAGIC {
+                return Err(invalid("bad header").into());
+            }
+            let field = |idx: usize| LittleEndian::read_u32(&buf[8 + idx * 4..]) as usize;
+            (
+                field(0),
+                field(1),
+                field(2),
+                field(3),
+                field(4),
+                field(5),
+                field(6),
+            )
+        };
+
+        let len = bytes.as_slice().len();
+        if titles_off + title_count * TITLE_RECORD_LEN > len
+            || tags_off + tag_count * TAG_RECORD_LEN > len
+            || postings_off > len
+            || data_off + options_len > len
+        {
+            return Err(invalid("truncated").into());
+        }
+
+        let options = bincode::deserialize(&bytes.as_slice()[data_off..data_off + options_len])?;
+
+        Ok(MappedImdb {
+            bytes,
+            title_count,
+            tag_count,
+            titles_off,
+            tags_off,
+            options,
+        })
+    }
+
+    #[inline]
+    fn u32_at(&self, off: usize) -> Option<u32> {
+        self.bytes
+            .as_slice()
+            .get(off..off + 4)
+            .map(LittleEndian::read_u32)
+    }
+
+    #[inline]
+    fn title_record(&self, idx: usize) -> &[u8] {
+        let off = self.titles_off + idx * TITLE_RECORD_LEN;
+        &self.bytes.as_slice()[off..off + TITLE_RECORD_LEN]
+    }
+
+    /// Get the bytes of the tag of the record and its postings.
+    fn tag_bytes(&self, idx: usize) -> Option<(&[u8], Postings)> {
+        let buf = self.bytes.as_slice();
+        let record = &buf[self.tags_off + idx * TAG_RECORD_LEN..];
+        let tag_off = LittleEndian::read_u32(record) as usize;
+        let tag_len = LittleEndian::read_u32(&record[4..]) as usize;
+        let postings_off = LittleEndian::read_u32(&record[8..]) as usize;
+        let count = LittleEndian::read_u32(&record[12..]) as usize;
+        Some((buf.get(tag_off..tag_off + tag_len)?, (postings_off, count)))
+    }
+
+    fn tag_at(&self, idx: usize) -> Option<(&str, Postings)> {
+        let (bytes, postings) = self.tag_bytes(idx)?;
+        Some((::std::str::from_utf8(bytes).ok()?, postings))
+    }
+
+    /// Position of the first tag not lower than the text.
+    fn lower_bound(&self, text: &str) -> usize {
+        let (mut low, mut high) = (0, self.tag_count);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.tag_bytes(mid) {
+                Some((bytes, _)) if bytes < text.as_bytes() => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        low
+    }
+
+    /// Iterate over the tags and their postings, in the order of the tags.
+    pub(crate) fn tags<'a>(&'a self) -> impl Iterator<Item = (&'a str, Postings)> + 'a {
+        (0..self.tag_count).filter_map(move |idx| self.tag_at(idx))
+    }
+
+    /// Iterate over the tags starting with the prefix and their postings.
+    pub(crate) fn prefixed<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = (&'a str, Postings)> + 'a {
+        (self.lower_bound(prefix)..self.tag_count)
+            .filter_map(move |idx| self.tag_at(idx))
+            .take_while(move |&(tag, _)| tag.starts_with(prefix))
+    }
+
+    pub(crate) fn tag_postings(&self, tag: &str) -> Option<Postings> {
+        let idx = self.lower_bound(tag);
+        if idx >= self.tag_count {
+            return None;
+        }
+        match self.tag_at(idx) {
+            Some((other, postings)) if other == tag => Some(postings),
+            _ => None,
+        }
+    }
+
+    /// Get the sorted ids of the titles of the postings.
+    pub(crate) fn ids(&self, (postings_off, count): Postings) -> Vec<u32> {
+        (0..count)
+            .filter_map(|pos| self.u32_at(postings_off + pos * 4))
+            .filter(|&idx| (idx as usize) < self.title_count)
+            .map(|idx| self.title_id(idx as usize))
+            .collect()
+    }
+
+    #[inline]
+    fn title_id(&self, idx: usize) -> u32 {
+        LittleEndian::read_u32(self.title_record(idx))
+    }
+
+    /// Iterate over the ids of all the titles, in increasing order.
+    pub(crate) fn title_ids<'a>(&'a self) -> impl Iterator<Item = u32> + 'a {
+        (0..self.title_count).map(move |idx| self.title_id(idx))
+    }
+
+    /// Decode the title of the record, with its name in the region and language preferred by the
+    /// options.
+    fn decode_with(&self, idx: usize, options: &IndexOptions) -> Option<Title> {
+        let record = self.title_record(idx);
+        let data_off = LittleEndian::read_u32(&record[12..]) as usize;
+        let data_len = LittleEndian::read_u32(&record[16..]) as usize;
+        let data = self.bytes.as_slice().get(data_off..data_off + data_len)?;
+        let mut title: Title = bincode::deserialize(data).ok()?;
+        title.prefer_region(options.get_preferred_region(), options.get_preferred_language());
+        Some(title)
+    }
+
+    /// Position of the record of the title.
+    fn position(&self, id: u32) -> Option<usize> {
+        let (mut low, mut high) = (0, self.title_count);
+        while low < high {
+            let mid = (low + high) / 2;
+            let mid_id = self.title_id(mid);
+            if mid_id < id {
+                low = mid + 1;
+            } else if mid_id > id {
+                high = mid;
+            } else {
+                return Some(mid);
+            }
+        }
+        None
+    }
+
+    /// Options the index was saved with.
+    pub(crate) fn options(&self) -> &IndexOptions {
+        &self.options
+    }
+
+    pub fn lookup(&self, text: &str, year: Option<i32>) -> Option<Title> {
+        self.lookup_n(text, year, 1).into_iter().next()
+    }
+
+    /// Find the `n` best matches for the text, best match first, like `Imdb::lookup_n`.
+    pub fn lookup_n(&self, text: &str, year: Option<i32>, n: usize) -> Vec<Title> {
+        let matches = matches(self, &self.options, &mut Scratch::new(), text, year);
+        rank_matches(matches, year, n)
+            .into_iter()
+            .map(Cow::into_owned)
+            .collect()
+    }
+
+    pub fn by_id(&self, id: u32) -> Option<Title> {
+        self.decode_with(self.position(id)?, &self.options)
+    }
+
+    pub fn len(&self) -> usize {
+        self.title_count
+    }
+}
+
+impl Source for MappedImdb {
+    fn tag_ids<'s>(&'s self, tag: &str) -> Option<Cow<'s, [u32]>> {
+        let postings = self.tag_postings(tag)?;
+        Some(Cow::Owned(self.ids(postings)))
+    }
+
+    fn tags_where<'s>(&'s self, filter: &dyn Fn(&str) -> bool) -> Vec<(&'s str, Cow<'s, [u32]>)> {
+        self.tags()
+            .filter(|&(tag, _)| filter(tag))
+            .map(|(tag, postings)| (tag, Cow::Owned(self.ids(postings))))
+            .collect()
+    }
+
+    /// Read the summary from the record of the title, without decoding it.
+    fn summary(&self, id: u32) -> Option<Summary> {
+        let record = self.title_record(self.position(id)?);
+        let kind = match record[6] {
+            0 => TitleKind::Movie,
+            1 => TitleKind::TvMovie,
+            2 => TitleKind::Video,
+            3 => TitleKind::Short,
+            _ => return None,
+        };
+        Some(Summary {
+            year: LittleEndian::read_u16(&record[4..]) as i32,
+            kind,
+            votes: LittleEndian::read_u32(&record[8..]),
+        })
+    }
+
+    fn decode<'s>(&'s self, id: u32, options: &IndexOptions) -> Option<Cow<'s, Title>> {
+        self.decode_with(self.position(id)?, options).map(Cow::Owned)
+    }
+}
+
+/// The titles of a mapped index loaded by `Imdb::load_index`. The lookups score the titles they
+/// decode and drop them, only the titles returned by reference are kept, so that the memory used
+/// grows with the titles returned rather than with the titles scored.
+pub(crate) struct LazyTitles {
+    pub(crate) mapped: MappedImdb,
+    decoded: Mutex<HashMap<u32, Box<Title>>>,
+}
+
+impl LazyTitles {
+    pub(crate) fn new(mapped: MappedImdb) -> LazyTitles {
+        LazyTitles {
+            mapped,
+            decoded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the title, decoded with the names preferred by the options and kept the first time.
+    pub(crate) fn get(&self, id: u32, options: &IndexOptions) -> Option<&Title> {
+        let kept = {
+            let decoded = self.decoded.lock().unwrap_or_else(PoisonError::into_inner);
+            decoded.get(&id).map(|title| &**title as *const Title)
+        };
+        match kept {
+            // See `keep`.
+            Some(title) => Some(unsafe { &*title }),
+            None => Some(self.keep(self.mapped.decode_with(self.mapped.position(id)?, options)?)),
+        }
+    }
+
+    /// Keep the decoded title, unless it already was, so that it can be returned by reference.
+    pub(crate) fn keep(&self, title: Title) -> &Title {
+        let mut decoded = self.decoded.lock().unwrap_or_else(PoisonError::into_inner);
+        let title: *const Title = &**decoded
+            .entry(title.id())
+            .or_insert_with(|| Box::new(title));
+        // The titles are boxed, growing the map moves the boxes but not the titles, and they are
+        // only dropped by `clear` and `drop`, which cannot happen while the reference is borrowed.
+        unsafe { &*title }
+    }
+
+    /// Forget the decoded titles, so that they are decoded again with other preferred names.
+    pub(crate) fn clear(&mut self) {
+        self.decoded
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+#[test]
+fn test_mapped_round_trip() {
+    use title::TitleKind;
+
+    let titles = vec![
+        Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000),
+        Title::new(83987, "Heat", 1986, TitleKind::Movie, 5000),
+        Title::new(133093, "The Matrix", 1999, TitleKind::Movie, 1700000),
+    ];
+    let imdb = Imdb::from_titles_with_options(titles, &IndexOptions::new().fuzzy(true));
+    let path = ::std::env::temp_dir().join(format!("imdb-mapped-{}", ::std::process::id()));
+    imdb.save_mapped(&path).unwrap();
+    let mapped = MappedImdb::open(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(mapped.len(), 3);
+    assert_eq!(mapped.lookup("heat", None).map(|t| t.id()), Some(113277));
+    assert_eq!(mapped.lookup("heat", Some(1986)).map(|t| t.id()), Some(83987));
+    assert_eq!(mapped.lookup("the matrix", None).map(|t| t.id()), Some(133093));
+    assert_eq!(mapped.lookup("the matirx", None).map(|t| t.id()), Some(133093));
+    assert_eq!(mapped.by_id(83987).map(|t| t.year()), Some(1986));
+    assert!(mapped.by_id(1).is_none());
+}
+
+#[test]
+fn test_load_mapped() {
+    use title::TitleKind;
+
+    let titles = vec![
+        Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000),
+        Title::new(83987, "Heat", 1986, TitleKind::Movie, 5000),
+        Title::new(133093, "The Matrix", 1999, TitleKind::Movie, 1700000),
+    ];
+    let imdb = Imdb::from_titles_with_options(titles, &IndexOptions::new().fuzzy(true));
+    let dir = ::std::env::temp_dir().join(format!("imdb-load-mapped-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("index.map");
+    imdb.save_mapped(&path).unwrap();
+    let plain_path = dir.join("index.gz");
+    imdb.save(&plain_path).unwrap();
+
+    // The layout is told by the start of the file.
+    assert!(!Imdb::load_index(&plain_path).unwrap().is_mapped());
+    let mapped = Imdb::load_index(&path).unwrap();
+    assert!(mapped.is_mapped());
+    assert_eq!(mapped.len(), 3);
+    assert_eq!(mapped.lookup("heat", None).map(|t| t.id()), Some(113277));
+    // Both titles named Heat were scored, only the one returned is kept.
+    let kept = |imdb: &Imdb| imdb.mapped.as_ref().unwrap().decoded.lock().unwrap().len();
+    assert_eq!(kept(&mapped), 1);
+    assert_eq!(mapped.lookup("heat", Some(1986)).map(|t| t.id()), Some(83987));
+    assert_eq!(mapped.lookup("the matirx", None).map(|t| t.id()), Some(133093));
+    assert_eq!(mapped.explain("the matirx", None, 1)[0].tags, ["matirx~matrix"]);
+    assert_eq!(mapped.complete("the mat", 5).len(), 1);
+    assert_eq!(mapped.by_id(83987).map(|t| t.year()), Some(1986));
+    assert_eq!(mapped.titles().count(), 3);
+    assert!(mapped.save(dir.join("again.gz")).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,307 @@
+use std::env;
+
+use title::TitleKind;
+use tokenize::Tokenizer;
+
+/// Titles with fewer votes are not indexed by default.
+pub(crate) const DEFAULT_MIN_VOTES: u32 = 50;
+/// Host of the IMDB datasets, used unless `IMDB_DATASET_URL` or `IndexOptions::dataset_url` point
+/// to a mirror.
+pub const DEFAULT_DATASET_URL: &str = "https://datasets.imdbws.com";
+
+/// Options deciding which titles are indexed and matched. They are saved with the index, and an
+/// index created with different options is created again.
+///
+/// ```
+/// use imdb::{IndexOptions, TitleKind};
+///
+/// let options = IndexOptions::new()
+///     .min_votes(10)
+///     .kinds(&[TitleKind::Movie, TitleKind::Short]);
+/// assert!(options.accepts(TitleKind::Short, 10, false));
+/// assert!(!options.accepts(TitleKind::Video, 10, false));
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct IndexOptions {
+    min_votes: u32,
+    adult: bool,
+    kinds: Vec<TitleKind>,
+    unreleased: bool,
+    fuzzy: bool,
+    min_score: f64,
+    match_min_votes: u32,
+    excluded_kinds: Vec<TitleKind>,
+    catalog_articles: bool,
+    offline: bool,
+    crew: bool,
+    dataset_url: String,
+    proxy: Option<String>,
+    tokenizer: Tokenizer,
+    preferred_region: Option<String>,
+    preferred_language: Option<String>,
+    kind_weights: Vec<(TitleKind, f64)>,
+}
+
+impl IndexOptions {
+    /// Index the titles with at least 50 votes of all the kinds, adult titles excluded. The
+    /// datasets are downloaded from the `IMDB_DATASET_URL` environment variable when it is set,
+    /// through the proxy of the `HTTPS_PROXY` environment variable.
+    pub fn new() -> IndexOptions {
+        IndexOptions {
+            min_votes: DEFAULT_MIN_VOTES,
+            adult: false,
+            kinds: vec![
+                TitleKind::Movie,
+                TitleKind::TvMovie,
+                TitleKind::Video,
+                TitleKind::Short,
+            ],
+            unreleased: false,
+            fuzzy: false,
+            min_score: 0.0,
+            match_min_votes: 0,
+            excluded_kinds: vec![],
+            catalog_articles: true,
+            offline: false,
+            crew: false,
+            dataset_url: env::var("IMDB_DATASET_URL")
+                .map(|url| url.trim_right_matches('/').to_string())
+                .unwrap_or_else(|_| DEFAULT_DATASET_URL.to_string()),
+            proxy: env::var("HTTPS_PROXY")
+                .or_else(|_| env::var("https_proxy"))
+                .ok(),
+            tokenizer: Tokenizer::new(),
+            preferred_region: None,
+            preferred_language: None,
+            kind_weights: vec![
+                (TitleKind::Movie, 1.0),
+                (TitleKind::TvMovie, 0.8),
+                (TitleKind::Video, 0.8),
+                (TitleKind::Short, 0.8),
+            ],
+        }
+    }
+
+    /// Minimum number of votes of the indexed titles.
+    pub fn min_votes(mut self, min_votes: u32) -> IndexOptions {
+        self.min_votes = min_votes;
+        self
+    }
+
+    /// Index the adult titles.
+    pub fn adult(mut self, adult: bool) -> IndexOptions {
+        self.adult = adult;
+        self
+    }
+
+    /// Kinds of the indexed titles.
+    pub fn kinds(mut self, kinds: &[TitleKind]) -> IndexOptions {
+        self.kinds = kinds.to_vec();
+        self
+    }
+
+    /// Match the titles announced for a future year. They are indexed either way, but left out of
+    /// the matches by default.
+    pub fn unreleased(mut self, unreleased: bool) -> IndexOptions {
+        self.unreleased = unreleased;
+        self
+    }
+
+    /// How the titles and the texts looked up are split into tags.
+    pub fn tokenizer(mut self, tokenizer: Tokenizer) -> IndexOptions {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// Look up the tags one typo away from a tag of the text missing from the index, so that
+    /// slightly misspelled file names still find their title. Disabled by default, as every tag
+    /// of the index is compared to each tag missing from it.
+    pub fn fuzzy(mut self, fuzzy: bool) -> IndexOptions {
+        self.fuzzy = fuzzy;
+        self
+    }
+
+    /// Minimum score of the titles matched by `Imdb::lookup`, between 0 and 1. Texts only close
+    /// to titles scoring lower, such as garbage file names, match nothing instead of their best
+    /// title. Every title is matched by default.
+    pub fn min_score(mut self, min_score: f64) -> IndexOptions {
+        self.min_score = min_score;
+        self
+    }
+
+    /// Minimum number of votes of the titles matched. Unlike `min_votes`, the titles with fewer
+    /// votes stay in the index, so that libraries with different tolerances for obscure titles
+    /// can share it.
+    pub fn match_min_votes(mut self, min_votes: u32) -> IndexOptions {
+        self.match_min_votes = min_votes;
+        self
+    }
+
+    /// Never match the titles of these kinds. Unlike `kinds`, the titles stay in the index.
+    pub fn exclude_kinds(mut self, kinds: &[TitleKind]) -> IndexOptions {
+        self.excluded_kinds = kinds.to_vec();
+        self
+    }
+
+    /// Score the texts and the titles written in the catalog form, such as "Matrix, The", as if
+    /// the article was in front. Enabled by default.
+    pub fn catalog_articles(mut self, catalog_articles: bool) -> IndexOptions {
+        self.catalog_articles = catalog_articles;
+        self
+    }
+
+    /// Never download the datasets. The existing index is loaded, and created again from the
+    /// datasets already downloaded when needed. Disabled by default.
+    pub fn offline(mut self, offline: bool) -> IndexOptions {
+        self.offline = offline;
+        self
+    }
+
+    /// Download the crew datasets along with the others, so that the titles have their directors.
+    /// They are large and disabled by default, an index created without them has no directors.
+    pub fn crew(mut self, crew: bool) -> IndexOptions {
+        self.crew = crew;
+        self
+    }
+
+    /// Download the datasets from a mirror of the IMDB datasets instead, such as
+    /// `http://mirror.local/imdb`. The files are expected under the same names.
+    pub fn dataset_url(mut self, url: impl Into<String>) -> IndexOptions {
+        self.dataset_url = url.into().trim_right_matches('/').to_string();
+        self
+    }
+
+    /// Download the datasets through this HTTP proxy, such as `http://proxy.local:3128`.
+    pub fn proxy(mut self, url: impl Into<String>) -> IndexOptions {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    /// Display the titles with the name they have in this region of the akas dataset, such as
+    /// `FR`, see `Title::display_title`. The primary title is displayed by default.
+    pub fn preferred_region(mut self, region: impl Into<String>) -> IndexOptions {
+        self.preferred_region = Some(region.into());
+        self
+    }
+
+    /// Display the titles with the name they have in this language of the akas dataset, such as
+    /// `fr`. Combined with a preferred region, the names of the region in another language are
+    /// avoided.
+    pub fn preferred_language(mut self, language: impl Into<String>) -> IndexOptions {
+        self.preferred_language = Some(language.into());
+        self
+    }
+
+    /// Multiply the score of the titles of this kind by the weight. Movies have a weight of 1 and
+    /// the other kinds a weight of 0.8 by default, so that movies are preferred. Collections of
+    /// music videos or concerts can prefer videos instead.
+    pub fn kind_weight(mut self, kind: TitleKind, weight: f64) -> IndexOptions {
+        match self.kind_weights.iter_mut().find(|&&mut (k, _)| k == kind) {
+            Some(&mut (_, ref mut w)) => *w = weight,
+            None => self.kind_weights.push((kind, weight)),
+        }
+        self
+    }
+
+    #[inline]
+    pub fn get_kind_weight(&self, kind: TitleKind) -> f64 {
+        self.kind_weights
+            .iter()
+            .find(|&&(k, _)| k == kind)
+            .map(|&(_, weight)| weight)
+            .unwrap_or(1.0)
+    }
+
+    #[inline]
+    pub fn get_tokenizer(&self) -> &Tokenizer {
+        &self.tokenizer
+    }
+
+    #[inline]
+    pub fn get_fuzzy(&self) -> bool {
+        self.fuzzy
+    }
+
+    #[inline]
+    pub fn get_min_score(&self) -> f64 {
+        self.min_score
+    }
+
+    #[inline]
+    pub fn get_catalog_articles(&self) -> bool {
+        self.catalog_articles
+    }
+
+    #[inline]
+    pub fn get_offline(&self) -> bool {
+        self.offline
+    }
+
+    #[inline]
+    pub fn get_crew(&self) -> bool {
+        self.crew
+    }
+
+    #[inline]
+    pub fn get_dataset_url(&self) -> &str {
+        &self.dataset_url
+    }
+
+    #[inline]
+    pub fn get_proxy(&self) -> Option<&str> {
+        self.proxy.as_ref().map(|s| s.as_str())
+    }
+
+    #[inline]
+    pub fn get_preferred_region(&self) -> Option<&str> {
+        self.preferred_region.as_ref().map(|s| s.as_str())
+    }
+
+    #[inline]
+    pub fn get_preferred_language(&self) -> Option<&str> {
+        self.preferred_language.as_ref().map(|s| s.as_str())
+    }
+
+    /// URL of a dataset file, such as `title.basics.tsv.gz`.
+    #[cfg(feature = "download")]
+    pub(crate) fn dataset_file_url(&self, name: &str) -> String {
+        format!("{}/{}", self.dataset_url, name)
+    }
+
+    #[inline]
+    pub fn get_min_votes(&self) -> u32 {
+        self.min_votes
+    }
+
+    /// Tell if a title released this year can be matched.
+    #[inline]
+    pub fn matches_year(&self, year: i32, current_year: i32) -> bool {
+        self.unreleased || year <= current_year
+    }
+
+    /// Tell if a title of this kind and with this many votes can be matched, see
+    /// `match_min_votes` and `exclude_kinds`.
+    #[inline]
+    pub fn matches_title(&self, kind: TitleKind, votes: u32) -> bool {
+        votes >= self.match_min_votes && !self.excluded_kinds.contains(&kind)
+    }
+
+    /// Tell if an index created with the other options contains the same titles and tags.
+    pub(crate) fn same_titles(&self, other: &IndexOptions) -> bool {
+        self.min_votes == other.min_votes
+            && self.adult == other.adult
+            && self.kinds == other.kinds
+            && self.tokenizer == other.tokenizer
+    }
+
+    /// Tell if a title with these properties is indexed.
+    pub fn accepts(&self, kind: TitleKind, votes: u32, adult: bool) -> bool {
+        self.kinds.contains(&kind) && votes >= self.min_votes && (self.adult || !adult)
+    }
+}
+
+impl Default for IndexOptions {
+    fn default() -> IndexOptions {
+        IndexOptions::new()
+    }
+}
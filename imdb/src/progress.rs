@@ -0,0 +1,81 @@
+use std::fmt;
+#[cfg(feature = "download")]
+use std::io::{self, Read, Write};
+
+/// Number of rows parsed between two progress reports.
+const ROWS_INTERVAL: usize = 100_000;
+
+/// Phases of the index creation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Ratings,
+    Titles,
+    Akas,
+    Directors,
+    Index,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
+        w.write_str(match self {
+            Phase::Ratings => "Reading ratings",
+            Phase::Titles => "Reading titles",
+            Phase::Akas => "Reading alternate titles",
+            Phase::Directors => "Reading directors",
+            Phase::Index => "Building index",
+        })
+    }
+}
+
+/// Receives the progress of the dataset downloads and of the index creation, all the methods do
+/// nothing by default.
+pub trait Progress {
+    /// Bytes of the dataset downloaded so far, out of the total when the server sent it.
+    fn download(&mut self, _file: &str, _bytes: u64, _total: Option<u64>) {}
+
+    /// A phase of the index creation started.
+    fn phase(&mut self, _phase: Phase) {}
+
+    /// Rows of the dataset read by the current phase so far.
+    fn rows(&mut self, _rows: u64) {}
+
+    /// The saved index cannot be used and is created again, for the reason given.
+    fn rebuild(&mut self, _reason: &str) {}
+}
+
+impl Progress for () {}
+
+#[inline]
+pub(crate) fn report_rows(progress: &mut dyn Progress, row: usize) {
+    if row % ROWS_INTERVAL == 0 {
+        progress.rows(row as u64);
+    }
+}
+
+/// Copy a download to the writer, reporting the bytes copied after each chunk.
+#[cfg(feature = "download")]
+pub(crate) fn copy_download(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    file: &str,
+    total: Option<u64>,
+    progress: &mut dyn Progress,
+) -> io::Result<u64> {
+    let mut buf = [0; 64 * 1024];
+    let mut bytes = 0;
+    progress.download(file, bytes, total);
+
+    loop {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buf[..len])?;
+        bytes += len as u64;
+        progress.download(file, bytes, total);
+    }
+
+    Ok(bytes)
+}
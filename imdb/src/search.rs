@@ -0,0 +1,115 @@
+use index::Summary;
+use title::{Title, TitleKind};
+
+/// A search of the index restricted by year, votes and kinds of titles, see `Imdb::search`.
+///
+/// ```
+/// use imdb::{SearchQuery, TitleKind};
+///
+/// let query = SearchQuery::new("heat")
+///     .years(1990, 1999)
+///     .min_votes(1000)
+///     .kinds(&[TitleKind::Movie])
+///     .limit(5);
+/// assert_eq!(query.text(), "heat");
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchQuery {
+    text: String,
+    years: Option<(i32, i32)>,
+    min_votes: u32,
+    kinds: Option<Vec<TitleKind>>,
+    limit: usize,
+}
+
+impl SearchQuery {
+    /// Search the titles matching the text, of any year, votes and kind, keeping the 10 best.
+    pub fn new(text: impl Into<String>) -> SearchQuery {
+        SearchQuery {
+            text: text.into(),
+            years: None,
+            min_votes: 0,
+            kinds: None,
+            limit: 10,
+        }
+    }
+
+    /// Only keep the titles released between the years, both included.
+    pub fn years(mut self, first: i32, last: i32) -> SearchQuery {
+        self.years = Some((first, last));
+        self
+    }
+
+    /// Only keep the titles with at least this many votes.
+    pub fn min_votes(mut self, min_votes: u32) -> SearchQuery {
+        self.min_votes = min_votes;
+        self
+    }
+
+    /// Only keep the titles of these kinds.
+    pub fn kinds(mut self, kinds: &[TitleKind]) -> SearchQuery {
+        self.kinds = Some(kinds.to_vec());
+        self
+    }
+
+    /// Maximum number of results.
+    pub fn limit(mut self, limit: usize) -> SearchQuery {
+        self.limit = limit;
+        self
+    }
+
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    #[inline]
+    pub fn get_limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Tell if the title passes the filters of the query.
+    pub fn accepts(&self, title: &Title) -> bool {
+        self.accepts_summary(Summary::of(title))
+    }
+
+    pub(crate) fn accepts_summary(&self, summary: Summary) -> bool {
+        let in_years = match self.years {
+            Some((first, last)) => first <= summary.year && summary.year <= last,
+            None => true,
+        };
+        let of_kind = match self.kinds {
+            Some(ref kinds) => kinds.contains(&summary.kind),
+            None => true,
+        };
+        in_years && of_kind && summary.votes >= self.min_votes
+    }
+}
+
+/// Why a match was ranked where it is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rank {
+    /// The match was ranked by its score.
+    Score,
+    /// The match was among `close` matches within 1% of the best score, which were ranked with the
+    /// titles of the year of the text first, then the most voted, then the best rated.
+    TieBreak { close: usize },
+}
+
+/// A title matched by `Imdb::explain`, with the reasons it was matched.
+#[derive(Clone, Debug)]
+pub struct ScoredTitle<'t> {
+    pub title: &'t Title,
+    /// Score of the title, between 0 and 1.
+    pub score: f64,
+    /// Tags of the text under which the title is indexed.
+    pub tags: Vec<String>,
+    pub rank: Rank,
+}
+
+/// A title found by a search and its score, between 0 and 1.
+#[derive(Clone, Debug)]
+pub struct SearchResult<'t> {
+    pub title: &'t Title,
+    pub score: f64,
+}
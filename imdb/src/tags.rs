@@ -0,0 +1,147 @@
+//! The reverse index from the tags to the ids of the titles indexed under them.
+//!
+//! Millions of tags are indexed, so they are not kept as separate strings and sets: the tags are
+//! interned one after the other in a single string, in sorted order, and the ids of each tag are
+//! a sorted run of a single vector.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct TagIndex {
+    /// The tags one after the other, in sorted order.
+    text: String,
+    /// End of each tag in `text` and end of its ids in `ids`, the start is the end of the
+    /// previous tag.
+    ends: Vec<(u32, u32)>,
+    ids: Vec<u32>,
+}
+
+/// Collects the ids of the tags before they are frozen into a `TagIndex`.
+pub(crate) struct TagIndexBuilder {
+    tags: HashMap<String, Vec<u32>>,
+}
+
+impl TagIndexBuilder {
+    pub(crate) fn new() -> TagIndexBuilder {
+        TagIndexBuilder {
+            tags: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, tag: String, id: u32) {
+        self.tags.entry(tag).or_insert_with(Vec::new).push(id);
+    }
+
+    pub(crate) fn build(self) -> TagIndex {
+        let mut tags: Vec<(String, Vec<u32>)> = self.tags.into_iter().collect();
+        tags.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut index = TagIndex {
+            text: String::with_capacity(tags.iter().map(|&(ref tag, _)| tag.len()).sum()),
+            ends: Vec::with_capacity(tags.len()),
+            ids: Vec::with_capacity(tags.iter().map(|&(_, ref ids)| ids.len()).sum()),
+        };
+        for (tag, mut ids) in tags {
+            ids.sort();
+            ids.dedup();
+            index.text.push_str(&tag);
+            index.ids.extend(ids);
+            index.ends.push((index.text.len() as u32, index.ids.len() as u32));
+        }
+        index.shrink_to_fit();
+        index
+    }
+}
+
+impl TagIndex {
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    fn start(&self, pos: usize) -> (usize, usize) {
+        match pos {
+            0 => (0, 0),
+            _ => {
+                let (text_end, ids_end) = self.ends[pos - 1];
+                (text_end as usize, ids_end as usize)
+            }
+        }
+    }
+
+    fn tag(&self, pos: usize) -> &str {
+        let (start, _) = self.start(pos);
+        &self.text[start..self.ends[pos].0 as usize]
+    }
+
+    fn tag_ids(&self, pos: usize) -> &[u32] {
+        let (_, start) = self.start(pos);
+        &self.ids[start..self.ends[pos].1 as usize]
+    }
+
+    /// Position of the first tag not lower than the text.
+    fn lower_bound(&self, text: &str) -> usize {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.tag(mid) < text {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    /// Get the sorted ids of the titles indexed under the tag.
+    pub(crate) fn get(&self, tag: &str) -> Option<&[u32]> {
+        let pos = self.lower_bound(tag);
+        if pos < self.len() && self.tag(pos) == tag {
+            Some(self.tag_ids(pos))
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over the tags and their ids, in the order of the tags.
+    pub(crate) fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a str, &'a [u32])> + 'a {
+        (0..self.len()).map(move |pos| (self.tag(pos), self.tag_ids(pos)))
+    }
+
+    /// Iterate over the tags starting with the prefix and their ids.
+    pub(crate) fn prefixed<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a [u32])> + 'a {
+        (self.lower_bound(prefix)..self.len())
+            .map(move |pos| (self.tag(pos), self.tag_ids(pos)))
+            .take_while(move |&(tag, _)| tag.starts_with(prefix))
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.text.shrink_to_fit();
+        self.ends.shrink_to_fit();
+        self.ids.shrink_to_fit();
+    }
+}
+
+#[test]
+fn test_tag_index() {
+    let mut builder = TagIndexBuilder::new();
+    builder.add("matrix".to_string(), 2);
+    builder.add("heat".to_string(), 3);
+    builder.add("matrix".to_string(), 1);
+    builder.add("matilda".to_string(), 4);
+    builder.add("matrix".to_string(), 2);
+    let index = builder.build();
+
+    assert_eq!(index.len(), 3);
+    assert_eq!(index.get("matrix"), Some(&[1, 2][..]));
+    assert_eq!(index.get("heat"), Some(&[3][..]));
+    assert_eq!(index.get("mat"), None);
+    assert_eq!(index.get("zzz"), None);
+    let prefixed: Vec<&str> = index.prefixed("mat").map(|(tag, _)| tag).collect();
+    assert_eq!(prefixed, vec!["matilda", "matrix"]);
+    let tags: Vec<&str> = index.iter().map(|(tag, _)| tag).collect();
+    assert_eq!(tags, vec!["heat", "matilda", "matrix"]);
+}
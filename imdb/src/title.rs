@@ -1,5 +1,20 @@
+use std::fmt;
 use std::hash::{Hash, Hasher};
 
+use tokenize::{fold, uncatalog};
+
+/// Parse an IMDB id written as `tt0133093` into its numeric form.
+pub fn parse_id(text: &str) -> Option<u32> {
+    if !text.starts_with("tt") || text.len() < 9 {
+        return None;
+    }
+    let digits = &text[2..];
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
 pub enum TitleKind {
     Movie,
@@ -8,6 +23,137 @@ pub enum TitleKind {
     Short,
 }
 
+impl TitleKind {
+    /// Parse the title type used by the IMDB datasets, such as `tvMovie`.
+    pub fn parse(text: &str) -> Option<TitleKind> {
+        match text {
+            "movie" => Some(TitleKind::Movie),
+            "tvMovie" => Some(TitleKind::TvMovie),
+            "video" => Some(TitleKind::Video),
+            "short" => Some(TitleKind::Short),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum Genre {
+    Action,
+    Adult,
+    Adventure,
+    Animation,
+    Biography,
+    Comedy,
+    Crime,
+    Documentary,
+    Drama,
+    Family,
+    Fantasy,
+    FilmNoir,
+    GameShow,
+    History,
+    Horror,
+    Music,
+    Musical,
+    Mystery,
+    News,
+    RealityTv,
+    Romance,
+    SciFi,
+    Short,
+    Sport,
+    TalkShow,
+    Thriller,
+    War,
+    Western,
+}
+
+impl Genre {
+    /// Parse a genre as written in the IMDB datasets, such as `Sci-Fi`.
+    pub fn parse(text: &str) -> Option<Genre> {
+        Some(match text {
+            "Action" => Genre::Action,
+            "Adult" => Genre::Adult,
+            "Adventure" => Genre::Adventure,
+            "Animation" => Genre::Animation,
+            "Biography" => Genre::Biography,
+            "Comedy" => Genre::Comedy,
+            "Crime" => Genre::Crime,
+            "Documentary" => Genre::Documentary,
+            "Drama" => Genre::Drama,
+            "Family" => Genre::Family,
+            "Fantasy" => Genre::Fantasy,
+            "Film-Noir" => Genre::FilmNoir,
+            "Game-Show" => Genre::GameShow,
+            "History" => Genre::History,
+            "Horror" => Genre::Horror,
+            "Music" => Genre::Music,
+            "Musical" => Genre::Musical,
+            "Mystery" => Genre::Mystery,
+            "News" => Genre::News,
+            "Reality-TV" => Genre::RealityTv,
+            "Romance" => Genre::Romance,
+            "Sci-Fi" => Genre::SciFi,
+            "Short" => Genre::Short,
+            "Sport" => Genre::Sport,
+            "Talk-Show" => Genre::TalkShow,
+            "Thriller" => Genre::Thriller,
+            "War" => Genre::War,
+            "Western" => Genre::Western,
+            _ => return None,
+        })
+    }
+
+    /// Name of the genre as written in the IMDB datasets.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Genre::Action => "Action",
+            Genre::Adult => "Adult",
+            Genre::Adventure => "Adventure",
+            Genre::Animation => "Animation",
+            Genre::Biography => "Biography",
+            Genre::Comedy => "Comedy",
+            Genre::Crime => "Crime",
+            Genre::Documentary => "Documentary",
+            Genre::Drama => "Drama",
+            Genre::Family => "Family",
+            Genre::Fantasy => "Fantasy",
+            Genre::FilmNoir => "Film-Noir",
+            Genre::GameShow => "Game-Show",
+            Genre::History => "History",
+            Genre::Horror => "Horror",
+            Genre::Music => "Music",
+            Genre::Musical => "Musical",
+            Genre::Mystery => "Mystery",
+            Genre::News => "News",
+            Genre::RealityTv => "Reality-TV",
+            Genre::Romance => "Romance",
+            Genre::SciFi => "Sci-Fi",
+            Genre::Short => "Short",
+            Genre::Sport => "Sport",
+            Genre::TalkShow => "Talk-Show",
+            Genre::Thriller => "Thriller",
+            Genre::War => "War",
+            Genre::Western => "Western",
+        }
+    }
+}
+
+impl fmt::Display for Genre {
+    fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
+        w.write_str(self.name())
+    }
+}
+
+/// The name a title is known by in a region or a language, from the akas dataset.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct RegionalTitle {
+    pub(crate) region: Option<String>,
+    pub(crate) language: Option<String>,
+    /// Index of the name in `Title::names`.
+    pub(crate) name: u16,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Title {
     pub(crate) id: u32,
@@ -17,9 +163,146 @@ pub struct Title {
     pub(crate) original_title: Option<String>,
     pub(crate) kind: TitleKind,
     pub(crate) votes: u32,
+    pub(crate) rating: f32,
+    pub(crate) genres: Vec<Genre>,
+    pub(crate) directors: Vec<String>,
+    pub(crate) akas: Vec<String>,
+    /// The primary title, original title and akas folded with `tokenize::fold`, so that lookups
+    /// do not fold them again for every candidate. Filled by `fold_names`.
+    pub(crate) folded_names: Vec<String>,
+    /// The folded names written in the catalog form, such as "matrix, the", with their article
+    /// moved back to the front by `tokenize::uncatalog`. Filled by `fold_names`.
+    pub(crate) uncataloged_names: Vec<String>,
+    pub(crate) regional_titles: Vec<RegionalTitle>,
+    /// Index of the name displayed in `Title::names`, chosen by `prefer_region`.
+    #[serde(skip)]
+    pub(crate) display: u16,
 }
 
 impl Title {
+    /// Create a title from in-memory data, mostly useful to build small indexes in tests with
+    /// `Imdb::from_titles`. The other fields are empty and the rating is zero.
+    pub fn new(
+        id: u32,
+        primary_title: impl Into<String>,
+        year: i32,
+        kind: TitleKind,
+        votes: u32,
+    ) -> Title {
+        let mut title = Title {
+            id,
+            year: year as u16,
+            runtime: 0,
+            primary_title: primary_title.into(),
+            original_title: None,
+            kind,
+            votes,
+            rating: 0.0,
+            genres: vec![],
+            directors: vec![],
+            akas: vec![],
+            folded_names: vec![],
+            uncataloged_names: vec![],
+            regional_titles: vec![],
+            display: 0,
+        };
+        title.fold_names();
+        title
+    }
+
+    /// Set the genres of a title created with `new`.
+    pub fn with_genres(mut self, genres: &[Genre]) -> Title {
+        self.genres = genres.to_vec();
+        self
+    }
+
+    /// Set the runtime in minutes of a title created with `new`.
+    pub fn with_runtime(mut self, runtime: i32) -> Title {
+        self.runtime = runtime as u16;
+        self
+    }
+
+    /// Add an alternate title used in the region or the language, such as `FR` and `fr`, created
+    /// with `new`.
+    pub fn with_regional_title(
+        mut self,
+        name: impl Into<String>,
+        region: Option<&str>,
+        language: Option<&str>,
+    ) -> Title {
+        let name = name.into();
+        let position = self.names().position(|n| n == name);
+        let index = match position {
+            Some(index) => index,
+            None => {
+                self.akas.push(name);
+                self.fold_names();
+                self.names().count() - 1
+            }
+        };
+        self.regional_titles.push(RegionalTitle {
+            region: region.map(|s| s.to_string()),
+            language: language.map(|s| s.to_string()),
+            name: index as u16,
+        });
+        self
+    }
+
+    /// The primary title, the original title and the akas, in the order of the indexes of
+    /// `RegionalTitle::name`.
+    pub(crate) fn names(&self) -> impl Iterator<Item = &str> {
+        Some(self.primary_title.as_str())
+            .into_iter()
+            .chain(self.original_title.as_ref().map(|s| s.as_str()))
+            .chain(self.akas.iter().map(|s| s.as_str()))
+    }
+
+    /// Display the name used in the region and the language, or the primary title when the
+    /// title has no name for them. A name of the region without a language is accepted when no
+    /// name has both.
+    pub(crate) fn prefer_region(&mut self, region: Option<&str>, language: Option<&str>) {
+        let accepts = |regional: &RegionalTitle, strict: bool| {
+            region.map_or(true, |region| regional.region.as_ref().map_or(false, |r| r == region))
+                && language.map_or(true, |language| match regional.language {
+                    Some(ref l) => l == language,
+                    None => !strict && region.is_some(),
+                })
+        };
+        self.display = if region.is_none() && language.is_none() {
+            0
+        } else {
+            self.regional_titles
+                .iter()
+                .find(|r| accepts(r, true))
+                .or_else(|| self.regional_titles.iter().find(|r| accepts(r, false)))
+                .map(|r| r.name)
+                .unwrap_or(0)
+        };
+    }
+
+    /// Fold the names of the title again, after they have changed.
+    pub(crate) fn fold_names(&mut self) {
+        let mut folded_names = vec![fold(&self.primary_title)];
+        folded_names.extend(self.original_title.iter().map(|name| fold(name)));
+        folded_names.extend(self.akas.iter().map(|name| fold(name)));
+        self.uncataloged_names = folded_names.iter().filter_map(|name| uncatalog(name)).collect();
+        self.folded_names = folded_names;
+    }
+
+    /// Names of the title folded with `tokenize::fold`: the primary title, the original title and
+    /// the akas.
+    #[inline]
+    pub(crate) fn folded_names(&self) -> &[String] {
+        &self.folded_names
+    }
+
+    /// Folded names of the title written in the catalog form, with their article moved back to
+    /// the front.
+    #[inline]
+    pub(crate) fn uncataloged_names(&self) -> &[String] {
+        &self.uncataloged_names
+    }
+
     #[inline]
     pub fn id(&self) -> u32 {
         self.id
@@ -40,6 +323,14 @@ impl Title {
         &self.primary_title
     }
 
+    /// The name of the title in the preferred region and language of the index, see
+    /// `IndexOptions::preferred_region`. It is the primary title by default.
+    pub fn display_title(&self) -> &str {
+        self.names()
+            .nth(self.display as usize)
+            .unwrap_or(&self.primary_title)
+    }
+
     #[inline]
     pub fn original_title(&self) -> Option<&str> {
         self.original_title.as_ref().map(|s| s.as_str())
@@ -54,6 +345,34 @@ impl Title {
     pub fn votes(&self) -> u32 {
         self.votes
     }
+
+    /// Average rating of the title out of 10.
+    #[inline]
+    pub fn rating(&self) -> f32 {
+        self.rating
+    }
+
+    #[inline]
+    pub fn genres(&self) -> &[Genre] {
+        &self.genres
+    }
+
+    #[inline]
+    pub fn has_genre(&self, genre: Genre) -> bool {
+        self.genres.contains(&genre)
+    }
+
+    /// Names of the directors, empty unless the index was created with the crew datasets.
+    #[inline]
+    pub fn directors(&self) -> &[String] {
+        &self.directors
+    }
+
+    /// Alternate titles of the title, such as the titles used in other countries.
+    #[inline]
+    pub fn akas(&self) -> &[String] {
+        &self.akas
+    }
 }
 
 impl Hash for Title {
@@ -74,3 +393,20 @@ impl PartialEq for Title {
 }
 
 impl Eq for Title {}
+
+#[test]
+fn test_parse_id() {
+    assert_eq!(parse_id("tt0133093"), Some(133093));
+    assert_eq!(parse_id("tt10872600"), Some(10872600));
+    assert_eq!(parse_id("tt133093"), None);
+    assert_eq!(parse_id("0133093"), None);
+    assert_eq!(parse_id("tt0133093a"), None);
+}
+
+#[test]
+fn test_parse_genre() {
+    assert_eq!(Genre::parse("Sci-Fi"), Some(Genre::SciFi));
+    assert_eq!(Genre::parse("Drama"), Some(Genre::Drama));
+    assert_eq!(Genre::parse("\\N"), None);
+    assert_eq!(Genre::FilmNoir.to_string(), "Film-Noir");
+}
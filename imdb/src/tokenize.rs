@@ -0,0 +1,259 @@
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Tell if the character separates two tags.
+///
+/// The tag splitter must be a superset of the binary's `filter_path` function.
+pub fn tag_splitter(c: char) -> bool {
+    match c {
+        c if c.is_whitespace() => true,
+        c if c.is_ascii_control() => true,
+        '/' | '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*' => true, // from filter_path
+        '_' => true,
+        '-' => true,
+        '.' => true,
+        ',' => true,
+        '\'' => true,
+        '(' => true,
+        ')' => true,
+        '[' => true,
+        ']' => true,
+        _ => false,
+    }
+}
+
+/// Languages whose stop words are skipped from the tags.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Language {
+    English,
+    French,
+    Spanish,
+    German,
+}
+
+impl Language {
+    pub fn parse(text: &str) -> Option<Language> {
+        match text {
+            "en" | "english" => Some(Language::English),
+            "fr" | "french" => Some(Language::French),
+            "es" | "spanish" => Some(Language::Spanish),
+            "de" | "german" => Some(Language::German),
+            _ => None,
+        }
+    }
+
+    /// The words too common to be useful, folded. German and French articles such as "die" or
+    /// "les" are also English or title words, which is why the languages are opt-in.
+    fn stop_words(self) -> &'static [&'static str] {
+        match self {
+            Language::English => &["a", "an", "the", "of", "in", "on", "to", "t", "s"],
+            Language::French => &[
+                "le", "la", "les", "l", "un", "une", "des", "de", "du", "d", "et", "au", "aux",
+                "a", "en",
+            ],
+            Language::Spanish => &[
+                "el", "la", "los", "las", "un", "una", "unos", "unas", "de", "del", "y", "al",
+                "en",
+            ],
+            Language::German => &[
+                "der", "die", "das", "den", "dem", "des", "ein", "eine", "einen", "einem", "einer",
+                "und", "von", "zu", "im",
+            ],
+        }
+    }
+}
+
+/// How the texts are split into tags: the languages whose stop words are skipped and whether the
+/// tags are stemmed. The titles must be indexed with the tokenizer used to look them up.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Tokenizer {
+    languages: Vec<Language>,
+    stemming: bool,
+}
+
+impl Tokenizer {
+    /// Skip the English stop words, without stemming.
+    pub fn new() -> Tokenizer {
+        Tokenizer {
+            languages: vec![Language::English],
+            stemming: false,
+        }
+    }
+
+    /// Languages whose stop words are skipped.
+    pub fn languages(mut self, languages: &[Language]) -> Tokenizer {
+        self.languages = languages.to_vec();
+        self
+    }
+
+    /// Stem the tags lightly by dropping the plural `s`, so that "Ghost" matches "Ghosts".
+    pub fn stemming(mut self, stemming: bool) -> Tokenizer {
+        self.stemming = stemming;
+        self
+    }
+
+    fn ignored(&self, tag: &str) -> bool {
+        self.languages
+            .iter()
+            .any(|language| language.stop_words().contains(&tag))
+    }
+
+    /// Fold the text and split it into tags, skipping the stop words.
+    ///
+    /// The `tags` vector is cleared before being filled, so it can be reused between calls.
+    pub fn tags(&self, text: &str, tags: &mut Vec<String>) {
+        let text = fold(text);
+        tags.clear();
+        for tag in text.split(tag_splitter) {
+            if tag.is_empty() || self.ignored(tag) {
+                continue;
+            }
+            if self.stemming && tag.len() > 3 && tag.ends_with('s') && !tag.ends_with("ss") {
+                tags.push(tag[..tag.len() - 1].to_string());
+            } else {
+                tags.push(tag.to_string());
+            }
+        }
+        tags.dedup();
+    }
+}
+
+impl Default for Tokenizer {
+    fn default() -> Tokenizer {
+        Tokenizer::new()
+    }
+}
+
+/// Lowercase the text, decompose its characters and strip the diacritics, so that "Amélie"
+/// becomes "amelie".
+pub fn fold(text: &str) -> String {
+    text.nfkd()
+        .filter(|&c| !is_combining_mark(c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Articles moved to the end of the titles sorted in catalogs, such as "Matrix, The". The elided
+/// "l'" loses its apostrophe when the text is split, it is the "l" of "Auberge espagnole, L'".
+const CATALOG_ARTICLES: &[&str] = &[
+    "the", "a", "an", "le", "la", "les", "l", "el", "los", "las", "der", "die", "das", "il", "lo",
+    "gli",
+];
+
+/// Move the article of a folded text written in the catalog form back to the front, so that
+/// "matrix, the" becomes "the matrix". Returns `None` when the text does not end with an article
+/// or is only an article.
+pub fn uncatalog(text: &str) -> Option<String> {
+    let words: Vec<&str> = text.split(tag_splitter).filter(|w| !w.is_empty()).collect();
+    match words.split_last() {
+        Some((&"l", rest)) if !rest.is_empty() => Some(format!("l'{}", rest.join(" "))),
+        Some((article, rest)) if !rest.is_empty() && CATALOG_ARTICLES.contains(article) => {
+            Some(format!("{} {}", article, rest.join(" ")))
+        }
+        _ => None,
+    }
+}
+
+/// Tags shorter than this are not matched with a typo, too many short words are one edit apart.
+pub(crate) const FUZZY_MIN_LEN: usize = 4;
+
+/// Tell if the tags are at most one insertion, deletion, substitution or transposition apart.
+pub(crate) fn within_one_edit(a: &str, b: &str) -> bool {
+    let (short, long) = if a.len() <= b.len() {
+        (a.as_bytes(), b.as_bytes())
+    } else {
+        (b.as_bytes(), a.as_bytes())
+    };
+    if long.len() - short.len() > 1 {
+        return false;
+    }
+
+    let common = short.iter().zip(long).take_while(|&(x, y)| x == y).count();
+    if common == short.len() {
+        return true;
+    }
+    if short.len() < long.len() {
+        return short[common..] == long[common + 1..];
+    }
+    short[common + 1..] == long[common + 1..]
+        || (common + 1 < short.len()
+            && short[common] == long[common + 1]
+            && short[common + 1] == long[common]
+            && short[common + 2..] == long[common + 2..])
+}
+
+/// Fold the text and split it into tags with the default tokenizer, skipping the English stop
+/// words.
+///
+/// The `tags` vector is cleared before being filled, so it can be reused between calls.
+pub fn text_to_tags(text: &str, tags: &mut Vec<String>) {
+    Tokenizer::new().tags(text, tags)
+}
+
+/// Fold the text and split it into words like `text_to_tags`, keeping the stop words. Only the
+/// letters after an apostrophe that `text_to_tags` skips are left out, such as the `s` of
+/// "Schindler's", so that the words of a name without stop words are its tags.
+///
+/// The `words` vector is cleared before being filled, so it can be reused between calls.
+pub fn text_to_words(text: &str, words: &mut Vec<String>) {
+    let tokenizer = Tokenizer::new();
+    let text = fold(text);
+    words.clear();
+    let mut start = 0;
+    let mut elided = false;
+    for (idx, c) in text.char_indices().chain(Some((text.len(), ' '))) {
+        if !tag_splitter(c) {
+            continue;
+        }
+        let word = &text[start..idx];
+        if !word.is_empty() && !(elided && tokenizer.ignored(word)) {
+            words.push(word.to_string());
+        }
+        elided = c == '\'';
+        start = idx + c.len_utf8();
+    }
+}
+
+#[test]
+fn test_fold() {
+    assert_eq!(fold("Amélie"), "amelie");
+    assert_eq!(fold("Léon: The Professional"), "leon: the professional");
+    assert_eq!(fold("ﬁve"), "five");
+
+    let mut tags = vec![];
+    text_to_tags("Le Fabuleux Destin d'Amélie Poulain", &mut tags);
+    assert!(tags.contains(&"amelie".to_string()));
+}
+
+#[test]
+fn test_within_one_edit() {
+    assert!(within_one_edit("gladiattor", "gladiator"));
+    assert!(within_one_edit("gladaitor", "gladiator"));
+    assert!(within_one_edit("gladiatpr", "gladiator"));
+    assert!(within_one_edit("gladiator", "gladiators"));
+    assert!(!within_one_edit("gladiator", "radiator"));
+    assert!(!within_one_edit("gladiator", "gladiatorial"));
+}
+
+#[test]
+fn test_tokenizer() {
+    let mut tags = vec![];
+    let tokenizer = Tokenizer::new().languages(&[Language::English, Language::French]);
+    tokenizer.tags("Les Quatre Cents Coups", &mut tags);
+    assert_eq!(tags, vec!["quatre", "cents", "coups"]);
+
+    Tokenizer::new().stemming(true).tags("Ghosts of Mississippi", &mut tags);
+    assert_eq!(tags, vec!["ghost", "mississippi"]);
+}
+
+#[test]
+fn test_uncatalog() {
+    assert_eq!(uncatalog("matrix, the").as_ref().map(String::as_str), Some("the matrix"));
+    assert_eq!(uncatalog("miserables les").as_ref().map(String::as_str), Some("les miserables"));
+    assert_eq!(
+        uncatalog("auberge espagnole, l'").as_ref().map(String::as_str),
+        Some("l'auberge espagnole")
+    );
+    assert_eq!(uncatalog("the matrix"), None);
+    assert_eq!(uncatalog("the"), None);
+}
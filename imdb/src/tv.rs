@@ -0,0 +1,514 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs::{DirBuilder, File};
+use std::io::BufReader;
+use std::path::Path;
+
+use bincode;
+use flate2::{read::GzDecoder, write::GzEncoder};
+
+use error::Result;
+use format::TV_FORMAT;
+#[cfg(feature = "download")]
+use index::{dataset_client, download_file_if_missing};
+use index::{
+    open_dataset, parse_none, prefix_jaro, read_ratings, SRC_FILE_BASICS, SRC_FILE_RATINGS,
+};
+use options::{IndexOptions, DEFAULT_MIN_VOTES};
+use tags::{TagIndex, TagIndexBuilder};
+use tokenize::{fold, text_to_tags};
+use util::{Counter, NonNan};
+use verify;
+
+const SRC_FILE_EPISODES: &str = "title.episode.tsv.gz";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Series {
+    id: u32,
+    start_year: u16,
+    end_year: Option<u16>,
+    primary_title: String,
+    original_title: Option<String>,
+    votes: u32,
+}
+
+impl Series {
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    #[inline]
+    pub fn start_year(&self) -> i32 {
+        self.start_year as i32
+    }
+
+    #[inline]
+    pub fn end_year(&self) -> Option<i32> {
+        self.end_year.map(|year| year as i32)
+    }
+
+    #[inline]
+    pub fn primary_title(&self) -> &str {
+        &self.primary_title
+    }
+
+    #[inline]
+    pub fn original_title(&self) -> Option<&str> {
+        self.original_title.as_ref().map(|s| s.as_str())
+    }
+
+    #[inline]
+    pub fn votes(&self) -> u32 {
+        self.votes
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Episode {
+    id: u32,
+    series_id: u32,
+    season: u16,
+    episode: u16,
+    title: String,
+    year: Option<u16>,
+}
+
+impl Episode {
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    #[inline]
+    pub fn series_id(&self) -> u32 {
+        self.series_id
+    }
+
+    #[inline]
+    pub fn season(&self) -> i32 {
+        self.season as i32
+    }
+
+    #[inline]
+    pub fn episode(&self) -> i32 {
+        self.episode as i32
+    }
+
+    #[inline]
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    #[inline]
+    pub fn year(&self) -> Option<i32> {
+        self.year.map(|year| year as i32)
+    }
+}
+
+fn read_series(
+    path: impl AsRef<Path>,
+    ratings_table: &HashMap<u32, (f32, u32)>,
+) -> Result<HashMap<u32, Series>> {
+    let mut reader = open_dataset(path)?;
+
+    let mut series = HashMap::new();
+
+    for record in reader.records() {
+        let record = record?;
+
+        match &record[1] {
+            "tvSeries" | "tvMiniSeries" => {}
+            _ => continue,
+        }
+
+        if parse_none(&record[4]) != Some(0u8) {
+            continue;
+        }
+
+        let start_year = match parse_none(&record[5]) {
+            Some(year) => year,
+            None => continue,
+        };
+
+        let id = record[0][2..].parse()?;
+        // skip series with no votes
+        let votes = match ratings_table.get(&id) {
+            Some(&(_, votes)) => votes,
+            None => continue,
+        };
+
+        let primary_title = &record[2];
+        let original_title = &record[3];
+
+        series.insert(
+            id,
+            Series {
+                id,
+                start_year,
+                end_year: parse_none(&record[6]),
+                primary_title: primary_title.to_string(),
+                original_title: if primary_title != original_title {
+                    Some(original_title.to_string())
+                } else {
+                    None
+                },
+                votes,
+            },
+        );
+    }
+
+    series.shrink_to_fit();
+    Ok(series)
+}
+
+fn read_episodes(
+    episodes_path: impl AsRef<Path>,
+    basics_path: impl AsRef<Path>,
+    series: &HashMap<u32, Series>,
+) -> Result<HashMap<(u32, u16, u16), Episode>> {
+    let mut reader = open_dataset(episodes_path)?;
+
+    // episode id -> (series id, season, episode) of the episodes of the indexed series
+    let mut numbers = HashMap::new();
+
+    for record in reader.records() {
+        let record = record?;
+
+        let series_id: u32 = record[1][2..].parse()?;
+        if !series.contains_key(&series_id) {
+            continue;
+        }
+
+        let (season, episode) = match (parse_none(&record[2]), parse_none(&record[3])) {
+            (Some(season), Some(episode)) => (season, episode),
+            _ => continue,
+        };
+
+        let id: u32 = record[0][2..].parse()?;
+        numbers.insert(id, (series_id, season, episode));
+    }
+
+    // The episode titles are in the basics dataset.
+    let mut reader = open_dataset(basics_path)?;
+    let mut episodes = HashMap::new();
+
+    for record in reader.records() {
+        let record = record?;
+
+        if &record[1] != "tvEpisode" {
+            continue;
+        }
+
+        let id: u32 = record[0][2..].parse()?;
+        if let Some(&(series_id, season, episode)) = numbers.get(&id) {
+            episodes.insert(
+                (series_id, season, episode),
+                Episode {
+                    id,
+                    series_id,
+                    season,
+                    episode,
+                    title: record[2].to_string(),
+                    year: parse_none(&record[5]),
+                },
+            );
+        }
+    }
+
+    episodes.shrink_to_fit();
+    Ok(episodes)
+}
+
+fn build_reverse_index(series: &HashMap<u32, Series>) -> TagIndex {
+    let mut index = TagIndexBuilder::new();
+    let mut tags = Vec::new();
+
+    for series in series.values() {
+        let mut index_title = |text: &str| {
+            text_to_tags(&text, &mut tags);
+            for tag in tags.drain(..) {
+                index.add(tag, series.id());
+            }
+        };
+
+        index_title(series.primary_title());
+        if let Some(original_title) = series.original_title() {
+            index_title(&original_title);
+        }
+    }
+
+    index.build()
+}
+
+#[cfg(feature = "download")]
+fn check_source_files(index_dir: &Path, options: &IndexOptions) -> Result<()> {
+    let client = dataset_client(options)?;
+
+    download_file_if_missing(
+        &client,
+        &options.dataset_file_url(SRC_FILE_BASICS),
+        index_dir.join(SRC_FILE_BASICS),
+    )?;
+
+    download_file_if_missing(
+        &client,
+        &options.dataset_file_url(SRC_FILE_RATINGS),
+        index_dir.join(SRC_FILE_RATINGS),
+    )?;
+
+    download_file_if_missing(
+        &client,
+        &options.dataset_file_url(SRC_FILE_EPISODES),
+        index_dir.join(SRC_FILE_EPISODES),
+    )?;
+
+    Ok(())
+}
+
+/// An index of the IMDB TV series and their episodes, kept apart from the movie index. Like
+/// `Imdb`, it is `Send` and `Sync`.
+#[derive(Deserialize, Serialize)]
+pub struct Tv {
+    series: HashMap<u32, Series>,
+    episodes: HashMap<(u32, u16, u16), Episode>,
+    index: TagIndex,
+}
+
+impl Tv {
+    pub fn create_index(index_dir: &Path) -> Result<Tv> {
+        for name in [SRC_FILE_BASICS, SRC_FILE_RATINGS, SRC_FILE_EPISODES].iter() {
+            verify::check_dataset(&index_dir.join(name))?;
+        }
+        let ratings_table =
+            read_ratings(index_dir.join(SRC_FILE_RATINGS), DEFAULT_MIN_VOTES, &mut ())?;
+        let series = read_series(index_dir.join(SRC_FILE_BASICS), &ratings_table)?;
+        let episodes = read_episodes(
+            index_dir.join(SRC_FILE_EPISODES),
+            index_dir.join(SRC_FILE_BASICS),
+            &series,
+        )?;
+
+        let index = build_reverse_index(&series);
+        Ok(Tv {
+            series,
+            episodes,
+            index,
+        })
+    }
+
+    pub fn load_index(path: impl AsRef<Path>) -> Result<Tv> {
+        let mut file = BufReader::new(File::open(path)?);
+        TV_FORMAT.read_header(&mut file)?;
+        let decompressor = GzDecoder::new(file);
+        let tv: Tv = bincode::deserialize_from(decompressor)?;
+        Ok(tv)
+    }
+
+    pub fn load_or_create_index(index_dir: impl AsRef<Path>) -> Result<Tv> {
+        Tv::load_or_create_index_with_options(index_dir, &IndexOptions::new())
+    }
+
+    /// Like `load_or_create_index`, but the missing datasets are downloaded with the mirror and
+    /// the proxy of the options. With `IndexOptions::offline`, they are never downloaded.
+    pub fn load_or_create_index_with_options(
+        index_dir: impl AsRef<Path>,
+        options: &IndexOptions,
+    ) -> Result<Tv> {
+        let index_dir = index_dir.as_ref();
+        let index_path = index_dir.join("tv.gz");
+
+        DirBuilder::new().recursive(true).create(index_dir)?;
+        // Without the download feature, the source files must already be in the index directory.
+        #[cfg(feature = "download")]
+        {
+            if !options.get_offline() {
+                check_source_files(index_dir, options)?;
+            }
+        }
+        let _ = options;
+
+        Ok(match Tv::load_index(&index_path) {
+            Ok(tv) => tv,
+            Err(_) => {
+                let tv = Tv::create_index(index_dir)?;
+                tv.save(&index_path)?;
+                tv
+            }
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut file = File::create(path)?;
+        TV_FORMAT.write_header(&mut file)?;
+        let compressor = GzEncoder::new(file, Default::default());
+        bincode::serialize_into(compressor, self)?;
+        Ok(())
+    }
+
+    /// Find the series best matching the text, the most popular one wins close matches.
+    pub fn lookup_series(&self, text: &str) -> Option<&Series> {
+        let mut tags = Vec::new();
+        text_to_tags(&text, &mut tags);
+        let text = &fold(text);
+
+        let mut counter = Counter::new();
+        for tag in tags.into_iter() {
+            if let Some(series_ids) = self.index.get(&tag) {
+                for &series_id in series_ids.iter() {
+                    counter.add(series_id);
+                }
+            }
+        }
+
+        let mut matches: Vec<_> = counter
+            .most_common()
+            .into_iter()
+            .map(|series_id| {
+                let series = &self.series[series_id];
+                let mut score = prefix_jaro(&fold(series.primary_title()), text);
+                if let Some(original_title) = series.original_title() {
+                    score = f64::max(score, prefix_jaro(&fold(original_title), text));
+                }
+                (NonNan::new(score), series)
+            }).collect();
+
+        matches.sort_by_key(|&(score, _)| Reverse(score));
+
+        let best_score = match matches.first() {
+            Some(&(score, _)) => score,
+            None => return None,
+        };
+
+        // the best matches with 1% error margin are sorted by popularity
+        matches
+            .into_iter()
+            .take_while(|&(score, _)| (*best_score - *score).abs() <= 0.01)
+            .map(|(_, series)| series)
+            .max_by_key(|series| series.votes())
+    }
+
+    /// Find an episode by the name of its series and its season and episode numbers. Numbers out
+    /// of the range of the episodes of the datasets match nothing.
+    pub fn lookup_episode(
+        &self,
+        series_text: &str,
+        season: i32,
+        episode: i32,
+    ) -> Option<(&Series, &Episode)> {
+        let number = |n: i32| {
+            if n >= 0 && n <= i32::from(u16::MAX) {
+                Some(n as u16)
+            } else {
+                None
+            }
+        };
+        let (season, episode) = (number(season)?, number(episode)?);
+        let series = self.lookup_series(series_text)?;
+        let key = (series.id(), season, episode);
+        self.episodes.get(&key).map(|episode| (series, episode))
+    }
+
+    #[inline]
+    pub fn series(&self, id: u32) -> Option<&Series> {
+        self.series.get(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.series.len()
+    }
+}
+
+#[cfg(test)]
+fn test_tv(name: &str) -> Tv {
+    use flate2::write::GzEncoder;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::process;
+
+    let dir = env::temp_dir().join(format!("imdb-tv-{}-{}", name, process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, rows: &[&str]| {
+        let mut encoder = GzEncoder::new(Vec::new(), Default::default());
+        for row in rows {
+            writeln!(encoder, "{}", row).unwrap();
+        }
+        fs::write(dir.join(name), encoder.finish().unwrap()).unwrap();
+    };
+    write(
+        SRC_FILE_BASICS,
+        &[
+            "tconst\ttitleType\tprimaryTitle\toriginalTitle\tisAdult\tstartYear\tendYear",
+            "tt0306414\ttvSeries\tThe Wire\tThe Wire\t0\t2002\t2008",
+            "tt0903747\ttvSeries\tBreaking Bad\tBreaking Bad\t0\t2008\t2013",
+            "tt0000001\ttvSeries\tThe Wired\tThe Wired\t0\t2015\t\\N",
+            "tt0000002\tmovie\tThe Wire\tThe Wire\t0\t1990\t\\N",
+            "tt0749451\ttvEpisode\tThe Target\tThe Target\t0\t2002\t\\N",
+            "tt0959621\ttvEpisode\tPilot\tPilot\t0\t2008\t\\N",
+            "tt0000003\ttvEpisode\tSpecial\tSpecial\t0\t2009\t\\N",
+        ],
+    );
+    write(
+        SRC_FILE_RATINGS,
+        &[
+            "tconst\taverageRating\tnumVotes",
+            "tt0306414\t9.3\t300000",
+            "tt0903747\t9.5\t1900000",
+            "tt0000001\t5.0\t10",
+            "tt0000002\t5.0\t100",
+        ],
+    );
+    write(
+        SRC_FILE_EPISODES,
+        &[
+            "tconst\tparentTconst\tseasonNumber\tepisodeNumber",
+            "tt0749451\ttt0306414\t1\t1",
+            "tt0959621\ttt0903747\t1\t1",
+            // Out of the range of the numbers, the row is skipped.
+            "tt0000003\ttt0903747\t70000\t1",
+        ],
+    );
+
+    let ratings = read_ratings(dir.join(SRC_FILE_RATINGS), DEFAULT_MIN_VOTES, &mut ()).unwrap();
+    let series = read_series(dir.join(SRC_FILE_BASICS), &ratings).unwrap();
+    let episodes = read_episodes(
+        dir.join(SRC_FILE_EPISODES),
+        dir.join(SRC_FILE_BASICS),
+        &series,
+    ).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    let index = build_reverse_index(&series);
+    Tv {
+        series,
+        episodes,
+        index,
+    }
+}
+
+#[test]
+fn test_read_datasets() {
+    let tv = test_tv("read");
+    // The movie and the series with too few votes are not indexed.
+    assert_eq!(tv.len(), 2);
+    let wire = tv.series(306414).unwrap();
+    assert_eq!(wire.primary_title(), "The Wire");
+    assert_eq!((wire.start_year(), wire.end_year()), (2002, Some(2008)));
+    assert_eq!(tv.episodes.len(), 2);
+}
+
+#[test]
+fn test_lookup() {
+    let tv = test_tv("lookup");
+    assert_eq!(tv.lookup_series("the wire").map(|s| s.id()), Some(306414));
+    assert_eq!(tv.lookup_series("breaking bad").map(|s| s.id()), Some(903747));
+    assert!(tv.lookup_series("heat").is_none());
+
+    let (series, episode) = tv.lookup_episode("Breaking Bad", 1, 1).unwrap();
+    assert_eq!((series.id(), episode.title()), (903747, "Pilot"));
+    assert_eq!(episode.year(), Some(2008));
+    assert!(tv.lookup_episode("the wire", 1, 2).is_none());
+    // A season out of the range of the numbers is not truncated to season 4464.
+    assert!(tv.lookup_episode("breaking bad", 70000, 1).is_none());
+    assert!(tv.lookup_episode("breaking bad", -1, 1).is_none());
+}
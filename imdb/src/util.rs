@@ -2,6 +2,17 @@ use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::ops::Deref;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current year according to the system clock, close enough around new year.
+pub fn current_year() -> i32 {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    // average length of a year in the gregorian calendar
+    1970 + (secs / 31_556_952) as i32
+}
 
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
 pub struct NonNan(f64);
@@ -48,6 +59,11 @@ where
         }
     }
 
+    /// Forget the counts, keeping the memory allocated for the next keys.
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
     pub fn add(&mut self, key: K) {
         *self.inner.entry(key).or_insert(0) += 1;
     }
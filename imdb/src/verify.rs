@@ -0,0 +1,169 @@
+//! Integrity checks of the datasets, so that a truncated or corrupted download is found before it
+//! silently produces a tiny index.
+//!
+//! A dataset is verified once after it is downloaded: the gzip stream is decompressed to its end,
+//! which checks its CRC, and its rows are counted. The checksum of the compressed file is then
+//! saved next to it in a `.sum` file, and compared before the index is created.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+
+use error::{Error, Result};
+
+/// Rows under which a dataset is considered truncated, the smallest dataset has over a million.
+const MIN_ROWS: u64 = 100_000;
+
+/// What the verification of a dataset found.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Digest {
+    /// FNV-1a hash of the compressed file.
+    pub(crate) checksum: u64,
+    pub(crate) rows: u64,
+}
+
+/// Reader computing the FNV-1a hash of the bytes read through it.
+struct Hashing<R> {
+    inner: R,
+    hash: u64,
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        for &byte in &buf[..len] {
+            self.hash ^= u64::from(byte);
+            self.hash = self.hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        Ok(len)
+    }
+}
+
+fn hashing<R: Read>(inner: R) -> Hashing<R> {
+    Hashing {
+        inner,
+        hash: 0xcbf2_9ce4_8422_2325,
+    }
+}
+
+fn corrupted(path: &Path, reason: impl Into<String>) -> Error {
+    Error::Dataset {
+        file: path.display().to_string(),
+        reason: reason.into(),
+    }
+}
+
+/// Path of the file remembering the checksum and rows of a dataset.
+fn sum_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".sum");
+    path.with_file_name(name)
+}
+
+/// Decompress the dataset to its end and count its rows. A corrupted gzip stream or a dataset with
+/// too few rows is reported with `Error::Dataset`.
+pub(crate) fn verify(path: &Path) -> Result<Digest> {
+    let mut reader = hashing(File::open(path)?);
+    let mut newlines = 0;
+    {
+        let mut decoder = GzDecoder::new(&mut reader);
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let len = match decoder.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(corrupted(path, err.to_string())),
+            };
+            newlines += buf[..len].iter().filter(|&&b| b == b'\n').count() as u64;
+        }
+    }
+    // Hash the bytes left after the gzip stream, if any.
+    io::copy(&mut reader, &mut io::sink())?;
+
+    // The first row is the header.
+    let rows = newlines.saturating_sub(1);
+    if rows < MIN_ROWS {
+        return Err(corrupted(path, format!("only {} rows", rows)));
+    }
+    Ok(Digest {
+        checksum: reader.hash,
+        rows,
+    })
+}
+
+/// Save the digest of a verified dataset next to it.
+pub(crate) fn write_sum(path: &Path, digest: &Digest) -> Result<()> {
+    fs::write(
+        sum_path(path),
+        format!("{:016x} {}\n", digest.checksum, digest.rows),
+    )?;
+    Ok(())
+}
+
+/// Check that the dataset did not change since it was verified. A dataset never verified, such as
+/// one downloaded by an older version, is verified now.
+pub(crate) fn check_dataset(path: &Path) -> Result<()> {
+    let expected = match fs::read_to_string(sum_path(path)) {
+        Ok(text) => text
+            .split_whitespace()
+            .next()
+            .and_then(|checksum| u64::from_str_radix(checksum, 16).ok()),
+        Err(_) => None,
+    };
+    match expected {
+        Some(expected) => {
+            let mut reader = hashing(File::open(path)?);
+            io::copy(&mut reader, &mut io::sink())?;
+            if reader.hash != expected {
+                return Err(corrupted(path, "its checksum changed since it was downloaded"));
+            }
+            Ok(())
+        }
+        None => {
+            let digest = verify(path)?;
+            write_sum(path, &digest)
+        }
+    }
+}
+
+/// Remove a corrupted dataset and its checksum, so that it is downloaded again.
+#[cfg(feature = "download")]
+pub(crate) fn remove_dataset(path: &Path) -> Result<()> {
+    fs::remove_file(path)?;
+    let _ = fs::remove_file(sum_path(path));
+    Ok(())
+}
+
+#[test]
+fn test_verify() {
+    use flate2::write::GzEncoder;
+    use std::env;
+    use std::io::Write;
+    use std::process;
+
+    let path = env::temp_dir().join(format!("imdb-verify-{}.tsv.gz", process::id()));
+    let mut encoder = GzEncoder::new(Vec::new(), Default::default());
+    writeln!(encoder, "tconst\taverageRating\tnumVotes").unwrap();
+    for id in 0..MIN_ROWS {
+        writeln!(encoder, "tt{:07}\t7.0\t100", id).unwrap();
+    }
+    let bytes = encoder.finish().unwrap();
+
+    fs::write(&path, &bytes).unwrap();
+    let digest = verify(&path).unwrap();
+    assert_eq!(digest.rows, MIN_ROWS);
+    check_dataset(&path).unwrap();
+    assert!(sum_path(&path).exists());
+
+    // A truncated download.
+    fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+    match check_dataset(&path) {
+        Err(Error::Dataset { .. }) => {}
+        _ => panic!("the truncated dataset was not detected"),
+    }
+    fs::remove_file(sum_path(&path)).unwrap();
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,158 @@
+//! Subtitle archives, such as the `Subs.rar` or `Subs.zip` files released next to some movies.
+//! Their subtitles are extracted with the `unzip` and `unrar` programs into a `Subs` folder next
+//! to the archive, where the scan finds them.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use scan::{is_subtitle_name, FileExt};
+use vfs::File;
+
+/// Name of the folder the subtitles are extracted into.
+pub const SUBS_FOLDER: &str = "Subs";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Rar,
+}
+
+impl ArchiveKind {
+    fn from_file(file: &File) -> Option<ArchiveKind> {
+        match file.extension()?.to_lowercase().as_str() {
+            "zip" => Some(ArchiveKind::Zip),
+            "rar" => Some(ArchiveKind::Rar),
+            _ => None,
+        }
+    }
+
+    fn program(&self) -> &'static str {
+        match self {
+            ArchiveKind::Zip => "unzip",
+            ArchiveKind::Rar => "unrar",
+        }
+    }
+}
+
+/// An archive next to a movie, with the subtitles it contains.
+#[derive(Debug)]
+pub struct SubtitleArchive {
+    pub file: File,
+    pub kind: ArchiveKind,
+    /// Paths of the subtitles inside the archive.
+    pub subtitles: Vec<String>,
+}
+
+impl SubtitleArchive {
+    /// Folder the subtitles are extracted into.
+    pub fn dest(&self) -> PathBuf {
+        self.file.path().with_file_name(SUBS_FOLDER)
+    }
+
+    /// Extract the subtitles into the `Subs` folder, flattening the folders of the archive, and
+    /// return their paths.
+    pub fn extract(&self) -> io::Result<Vec<PathBuf>> {
+        let dest = self.dest();
+        fs::create_dir_all(&dest)?;
+        let mut command = Command::new(self.kind.program());
+        match self.kind {
+            ArchiveKind::Zip => {
+                command
+                    .args(&["-o", "-j", "-qq"])
+                    .arg(self.file.path())
+                    .args(&self.subtitles)
+                    .arg("-d")
+                    .arg(&dest);
+            }
+            ArchiveKind::Rar => {
+                command
+                    .args(&["e", "-o+", "-inul"])
+                    .arg(self.file.path())
+                    .args(&self.subtitles)
+                    .arg(format!("{}/", dest.display()));
+            }
+        }
+        let status = command.status()?;
+        if !status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("{} exited with {}", self.kind.program(), status),
+            ));
+        }
+        Ok(self
+            .subtitles
+            .iter()
+            .filter_map(|entry| Path::new(entry).file_name())
+            .map(|name| dest.join(name))
+            .collect())
+    }
+}
+
+/// Find the subtitle archives in folders holding a single movie, where the scan associates the
+/// subtitles of the `Subs` folder with the movie. Archives without subtitles are left out, as
+/// well as the archives that could not be listed, which are returned with the error.
+pub fn find(root: &File) -> (Vec<SubtitleArchive>, Vec<(File, io::Error)>) {
+    let mut archives = vec![];
+    let mut errors = vec![];
+
+    for file in root.descendants().filter(File::is_file) {
+        let kind = match ArchiveKind::from_file(&file) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        let movies = file.siblings().map(|s| s.filter(FileExt::is_video).count());
+        if movies != Some(1) {
+            continue;
+        }
+        match list(kind, file.path()) {
+            Ok(subtitles) => if !subtitles.is_empty() {
+                archives.push(SubtitleArchive {
+                    file,
+                    kind,
+                    subtitles,
+                })
+            },
+            Err(err) => errors.push((file, err)),
+        }
+    }
+
+    (archives, errors)
+}
+
+/// List the subtitles inside the archive.
+fn list(kind: ArchiveKind, path: &Path) -> io::Result<Vec<String>> {
+    let mut command = Command::new(kind.program());
+    match kind {
+        ArchiveKind::Zip => command.arg("-Z1").arg(path),
+        ArchiveKind::Rar => command.arg("lb").arg(path),
+    };
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(subtitle_entries(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Keep the subtitles of the listing of an archive, one path per line.
+fn subtitle_entries(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .map(|line| line.trim_right_matches('\r'))
+        .filter(|line| !line.ends_with('/') && is_subtitle_name(line))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[test]
+fn test_subtitle_entries() {
+    let listing = "Subs/\nSubs/English.srt\r\nSubs/French.srt\nHeat.nfo\nHeat.idx\nHeat.sub\n";
+    assert_eq!(
+        subtitle_entries(listing),
+        vec!["Subs/English.srt", "Subs/French.srt", "Heat.idx", "Heat.sub"]
+    );
+}
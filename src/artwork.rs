@@ -0,0 +1,168 @@
+use std::path::{Path, PathBuf};
+
+use failure::Error;
+use yansi::Paint;
+
+use rename::{format_base, Naming};
+use scan::ScanEntry;
+use tmdb::Tmdb;
+use vfs::File;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageKind {
+    Poster,
+    Backdrop,
+}
+
+impl ImageKind {
+    fn of(file: &File) -> ImageKind {
+        let stem = file.stem().trim_right_matches(|c: char| c.is_ascii_digit());
+        if stem.ends_with("backdrop") || stem.ends_with("fanart") {
+            ImageKind::Backdrop
+        } else {
+            ImageKind::Poster
+        }
+    }
+}
+
+/// Which images of the movies are downloaded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Only the images the movies lack.
+    MissingOnly,
+    /// All the images, the existing ones are replaced.
+    Refresh,
+}
+
+/// Name of the image of the movie with the naming preset, the same name the images found next to
+/// the movie are renamed to.
+pub fn image_name<'i>(entry: &ScanEntry<'i>, kind: ImageKind, naming: Naming) -> String {
+    match (naming, kind) {
+        (Naming::Default, ImageKind::Poster) => "poster.jpg".to_string(),
+        (Naming::Default, ImageKind::Backdrop) => "backdrop.jpg".to_string(),
+        (Naming::Kodi, ImageKind::Poster) => format!("{}-poster.jpg", format_base(entry)),
+        (Naming::Kodi, ImageKind::Backdrop) => format!("{}-fanart.jpg", format_base(entry)),
+    }
+}
+
+/// Path the image of the movie is downloaded to: the existing image of the kind, which is
+/// replaced, or the name of the image with the naming preset in the folder of the movie.
+pub fn image_dest<'i>(
+    entry: &ScanEntry<'i>,
+    dir: &Path,
+    kind: ImageKind,
+    naming: Naming,
+) -> PathBuf {
+    match entry.images.iter().find(|image| ImageKind::of(image) == kind) {
+        Some(image) => image.path().to_owned(),
+        None => dir.join(image_name(entry, kind, naming)),
+    }
+}
+
+/// The kinds of images to download for the movie.
+pub fn wanted_images<'i>(entry: &ScanEntry<'i>, mode: Mode) -> Vec<ImageKind> {
+    [ImageKind::Poster, ImageKind::Backdrop]
+        .iter()
+        .cloned()
+        .filter(|&kind| {
+            mode == Mode::Refresh || !entry.images.iter().any(|image| ImageKind::of(image) == kind)
+        }).collect()
+}
+
+/// Download the images of the movies organized in a folder of their own. Movies left at the root
+/// of the library have not been organized yet and are skipped.
+pub fn run<'i>(
+    root_path: &Path,
+    entries: &[ScanEntry<'i>],
+    naming: Naming,
+    mode: Mode,
+    tmdb: &Tmdb,
+) -> Result<(), Error> {
+    let mut downloaded = 0;
+
+    for entry in entries.iter() {
+        let dir = match entry.movie.parent() {
+            Some(ref dir) if dir.path() != root_path => dir.path().to_owned(),
+            _ => continue,
+        };
+        let kinds = wanted_images(entry, mode);
+        if kinds.is_empty() {
+            continue;
+        }
+
+        let images = match tmdb.find(entry.title.id()) {
+            Ok(Some(images)) => images,
+            Ok(None) => {
+                println!("{} {}: not found", Paint::yellow("warning:"), format_base(entry));
+                continue;
+            }
+            Err(err) => {
+                println!("{} {}: {}", Paint::yellow("warning:"), format_base(entry), err);
+                continue;
+            }
+        };
+
+        for kind in kinds {
+            let image_path = match kind {
+                ImageKind::Poster => images.poster_path.as_ref(),
+                ImageKind::Backdrop => images.backdrop_path.as_ref(),
+            };
+            if let Some(image_path) = image_path {
+                let dest = image_dest(entry, &dir, kind, naming);
+                if let Err(err) = tmdb.download_image(image_path, &dest) {
+                    let dest = dest.strip_prefix(root_path).unwrap();
+                    println!("{} {}: {}", Paint::yellow("warning:"), dest.display(), err);
+                    continue;
+                }
+                println!(
+                    "{}",
+                    Paint::green(dest.strip_prefix(root_path).unwrap().display())
+                );
+                downloaded += 1;
+            }
+        }
+    }
+
+    println!("Downloaded {} images.", downloaded);
+    Ok(())
+}
+
+#[test]
+fn test_wanted_images() {
+    use test_util::{listing, scan, test_index, GB};
+
+    let imdb = test_index();
+    let root = listing(
+        "/movies",
+        &[
+            ("Heat (1995)/", 0),
+            ("Heat (1995)/Heat (1995).mkv", GB),
+            ("Heat (1995)/Heat (1995)-poster.jpg", 1),
+        ],
+    );
+
+    let entries = scan(&root, &imdb);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(
+        wanted_images(&entries[0], Mode::MissingOnly),
+        vec![ImageKind::Backdrop]
+    );
+    assert_eq!(
+        wanted_images(&entries[0], Mode::Refresh),
+        vec![ImageKind::Poster, ImageKind::Backdrop]
+    );
+    assert_eq!(
+        image_name(&entries[0], ImageKind::Backdrop, Naming::Kodi),
+        "Heat (1995)-fanart.jpg"
+    );
+    // The images refreshed replace the existing ones.
+    let dir = Path::new("/movies/Heat (1995)");
+    assert_eq!(
+        image_dest(&entries[0], dir, ImageKind::Poster, Naming::Default),
+        dir.join("Heat (1995)-poster.jpg")
+    );
+    assert_eq!(
+        image_dest(&entries[0], dir, ImageKind::Backdrop, Naming::Default),
+        dir.join("backdrop.jpg")
+    );
+}
@@ -0,0 +1,78 @@
+use std::collections::{HashMap, HashSet};
+
+use failure::Error;
+
+use rename::Category;
+
+/// Parse a size such as `500K`, `10M` or `5G` into bytes.
+fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let (digits, multiplier) = match text.chars().last()?.to_ascii_uppercase() {
+        'K' => (&text[..text.len() - 1], 1024),
+        'M' => (&text[..text.len() - 1], 1024 * 1024),
+        'G' => (&text[..text.len() - 1], 1024 * 1024 * 1024),
+        _ => (text, 1),
+    };
+    digits.parse::<u64>().ok().map(|size| size * multiplier)
+}
+
+/// Size limits on the companion files kept along with a movie, wherever it is moved.
+pub struct Budget {
+    limits: HashMap<Category, u64>,
+    exempt: HashSet<String>,
+}
+
+impl Budget {
+    pub fn new() -> Budget {
+        Budget {
+            limits: HashMap::new(),
+            exempt: HashSet::new(),
+        }
+    }
+
+    /// Add a limit written as `category=size`, such as `images=50M`.
+    pub fn add_limit(&mut self, text: &str) -> Result<(), Error> {
+        let mut parts = text.splitn(2, '=');
+        let category = match parts.next().unwrap_or("") {
+            "images" => Category::Image,
+            "subtitles" => Category::Subtitle,
+            "audio" => Category::Audio,
+            other => bail!("unknown budget category: {}", other),
+        };
+        let size = match parts.next().and_then(parse_size) {
+            Some(size) => size,
+            None => bail!("invalid budget size: {}", text),
+        };
+        self.limits.insert(category, size);
+        Ok(())
+    }
+
+    /// Exempt the movie whose folder has the given name, such as `Heat (1995)`, from the limits.
+    pub fn add_exempt(&mut self, name: impl Into<String>) {
+        self.exempt.insert(name.into());
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.limits.is_empty()
+    }
+
+    #[inline]
+    pub fn is_exempt(&self, name: &str) -> bool {
+        self.exempt.contains(name)
+    }
+
+    #[inline]
+    pub fn limit(&self, category: Category) -> Option<u64> {
+        self.limits.get(&category).cloned()
+    }
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("512"), Some(512));
+    assert_eq!(parse_size("10k"), Some(10 * 1024));
+    assert_eq!(parse_size("5G"), Some(5 * 1024 * 1024 * 1024));
+    assert_eq!(parse_size("G"), None);
+    assert_eq!(parse_size("lots"), None);
+}
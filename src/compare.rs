@@ -0,0 +1,234 @@
+//! Comparison of two libraries, such as a friend's collection before merging it into ours or two
+//! disks before consolidating them.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use failure::Error;
+use ffprobe::{self, Hdr};
+use yansi::Paint;
+
+use parse::parse_resolution;
+use rename::format_base;
+use scan::ScanEntry;
+use stats::{format_bit_rate, format_bytes};
+
+/// Copies of a movie whose sizes, or video bit rates, are within this fraction of each other are
+/// of the same quality.
+const SIZE_TOLERANCE: f64 = 0.1;
+
+/// What tells two copies of a movie apart.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quality {
+    /// Height of the video, probed with ffprobe or read from the file name.
+    pub height: Option<u32>,
+    /// Kind of HDR of the video, only known when probed.
+    pub hdr: Option<Hdr>,
+    /// Bit rate of the video in bits per second, only known when probed.
+    pub bit_rate: Option<u64>,
+    pub size: u64,
+}
+
+impl Quality {
+    /// Get the quality of the movie, its height is read from the file name when ffprobe cannot
+    /// read the file.
+    fn of(entry: &ScanEntry) -> Quality {
+        let info = ffprobe::scan(entry.movie.path()).ok();
+        let video = info.as_ref().and_then(|info| info.video.first());
+        Quality {
+            height: video
+                .map(|video| video.height)
+                .or_else(|| parse_resolution(entry.movie.name())),
+            hdr: video.and_then(|video| video.hdr()),
+            bit_rate: info.as_ref().and_then(|info| info.video_bit_rate()),
+            size: entry.movie.len(),
+        }
+    }
+
+    /// The video bit rates of both copies when they are known, or else their sizes. The bit rate
+    /// tells the better encode apart, the size also counts the audio and subtitle streams.
+    fn rates(&self, other: &Quality) -> (u64, u64) {
+        match (self.bit_rate, other.bit_rate) {
+            (Some(a), Some(b)) => (a, b),
+            _ => (self.size, other.size),
+        }
+    }
+
+    /// Tell if the copies are of the same quality: the same height and dynamic range, and about
+    /// the same video bit rate or size.
+    fn same(&self, other: &Quality) -> bool {
+        let (a, b) = self.rates(other);
+        let (small, large) = if a < b { (a, b) } else { (b, a) };
+        self.height == other.height
+            && self.hdr.is_some() == other.hdr.is_some()
+            && (large - small) as f64 <= large as f64 * SIZE_TOLERANCE
+    }
+
+    /// Tell if this copy is better than the other one: taller, or HDR at the same height, or of a
+    /// higher video bit rate or larger at the same height and dynamic range.
+    fn better(&self, other: &Quality) -> bool {
+        let (a, b) = self.rates(other);
+        (self.height, self.hdr.is_some(), a) > (other.height, other.hdr.is_some(), b)
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
+        match self.height {
+            Some(height) => write!(w, "{}p", height)?,
+            None => write!(w, "unknown resolution")?,
+        }
+        if let Some(hdr) = self.hdr {
+            write!(w, " {}", hdr)?;
+        }
+        if let Some(bit_rate) = self.bit_rate {
+            write!(w, " {}", format_bit_rate(bit_rate))?;
+        }
+        write!(w, ", {}", format_bytes(self.size))
+    }
+}
+
+/// The movies of two libraries matched by title.
+pub struct Comparison<'a, 'i: 'a> {
+    pub only_a: Vec<&'a ScanEntry<'i>>,
+    pub only_b: Vec<&'a ScanEntry<'i>>,
+    pub shared: Vec<(&'a ScanEntry<'i>, &'a ScanEntry<'i>)>,
+}
+
+/// Match the movies of the two libraries by title, each list is sorted by name. Only the first
+/// copy of a title is kept when a library has several.
+pub fn compare<'a, 'i>(a: &'a [ScanEntry<'i>], b: &'a [ScanEntry<'i>]) -> Comparison<'a, 'i> {
+    let by_title = |entries: &'a [ScanEntry<'i>]| {
+        let mut by_title: HashMap<u32, &'a ScanEntry<'i>> = HashMap::new();
+        for entry in entries.iter() {
+            by_title.entry(entry.title.id()).or_insert(entry);
+        }
+        by_title
+    };
+    let (a, b) = (by_title(a), by_title(b));
+
+    let mut comparison = Comparison {
+        only_a: a.values().filter(|e| !b.contains_key(&e.title.id())).cloned().collect(),
+        only_b: b.values().filter(|e| !a.contains_key(&e.title.id())).cloned().collect(),
+        shared: a
+            .values()
+            .filter_map(|e| b.get(&e.title.id()).map(|other| (*e, *other)))
+            .collect(),
+    };
+    comparison.only_a.sort_by_key(|e| format_base(e));
+    comparison.only_b.sort_by_key(|e| format_base(e));
+    comparison.shared.sort_by_key(|&(e, _)| format_base(e));
+    comparison
+}
+
+/// Report the movies found in only one of the libraries, and the movies found in both whose
+/// copies differ in quality. The shared movies are probed with ffprobe.
+pub fn run<'i>(
+    a_path: &Path,
+    a: &[ScanEntry<'i>],
+    b_path: &Path,
+    b: &[ScanEntry<'i>],
+) -> Result<(), Error> {
+    let comparison = compare(a, b);
+
+    for &(path, ref only) in [(a_path, &comparison.only_a), (b_path, &comparison.only_b)].iter() {
+        println!("Only in {} ({}):", path.display(), only.len());
+        for entry in only.iter() {
+            println!("\t{} ({})", format_base(entry), format_bytes(entry.movie.len()));
+        }
+        println!();
+    }
+
+    println!("In both with a different quality:");
+    let mut different = 0;
+    for &(a, b) in comparison.shared.iter() {
+        let (qa, qb) = (Quality::of(a), Quality::of(b));
+        if qa.same(&qb) {
+            continue;
+        }
+        different += 1;
+        let paint = |quality: &Quality, other: &Quality| {
+            if quality.better(other) {
+                Paint::green(quality.to_string())
+            } else {
+                Paint::red(quality.to_string())
+            }
+        };
+        println!("\t{}: {} | {}", format_base(a), paint(&qa, &qb), paint(&qb, &qa));
+    }
+    println!();
+
+    println!(
+        "{} movies in both, {} with a different quality.",
+        comparison.shared.len(),
+        different
+    );
+    Ok(())
+}
+
+#[test]
+fn test_compare() {
+    use test_util::{listing, scan, test_index, GB};
+
+    let imdb = test_index();
+    let ours = listing(
+        "/ours",
+        &[
+            ("Heat.1995.1080p.mkv", 8 * GB),
+            ("The.Matrix.1999.720p.mkv", 4 * GB),
+        ],
+    );
+    let theirs = listing(
+        "/theirs",
+        &[
+            ("The.Matrix.1999.2160p.mkv", 20 * GB),
+            ("Inception.2010.1080p.mkv", 9 * GB),
+        ],
+    );
+    let ours = scan(&ours, &imdb);
+    let theirs = scan(&theirs, &imdb);
+
+    let comparison = compare(&ours, &theirs);
+    assert_eq!(comparison.only_a[0].title.id(), 113277);
+    assert_eq!(comparison.only_b[0].title.id(), 1375666);
+    assert_eq!(comparison.shared.len(), 1);
+
+    let (a, b) = comparison.shared[0];
+    let (qa, qb) = (Quality::of(a), Quality::of(b));
+    assert_eq!(qa.height, Some(720));
+    assert!(!qa.same(&qb));
+    assert!(qb.better(&qa));
+
+    // An HDR copy beats a larger SDR copy of the same height.
+    let sdr = Quality {
+        height: Some(2160),
+        hdr: None,
+        bit_rate: None,
+        size: 60 * GB,
+    };
+    let hdr = Quality {
+        hdr: Some(Hdr::Hdr10),
+        size: 58 * GB,
+        ..sdr
+    };
+    assert!(!hdr.same(&sdr));
+    assert!(hdr.better(&sdr));
+    assert_eq!(hdr.to_string(), format!("2160p HDR10, {}", format_bytes(58 * GB)));
+
+    // The encode of the higher video bit rate beats a larger copy of the same height.
+    let remux = Quality {
+        height: Some(1080),
+        hdr: None,
+        bit_rate: Some(30_000_000),
+        size: 25 * GB,
+    };
+    let encode = Quality {
+        bit_rate: Some(8_200_000),
+        size: 28 * GB,
+        ..remux
+    };
+    assert!(!remux.same(&encode));
+    assert!(remux.better(&encode));
+    assert_eq!(encode.to_string(), format!("1080p 8.2 Mb/s, {}", format_bytes(28 * GB)));
+}
@@ -0,0 +1,75 @@
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Owner given to the files and directories created, read from the `PUID` and `PGID` environment
+/// variables commonly used by container images.
+#[derive(Copy, Clone, Debug)]
+pub struct Owner {
+    uid: u32,
+    gid: u32,
+}
+
+impl Owner {
+    pub fn from_env() -> Option<Owner> {
+        let uid = env::var("PUID").ok()?.parse().ok()?;
+        let gid = env::var("PGID").ok()?.parse().ok()?;
+        Some(Owner { uid, gid })
+    }
+
+    #[cfg(unix)]
+    pub fn apply(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        ::std::os::unix::fs::chown(path, Some(self.uid), Some(self.gid))
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply(&self, _path: impl AsRef<Path>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Maps the paths seen inside a container to the paths on the host, so that the paths displayed
+/// make sense outside of the container.
+#[derive(Clone, Debug)]
+pub struct PathMap {
+    container: PathBuf,
+    host: PathBuf,
+}
+
+impl PathMap {
+    /// Parse a mapping written as `container_path=host_path`.
+    pub fn parse(text: &str) -> Option<PathMap> {
+        let mut parts = text.splitn(2, '=');
+        let container = parts.next()?;
+        let host = parts.next()?;
+        if container.is_empty() || host.is_empty() {
+            return None;
+        }
+        Some(PathMap {
+            container: container.into(),
+            host: host.into(),
+        })
+    }
+
+    pub fn from_env() -> Option<PathMap> {
+        PathMap::parse(&env::var("MERO_PATH_MAP").ok()?)
+    }
+
+    pub fn map(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.container) {
+            Ok(rest) => self.host.join(rest),
+            Err(_) => path.to_owned(),
+        }
+    }
+}
+
+#[test]
+fn test_path_map() {
+    let map = PathMap::parse("/movies=/mnt/storage/movies").unwrap();
+    assert_eq!(
+        map.map(Path::new("/movies/Heat (1995)/Heat (1995).mkv")),
+        Path::new("/mnt/storage/movies/Heat (1995)/Heat (1995).mkv")
+    );
+    assert_eq!(map.map(Path::new("/other/file.mkv")), Path::new("/other/file.mkv"));
+    assert!(PathMap::parse("/movies").is_none());
+}
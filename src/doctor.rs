@@ -0,0 +1,327 @@
+use std::env;
+use std::fs;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use ffprobe::{self, Ffprobe, Mediainfo};
+use yansi::Paint;
+
+use imdb::{IndexOptions, Language, TitleKind, MAPPED_INDEX};
+
+use budget::Budget;
+use container::PathMap;
+use rename::Naming;
+use route::Routes;
+use util;
+use {parse_kind_weight, App};
+
+/// Free space under which the library root is reported, renames across devices need room.
+const MIN_FREE_SPACE: u64 = 1024 * 1024 * 1024;
+/// Age after which the index is reported as old.
+const INDEX_MAX_AGE: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+    hint: Option<&'static str>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Check {
+        Check {
+            name,
+            status: Status::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, hint: &'static str) -> Check {
+        Check {
+            name,
+            status: Status::Warn,
+            detail: detail.into(),
+            hint: Some(hint),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: &'static str) -> Check {
+        Check {
+            name,
+            status: Status::Fail,
+            detail: detail.into(),
+            hint: Some(hint),
+        }
+    }
+
+    fn print(&self) {
+        let status = match self.status {
+            Status::Pass => Paint::green("pass"),
+            Status::Warn => Paint::yellow("warn"),
+            Status::Fail => Paint::red("fail"),
+        };
+        println!("[{}] {}: {}", status, self.name, self.detail);
+        if let Some(hint) = self.hint {
+            println!("       {}", hint);
+        }
+    }
+}
+
+/// Get the first line printed by `<program> -version`.
+fn program_version(program: &str) -> Option<String> {
+    let output = Command::new(program).arg("-version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().next().map(|line| line.to_string())
+}
+
+/// Check a program only needed by some commands, a missing program is a warning.
+fn check_optional_program(name: &'static str, hint: &'static str) -> Check {
+    match program_version(name) {
+        Some(version) => Check::pass(name, version),
+        None => Check::warn(name, "not found", hint),
+    }
+}
+
+fn check_ffprobe() -> Check {
+    let name = "ffprobe";
+    let ffprobe = Ffprobe::default();
+    match ffprobe.version() {
+        Ok(version) => Check::pass(name, format!("{} at {}", version, ffprobe.program().display())),
+        Err(ffprobe::Error::NotFound(program)) => {
+            let mediainfo = Mediainfo::default();
+            match mediainfo.version() {
+                Ok(version) => Check::warn(
+                    name,
+                    format!(
+                        "{} not found, using mediainfo {} at {}",
+                        program.display(),
+                        version,
+                        mediainfo.program().display()
+                    ),
+                    "Install ffmpeg for the complete probes, or set FFPROBE_PATH to its path.",
+                ),
+                Err(_) => Check::fail(
+                    name,
+                    format!("{} not found", program.display()),
+                    "Install ffmpeg, it provides ffprobe, or set FFPROBE_PATH to its path. \
+                     mediainfo is used instead when installed.",
+                ),
+            }
+        }
+        Err(err) => Check::fail(name, err.to_string(), "Check that ffprobe runs."),
+    }
+}
+
+fn check_index(index_dir: &Path) -> Check {
+    let name = "index";
+    // The mapped index is loaded instead of the plain one when both exist.
+    let modified = [MAPPED_INDEX, "index.gz"]
+        .iter()
+        .filter_map(|file| fs::metadata(index_dir.join(file)).and_then(|m| m.modified()).ok())
+        .next();
+    let modified = match modified {
+        Some(modified) => modified,
+        None => {
+            return Check::warn(
+                name,
+                "missing",
+                "The index is created from the IMDB datasets on the next run.",
+            )
+        }
+    };
+
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default();
+    let days = age.as_secs() / (24 * 60 * 60);
+    if age > INDEX_MAX_AGE {
+        Check::warn(
+            name,
+            format!("created {} days ago", days),
+            "Refresh the datasets with --dataset-max-age to match recent movies.",
+        )
+    } else {
+        Check::pass(name, format!("created {} days ago", days))
+    }
+}
+
+fn check_config(args: &App) -> Check {
+    let name = "config";
+    let hint = "Fix the value of the option or environment variable.";
+
+    if Naming::parse(&args.naming).is_none() {
+        return Check::fail(name, format!("unknown naming preset: {}", args.naming), hint);
+    }
+    let mut budget = Budget::new();
+    for limit in args.budget.iter() {
+        if let Err(err) = budget.add_limit(limit) {
+            return Check::fail(name, err.to_string(), hint);
+        }
+    }
+    for kind in args.kinds.iter().chain(args.exclude_kinds.iter()) {
+        if TitleKind::parse(kind).is_none() {
+            return Check::fail(name, format!("unknown title kind: {}", kind), hint);
+        }
+    }
+    for text in args.kind_weights.iter() {
+        if parse_kind_weight(text).is_none() {
+            return Check::fail(name, format!("invalid kind weight: {}", text), hint);
+        }
+    }
+    for language in args.stop_words.iter() {
+        if Language::parse(language).is_none() {
+            return Check::fail(name, format!("unknown language: {}", language), hint);
+        }
+    }
+    if args.min_score < 0.0 || args.min_score > 1.0 {
+        let detail = format!("minimum score not within 0 and 1: {}", args.min_score);
+        return Check::fail(name, detail, hint);
+    }
+    let mut routes = Routes::new();
+    for text in args.routes.iter() {
+        if let Err(err) = routes.add(text) {
+            return Check::fail(name, err.to_string(), hint);
+        }
+    }
+    let path_map = args
+        .path_map
+        .clone()
+        .or_else(|| env::var("MERO_PATH_MAP").ok());
+    if let Some(ref text) = path_map {
+        if PathMap::parse(text).is_none() {
+            return Check::fail(name, format!("invalid path map: {}", text), hint);
+        }
+    }
+    for var in &["PUID", "PGID"] {
+        if let Ok(value) = env::var(var) {
+            if value.parse::<u32>().is_err() {
+                return Check::fail(name, format!("invalid {}: {}", var, value), hint);
+            }
+        }
+    }
+
+    Check::pass(name, "valid")
+}
+
+fn check_writable(root: &Path) -> Check {
+    let name = "permissions";
+    match util::check_writable(root) {
+        Ok(()) => Check::pass(name, format!("{} is writable", root.display())),
+        Err(err) => Check::fail(
+            name,
+            format!("cannot write to {}: {}", root.display(), err),
+            "Run as a user allowed to write to the library, or set PUID and PGID, or use --link.",
+        ),
+    }
+}
+
+fn check_free_space(root: &Path) -> Check {
+    let name = "disk space";
+    match util::free_space(root) {
+        Some(free) if free < MIN_FREE_SPACE => Check::warn(
+            name,
+            format!("{} MB free", free / (1024 * 1024)),
+            "Movies renamed to another device are copied, free some space first.",
+        ),
+        Some(free) => Check::pass(name, format!("{} MB free", free / (1024 * 1024))),
+        None => Check::warn(name, "unknown", "The free space could not be read with df."),
+    }
+}
+
+/// Get the host and port of an `http` or `https` URL.
+fn url_host(url: &str) -> Option<(&str, u16)> {
+    let (rest, default_port) = if url.starts_with("https://") {
+        (&url[8..], 443)
+    } else if url.starts_with("http://") {
+        (&url[7..], 80)
+    } else {
+        return None;
+    };
+    let authority = rest.split('/').next()?;
+    match authority.rfind(':') {
+        Some(idx) => Some((&authority[..idx], authority[idx + 1..].parse().ok()?)),
+        None => Some((authority, default_port)),
+    }
+}
+
+/// Check that the host the datasets are downloaded from, the proxy or the dataset host, is reachable.
+fn check_network(offline: bool, url: &str) -> Check {
+    let name = "network";
+    if offline {
+        return Check::pass(name, "not used offline");
+    }
+    let hint = "The IMDB datasets cannot be downloaded, check the connection or the proxy.";
+    let (host, port) = match url_host(url) {
+        Some(host) => host,
+        None => {
+            return Check::fail(
+                name,
+                format!("invalid URL: {}", url),
+                "Use an http or https URL.",
+            )
+        }
+    };
+    let addr = match (host, port).to_socket_addrs().ok().and_then(|mut a| a.next()) {
+        Some(addr) => addr,
+        None => return Check::fail(name, format!("cannot resolve {}", host), hint),
+    };
+    match TcpStream::connect_timeout(&addr, Duration::from_secs(5)) {
+        Ok(_) => Check::pass(name, format!("{} is reachable", host)),
+        Err(err) => Check::fail(name, format!("{}: {}", host, err), hint),
+    }
+}
+
+/// Check the environment and print the results, returns true if no check failed.
+pub fn run(args: &App, root: &Path, index_dir: &Path) -> bool {
+    let defaults = IndexOptions::new();
+    let url = args
+        .proxy
+        .as_ref()
+        .map(|url| url.as_str())
+        .or_else(|| defaults.get_proxy())
+        .or_else(|| args.dataset_url.as_ref().map(|url| url.as_str()))
+        .unwrap_or_else(|| defaults.get_dataset_url())
+        .to_string();
+    let checks = vec![
+        check_ffprobe(),
+        check_optional_program("ffmpeg", "Install ffmpeg to verify the movies with `verify`."),
+        check_index(index_dir),
+        check_config(args),
+        check_writable(root),
+        check_free_space(root),
+        check_network(args.offline, &url),
+    ];
+
+    for check in checks.iter() {
+        check.print();
+    }
+
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    println!();
+    if failed > 0 {
+        println!("{} of {} checks failed.", failed, checks.len());
+    } else {
+        println!("All checks passed.");
+    }
+    failed == 0
+}
+
+#[test]
+fn test_url_host() {
+    assert_eq!(url_host("https://datasets.imdbws.com"), Some(("datasets.imdbws.com", 443)));
+    assert_eq!(url_host("http://mirror.local:8080/imdb"), Some(("mirror.local", 8080)));
+    assert_eq!(url_host("ftp://mirror.local"), None);
+}
@@ -0,0 +1,252 @@
+//! End-to-end tests running the scan, rename and cleanup steps against synthetic libraries built
+//! in temporary directories, using a small in-memory index instead of the IMDB datasets.
+
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use imdb::{Imdb, Title, TitleKind};
+
+use fault::{self, Fault, Op};
+use plan::{self, Local, Planner};
+use rename::{Cleaner, Naming, Renames};
+use scan::Scanner;
+use vfs;
+
+static NEXT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// A temporary directory removed when dropped.
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn new() -> TempDir {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let path = env::temp_dir().join(format!("mero-e2e-{}-{}", process::id(), id));
+        let _ = fs::remove_dir_all(&path);
+        DirBuilder::new().recursive(true).create(&path).unwrap();
+        TempDir {
+            path: fs::canonicalize(path).unwrap(),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Create the files at the given relative paths, along with their parent directories.
+    fn create(&self, files: &[&str]) {
+        for file in files {
+            let path = self.path.join(file);
+            DirBuilder::new()
+                .recursive(true)
+                .create(path.parent().unwrap())
+                .unwrap();
+            fs::write(path, b"").unwrap();
+        }
+    }
+
+    /// List the files under the directory as sorted relative paths.
+    fn files(&self) -> Vec<String> {
+        let root = vfs::walk(&self.path).unwrap();
+        let mut files: Vec<String> = root
+            .descendants()
+            .filter(|f| f.is_file())
+            .map(|f| {
+                let path = f.path().strip_prefix(&self.path).unwrap();
+                path.to_string_lossy().replace('\\', "/")
+            }).collect();
+        files.sort();
+        files
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+fn test_index() -> Imdb {
+    Imdb::from_titles(vec![
+        Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000),
+        Title::new(83987, "Heat", 1986, TitleKind::Movie, 5000),
+        Title::new(133093, "The Matrix", 1999, TitleKind::Movie, 1700000),
+        Title::new(1375666, "Inception", 2010, TitleKind::Movie, 2000000),
+    ])
+}
+
+/// Run the pipeline of the command line without any confirmation: apply the plan of the movies,
+/// then remove the unused files and the empty directories. Nothing changes between the scan and
+/// the apply, so nothing drifts.
+fn organize(root_path: &Path, imdb: &Imdb, naming: Naming) {
+    let root = vfs::walk(root_path).unwrap();
+    let entries = Scanner::new(&root, imdb).scan_root().unwrap();
+    let planner = Planner::new(naming);
+    let target = Local::new(None);
+    let mut cleaner = Cleaner::new();
+
+    for entry in entries.iter() {
+        cleaner.mark(entry);
+        let planned = planner.entry(entry, root_path, false);
+        assert_eq!(plan::apply(&target, &planned.renames, &mut ()).unwrap(), None);
+    }
+
+    for file in plan::unused_files(&root, &cleaner) {
+        assert_eq!(plan::remove(&target, &file, &mut ()).unwrap(), None);
+    }
+    plan::remove_empty_dirs(&target, &root);
+}
+
+#[test]
+fn test_organize_release_names() {
+    let dir = TempDir::new();
+    dir.create(&[
+        "Heat.1995.1080p.BluRay.x264-GRP/Heat.1995.1080p.BluRay.x264-GRP.mkv",
+        "Heat.1995.1080p.BluRay.x264-GRP/Heat.1995.1080p.BluRay.x264-GRP.nfo",
+        "Heat.1995.1080p.BluRay.x264-GRP/Sample/heat.sample.mkv",
+        "Heat.1995.1080p.BluRay.x264-GRP/Subs/English.srt",
+        "The.Matrix.1999.720p.WEB-DL.mp4",
+        "The.Matrix.1999.720p.WEB-DL.en.srt",
+        "[Group] Inception (2010) [1080p]/Inception (2010) [1080p].mkv",
+        "[Group] Inception (2010) [1080p]/poster.jpg",
+        "[Group] Inception (2010) [1080p]/RARBG.txt",
+    ]);
+
+    organize(dir.path(), &test_index(), Naming::Default);
+
+    assert_eq!(
+        dir.files(),
+        vec![
+            "Heat (1995)/Heat (1995).mkv",
+            "Heat (1995)/Heat (1995).srt",
+            "Inception (2010)/Inception (2010).mkv",
+            "Inception (2010)/poster.jpg",
+            "The Matrix (1999)/The Matrix (1999).en.srt",
+            "The Matrix (1999)/The Matrix (1999).mp4",
+        ],
+    );
+}
+
+#[test]
+fn test_organize_is_idempotent() {
+    let dir = TempDir::new();
+    dir.create(&["heat.1986.dvdrip.avi", "Heat.1995.mkv", "Heat.1995.srt"]);
+
+    let imdb = test_index();
+    organize(dir.path(), &imdb, Naming::Kodi);
+    let first = dir.files();
+    organize(dir.path(), &imdb, Naming::Kodi);
+
+    assert_eq!(dir.files(), first);
+    assert_eq!(
+        first,
+        vec![
+            "Heat (1986)/Heat (1986).avi",
+            "Heat (1995)/Heat (1995).mkv",
+            "Heat (1995)/Heat (1995).srt",
+        ],
+    );
+}
+
+#[test]
+fn test_apply_cross_device_fallback() {
+    let dir = TempDir::new();
+    dir.create(&["The.Matrix.1999.mkv", "The.Matrix.1999.srt"]);
+
+    // The first rename fails as if the library was on another device, the file is copied.
+    fault::inject(Op::Rename, 1, Fault::CrossDevice);
+    organize(dir.path(), &test_index(), Naming::Default);
+
+    assert_eq!(
+        dir.files(),
+        vec![
+            "The Matrix (1999)/The Matrix (1999).mkv",
+            "The Matrix (1999)/The Matrix (1999).srt",
+        ],
+    );
+}
+
+#[test]
+fn test_apply_retries_transient_failures() {
+    let dir = TempDir::new();
+    dir.create(&["Inception.2010.mkv"]);
+
+    // The share goes away for the first two attempts of the rename.
+    fault::inject(Op::Rename, 1, Fault::Stale);
+    fault::inject(Op::Rename, 2, Fault::Io);
+    organize(dir.path(), &test_index(), Naming::Default);
+
+    assert_eq!(dir.files(), vec!["Inception (2010)/Inception (2010).mkv"]);
+}
+
+#[test]
+fn test_apply_failure_keeps_original() {
+    let dir = TempDir::new();
+    dir.create(&["Inception.2010.mkv"]);
+
+    let root = vfs::walk(dir.path()).unwrap();
+    let imdb = test_index();
+    let entries = Scanner::new(&root, &imdb).scan_root().unwrap();
+    let renames = Renames::new(dir.path(), &entries[0], Naming::Default);
+
+    // The copy fails after the cross device rename, the original file must be left in place.
+    fault::inject(Op::Rename, 1, Fault::CrossDevice);
+    fault::inject(Op::Copy, 1, Fault::NoSpace);
+    let err = renames.apply(None, &mut ()).unwrap_err();
+
+    assert_eq!(err.raw_os_error(), Fault::NoSpace.to_error().raw_os_error());
+    assert_eq!(dir.files(), vec!["Inception.2010.mkv"]);
+}
+
+#[test]
+fn test_drift_since_scan() {
+    let dir = TempDir::new();
+    dir.create(&["Heat.1995.mkv", "Heat.1995.srt"]);
+
+    let root = vfs::walk(dir.path()).unwrap();
+    let imdb = test_index();
+    let entries = Scanner::new(&root, &imdb).scan_root().unwrap();
+    let renames = Renames::new(dir.path(), &entries[0], Naming::Default);
+    assert_eq!(renames.drifted(), None);
+
+    fs::write(dir.path().join("Heat.1995.srt"), b"1\n00:00:01,000 --> 00:00:02,000\n").unwrap();
+    let (path, reason) = renames.drifted().unwrap();
+    assert_eq!(path, dir.path().join("Heat.1995.srt"));
+    assert_eq!(reason, "changed size");
+
+    fs::remove_file(dir.path().join("Heat.1995.mkv")).unwrap();
+    assert_eq!(renames.drifted().unwrap().1, "disappeared");
+}
+
+#[test]
+fn test_drift_skips_apply_and_cleanup() {
+    let dir = TempDir::new();
+    dir.create(&["Heat.1995.mkv", "Heat.1995.srt", "RARBG.txt"]);
+
+    let root = vfs::walk(dir.path()).unwrap();
+    let imdb = test_index();
+    let entries = Scanner::new(&root, &imdb).scan_root().unwrap();
+    let target = Local::new(None);
+    let planned = Planner::new(Naming::Default).entry(&entries[0], dir.path(), false);
+    let mut cleaner = Cleaner::new();
+    cleaner.mark(&entries[0]);
+
+    // The files changed after the scan are neither renamed nor removed.
+    fs::write(dir.path().join("Heat.1995.srt"), b"1\n").unwrap();
+    fs::write(dir.path().join("RARBG.txt"), b"www\n").unwrap();
+    let srt = dir.path().join("Heat.1995.srt");
+    assert_eq!(
+        plan::apply(&target, &planned.renames, &mut ()).unwrap(),
+        Some((srt.as_path(), "changed size"))
+    );
+    for file in plan::unused_files(&root, &cleaner) {
+        assert_eq!(plan::remove(&target, &file, &mut ()).unwrap(), Some("changed size"));
+    }
+    plan::remove_empty_dirs(&target, &root);
+
+    assert_eq!(dir.files(), vec!["Heat.1995.mkv", "Heat.1995.srt", "RARBG.txt"]);
+}
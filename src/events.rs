@@ -0,0 +1,86 @@
+//! Events reported by the scan and the renames while they run, so that the command line, a server
+//! or a GUI can follow the progress of a run without reading its output.
+
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use scan::Confidence;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A movie file was matched to a title.
+    Matched {
+        movie: PathBuf,
+        imdb_id: String,
+        title: String,
+        year: i32,
+        confidence: Confidence,
+    },
+    /// The probe of a movie file was killed after the timeout, the movie is matched without it.
+    ProbeTimedOut { movie: PathBuf, timeout: Duration },
+    /// `bytes` of the `total` bytes of a file renamed to another device were copied so far. A copy
+    /// retried after an error reports fewer bytes than before, it starts over.
+    Copied {
+        file: PathBuf,
+        bytes: u64,
+        total: u64,
+    },
+    /// A file was moved to its organized path.
+    Renamed { from: PathBuf, to: PathBuf },
+    /// An operation on a file failed with a transient error, such as a network share that briefly
+    /// went away, and is retried after the delay. The first retry is attempt 1.
+    Retrying {
+        op: &'static str,
+        path: PathBuf,
+        error: String,
+        attempt: u32,
+        delay: Duration,
+    },
+}
+
+/// Receives the events of a run.
+pub trait Listener {
+    fn event(&mut self, event: Event);
+}
+
+impl Listener for () {
+    fn event(&mut self, _event: Event) {}
+}
+
+impl<'a, L: Listener + ?Sized> Listener for &'a mut L {
+    fn event(&mut self, event: Event) {
+        (**self).event(event)
+    }
+}
+
+/// Send the events to another thread, they are dropped once the receiver is gone.
+impl Listener for Sender<Event> {
+    fn event(&mut self, event: Event) {
+        let _ = self.send(event);
+    }
+}
+
+#[test]
+fn test_channel() {
+    use std::sync::mpsc::channel;
+
+    use scan::Scanner;
+    use test_util::{listing, test_index, GB};
+
+    let imdb = test_index();
+    let root = listing("/movies", &[("Heat.1995.mkv", GB)]);
+    let (sender, receiver) = channel();
+    Scanner::new(&root, &imdb).listener(sender).scan_root().unwrap();
+
+    assert_eq!(
+        receiver.iter().collect::<Vec<_>>(),
+        vec![Event::Matched {
+            movie: PathBuf::from("/movies/Heat.1995.mkv"),
+            imdb_id: "tt0113277".into(),
+            title: "Heat".into(),
+            year: 1995,
+            confidence: Confidence::Normal,
+        }]
+    );
+}
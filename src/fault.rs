@@ -0,0 +1,347 @@
+//! File system operations used when applying the changes, with fault injection to test how
+//! failures are handled.
+//!
+//! A fault is injected by setting the MERO_FAULT environment variable to `op:n:error`, which makes
+//! the n-th call of the operation fail with the error. The operations are `rename`, `copy`,
+//! `remove` and `mkdir` and the errors are `exdev`, `enospc`, `eacces`, `eio` and `estale`. For
+//! instance `rename:2:exdev` makes the second rename fail as if the files were on different
+//! devices.
+//!
+//! The operations failing with a transient error, `EIO` or `ESTALE` as returned by network shares
+//! that briefly go away, are retried a few times with a growing delay before their error is
+//! returned. Each retry is reported to the listener of the operation.
+
+use std::cell::RefCell;
+use std::env;
+use std::fs::{self, DirBuilder, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use events::{Event, Listener};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    Rename,
+    Copy,
+    RemoveFile,
+    CreateDir,
+}
+
+impl Op {
+    fn parse(text: &str) -> Option<Op> {
+        match text {
+            "rename" => Some(Op::Rename),
+            "copy" => Some(Op::Copy),
+            "remove" => Some(Op::RemoveFile),
+            "mkdir" => Some(Op::CreateDir),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Op::Rename => "rename",
+            Op::Copy => "copy",
+            Op::RemoveFile => "remove",
+            Op::CreateDir => "mkdir",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    CrossDevice,
+    NoSpace,
+    PermissionDenied,
+    /// An I/O error, transient on network shares.
+    Io,
+    /// A stale file handle of a network share, transient.
+    Stale,
+}
+
+impl Fault {
+    fn parse(text: &str) -> Option<Fault> {
+        match text {
+            "exdev" => Some(Fault::CrossDevice),
+            "enospc" => Some(Fault::NoSpace),
+            "eacces" => Some(Fault::PermissionDenied),
+            "eio" => Some(Fault::Io),
+            "estale" => Some(Fault::Stale),
+            _ => None,
+        }
+    }
+
+    #[cfg(unix)]
+    fn raw_os_error(self) -> i32 {
+        match self {
+            Fault::CrossDevice => 18,
+            Fault::NoSpace => 28,
+            Fault::PermissionDenied => 13,
+            Fault::Io => 5,
+            Fault::Stale => STALE,
+        }
+    }
+
+    #[cfg(windows)]
+    fn raw_os_error(self) -> i32 {
+        match self {
+            Fault::CrossDevice => 17,
+            Fault::NoSpace => 112,
+            Fault::PermissionDenied => 5,
+            Fault::Io => 59,
+            Fault::Stale => 64,
+        }
+    }
+
+    pub fn to_error(self) -> io::Error {
+        io::Error::from_raw_os_error(self.raw_os_error())
+    }
+}
+
+struct Injection {
+    op: Op,
+    nth: usize,
+    fault: Fault,
+    calls: usize,
+}
+
+impl Injection {
+    fn new(op: Op, nth: usize, fault: Fault) -> Injection {
+        Injection {
+            op,
+            nth,
+            fault,
+            calls: 0,
+        }
+    }
+
+    fn from_env() -> Vec<Injection> {
+        Injection::parse_env().into_iter().collect()
+    }
+
+    fn parse_env() -> Option<Injection> {
+        let text = env::var("MERO_FAULT").ok()?;
+        let mut parts = text.split(':');
+        let op = Op::parse(parts.next()?)?;
+        let nth = parts.next()?.parse().ok()?;
+        let fault = Fault::parse(parts.next()?)?;
+        Some(Injection::new(op, nth, fault))
+    }
+}
+
+/// Bytes copied between two progress reports.
+const COPY_CHUNK: usize = 1024 * 1024;
+/// Attempts of an operation failing with a transient error.
+const RETRY_ATTEMPTS: u32 = 4;
+/// Delay before the first retry in milliseconds, doubled before each following retry.
+const RETRY_DELAY: u64 = 200;
+
+#[cfg(target_os = "macos")]
+const STALE: i32 = 70;
+#[cfg(all(unix, not(target_os = "macos")))]
+const STALE: i32 = 116;
+
+/// Tell if the error may go away when the operation is tried again.
+#[cfg(unix)]
+fn is_transient(err: &io::Error) -> bool {
+    match err.raw_os_error() {
+        Some(code) => code == 5 || code == STALE,
+        None => false,
+    }
+}
+
+/// Tell if the error may go away when the operation is tried again: an unexpected network error
+/// or a network name no longer available.
+#[cfg(windows)]
+fn is_transient(err: &io::Error) -> bool {
+    match err.raw_os_error() {
+        Some(code) => code == 59 || code == 64,
+        None => false,
+    }
+}
+
+/// Run the operation on the path, retrying it while it fails with a transient error. Each retry
+/// is reported to the listener, which the operation is given too.
+fn retry<T>(
+    op: Op,
+    path: &Path,
+    listener: &mut dyn Listener,
+    mut f: impl FnMut(&mut dyn Listener) -> io::Result<T>,
+) -> io::Result<T> {
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match f(listener) {
+            Err(ref err) if is_transient(err) && attempt < RETRY_ATTEMPTS => {
+                listener.event(Event::Retrying {
+                    op: op.name(),
+                    path: path.to_owned(),
+                    error: err.to_string(),
+                    attempt,
+                    delay: Duration::from_millis(delay),
+                });
+                thread::sleep(Duration::from_millis(delay));
+                delay *= 2;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+thread_local! {
+    static INJECTIONS: RefCell<Vec<Injection>> = RefCell::new(Injection::from_env());
+}
+
+/// Make the n-th call of the operation on the current thread fail with the fault.
+#[cfg(test)]
+pub fn inject(op: Op, nth: usize, fault: Fault) {
+    INJECTIONS.with(|injections| {
+        injections
+            .borrow_mut()
+            .push(Injection::new(op, nth, fault))
+    });
+}
+
+fn check(op: Op) -> io::Result<()> {
+    INJECTIONS.with(|injections| {
+        for injection in injections.borrow_mut().iter_mut() {
+            if injection.op == op {
+                injection.calls += 1;
+                if injection.calls == injection.nth {
+                    return Err(injection.fault.to_error());
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+pub fn rename(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    listener: &mut dyn Listener,
+) -> io::Result<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    retry(Op::Rename, from, listener, |_| {
+        check(Op::Rename)?;
+        fs::rename(from, to)
+    })
+}
+
+/// Copy the file like `fs::copy`, reporting the bytes copied so far after each chunk with
+/// `Event::Copied`. A copy retried after a transient error starts over, its progress goes back to
+/// 0 bytes. The copy is synced to the disk before returning, so that the original can be removed.
+/// A failed copy is removed, a partial file is never left at the destination.
+pub fn copy(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    listener: &mut dyn Listener,
+) -> io::Result<u64> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    let res = retry(Op::Copy, from, listener, |listener| copy_once(from, to, listener));
+    if res.is_err() {
+        let _ = fs::remove_file(to);
+    }
+    res
+}
+
+fn copy_once(from: &Path, to: &Path, listener: &mut dyn Listener) -> io::Result<u64> {
+    check(Op::Copy)?;
+    let mut reader = File::open(from)?;
+    let metadata = reader.metadata()?;
+    let total = metadata.len();
+    let mut writer = File::create(to)?;
+    let mut progress = |bytes| {
+        listener.event(Event::Copied {
+            file: to.to_owned(),
+            bytes,
+            total,
+        })
+    };
+    progress(0);
+
+    let mut buf = vec![0; COPY_CHUNK];
+    let mut copied = 0;
+    loop {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buf[..len])?;
+        copied += len as u64;
+        progress(copied);
+    }
+    fs::set_permissions(to, metadata.permissions())?;
+    writer.sync_all()?;
+    Ok(copied)
+}
+
+pub fn remove_file(path: impl AsRef<Path>, listener: &mut dyn Listener) -> io::Result<()> {
+    let path = path.as_ref();
+    retry(Op::RemoveFile, path, listener, |_| {
+        check(Op::RemoveFile)?;
+        fs::remove_file(path)
+    })
+}
+
+pub fn create_dir_all(path: impl AsRef<Path>, listener: &mut dyn Listener) -> io::Result<()> {
+    let path = path.as_ref();
+    retry(Op::CreateDir, path, listener, |_| {
+        check(Op::CreateDir)?;
+        DirBuilder::new().recursive(true).create(path)
+    })
+}
+
+#[test]
+fn test_copy_retried() {
+    use std::process;
+    use std::sync::mpsc::channel;
+
+    let dir = env::temp_dir().join(format!("mero-fault-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (from, to) = (dir.join("Heat.mkv"), dir.join("Heat (1995).mkv"));
+    fs::write(&from, vec![0; COPY_CHUNK + 10]).unwrap();
+
+    // Each attempt reports 0 bytes first, so that the progress of a retried copy starts over.
+    inject(Op::Copy, 1, Fault::Io);
+    let (mut sender, receiver) = channel();
+    assert_eq!(copy(&from, &to, &mut sender).unwrap(), 1048586);
+    let events: Vec<Event> = receiver.try_iter().collect();
+    match events[0] {
+        Event::Retrying { op, attempt, .. } => assert_eq!((op, attempt), ("copy", 1)),
+        ref event => panic!("unexpected event {:?}", event),
+    }
+    let reports: Vec<u64> = events[1..]
+        .iter()
+        .map(|event| match *event {
+            Event::Copied { bytes, .. } => bytes,
+            ref event => panic!("unexpected event {:?}", event),
+        }).collect();
+    assert_eq!(reports, vec![0, COPY_CHUNK as u64, 1048586]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_copy_failed() {
+    use std::process;
+
+    let dir = env::temp_dir().join(format!("mero-fault-failed-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (from, to) = (dir.join("Heat.mkv"), dir.join("Heat (1995).mkv"));
+    fs::write(&from, vec![0; 10]).unwrap();
+    // A partial copy left by an earlier attempt.
+    fs::write(&to, vec![0; 5]).unwrap();
+
+    inject(Op::Copy, 1, Fault::NoSpace);
+    assert!(copy(&from, &to, &mut ()).is_err());
+    assert!(from.exists());
+    assert!(!to.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
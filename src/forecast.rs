@@ -0,0 +1,180 @@
+//! Forecast of the growth of the library, from the dates and sizes recorded by the manifests of
+//! the movies organized.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use failure::Error;
+
+use manifest::{Manifest, MANIFEST_NAME};
+use stats::format_bytes;
+use util::{free_space, same_device};
+use vfs::{self, NodeKind, WalkOptions};
+
+const DAY: u64 = 24 * 60 * 60;
+/// Days of history the rate of ingestion is measured over.
+const WINDOW_DAYS: u64 = 90;
+
+/// Movies organized into a file system over the window.
+#[derive(Debug, Default, PartialEq)]
+pub struct Growth {
+    pub movies: usize,
+    pub bytes: u64,
+}
+
+impl Growth {
+    /// Add up the movies organized during the `WINDOW_DAYS` days before `now`, both in seconds
+    /// since the Unix epoch.
+    pub fn measure(manifests: &[Manifest], now: u64) -> Growth {
+        let since = now.saturating_sub(WINDOW_DAYS * DAY);
+        let recent = manifests.iter().filter(|m| m.organized_at >= since);
+        Growth {
+            movies: recent.clone().count(),
+            bytes: recent.map(|m| m.size).sum(),
+        }
+    }
+
+    /// Bytes organized per day.
+    pub fn rate(&self) -> f64 {
+        self.bytes as f64 / WINDOW_DAYS as f64
+    }
+
+    /// Days until the free space is used up at the current rate, `None` without growth.
+    pub fn days_until_full(&self, free: u64) -> Option<f64> {
+        if self.bytes == 0 {
+            return None;
+        }
+        Some(free as f64 / self.rate())
+    }
+}
+
+/// Describe a number of days the way people count them, such as `3 weeks` or `14 months`.
+fn format_days(days: f64) -> String {
+    let days = days.round() as u64;
+    match days {
+        0 => "less than a day".to_string(),
+        1 => "1 day".to_string(),
+        2...20 => format!("{} days", days),
+        21...89 => format!("{} weeks", days / 7),
+        90...729 => format!("{} months", days / 30),
+        _ => format!("{} years", days / 365),
+    }
+}
+
+/// Load the manifests of the movies organized under the root. The root is streamed, the tree of a
+/// large library is not built only to find its manifests.
+fn load_manifests(root: &Path) -> Result<Vec<Manifest>, Error> {
+    let mut manifests = vec![];
+    for entry in vfs::stream(root, &WalkOptions::default())? {
+        let entry = entry?;
+        if entry.kind != NodeKind::File || entry.path.file_name() != Some(MANIFEST_NAME.as_ref()) {
+            continue;
+        }
+        if let Some(manifest) = entry.path.parent().and_then(|dir| Manifest::load(dir).ok()) {
+            manifests.push(manifest);
+        }
+    }
+    Ok(manifests)
+}
+
+/// Report the movies organized recently into the file system of each library root, and with
+/// `forecast` estimate when it will be full at that rate. Roots on the same file system are
+/// reported together.
+pub fn run(roots: &[PathBuf], forecast: bool) -> Result<(), Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut devices: Vec<Vec<&Path>> = vec![];
+    for root in roots.iter() {
+        let mut found = false;
+        for device in devices.iter_mut() {
+            if same_device(device[0], root)? {
+                device.push(root);
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            devices.push(vec![root]);
+        }
+    }
+
+    for device in devices.iter() {
+        let mut manifests = vec![];
+        for root in device.iter() {
+            manifests.extend(load_manifests(root)?);
+        }
+        let growth = Growth::measure(&manifests, now);
+        let names: Vec<String> = device.iter().map(|r| r.display().to_string()).collect();
+        println!("{}", names.join(", "));
+        println!(
+            "\t{} movies organized over the last {} days, {} per month",
+            growth.movies,
+            WINDOW_DAYS,
+            format_bytes((growth.rate() * 30.0) as u64)
+        );
+        if !forecast {
+            continue;
+        }
+        match free_space(device[0]) {
+            Some(free) => match growth.days_until_full(free) {
+                Some(days) => println!(
+                    "\t{} free, full in about {}",
+                    format_bytes(free),
+                    format_days(days)
+                ),
+                None => println!("\t{} free, not growing", format_bytes(free)),
+            },
+            None => println!("\tThe free space could not be read with df."),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_growth() {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use test_util::heat;
+
+    let dir = env::temp_dir().join(format!("mero-forecast-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let movie = dir.join("Heat (1995).mkv");
+    fs::write(&movie, b"movie").unwrap();
+    let title = heat();
+    let manifest = |organized_at, size| {
+        let mut manifest = Manifest::new(&title, "Heat.1995.mkv", &movie).unwrap();
+        manifest.organized_at = organized_at;
+        manifest.size = size;
+        manifest
+    };
+    let now = 1000 * DAY;
+    let gb = 1 << 30;
+    let manifests = vec![
+        manifest(now - DAY, 9 * gb),
+        manifest(now - 30 * DAY, 9 * gb),
+        manifest(now - 200 * DAY, 50 * gb),
+    ];
+    fs::remove_dir_all(&dir).unwrap();
+
+    let growth = Growth::measure(&manifests, now);
+    assert_eq!(
+        growth,
+        Growth {
+            movies: 2,
+            bytes: 18 * gb,
+        }
+    );
+    assert_eq!(growth.rate(), 0.2 * gb as f64);
+    assert_eq!(growth.days_until_full(100 * gb), Some(500.0));
+    assert_eq!(Growth::default().days_until_full(100 * gb), None);
+
+    assert_eq!(format_days(500.0), "16 months");
+    assert_eq!(format_days(30.0), "4 weeks");
+    assert_eq!(format_days(0.2), "less than a day");
+}
@@ -0,0 +1,31 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Load the list of files scheduled for removal by the previous run. A missing list is empty.
+pub fn load_cleanup_list(path: impl AsRef<Path>) -> io::Result<HashSet<PathBuf>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut list = HashSet::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if !line.is_empty() {
+            list.insert(PathBuf::from(line));
+        }
+    }
+    Ok(list)
+}
+
+/// Save the list of files scheduled for removal, one path per line.
+pub fn save_cleanup_list(path: impl AsRef<Path>, list: &[PathBuf]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for item in list {
+        writeln!(writer, "{}", item.display())?;
+    }
+    writer.flush()
+}
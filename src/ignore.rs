@@ -0,0 +1,150 @@
+//! `.meroignore` files, listing the files and folders the walk leaves out with the patterns of a
+//! `.gitignore` file, such as `Home Videos/` or `*.iso`. A file applies to the folder holding it
+//! and to its descendants, the files of the deeper folders take precedence.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the ignore files.
+pub const IGNORE_FILE: &str = ".meroignore";
+
+/// A line of an ignore file.
+#[derive(Debug)]
+struct Pattern {
+    glob: Vec<char>,
+    /// The pattern starts with `!`, it includes again what a previous pattern ignored.
+    negated: bool,
+    /// The pattern ends with `/`, it only matches folders.
+    dir_only: bool,
+    /// The pattern contains a `/` before its end, it matches the path from the folder of the file
+    /// instead of the name at any depth.
+    anchored: bool,
+}
+
+#[derive(Debug)]
+pub struct IgnoreFile {
+    /// Folder holding the file, the anchored patterns start from it.
+    base: PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+impl IgnoreFile {
+    /// Load the ignore file of the folder, `None` when it has none.
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Option<IgnoreFile>> {
+        let dir = dir.as_ref();
+        match fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(content) => Ok(Some(IgnoreFile::parse(dir, &content))),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Parse the content of an ignore file found in the folder. Blank lines and the lines
+    /// starting with `#` are skipped.
+    pub fn parse(base: impl Into<PathBuf>, content: &str) -> IgnoreFile {
+        let patterns = content
+            .lines()
+            .map(|line| line.trim_right())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let negated = line.starts_with('!');
+                let line = if negated { &line[1..] } else { line };
+                let dir_only = line.ends_with('/');
+                let line = line.trim_right_matches('/');
+                let anchored = line.contains('/');
+                Pattern {
+                    glob: line.trim_left_matches('/').chars().collect(),
+                    negated,
+                    dir_only,
+                    anchored,
+                }
+            })
+            .collect();
+        IgnoreFile {
+            base: base.into(),
+            patterns,
+        }
+    }
+
+    /// Folder holding the file.
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    /// Tell if the file or folder is ignored, `Some(false)` when a negated pattern includes it
+    /// again and `None` when no pattern matches it. The last pattern matching wins.
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.base).ok()?;
+        let relative: Vec<char> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+            .chars()
+            .collect();
+        let name = match relative.iter().rposition(|&c| c == '/') {
+            Some(pos) => &relative[pos + 1..],
+            None => &relative[..],
+        };
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| {
+                (is_dir || !pattern.dir_only)
+                    && glob_match(&pattern.glob, if pattern.anchored { &relative } else { name })
+            })
+            .map(|pattern| !pattern.negated)
+    }
+}
+
+/// Match the text against a glob: `*` matches anything but a `/`, `**` anything, `?` a character
+/// but a `/`, and `\` escapes the next character.
+fn glob_match(glob: &[char], text: &[char]) -> bool {
+    match glob.first() {
+        None => text.is_empty(),
+        Some('*') if glob.get(1) == Some(&'*') => {
+            let rest = &glob[2..];
+            match rest.first() {
+                // `**/` matches no folder or any number of them.
+                Some('/') => (0..=text.len())
+                    .filter(|&i| i == 0 || text[i - 1] == '/')
+                    .any(|i| glob_match(&rest[1..], &text[i..])),
+                _ => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+            }
+        }
+        Some('*') => {
+            let end = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=end).any(|i| glob_match(&glob[1..], &text[i..]))
+        }
+        Some('?') => match text.first() {
+            Some(&c) if c != '/' => glob_match(&glob[1..], &text[1..]),
+            _ => false,
+        },
+        Some('\\') if glob.len() > 1 => {
+            text.first() == Some(&glob[1]) && glob_match(&glob[2..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && glob_match(&glob[1..], &text[1..]),
+    }
+}
+
+#[test]
+fn test_ignore_file() {
+    let ignore = IgnoreFile::parse(
+        "/movies",
+        "# Not movies\nHome Videos/\n*.iso\n!Heat*.iso\n/Extras/**/*.mkv\nsample?.mkv\n",
+    );
+    let matched = |path: &str, is_dir| ignore.matched(&Path::new("/movies").join(path), is_dir);
+
+    assert_eq!(matched("Home Videos", true), Some(true));
+    assert_eq!(matched("Family/Home Videos", true), Some(true));
+    assert_eq!(matched("Home Videos", false), None);
+    assert_eq!(matched("Ronin (1998)/Ronin.iso", false), Some(true));
+    assert_eq!(matched("Heat (1995)/Heat.1995.iso", false), Some(false));
+    assert_eq!(matched("Extras/Heat.mkv", false), Some(true));
+    assert_eq!(matched("Extras/Heat/Trailer.mkv", false), Some(true));
+    assert_eq!(matched("Heat/Extras/Trailer.mkv", false), None);
+    assert_eq!(matched("Heat/sample1.mkv", false), Some(true));
+    assert_eq!(matched("Heat/sample10.mkv", false), None);
+    assert_eq!(matched("Heat (1995)/Heat.1995.mkv", false), None);
+}
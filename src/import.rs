@@ -0,0 +1,216 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use serde_json::{self, Value};
+
+use imdb::parse_id;
+use library::{LibraryEntry, Source};
+use scan::FileExt;
+use vfs::File;
+
+/// Extended attribute where FileBot saves the metadata of the movies it renamed.
+#[cfg(not(target_os = "macos"))]
+const FILEBOT_XATTR: &str = "user.net.filebot.metadata";
+#[cfg(target_os = "macos")]
+const FILEBOT_XATTR: &str = "net.filebot.metadata";
+
+/// Suffixes of the extras tinyMediaManager keeps next to the movie.
+const TMM_EXTRAS: &[&str] = &["-trailer", "-sample", "-extra"];
+
+/// Rebuild the list of the movies of a library organized by FileBot or tinyMediaManager, from the
+/// metadata FileBot saves in the extended attributes of the files it renamed and from the `.nfo`
+/// files tinyMediaManager writes. Movies identified by neither are left out.
+pub fn import(root: &File, root_path: &Path) -> Vec<LibraryEntry> {
+    let mut entries = vec![];
+
+    for dir in root.descendants().filter(File::is_dir) {
+        let movies: Vec<File> = dir.children().filter(FileExt::is_video).collect();
+        for movie in &movies {
+            let entry = read_filebot(movie).or_else(|| read_tmm(movie, &movies));
+            if let Some((source, identity)) = entry {
+                entries.push(LibraryEntry {
+                    imdb_id: identity.imdb_id,
+                    title: identity.title,
+                    year: identity.year,
+                    path: movie.path().strip_prefix(root_path).unwrap_or(movie.path()).to_owned(),
+                    source,
+                });
+            }
+        }
+    }
+
+    entries
+}
+
+/// Identity of a movie found in the artifacts of another tool.
+#[derive(Debug, PartialEq)]
+struct Identity {
+    imdb_id: String,
+    title: Option<String>,
+    year: Option<i32>,
+}
+
+fn read_filebot(movie: &File) -> Option<(Source, Identity)> {
+    let output = xattr_command(movie.path()).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_filebot(&output.stdout).map(|identity| (Source::FileBot, identity))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn xattr_command(path: &Path) -> Command {
+    let mut command = Command::new("getfattr");
+    command.args(&["--only-values", "-n", FILEBOT_XATTR]).arg(path);
+    command
+}
+
+#[cfg(target_os = "macos")]
+fn xattr_command(path: &Path) -> Command {
+    let mut command = Command::new("xattr");
+    command.args(&["-p", FILEBOT_XATTR]).arg(path);
+    command
+}
+
+/// Parse the metadata saved by FileBot, a JSON object such as
+/// `{"@type": "Movie", "name": "Heat", "year": 1995, "imdbId": 113277, "tmdbId": 949}`. Movies
+/// FileBot only knew from TMDB have no IMDB id and are skipped.
+fn parse_filebot(metadata: &[u8]) -> Option<Identity> {
+    let value: Value = serde_json::from_slice(metadata).ok()?;
+    if value["@type"] != "Movie" {
+        return None;
+    }
+    let id = value["imdbId"].as_u64().filter(|&id| id > 0)?;
+    Some(Identity {
+        imdb_id: format!("tt{:07}", id),
+        title: value["name"].as_str().map(|s| s.to_string()),
+        year: value["year"].as_i64().map(|year| year as i32),
+    })
+}
+
+/// Read the `.nfo` file of the movie written by tinyMediaManager, named after the movie or
+/// `movie.nfo` when the movie is the only one of its folder besides its extras.
+fn read_tmm(movie: &File, movies: &[File]) -> Option<(Source, Identity)> {
+    let is_extra = |f: &File| TMM_EXTRAS.iter().any(|suffix| f.stem().ends_with(suffix));
+    let single = !is_extra(movie) && movies.iter().filter(|f| !is_extra(f)).count() == 1;
+
+    movie.siblings()?.find_map(|entry| {
+        let is_nfo = entry.is_file() && entry
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("nfo"))
+            .unwrap_or(false);
+        if !is_nfo || !(entry.stem() == movie.stem() || (single && entry.stem() == "movie")) {
+            return None;
+        }
+        let content = fs::read_to_string(entry.path()).ok()?;
+        parse_tmm(&content).map(|identity| (Source::Tmm, identity))
+    })
+}
+
+/// Parse a movie `.nfo` file, the IMDB id is in a `uniqueid` element of type `imdb` or in the
+/// `imdb` or `id` elements of older versions.
+fn parse_tmm(content: &str) -> Option<Identity> {
+    if !content.contains("<movie") {
+        return None;
+    }
+    let imdb_id = elements(content, "uniqueid")
+        .into_iter()
+        .filter(|&(attrs, _)| attrs.contains("type=\"imdb\""))
+        .chain(elements(content, "imdb"))
+        .chain(elements(content, "id"))
+        .map(|(_, value)| value.trim())
+        .find(|value| parse_id(value).is_some())?;
+    let year = elements(content, "year")
+        .into_iter()
+        .chain(elements(content, "premiered"))
+        .filter_map(|(_, value)| value.trim().get(..4)?.parse().ok())
+        .next();
+    Some(Identity {
+        imdb_id: imdb_id.to_string(),
+        title: elements(content, "title").first().map(|(_, value)| unescape(value.trim())),
+        year,
+    })
+}
+
+/// Find the elements with the tag, as their attributes and their text.
+fn elements<'a>(content: &'a str, tag: &str) -> Vec<(&'a str, &'a str)> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    content
+        .match_indices(open.as_str())
+        .filter_map(|(start, _)| {
+            let rest = &content[start + open.len()..];
+            if !rest.starts_with('>') && !rest.starts_with(' ') {
+                return None;
+            }
+            let end = rest.find('>')?;
+            let text = &rest[end + 1..];
+            Some((&rest[..end], &text[..text.find(close.as_str())?]))
+        })
+        .collect()
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[test]
+fn test_parse_filebot() {
+    let metadata = br#"{"@type": "Movie", "name": "Heat", "year": 1995, "imdbId": 113277}"#;
+    assert_eq!(
+        parse_filebot(metadata),
+        Some(Identity {
+            imdb_id: "tt0113277".into(),
+            title: Some("Heat".into()),
+            year: Some(1995),
+        })
+    );
+    assert_eq!(parse_filebot(br#"{"@type": "Movie", "name": "Heat", "imdbId": -1}"#), None);
+    assert_eq!(parse_filebot(br#"{"@type": "Episode", "imdbId": 113277}"#), None);
+    assert_eq!(parse_filebot(b"garbage"), None);
+}
+
+#[test]
+fn test_import_tmm() {
+    use std::process;
+    use vfs;
+
+    let root_path = ::std::env::temp_dir().join(format!("mero-import-{}", process::id()));
+    let heat = root_path.join("Heat (1995)");
+    let misc = root_path.join("Misc");
+    fs::create_dir_all(&heat).unwrap();
+    fs::create_dir_all(&misc).unwrap();
+    fs::write(heat.join("Heat.mkv"), b"movie").unwrap();
+    fs::write(heat.join("Heat-trailer.mkv"), b"movie").unwrap();
+    fs::write(
+        heat.join("movie.nfo"),
+        "<?xml version=\"1.0\"?>\n<!--created by tinyMediaManager-->\n<movie>\n\
+         <title>Heat &amp; Dust</title>\n<year>1995</year>\n<id>tt0113277</id>\n\
+         <uniqueid default=\"false\" type=\"tmdb\">949</uniqueid>\n\
+         <uniqueid default=\"true\" type=\"imdb\">tt0113277</uniqueid>\n</movie>\n",
+    ).unwrap();
+    // A movie.nfo in a folder of several movies tells nothing about them.
+    fs::write(misc.join("A.mkv"), b"movie").unwrap();
+    fs::write(misc.join("B.mkv"), b"movie").unwrap();
+    fs::write(misc.join("movie.nfo"), "<movie><id>tt0133093</id></movie>").unwrap();
+
+    let root = vfs::walk(&root_path).unwrap();
+    let entries = import(&root, &root_path);
+    fs::remove_dir_all(&root_path).unwrap();
+
+    assert_eq!(
+        entries,
+        vec![LibraryEntry {
+            imdb_id: "tt0113277".into(),
+            title: Some("Heat & Dust".into()),
+            year: Some(1995),
+            path: Path::new("Heat (1995)").join("Heat.mkv"),
+            source: Source::Tmm,
+        }]
+    );
+}
@@ -0,0 +1,38 @@
+//! The engine of merovingian: the scan matching the movie files of a library to their titles, the
+//! plan of the renames organizing them and its application. The progress of a run is reported
+//! through the `events` module, for the command line and the other frontends alike.
+
+#![feature(nll)]
+
+#[macro_use]
+extern crate failure;
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate maplit;
+extern crate same_file;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+extern crate ffprobe;
+extern crate imdb;
+
+pub mod budget;
+pub mod container;
+#[cfg(test)]
+mod e2e;
+pub mod events;
+pub mod fault;
+pub mod ignore;
+pub mod manifest;
+pub mod overrides;
+pub mod parse;
+pub mod plan;
+pub mod rename;
+pub mod scan;
+#[cfg(test)]
+mod test_util;
+pub mod util;
+pub mod vfs;
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::fs::File as FsFile;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+use imdb::parse_id;
+use manifest::{Manifest, MANIFEST_NAME};
+use plan::Local;
+use scan::{find_imdb_id, FileExt};
+use vfs::File;
+
+/// Where the movies of the library were identified from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Manifest,
+    Nfo,
+    /// The metadata saved by FileBot in the extended attributes of the movie file.
+    FileBot,
+    /// The `.nfo` file written by tinyMediaManager.
+    Tmm,
+}
+
+/// A movie of the library, identified without matching its name.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub imdb_id: String,
+    /// The title and the year are only known from manifests and from the other tools.
+    pub title: Option<String>,
+    pub year: Option<i32>,
+    /// Path of the movie file, relative to the root of the library.
+    pub path: PathBuf,
+    pub source: Source,
+}
+
+/// Rebuild the list of the movies of a library from the manifests written in the movie folders,
+/// or from the IMDB ids found in the names of the movie files and in their `.nfo` files. Nothing
+/// is matched by name and nothing is downloaded, movies without either are left out.
+pub fn adopt(root: &File, root_path: &Path) -> Vec<LibraryEntry> {
+    let mut entries = vec![];
+
+    for dir in root.descendants().filter(File::is_dir) {
+        let manifest = dir
+            .children()
+            .find(|f| f.is_file() && f.name() == MANIFEST_NAME)
+            .and_then(|_| Manifest::load(dir.path()).ok());
+        if let Some(manifest) = manifest {
+            if parse_id(&manifest.imdb_id).is_some() {
+                entries.push(LibraryEntry {
+                    imdb_id: manifest.imdb_id,
+                    title: Some(manifest.title),
+                    year: Some(manifest.year),
+                    path: relative(&dir.path().join(&manifest.filename), root_path),
+                    source: Source::Manifest,
+                });
+                continue;
+            }
+        }
+
+        for movie in dir.children().filter(FileExt::is_video) {
+            if let Some(id) = find_imdb_id(&movie, &Local::new(None)) {
+                entries.push(LibraryEntry {
+                    imdb_id: format!("tt{:07}", id),
+                    title: None,
+                    year: None,
+                    path: relative(movie.path(), root_path),
+                    source: Source::Nfo,
+                });
+            }
+        }
+    }
+
+    entries
+}
+
+fn relative(path: &Path, root_path: &Path) -> PathBuf {
+    path.strip_prefix(root_path).unwrap_or(path).to_owned()
+}
+
+/// Name of the file listing the movies of the libraries, in the `.merovingian` folder.
+pub const LIBRARIES_NAME: &str = "libraries.json";
+
+/// The movies adopted or imported into each library, by the canonical root of the library. The
+/// scan of a library identifies its movies by their IMDB ids instead of matching their names.
+#[derive(Debug, Default)]
+pub struct Libraries {
+    roots: BTreeMap<PathBuf, Vec<LibraryEntry>>,
+}
+
+impl Libraries {
+    /// Load the list of the movies of the libraries, a missing file has no movies.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Libraries> {
+        let file = match FsFile::open(path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Libraries::default())
+            }
+            Err(err) => return Err(err),
+        };
+        let roots = serde_json::from_reader(file)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(Libraries { roots })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = FsFile::create(path)?;
+        serde_json::to_writer_pretty(file, &self.roots)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+
+    /// Get the movies of the library at the root.
+    pub fn entries(&self, root_path: &Path) -> &[LibraryEntry] {
+        self.roots.get(root_path).map(|e| e.as_slice()).unwrap_or(&[])
+    }
+
+    /// Get the movies of the library at the root to change them.
+    pub fn entries_mut(&mut self, root_path: &Path) -> &mut Vec<LibraryEntry> {
+        self.roots.entry(root_path.to_owned()).or_insert_with(Vec::new)
+    }
+}
+
+#[test]
+fn test_adopt() {
+    use std::fs;
+    use std::process;
+
+    use test_util;
+    use vfs;
+
+    let root_path = ::std::env::temp_dir().join(format!("mero-adopt-{}", process::id()));
+    let heat = root_path.join("Heat (1995)");
+    let matrix = root_path.join("The Matrix (1999)");
+    fs::create_dir_all(&heat).unwrap();
+    fs::create_dir_all(&matrix).unwrap();
+    fs::write(heat.join("Heat (1995).mkv"), b"movie").unwrap();
+    fs::write(matrix.join("The Matrix (1999).mkv"), b"movie").unwrap();
+    fs::write(matrix.join("movie.nfo"), b"https://www.imdb.com/title/tt0133093/").unwrap();
+
+    let title = test_util::heat();
+    Manifest::new(&title, "Heat.1995.mkv", &heat.join("Heat (1995).mkv"))
+        .unwrap()
+        .save(&heat)
+        .unwrap();
+
+    let root = vfs::walk(&root_path).unwrap();
+    let mut entries = adopt(&root, &root_path);
+    fs::remove_dir_all(&root_path).unwrap();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].imdb_id, "tt0113277");
+    assert_eq!(entries[0].source, Source::Manifest);
+    assert_eq!(entries[0].path, Path::new("Heat (1995)/Heat (1995).mkv"));
+    assert_eq!(entries[1].imdb_id, "tt0133093");
+    assert_eq!(entries[1].source, Source::Nfo);
+    assert_eq!(entries[1].title, None);
+
+    // The movies are kept apart for each library.
+    let path = ::std::env::temp_dir().join(format!("mero-libraries-{}.json", process::id()));
+    let mut libraries = Libraries::default();
+    *libraries.entries_mut(&root_path) = entries;
+    libraries.save(&path).unwrap();
+    let libraries = Libraries::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(libraries.entries(&root_path).len(), 2);
+    assert!(libraries.entries(Path::new("/other")).is_empty());
+}
@@ -6,31 +6,156 @@ extern crate failure;
 extern crate lazy_static;
 #[macro_use]
 extern crate maplit;
+extern crate reqwest;
 extern crate same_file;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 #[macro_use]
 extern crate structopt;
 extern crate yansi;
 
 extern crate ffprobe;
 extern crate imdb;
+extern crate mero3;
 
+mod archive;
+mod artwork;
+mod compare;
+mod doctor;
+mod forecast;
+mod history;
+mod import;
 mod input;
-mod parse;
-mod rename;
-mod scan;
-mod util;
-mod vfs;
+mod library;
+mod progress;
+mod quality;
+mod remote;
+mod route;
+mod rules;
+mod stats;
+#[cfg(test)]
+mod test_util;
+mod tmdb;
+mod verify;
+mod warning;
 
+use std::collections::HashMap;
+use std::env;
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
 
 use failure::Error;
+use ffprobe::{Ffprobe, Mediainfo, ProbeCache};
 use structopt::StructOpt;
 use yansi::Paint;
 
-use imdb::Imdb;
-use rename::{Cleaner, Renames};
-use scan::Scanner;
-use util::format_runtime;
+use mero3::{
+    budget, container, events, ignore, manifest, overrides, parse, plan, rename, scan, util, vfs,
+};
+
+use budget::Budget;
+use container::{Owner, PathMap};
+use imdb::{Imdb, IndexOptions, Language, Rank, TitleKind, Tokenizer, MAPPED_INDEX};
+use input::Input;
+use progress::TermProgress;
+use remote::Remote;
+use route::Routes;
+use library::{Libraries, Source, LIBRARIES_NAME};
+use manifest::Manifest;
+use overrides::Overrides;
+use rename::{Category, Cleaner, Naming, Renames};
+use rules::{rule_folder, Question, Rules};
+use parse::parse_movie;
+use plan::{Local, Operation, Plan, Planned, Planner, Target};
+use scan::{
+    Confidence, ContainerMismatch, MatchedBy, ScanEntry, Scanner, RUNTIME_CANDIDATES,
+};
+use stats::RunStats;
+use tmdb::{Certifications, Tmdb};
+use util::{check_writable, format_runtime, same_device, PlanFilter};
+use vfs::{SymlinkPolicy, WalkOptions};
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Check the environment: ffprobe, the index, the options, the permissions and free space of
+    /// the library, and the access to the IMDB datasets.
+    #[structopt(name = "doctor")]
+    Doctor {
+        /// Path to the directory containing movies.
+        path: Option<String>,
+    },
+    /// Rebuild the list of the movies of a library moved elsewhere from the manifests and the
+    /// `.nfo` files of its folders, without matching names or accessing the network. The next
+    /// runs trust their IMDB ids over the matching of their names.
+    #[structopt(name = "adopt")]
+    Adopt {
+        /// Path to the organized library.
+        path: String,
+    },
+    /// Import the movies identified by FileBot or tinyMediaManager into the list of the movies of
+    /// the library, and trust their IMDB ids over the matching of their names in the next runs.
+    #[structopt(name = "import")]
+    Import {
+        /// Path to the library organized by FileBot or tinyMediaManager.
+        path: String,
+    },
+    /// Download the posters and backdrops of the movies of an organized library from TMDB.
+    #[structopt(name = "artwork")]
+    Artwork {
+        /// Path to the directory containing movies.
+        path: Option<String>,
+        /// Only download the images the movies lack, the default.
+        #[structopt(long = "--missing-only")]
+        missing_only: bool,
+        /// Download all the images again, replacing the existing ones.
+        #[structopt(long = "--refresh")]
+        refresh: bool,
+        /// Key of the TMDB API, read from TMDB_API_KEY by default.
+        #[structopt(long = "--tmdb-api-key")]
+        tmdb_api_key: Option<String>,
+    },
+    /// Match the movies of two libraries and report the movies found in only one of them, and the
+    /// movies found in both whose copies differ in resolution, video bit rate or size.
+    #[structopt(name = "compare")]
+    Compare {
+        /// Path to the first library.
+        a: String,
+        /// Path to the second library.
+        b: String,
+    },
+    /// Report the movies organized into the library recently, from the manifests of its folders.
+    #[structopt(name = "stats")]
+    Stats {
+        /// Path to the organized library.
+        path: Option<String>,
+        /// Estimate when the file system of the library and of each route root will be full at
+        /// the rate movies were organized into it over the last 90 days.
+        #[structopt(long = "--forecast")]
+        forecast: bool,
+    },
+    /// Probe the movies with ffprobe and report those worth re-encoding, such as interlaced movies
+    /// and movies with a non-standard frame rate, or replacing, such as foreign-audio releases.
+    #[structopt(name = "deep-scan")]
+    DeepScan {
+        /// Path to the directory containing movies.
+        path: Option<String>,
+        /// Also report the movies with no audio stream in this language, such as `en` or `eng`.
+        #[structopt(long = "--audio-language")]
+        audio_language: Option<String>,
+    },
+    /// Decode the movies with ffmpeg and report those with decode errors, such as broken rips,
+    /// before they are organized. Takes about as long as reading the movies.
+    #[structopt(name = "verify")]
+    Verify {
+        /// Path to the directory containing movies.
+        path: Option<String>,
+    },
+}
 
 #[derive(Debug, StructOpt)]
 struct App {
@@ -39,94 +164,1027 @@ struct App {
     /// Apply the changes.
     #[structopt(short = "a", long = "--apply")]
     apply: bool,
+    /// Scan and organize the library on a remote host over SSH, such as `user@nas` (experimental).
+    #[structopt(long = "--remote")]
+    remote: Option<String>,
+    /// Map container paths to host paths in the output, written as `/container/path=/host/path`.
+    /// Defaults to the MERO_PATH_MAP environment variable.
+    #[structopt(long = "--path-map")]
+    path_map: Option<String>,
+    /// Leave the movies in place and build an organized library of links to them in this directory.
+    #[structopt(long = "--link")]
+    link: Option<String>,
+    /// Limit the size of the companion files kept along with a movie, written as `category=size`
+    /// such as `images=50M`. Categories are images, subtitles and audio.
+    #[structopt(long = "--budget")]
+    budget: Vec<String>,
+    /// Exempt a movie from the budget, using its folder name such as `Heat (1995)`.
+    #[structopt(long = "--budget-exempt")]
+    budget_exempt: Vec<String>,
+    /// Send a kind of titles to another library root, written as `route=path` such as
+    /// `documentary=/mnt/documentaries`. Routes are documentary, standup and family. The family
+    /// route uses the TMDB certifications when TMDB_API_KEY is set, the genres otherwise.
+    #[structopt(long = "--route")]
+    routes: Vec<String>,
+    /// Country of the TMDB certifications used by the family route.
+    #[structopt(long = "--certification-country", default_value = "US")]
+    certification_country: String,
+    /// Only list and remove the files scheduled for removal that were not listed by the previous
+    /// run of the library.
+    #[structopt(long = "--new-only")]
+    new_only: bool,
+    /// Keep the audio files next to the movies with them, such as the `.flac` and `.cue` files of
+    /// concert films, instead of removing them.
+    #[structopt(long = "--keep-audio")]
+    keep_audio: bool,
+    /// Extract the subtitle archives next to the movies, such as `Subs.rar` or `Subs.zip`, into a
+    /// `Subs` folder. The archives are removed once extracted. Needs `unzip` and `unrar`.
+    #[structopt(long = "--extract-subtitles")]
+    extract_subtitles: bool,
+    /// What to do with the symbolic links of the library: `follow` walks their target, `skip`
+    /// leaves them out and `record` keeps them without walking their target. Links leading back
+    /// to a folder being walked are never followed.
+    #[structopt(long = "--symlinks", default_value = "record")]
+    symlinks: String,
+    /// Ignore the files and folders whose name starts with a dot, such as `.DS_Store` or `.git`.
+    /// They are neither matched nor removed.
+    #[structopt(long = "--skip-hidden")]
+    skip_hidden: bool,
+    /// Ignore the folders containing a `.nomedia` file, as Kodi and Jellyfin do. They are neither
+    /// matched nor removed.
+    #[structopt(long = "--skip-nomedia")]
+    skip_nomedia: bool,
+    /// Write a merovingian.json manifest describing the movie in each folder organized.
+    #[structopt(long = "--manifest")]
+    manifest: bool,
+    /// Explain the matches: their score, the words of the file name that hit the index and how
+    /// close matches were ranked.
+    #[structopt(long = "--explain")]
+    explain: bool,
+    /// Naming preset, `default` keeps the image names and `kodi` prefixes them with the movie name.
+    #[structopt(long = "--naming", default_value = "default")]
+    naming: String,
+    /// Check for newer IMDB datasets when they were last checked more than this many days ago, and
+    /// rebuild the index if they changed. By default the datasets are never refreshed.
+    #[structopt(long = "--dataset-max-age")]
+    dataset_max_age: Option<u64>,
+    /// Never download the IMDB datasets. The existing index is used, or created again from the
+    /// datasets already downloaded when the options change.
+    #[structopt(long = "--offline")]
+    offline: bool,
+    /// Download the IMDB crew datasets to show the directors of the movies. They are large, the
+    /// index is created again once they are downloaded.
+    #[structopt(long = "--directors")]
+    directors: bool,
+    /// Keep the index in a layout mapped in memory instead of loaded, which opens instantly and
+    /// only decodes the titles matched. The index is converted by the first run with the flag,
+    /// and loaded again by the first run without it.
+    #[structopt(long = "--mapped-index")]
+    mapped_index: bool,
+    /// Download the IMDB datasets from this mirror instead, such as `http://mirror.local/imdb`.
+    /// Defaults to the IMDB_DATASET_URL environment variable, then to the IMDB host.
+    #[structopt(long = "--dataset-url")]
+    dataset_url: Option<String>,
+    /// Download the IMDB datasets through this HTTP proxy, such as `http://proxy.local:3128`.
+    /// Defaults to the HTTPS_PROXY environment variable.
+    #[structopt(long = "--proxy")]
+    proxy: Option<String>,
+    /// Name the movies with their title in this region, such as `FR`, when IMDB knows one.
+    #[structopt(long = "--preferred-region")]
+    preferred_region: Option<String>,
+    /// Name the movies with their title in this language, such as `fr`, when IMDB knows one.
+    #[structopt(long = "--preferred-language")]
+    preferred_language: Option<String>,
+    /// Minimum number of votes of the titles in the index.
+    #[structopt(long = "--min-votes", default_value = "50")]
+    min_votes: u32,
+    /// Include the adult titles in the index.
+    #[structopt(long = "--include-adult")]
+    include_adult: bool,
+    /// Kind of titles in the index, such as `movie`, `tvMovie`, `video` or `short`. Defaults to
+    /// all of them. The index is created again when the options change.
+    #[structopt(long = "--kind")]
+    kinds: Vec<String>,
+    /// Never match the titles of this kind, such as `short`. Unlike `--kind`, the titles stay in
+    /// the index, so that libraries can share it.
+    #[structopt(long = "--exclude-kind")]
+    exclude_kinds: Vec<String>,
+    /// Minimum number of votes of the titles matched. Unlike `--min-votes`, the titles with fewer
+    /// votes stay in the index.
+    #[structopt(long = "--match-min-votes", default_value = "0")]
+    match_min_votes: u32,
+    /// Weight of the score of a kind of titles, written as `kind=weight` such as `video=1.0`.
+    /// Movies have a weight of 1 and the other kinds 0.8 by default.
+    #[structopt(long = "--kind-weight")]
+    kind_weights: Vec<String>,
+    /// Match the titles announced for a future year.
+    #[structopt(long = "--unreleased")]
+    unreleased: bool,
+    /// Language whose stop words are skipped from the titles, such as `en`, `fr`, `es` or `de`.
+    /// Defaults to English. The index is created again when the languages change.
+    #[structopt(long = "--stop-words")]
+    stop_words: Vec<String>,
+    /// Stem the words of the titles by dropping the plural `s`. The index is created again when
+    /// stemming is turned on or off.
+    #[structopt(long = "--stemming")]
+    stemming: bool,
+    /// Minimum score of a match between 0 and 1, the files matching no title this well are left
+    /// alone instead of being renamed after their closest title.
+    #[structopt(long = "--min-score", default_value = "0.5")]
+    min_score: f64,
+    /// Compare the duration of the movie files to the runtime of their matches with ffprobe, and
+    /// prefer the close matches whose runtime agrees, such as the original over its remake. The
+    /// matches whose runtime disagrees are reviewed. With `--remote`, the ffprobe of the remote
+    /// host probes the files.
+    #[structopt(long = "--check-runtime")]
+    check_runtime: bool,
+    /// Probe the subtitle streams muxed in the movie files with ffprobe, and leave to the cleanup
+    /// the subtitle files of a language and flavor already muxed in.
+    #[structopt(long = "--drop-muxed-subtitles")]
+    drop_muxed_subtitles: bool,
+    /// Probe the container of the movie files with ffprobe, and flag the files whose container
+    /// does not match their extension, such as an `.avi` file that is a Matroska file, or that
+    /// ffprobe cannot read.
+    #[structopt(long = "--check-container")]
+    check_container: bool,
+    /// Look up the words one typo away from a word of the file name missing from the index. Slows
+    /// down the matching of the file names with such words.
+    #[structopt(long = "--fuzzy")]
+    fuzzy: bool,
+    /// Do not match the names written in the catalog form, such as "Matrix, The", as if the
+    /// article was in front.
+    #[structopt(long = "--no-catalog-articles")]
+    no_catalog_articles: bool,
+    /// Write the plan of the run to this file as JSON: the matches, the renames and the removals.
+    #[structopt(long = "--export-plan")]
+    export_plan: Option<String>,
+    /// Only apply the operations with these identifiers, separated by commas. The identifiers are
+    /// shown next to each operation and stay the same between runs.
+    #[structopt(long = "--only-ids")]
+    only_ids: Option<String>,
+    /// Do not apply the operations with these identifiers, separated by commas.
+    #[structopt(long = "--exclude-ids")]
+    exclude_ids: Option<String>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+/// Print the score of the match and of the runner-up matches, and how they were ranked.
+fn print_explanation(imdb: &Imdb, entry: &ScanEntry) {
+    let runtime_agrees = match entry.matched_by {
+        MatchedBy::Override => return println!("\tExplain: matched by an override"),
+        MatchedBy::ImdbId => return println!("\tExplain: matched by IMDB id"),
+        MatchedBy::Name { runtime_agrees } => runtime_agrees,
+    };
+
+    // The candidates of the scan, the title picked is not the first when its runtime agrees and
+    // those of the better ones do not.
+    let (name, year) = parse_movie(entry.movie.stem());
+    let candidates = imdb.explain(&name, year, RUNTIME_CANDIDATES);
+    for (pos, scored) in candidates.iter().enumerate() {
+        let rank = match scored.rank {
+            Rank::Score => "by score".to_string(),
+            Rank::TieBreak { close } => format!("by year and votes among {} close matches", close),
+        };
+        println!(
+            "\t{} {} ({}), score {:.3}, tags [{}], ranked {}",
+            if pos == 0 { "Explain:" } else { "        " },
+            scored.title.primary_title(),
+            scored.title.year(),
+            scored.score,
+            scored.tags.join(", "),
+            rank
+        );
+    }
+    match runtime_agrees {
+        Some(true) if candidates.first().map(|s| s.title) != Some(entry.title) => println!(
+            "\t         picked {} ({}), the first whose runtime agrees with the file",
+            entry.title.primary_title(),
+            entry.title.year()
+        ),
+        Some(false) => println!("\t         no runtime agrees with the file, the best is kept"),
+        _ => {}
+    }
+}
+
+/// Record the answer and offer to save it as a rule once it was given several times.
+fn offer_rule(
+    rules: &mut Rules,
+    input: &Input,
+    question: Question,
+    folder: &str,
+    answer: &str,
+) -> io::Result<()> {
+    if rules.record(question, folder, answer) {
+        let offer = format!("Always answer \"{}\" for the movies in {}?", answer, folder);
+        if input.confirm(&offer, Some(false)) {
+            rules.add(question, folder, answer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the manifest of the movie in the folder it was renamed to.
+fn write_manifest(entry: &ScanEntry, renames: &Renames) -> io::Result<()> {
+    let movie = match renames.iter().find(|r| r.category == Category::Movie) {
+        Some(movie) => movie,
+        None => return Ok(()),
+    };
+    let dir = match movie.renamed().parent() {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+    let mut manifest = Manifest::new(entry.title, entry.movie.name(), movie.renamed())?;
+    // A movie organized again keeps the name it had before it was first organized.
+    if let Ok(previous) = Manifest::load(dir) {
+        if previous.imdb_id == manifest.imdb_id {
+            manifest.original_filename = previous.original_filename;
+        }
+    }
+    manifest.save(dir)
+}
+
+/// Parse a kind weight written as `kind=weight`.
+/// Load the overrides, along with the IMDB ids of the movies adopted or imported into the library
+/// at the root, unless they were overridden.
+fn load_overrides(root_path: &Path) -> Result<Overrides, Error> {
+    let mut overrides = Overrides::load(Path::new(".merovingian").join("overrides.json"))?;
+    let libraries = Libraries::load(Path::new(".merovingian").join(LIBRARIES_NAME))?;
+    for entry in libraries.entries(root_path) {
+        let path = root_path.join(&entry.path);
+        if overrides.get(&path).is_none() {
+            overrides.insert(path, entry.imdb_id.as_str());
+        }
+    }
+    Ok(overrides)
+}
+
+fn parse_kind_weight(text: &str) -> Option<(TitleKind, f64)> {
+    let mut parts = text.splitn(2, '=');
+    let kind = TitleKind::parse(parts.next()?)?;
+    let weight = parts.next()?.parse().ok()?;
+    Some((kind, weight))
 }
 
 fn foo() -> Result<(), Error> {
     let args = App::from_args();
+    let symlinks = match SymlinkPolicy::parse(&args.symlinks) {
+        Some(symlinks) => symlinks,
+        None => bail!("unknown symlink policy: {}", args.symlinks),
+    };
+    let walk_options = WalkOptions::default()
+        .symlinks(symlinks)
+        .skip_hidden(args.skip_hidden)
+        .skip_nomedia(args.skip_nomedia);
+
+    if let Some(Command::Doctor { ref path }) = args.command {
+        let path = path.as_ref().or(args.path.as_ref());
+        let path = path.map(|s| s.as_str()).unwrap_or(".");
+        if !doctor::run(&args, Path::new(path), Path::new(".merovingian")) {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Stats { ref path, forecast }) = args.command {
+        let path = path.as_ref().or(args.path.as_ref());
+        let mut roots = vec![fs::canonicalize(path.map(|s| s.as_str()).unwrap_or("."))?];
+        if forecast {
+            let mut routes = Routes::new();
+            for text in args.routes.iter() {
+                routes.add(text)?;
+            }
+            roots.extend(routes.roots().map(|root| root.to_owned()));
+        }
+        return forecast::run(&roots, forecast);
+    }
+
+    if let Some(Command::Adopt { ref path }) = args.command {
+        let root_path = fs::canonicalize(path)?;
+        let root = vfs::walk_with(&root_path, &walk_options)?.0;
+        let entries = library::adopt(&root, &root_path);
+        let count = entries.len();
+        fs::create_dir_all(".merovingian")?;
+        let libraries_path = Path::new(".merovingian").join(LIBRARIES_NAME);
+        let mut libraries = Libraries::load(&libraries_path)?;
+        *libraries.entries_mut(&root_path) = entries;
+        libraries.save(&libraries_path)?;
+        println!("Adopted {} movies from {}.", count, root_path.display());
+        return Ok(());
+    }
+
+    if let Some(Command::Import { ref path }) = args.command {
+        let root_path = fs::canonicalize(path)?;
+        let root = vfs::walk_with(&root_path, &walk_options)?.0;
+        let imported = import::import(&root, &root_path);
+        fs::create_dir_all(".merovingian")?;
+
+        // The movies imported replace the movies of the same path adopted or imported before.
+        let libraries_path = Path::new(".merovingian").join(LIBRARIES_NAME);
+        let mut libraries = Libraries::load(&libraries_path)?;
+        libraries
+            .entries_mut(&root_path)
+            .retain(|e| !imported.iter().any(|i| i.path == e.path));
+        let count = |source| imported.iter().filter(|e| e.source == source).count();
+        println!(
+            "Imported {} movies from FileBot and {} from tinyMediaManager in {}.",
+            count(Source::FileBot),
+            count(Source::Tmm),
+            root_path.display()
+        );
+        libraries.entries_mut(&root_path).extend(imported);
+        libraries.save(&libraries_path)?;
+        return Ok(());
+    }
+
+    let max_age = args
+        .dataset_max_age
+        .map(|days| Duration::from_secs(days * 24 * 60 * 60));
+    let mut options = IndexOptions::new()
+        .min_votes(args.min_votes)
+        .adult(args.include_adult)
+        .unreleased(args.unreleased)
+        .fuzzy(args.fuzzy)
+        .min_score(args.min_score)
+        .match_min_votes(args.match_min_votes)
+        .catalog_articles(!args.no_catalog_articles)
+        .offline(args.offline)
+        .crew(args.directors);
+    if let Some(ref url) = args.dataset_url {
+        options = options.dataset_url(url.as_str());
+    }
+    if let Some(ref proxy) = args.proxy {
+        options = options.proxy(proxy.as_str());
+    }
+    if let Some(ref region) = args.preferred_region {
+        options = options.preferred_region(region.as_str());
+    }
+    if let Some(ref language) = args.preferred_language {
+        options = options.preferred_language(language.as_str());
+    }
+    if !args.kinds.is_empty() {
+        let mut kinds = vec![];
+        for kind in args.kinds.iter() {
+            match TitleKind::parse(kind) {
+                Some(kind) => kinds.push(kind),
+                None => bail!("unknown title kind: {}", kind),
+            }
+        }
+        options = options.kinds(&kinds);
+    }
+    if !args.exclude_kinds.is_empty() {
+        let mut kinds = vec![];
+        for kind in args.exclude_kinds.iter() {
+            match TitleKind::parse(kind) {
+                Some(kind) => kinds.push(kind),
+                None => bail!("unknown title kind: {}", kind),
+            }
+        }
+        options = options.exclude_kinds(&kinds);
+    }
+    for text in args.kind_weights.iter() {
+        let (kind, weight) = match parse_kind_weight(text) {
+            Some(kind_weight) => kind_weight,
+            None => bail!("invalid kind weight: {}", text),
+        };
+        options = options.kind_weight(kind, weight);
+    }
+    let mut tokenizer = Tokenizer::new().stemming(args.stemming);
+    if !args.stop_words.is_empty() {
+        let mut languages = vec![];
+        for language in args.stop_words.iter() {
+            match Language::parse(language) {
+                Some(language) => languages.push(language),
+                None => bail!("unknown language: {}", language),
+            }
+        }
+        tokenizer = tokenizer.languages(&languages);
+    }
+    options = options.tokenizer(tokenizer);
+    let mut stats = RunStats::new();
+    let mut progress = TermProgress::new();
+    // The mapped index is used as long as it exists.
+    let mapped_path = Path::new(".merovingian").join(MAPPED_INDEX);
+    if !args.mapped_index && mapped_path.exists() {
+        fs::remove_file(&mapped_path)?;
+    }
+    let imdb = stats.time("index", || -> Result<_, Error> {
+        let imdb = Imdb::load_or_refresh_index(".merovingian", &options, max_age, &mut progress)?;
+        if args.mapped_index && !imdb.is_mapped() {
+            imdb.save_mapped(&mapped_path)?;
+        }
+        Ok(imdb)
+    })?;
+    progress.finish();
+
+    if let Some(Command::Artwork {
+        ref path,
+        missing_only,
+        refresh,
+        ref tmdb_api_key,
+    }) = args.command
+    {
+        let mode = match (missing_only, refresh) {
+            (true, true) => bail!("--missing-only cannot be used with --refresh"),
+            (_, true) => artwork::Mode::Refresh,
+            _ => artwork::Mode::MissingOnly,
+        };
+        let api_key = match tmdb_api_key.clone().or_else(|| env::var("TMDB_API_KEY").ok()) {
+            Some(api_key) => api_key,
+            None => bail!("artwork needs a TMDB API key, set --tmdb-api-key or TMDB_API_KEY"),
+        };
+        let naming = match Naming::parse(&args.naming) {
+            Some(naming) => naming,
+            None => bail!("unknown naming preset: {}", args.naming),
+        };
+        let path = path.as_ref().or(args.path.as_ref());
+        let root_path = fs::canonicalize(path.map(|s| s.as_str()).unwrap_or("."))?;
+        let root = vfs::walk_with(&root_path, &walk_options)?.0;
+        let entries = Scanner::new(&root, &imdb)
+            .overrides(load_overrides(&root_path)?)
+            .scan_root()?;
+        return artwork::run(&root_path, &entries, naming, mode, &Tmdb::new(api_key));
+    }
+
+    if let Some(Command::DeepScan {
+        ref path,
+        ref audio_language,
+    }) = args.command
+    {
+        let path = path.as_ref().or(args.path.as_ref());
+        let root_path = fs::canonicalize(path.map(|s| s.as_str()).unwrap_or("."))?;
+        let root = vfs::walk_with(&root_path, &walk_options)?.0;
+        let entries = Scanner::new(&root, &imdb).scan_root()?;
+        let audio_language = audio_language.as_ref().map(|s| s.as_str());
+        return quality::run(&root_path, &entries, audio_language);
+    }
+
+    if let Some(Command::Verify { ref path }) = args.command {
+        let path = path.as_ref().or(args.path.as_ref());
+        let root_path = fs::canonicalize(path.map(|s| s.as_str()).unwrap_or("."))?;
+        let root = vfs::walk_with(&root_path, &walk_options)?.0;
+        let entries = Scanner::new(&root, &imdb).scan_root()?;
+        return verify::run(&root_path, &entries);
+    }
 
-    let imdb = Imdb::load_or_create_index(".merovingian")?;
+    if let Some(Command::Compare { ref a, ref b }) = args.command {
+        let (a_path, b_path) = (fs::canonicalize(a)?, fs::canonicalize(b)?);
+        let a_root = vfs::walk_with(&a_path, &walk_options)?.0;
+        let b_root = vfs::walk_with(&b_path, &walk_options)?.0;
+        let a_entries = Scanner::new(&a_root, &imdb).scan_root()?;
+        let b_entries = Scanner::new(&b_root, &imdb).scan_root()?;
+        return compare::run(&a_path, &a_entries, &b_path, &b_entries);
+    }
+
+    let path = args.path.as_ref().map(|s| s.as_str()).unwrap_or(".");
+    let remote = args.remote.as_ref().map(Remote::new);
+    let link_root = args.link.as_ref().map(PathBuf::from);
+    if remote.is_some() && link_root.is_some() {
+        bail!("--link cannot be used with --remote");
+    }
+    let owner = Owner::from_env();
+    let path_map = match args.path_map {
+        Some(ref text) => {
+            Some(PathMap::parse(text).ok_or_else(|| format_err!("invalid path map: {}", text))?)
+        }
+        None => PathMap::from_env(),
+    };
+    let naming = match Naming::parse(&args.naming) {
+        Some(naming) => naming,
+        None => bail!("unknown naming preset: {}", args.naming),
+    };
+    let mut budget = Budget::new();
+    for limit in args.budget.iter() {
+        budget.add_limit(limit)?;
+    }
+    for name in args.budget_exempt.iter() {
+        budget.add_exempt(name.as_str());
+    }
+    let mut routes = Routes::new();
+    for text in args.routes.iter() {
+        routes.add(text)?;
+    }
+    if remote.is_none() {
+        routes.check_roots()?;
+    }
+    let plan_filter = PlanFilter::new(
+        args.only_ids.as_ref().map(|s| s.as_str()),
+        args.exclude_ids.as_ref().map(|s| s.as_str()),
+    );
+    let display_path = |path: &Path| match path_map {
+        Some(ref path_map) => path_map.map(path),
+        None => path.to_owned(),
+    };
 
     println!("Index contains {} titles.", imdb.len());
+
+    // The probes are optional, a missing ffprobe is reported once instead of for every movie.
+    let probes = args.check_runtime || args.drop_muxed_subtitles || args.check_container;
+    if remote.is_none() && (probes || args.manifest) {
+        // mediainfo is used instead of a missing ffprobe.
+        match Ffprobe::default().version() {
+            Err(ffprobe::Error::NotFound(_)) if Mediainfo::default().version().is_ok() => {
+                println!("ffprobe not found, the movies are probed with mediainfo.")
+            }
+            Err(err) => println!(
+                "{} {}, the movies are not probed. Set FFPROBE_PATH to the path of ffprobe.",
+                Paint::yellow("warning:"),
+                err
+            ),
+            Ok(_) => {}
+        }
+    }
+
     println!("Scanning folder...");
 
-    let root_path = fs::canonicalize(args.path.as_ref().map(|s| s.as_str()).unwrap_or("."))
-        .expect("unable to canonicalize root path");
-    let root = vfs::walk(&root_path)?;
-    let mut entries = Scanner::new(&root, &imdb).scan_root()?;
+    let (root_path, root) = stats.time("walk", || -> Result<_, Error> {
+        Ok(match remote {
+            Some(ref remote) => {
+                let root_path = PathBuf::from(path);
+                let root = remote.walk(&root_path, &walk_options)?;
+                (root_path, root)
+            }
+            None => {
+                let root_path = fs::canonicalize(path).expect("unable to canonicalize root path");
+                let (root, timing) = vfs::walk_with(&root_path, &walk_options)?;
+                if timing.is_slow() {
+                    println!(
+                        "{} {} is slow to list, {:.0}ms per file on average. A network share is \
+                         faster to organize from the machine hosting it with --remote.",
+                        Paint::yellow("warning:"),
+                        display_path(&root_path).display(),
+                        timing.average_ms()
+                    );
+                }
+                (root_path, root)
+            }
+        })
+    })?;
+    let dest_root = link_root.clone().unwrap_or_else(|| root_path.clone());
+    // A library on a read-only mount is only reported, unless it is left untouched with --link.
+    let read_only_root = match (&remote, &link_root) {
+        (None, None) => check_writable(&root_path).err(),
+        _ => None,
+    };
+    let mut writable_roots = HashMap::new();
+    let mut is_read_only = |entry_root: &Path| {
+        if read_only_root.is_some() {
+            return true;
+        }
+        // The roots of the routes are checked once, a missing root is created when renaming.
+        remote.is_none() && !*writable_roots.entry(entry_root.to_owned()).or_insert_with(|| {
+            !entry_root.exists() || check_writable(entry_root).is_ok()
+        })
+    };
+
+    // The subtitle archives are extracted before the scan, which finds the subtitles in the Subs
+    // folders. The archives that could not be extracted are kept.
+    let mut kept_archives = vec![];
+    let root = if args.extract_subtitles && remote.is_none() && link_root.is_none() {
+        let (archives, errors) = archive::find(&root);
+        for (file, err) in errors {
+            println!(
+                "{} Could not list {}: {}",
+                Paint::yellow("warning:"),
+                display_path(file.path()).display(),
+                err
+            );
+            kept_archives.push(file.path().to_owned());
+        }
+        let mut extracted = false;
+        for archive in archives.iter() {
+            println!(
+                "Subtitle archive {}, {} subtitles",
+                display_path(archive.file.path()).display(),
+                archive.subtitles.len()
+            );
+            if !args.apply || read_only_root.is_some() {
+                continue;
+            }
+            match archive.extract() {
+                Ok(paths) => {
+                    println!(
+                        "=> Extracted {} subtitles into {}",
+                        paths.len(),
+                        display_path(&archive.dest()).display()
+                    );
+                    extracted = true;
+                }
+                Err(err) => {
+                    println!("=> Could not extract: {}", err);
+                    kept_archives.push(archive.file.path().to_owned());
+                }
+            }
+        }
+        if extracted {
+            vfs::walk_with(&root_path, &walk_options)?.0
+        } else {
+            root
+        }
+    } else {
+        root
+    };
+
+    let overrides = load_overrides(&root_path)?;
+    let local = Local::new(owner).link(link_root.is_some());
+    let target: &dyn Target = match remote {
+        Some(ref remote) => remote,
+        None => &local,
+    };
+    // The probes of the files that did not change since the last run are not run again.
+    let probe_cache_path = Path::new(".merovingian").join("probes.json");
+    let probe_cache = ProbeCache::load(&probe_cache_path)?;
+    let mut entries = stats.time("match", || {
+        let mut scanner = Scanner::new(&root, &imdb)
+            .overrides(overrides)
+            .probe_cache(&probe_cache)
+            .check_runtime(args.check_runtime)
+            .probe_subtitles(args.drop_muxed_subtitles)
+            .check_container(args.check_container)
+            .keep_audio(args.keep_audio)
+            .target(target)
+            .listener(TermProgress::new());
+        if let Some(ref host) = args.remote {
+            scanner = scanner.remote(host.as_str());
+        }
+        scanner.scan_root()
+    })?;
+    if let Err(err) = probe_cache.save(&probe_cache_path) {
+        println!("{} could not save the probes: {}", Paint::yellow("warning:"), err);
+    }
     let mut cleaner = Cleaner::new();
+    for file in root.descendants() {
+        if kept_archives.iter().any(|path| path == file.path()) {
+            cleaner.mark_file(&file);
+        }
+    }
+    let mut plan = Plan::default();
+    let input = Input::new();
+    let mut rules = Rules::load(Path::new(".merovingian").join("rules.txt"))?;
 
-    println!("Scan found {} movies.", entries.len());
+    println!(
+        "Scan found {} movies in {}.",
+        entries.len(),
+        display_path(&root_path).display()
+    );
     println!();
 
+    if let Some(ref err) = read_only_root {
+        println!(
+            "{} {} is read-only ({}), the changes are only reported. Use --link to organize it \
+             into a library of links elsewhere.",
+            Paint::yellow("warning:"),
+            display_path(&root_path).display(),
+            err
+        );
+        println!();
+    }
+
+    let warnings = warning::check(&root, &entries);
+    if !warnings.is_empty() {
+        println!("Folders that should be reorganized by hand:");
+        for warning in warnings.iter() {
+            println!("{} {}", Paint::yellow("warning:"), warning);
+        }
+        println!();
+    }
+
+    // The certifications of the titles routed by the family route are looked up on TMDB, once.
+    let certifications_path = Path::new(".merovingian").join("certifications.json");
+    let mut certifications =
+        Certifications::load(&certifications_path, &args.certification_country)?;
+    if let Ok(api_key) = env::var("TMDB_API_KEY") {
+        let tmdb = Tmdb::new(api_key);
+        let routed = entries.iter().filter(|entry| routes.needs_certification(&entry.title));
+        for entry in routed {
+            if let Err(err) = certifications.fetch(&tmdb, entry.title.id()) {
+                println!("{} {}: {}", Paint::yellow("warning:"), entry.movie.name(), err)
+            }
+        }
+        if let Err(err) = certifications.save(&certifications_path) {
+            println!("{} could not save the certifications: {}", Paint::yellow("warning:"), err);
+        }
+    }
+    let certification = |id: u32| certifications.get(id);
+
+    // The movies on another device are copied, show the progress of the whole plan.
+    if args.apply && remote.is_none() && link_root.is_none() {
+        let mut plan_total = 0;
+        for entry in entries.iter() {
+            let certification = certification(entry.title.id());
+            let entry_root = routes.root(&entry.title, certification, &dest_root);
+            if !is_read_only(entry_root) && is_cross_device(entry.movie.path(), entry_root) {
+                let renames = Renames::new(entry_root, &entry, naming);
+                plan_total += renames.iter().map(|r| r.orig.len()).sum::<u64>();
+            }
+        }
+        progress.set_plan_total(plan_total);
+    }
+
+    let planner = Planner::new(naming)
+        .link(link_root.is_some())
+        .budget(&budget)
+        .filter(&plan_filter);
     for entry in entries.iter_mut() {
         cleaner.mark(&entry);
-        let renames = Renames::new(&root_path, &entry);
+        let entry_root = routes.root(&entry.title, certification(entry.title.id()), &dest_root);
+        let read_only = is_read_only(entry_root);
+        let impossible = if read_only {
+            format!(" {}", Paint::yellow("(impossible, read-only)"))
+        } else {
+            String::new()
+        };
+
+        let Planned {
+            renames,
+            stale,
+            entry: planned,
+        } = planner.entry(&entry, entry_root, read_only);
 
         if !renames.is_empty() {
             println!("\tFile: {}", Paint::yellow(entry.movie.name()));
             println!(
-                "\tMatch: {} ({}) | {}",
+                "\tMatch: {} ({}, {:.1}/10) | {}",
                 Paint::yellow(format!(
                     "{} ({})",
-                    entry.title.primary_title(),
+                    entry.title.display_title(),
                     entry.title.year()
                 )).underline(),
                 format_runtime(entry.title.runtime()),
+                entry.title.rating(),
                 Paint::new(format!("https://imdb.com/title/tt{:07}/", entry.title.id()))
                     .underline(),
             );
 
+            if !entry.title.directors().is_empty() {
+                println!("\tDirected by: {}", entry.title.directors().join(", "));
+            }
+
+            if let Some(certification) = certification(entry.title.id()) {
+                println!("\tCertification: {}", certification);
+            }
+
+            match entry.confidence {
+                Confidence::High => println!("\tConfidence: {}", Paint::green("high")),
+                Confidence::Normal => {}
+                Confidence::Low => println!(
+                    "\tConfidence: {} (parent folder disagrees)",
+                    Paint::red("low")
+                ),
+            }
+
+            match entry.container_mismatch {
+                Some(ContainerMismatch::Format(ref format)) => println!(
+                    "\tContainer: {} (not .{})",
+                    Paint::red(format),
+                    entry.movie.extension().unwrap_or_default()
+                ),
+                Some(ContainerMismatch::Unreadable) => {
+                    println!("\tContainer: {}", Paint::red("unreadable by ffprobe"))
+                }
+                None => {}
+            }
+
+            if args.explain {
+                print_explanation(&imdb, &entry);
+            }
+
             println!();
 
-            for rename in renames.iter() {
+            for op in planned.operations.iter() {
                 println!(
-                    "{}",
-                    Paint::red(rename.orig().strip_prefix(&root_path).unwrap().display())
+                    "{} {}{}",
+                    op.id,
+                    Paint::red(op.from.strip_prefix(&root_path).unwrap().display()),
+                    impossible
                 );
             }
-            for rename in renames.iter() {
+            for op in planned.operations.iter() {
+                let to = op.to.as_ref().unwrap();
+                println!("{}", Paint::green(to.strip_prefix(entry_root).unwrap().display()));
+            }
+
+            for path in planned.over_budget.iter() {
                 println!(
-                    "{}",
-                    Paint::green(rename.renamed().strip_prefix(&root_path).unwrap().display())
+                    "{} (over budget, left in place)",
+                    Paint::yellow(path.strip_prefix(&root_path).unwrap().display())
                 );
             }
 
-            if args.apply {
-                if let Err(err) = renames.apply() {
-                    println!("=> Could not rename movie: {}", err);
+            if args.apply && read_only {
+                println!("=> Skipped, read-only");
+            } else if args.apply {
+                let folder = rule_folder(entry.movie.path(), &root_path);
+                let skip = entry.confidence == Confidence::Low && {
+                    let prompt = "Apply the renames for this low confidence match?";
+                    match rules.answer(Question::LowConfidence, &folder) {
+                        Some(answer) => answer == "skip",
+                        None => {
+                            let answer = if input.confirm(prompt, Some(false)) {
+                                "apply"
+                            } else {
+                                "skip"
+                            };
+                            let question = Question::LowConfidence;
+                            offer_rule(&mut rules, &input, question, &folder, answer)?;
+                            answer == "skip"
+                        }
+                    }
+                };
+                if skip {
+                    println!("=> Skipped");
+                } else {
+                    let res = stats.time("apply", || plan::apply(target, &renames, &mut progress));
+                    match res {
+                        Ok(Some((path, reason))) => {
+                            let path = display_path(path);
+                            println!("=> Skipped, {} {} since the scan", path.display(), reason);
+                        }
+                        Ok(None) => {
+                            if link_root.is_none() {
+                                stats.add_bytes_moved(renames.iter().map(|r| r.orig.len()).sum());
+                            }
+                            // Manifests are written locally, the remote files cannot be probed.
+                            if args.manifest && remote.is_none() {
+                                if let Err(err) = write_manifest(&entry, &renames) {
+                                    println!("=> Could not write manifest: {}", err);
+                                }
+                            }
+                        }
+                        Err(err) => println!("=> Could not rename movie: {}", err),
+                    }
                 }
             }
 
             println!();
         }
-    }
 
-    println!("Files that will be removed:");
+        // The stale companions are left alone when building a library of links.
+        if !stale.is_empty() {
+            println!("\tStale companions of: {}", Paint::yellow(entry.movie.name()));
+            println!();
+            for op in planned.stale.iter() {
+                let to = op.to.as_ref().unwrap();
+                println!(
+                    "{} {} -> {}{}",
+                    op.id,
+                    Paint::red(op.from.strip_prefix(&root_path).unwrap().display()),
+                    Paint::green(to.strip_prefix(&root_path).unwrap().display()),
+                    impossible
+                );
+            }
 
-    for file in root.descendants() {
-        if file.is_file() && !cleaner.is_marked(&file) {
-            println!("{}", Paint::red(file.path().display()));
-            if args.apply {
-                if let Err(err) = fs::remove_file(file.path()) {
-                    println!("=> Could not remove {}: {}", file.path().display(), err);
+            if args.apply && read_only {
+                for rename in stale.iter() {
+                    cleaner.mark_file(&rename.orig);
+                }
+                println!("=> Skipped, read-only");
+            } else if args.apply {
+                let choices = [("r", "rename"), ("d", "delete"), ("k", "keep")];
+                let question = "What to do with the stale companions?";
+                let folder = rule_folder(entry.movie.path(), &root_path);
+                let saved = rules.answer(Question::Stale, &folder).and_then(|answer| {
+                    choices.iter().find(|&&(_, name)| name == answer).map(|&(code, _)| code)
+                });
+                let choice = match saved {
+                    Some(code) => code,
+                    None => {
+                        let code = input.select(question, &choices, Some("k"));
+                        let name = choices.iter().find(|&&(c, _)| c == code).unwrap().1;
+                        offer_rule(&mut rules, &input, Question::Stale, &folder, name)?;
+                        code
+                    }
+                };
+                if choice != "d" {
+                    for rename in stale.iter() {
+                        cleaner.mark_file(&rename.orig);
+                    }
+                }
+                if choice == "r" {
+                    match plan::apply(target, &stale, &mut progress) {
+                        Ok(Some((path, reason))) => {
+                            let path = display_path(path);
+                            println!("=> Skipped, {} {} since the scan", path.display(), reason);
+                        }
+                        Ok(None) => {}
+                        Err(err) => println!("=> Could not rename stale companions: {}", err),
+                    }
+                }
+            } else {
+                // The stale companions are offered a rename, the cleanup does not list them too.
+                for rename in stale.iter() {
+                    cleaner.mark_file(&rename.orig);
                 }
             }
+
+            println!();
         }
+
+        plan.entries.push(planned);
     }
 
-    // Remove all the empty directories.
-    if args.apply {
-        for file in root.descendants() {
-            if file.is_dir() {
-                //println!("Trying to remove {}", file.path().display());
-                let _ = fs::remove_dir(file.path());
+    // The original library is left untouched when building a library of links.
+    if link_root.is_some() {
+        if let Some(ref path) = args.export_plan {
+            plan.save(path)?;
+        }
+        println!("Run report: {}", stats);
+        return Ok(());
+    }
+
+    let clean_start = Instant::now();
+
+    // Each library has its own list, the removals of another one are not new.
+    let host = args.remote.as_ref().map(|s| s.as_str());
+    let cleanup_list_path = Path::new(".merovingian")
+        .join(format!("cleanup-{}.txt", util::library_key(host, &root_path)));
+    let previous_cleanup = history::load_cleanup_list(&cleanup_list_path)?;
+    let mut cleanup = vec![];
+    let mut hidden = 0;
+
+    let impossible = if read_only_root.is_some() {
+        format!(" {}", Paint::yellow("(impossible, read-only)"))
+    } else {
+        String::new()
+    };
+    println!("Files that will be removed:");
+
+    for file in plan::unused_files(&root, &cleaner) {
+        // The removals left out by the filter are still listed, they are not new next time.
+        cleanup.push(file.path().to_owned());
+        let removal = Operation::remove(file.path(), read_only_root.is_some());
+        if !plan_filter.allows(&removal.id) {
+            continue;
+        }
+        if !previous_cleanup.contains(file.path()) {
+            println!(
+                "{} {} {}{}",
+                removal.id,
+                Paint::red(display_path(file.path()).display()).bold(),
+                Paint::yellow("(new)"),
+                impossible
+            );
+        } else if args.new_only {
+            // The removals that are not shown are not applied either.
+            hidden += 1;
+            continue;
+        } else {
+            let path = display_path(file.path());
+            println!("{} {}{}", removal.id, Paint::red(path.display()), impossible);
+        }
+        plan.removals.push(removal);
+        if args.apply && read_only_root.is_none() {
+            match plan::remove(target, &file, &mut progress) {
+                Ok(Some(reason)) => println!("=> Skipped, {} since the scan", reason),
+                Ok(None) => {}
+                Err(err) => println!(
+                    "=> Could not remove {}: {}",
+                    display_path(file.path()).display(),
+                    err
+                ),
             }
         }
     }
 
+    if hidden > 0 {
+        println!(
+            "{} files already listed by the previous run are not shown nor removed.",
+            hidden
+        );
+    }
+
+    history::save_cleanup_list(&cleanup_list_path, &cleanup)?;
+    if let Some(ref path) = args.export_plan {
+        plan.save(path)?;
+    }
+
+    // Remove all the empty directories.
+    if args.apply && read_only_root.is_none() {
+        plan::remove_empty_dirs(target, &root);
+    }
+
+    stats.add_time("clean", clean_start.elapsed());
+    println!();
+    println!("Run report: {}", stats);
+
     Ok(())
 }
 
+/// Tell if the file has to be copied to reach the root. When the devices cannot be compared, such
+/// as when the root does not exist yet, the file is assumed to be copied with a warning.
+fn is_cross_device(path: &Path, root: &Path) -> bool {
+    !same_device(path, root).unwrap_or_else(|err| {
+        println!(
+            "{} cannot compare the devices of {} and {}, assuming a copy: {}",
+            Paint::yellow("warning:"),
+            path.display(),
+            root.display(),
+            err
+        );
+        false
+    })
+}
+
 fn main() {
     if let Err(e) = foo() {
         println!("{}", e);
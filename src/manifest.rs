@@ -0,0 +1,167 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ffprobe;
+use serde_json;
+
+use imdb::Title;
+use util::Fnv1a;
+
+/// Name of the manifest written in each movie folder.
+pub const MANIFEST_NAME: &str = "merovingian.json";
+/// Bytes hashed at the start and at the end of the movie file by `quick_hash`.
+const HASH_CHUNK: u64 = 1024 * 1024;
+
+/// A chapter of the movie, its start in seconds.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChapterSummary {
+    pub start: f64,
+    pub title: Option<String>,
+}
+
+/// Summary of a stream reported by ffprobe.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct StreamSummary {
+    pub codec: String,
+    pub language: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProbeSummary {
+    pub width: u32,
+    pub height: u32,
+    pub video: Vec<StreamSummary>,
+    pub audio: Vec<StreamSummary>,
+    pub subtitles: Vec<StreamSummary>,
+    /// Channels of the first audio stream, such as `5.1` or `7.1 Atmos`.
+    #[serde(default)]
+    pub audio_channels: Option<String>,
+    #[serde(default)]
+    pub chapters: Vec<ChapterSummary>,
+}
+
+/// What the organizer knows about the movie of a folder, saved next to it so that other tools and
+/// later runs do not have to match it again.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub imdb_id: String,
+    pub title: String,
+    pub year: i32,
+    /// Name of the movie file before it was organized.
+    pub original_filename: String,
+    /// Name of the movie file in the folder.
+    pub filename: String,
+    /// Seconds since the Unix epoch.
+    pub organized_at: u64,
+    pub size: u64,
+    /// See `quick_hash`.
+    pub quick_hash: String,
+    pub probe: Option<ProbeSummary>,
+}
+
+impl Manifest {
+    /// Describe the movie file found at `path`, which was called `original_filename`. The movie
+    /// file is probed with ffprobe, the probe is left out when ffprobe fails.
+    pub fn new(title: &Title, original_filename: &str, path: &Path) -> io::Result<Manifest> {
+        let organized_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Ok(Manifest {
+            imdb_id: format!("tt{:07}", title.id()),
+            title: title.primary_title().to_string(),
+            year: title.year(),
+            original_filename: original_filename.to_string(),
+            filename: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            organized_at,
+            size: fs::metadata(path)?.len(),
+            quick_hash: quick_hash(path)?,
+            probe: probe_summary(path),
+        })
+    }
+
+    pub fn load(dir: &Path) -> io::Result<Manifest> {
+        let file = File::open(dir.join(MANIFEST_NAME))?;
+        serde_json::from_reader(file).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let file = File::create(dir.join(MANIFEST_NAME))?;
+        serde_json::to_writer_pretty(file, self)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+}
+
+/// Hash the size of the file and its first and last megabyte with FNV-1a. Hashing whole movies
+/// takes minutes, this is enough to tell copies of a movie apart from other files.
+pub fn quick_hash(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut hash = Fnv1a::new();
+    hash.feed(&len.to_le_bytes());
+
+    let mut buf = vec![];
+    (&mut file).take(HASH_CHUNK).read_to_end(&mut buf)?;
+    hash.feed(&buf);
+    if len > 2 * HASH_CHUNK {
+        buf.clear();
+        file.seek(SeekFrom::End(-(HASH_CHUNK as i64)))?;
+        file.read_to_end(&mut buf)?;
+        hash.feed(&buf);
+    }
+
+    Ok(format!("{:016x}", hash.finish()))
+}
+
+fn probe_summary(path: &Path) -> Option<ProbeSummary> {
+    let info = ffprobe::scan(path).ok()?;
+    let summary = |codec: &str, language: Option<&str>| StreamSummary {
+        codec: codec.to_string(),
+        language: language.map(|s| s.to_string()),
+    };
+    Some(ProbeSummary {
+        width: info.video.first().map(|v| v.width).unwrap_or(0),
+        height: info.video.first().map(|v| v.height).unwrap_or(0),
+        video: info.video.iter().map(|s| summary(&s.codec_name, s.language())).collect(),
+        audio: info.audio.iter().map(|s| summary(&s.codec_name, s.language())).collect(),
+        subtitles: info.subtitle.iter().map(|s| summary(&s.codec_name, s.language())).collect(),
+        audio_channels: info.audio_channels(),
+        chapters: info
+            .chapters
+            .iter()
+            .map(|chapter| ChapterSummary {
+                start: chapter.start,
+                title: chapter.title.clone(),
+            })
+            .collect(),
+    })
+}
+
+#[test]
+fn test_manifest_round_trip() {
+    use test_util::heat;
+    use std::env;
+    use std::process;
+
+    let dir = env::temp_dir().join(format!("mero-manifest-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let movie = dir.join("Heat (1995).mkv");
+    fs::write(&movie, b"movie").unwrap();
+
+    let title = heat();
+    let manifest = Manifest::new(&title, "Heat.1995.1080p.mkv", &movie).unwrap();
+    manifest.save(&dir).unwrap();
+    let loaded = Manifest::load(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(loaded, manifest);
+    assert_eq!(loaded.imdb_id, "tt0113277");
+    assert_eq!(loaded.filename, "Heat (1995).mkv");
+    assert_eq!(loaded.size, 5);
+}
@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File as FsFile};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use failure::Error;
+use serde_json;
+
+use imdb::parse_id;
+
+/// IMDB ids of movie files identified before, such as by another tool, trusted over the matching
+/// of their names. The file is a JSON object of the paths of the movie files to their IMDB ids.
+#[derive(Debug, Default)]
+pub struct Overrides {
+    ids: BTreeMap<PathBuf, String>,
+}
+
+impl Overrides {
+    /// Load the overrides file, a missing file has no overrides.
+    pub fn load(path: impl AsRef<Path>) -> Result<Overrides, Error> {
+        let path = path.as_ref();
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Overrides::default())
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let ids = serde_json::from_str(&content)
+            .map_err(|err| format_err!("invalid overrides in {}: {}", path.display(), err))?;
+        Ok(Overrides { ids })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = FsFile::create(path)?;
+        serde_json::to_writer_pretty(file, &self.ids)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+
+    /// Get the IMDB id of the movie file, if it has a valid one.
+    pub fn get(&self, path: &Path) -> Option<u32> {
+        self.ids.get(path).and_then(|id| parse_id(id))
+    }
+
+    /// Set the IMDB id of the movie file, replacing the previous one.
+    pub fn insert(&mut self, path: impl Into<PathBuf>, imdb_id: impl Into<String>) {
+        self.ids.insert(path.into(), imdb_id.into());
+    }
+}
+
+#[test]
+fn test_overrides() {
+    use std::process;
+
+    let path = ::std::env::temp_dir().join(format!("mero-overrides-{}.json", process::id()));
+    let heat = Path::new("/movies/Heat (1995)/Heat (1995).mkv");
+    assert_eq!(Overrides::load(&path).unwrap().get(heat), None);
+
+    let mut overrides = Overrides::default();
+    overrides.insert("/movies/Heat (1995)/Heat (1995).mkv", "tt0113277");
+    overrides.insert("/movies/Bogus.mkv", "bogus");
+    overrides.save(&path).unwrap();
+
+    let overrides = Overrides::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(overrides.get(heat), Some(113277));
+    assert_eq!(overrides.get(Path::new("/movies/Bogus.mkv")), None);
+    assert_eq!(overrides.get(Path::new("/movies/Other.mkv")), None);
+}
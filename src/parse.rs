@@ -1,6 +1,9 @@
 use std::cmp;
 use std::collections::HashSet;
 
+use imdb::current_year;
+use imdb::tokenize::text_to_words;
+
 lazy_static! {
     static ref QUALITY: HashSet<&'static str> = hashset!{
         "2160p",
@@ -37,33 +40,83 @@ lazy_static! {
 }
 
 pub fn tokenize_filename(name: &str) -> Vec<String> {
-    let mut tokens = Vec::new();
-    let mut pos = 0;
-
-    for (idx, car) in name.char_indices() {
-        match car {
-            ' ' | '.' | '_' | '-' | ':' | '(' | ')' | '[' | ']' => {
-                let text = &name[pos..idx];
-                if !text.is_empty() {
-                    tokens.push(text.to_lowercase());
-                }
+    // Use the normalization of the index so that parsed names and indexed titles agree.
+    let mut words = Vec::new();
+    text_to_words(name, &mut words);
+    words
+}
 
-                pos = idx + car.len_utf8();
-            }
-            _ => {}
+/// Get the vertical resolution written in the file name, such as 1080 for `1080p`.
+pub fn parse_resolution(filename: &str) -> Option<u32> {
+    tokenize_filename(filename)
+        .iter()
+        .find(|token| QUALITY.contains(token.as_str()))
+        .and_then(|token| token.trim_right_matches('p').parse().ok())
+}
+
+/// Tell if the token is a TV episode number, such as `s01e02`.
+fn is_episode_token(token: &str) -> bool {
+    if !token.starts_with('s') {
+        return false;
+    }
+    let mut parts = token[1..].splitn(2, 'e');
+    match (parts.next(), parts.next()) {
+        (Some(season), Some(episode)) => {
+            !season.is_empty()
+                && !episode.is_empty()
+                && season.chars().all(|c| c.is_ascii_digit())
+                && episode.chars().all(|c| c.is_ascii_digit())
         }
+        _ => false,
     }
+}
+
+/// Tell if the file name looks like the name of a TV episode.
+pub fn is_episode(filename: &str) -> bool {
+    tokenize_filename(filename)
+        .iter()
+        .any(|token| is_episode_token(token))
+}
+
+/// Year of the first motion pictures, earlier numbers are not years.
+const FIRST_YEAR: i32 = 1888;
 
-    let text = &name[pos..];
-    if !text.is_empty() {
-        tokens.push(text.to_lowercase());
+fn is_year(token: &str) -> bool {
+    if token.len() != 4 || !token.chars().all(|c| char::is_digit(c, 10)) {
+        return false;
     }
+    // Movies are released at most a year from now, a larger number is part of the title such as
+    // in 'Blade Runner 2049'.
+    let year: i32 = token.parse().unwrap();
+    year >= FIRST_YEAR && year <= current_year() + 1
+}
 
-    tokens
+/// Tell if the tokens are a day and a month in any order, such as `14` and `02`. Both are written
+/// with two digits, so that audio channels such as `5.1` after a year are not taken for a date.
+fn is_day_month(a: &str, b: &str) -> bool {
+    let parse = |token: &str| -> Option<u32> {
+        if token.len() != 2 || !token.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        token.parse().ok().filter(|&n| n >= 1 && n <= 31)
+    };
+    match (parse(a), parse(b)) {
+        (Some(a), Some(b)) => a <= 12 || b <= 12,
+        _ => false,
+    }
 }
 
-fn is_year(token: &str) -> bool {
-    return token.len() == 4 && token.chars().all(|c| char::is_digit(c, 10));
+/// Find where a date such as `2023-02-14` or `14.02.2023` containing the year at the index starts.
+/// Dates are found in the names of videos from cameras and phones, their year is not a release
+/// year.
+fn date_start(tokens: &[String], year_idx: usize) -> Option<usize> {
+    if year_idx + 2 < tokens.len() && is_day_month(&tokens[year_idx + 1], &tokens[year_idx + 2]) {
+        Some(year_idx)
+    } else if year_idx >= 2 && is_day_month(&tokens[year_idx - 2], &tokens[year_idx - 1]) {
+        Some(year_idx - 2)
+    } else {
+        None
+    }
 }
 
 /// Try to extract title and year from filename.
@@ -85,14 +138,22 @@ pub fn parse_movie(filename: &str) -> (String, Option<i32>) {
     let mut first_metadata_token = None;
 
     for (idx, token) in tokens.iter().enumerate() {
+        let mut is_metadata = ALL.contains(token.as_str());
         if is_year(token) {
-            year_candidates.push(idx);
-        }
-        if first_metadata_token.is_none() {
-            if ALL.contains(token.as_str()) {
-                first_metadata_token = Some(idx);
+            match date_start(&tokens, idx) {
+                // The title stops before a date.
+                Some(start) => {
+                    if first_metadata_token.map(|first| start < first).unwrap_or(true) {
+                        first_metadata_token = Some(start);
+                    }
+                    is_metadata = false;
+                }
+                None => year_candidates.push(idx),
             }
         }
+        if first_metadata_token.is_none() && is_metadata {
+            first_metadata_token = Some(idx);
+        }
     }
 
     let first_metadata_token = first_metadata_token.unwrap_or(tokens.len());
@@ -123,6 +184,53 @@ pub fn parse_movie(filename: &str) -> (String, Option<i32>) {
 fn test_is_year() {
     assert!(is_year("2009"));
     assert!(!is_year("1080p"));
+    assert!(!is_year("1080"));
+    assert!(!is_year("2999"));
+}
+
+#[test]
+fn test_future_year() {
+    // The year in the title is past the current year whenever the test runs.
+    let future = current_year() + 2;
+    assert_eq!(
+        parse_movie(&format!("Blade Runner {}", future)),
+        (format!("blade runner {}", future), None)
+    );
+    assert_eq!(
+        parse_movie(&format!("Blade.Runner.{}.2017.1080p", future)),
+        (format!("blade runner {}", future), Some(2017))
+    );
+}
+
+#[test]
+fn test_date() {
+    assert_eq!(parse_movie("VID 2023-02-14 Birthday"), ("vid".into(), None));
+    assert_eq!(parse_movie("Wedding.14.02.2015"), ("wedding".into(), None));
+    assert_eq!(
+        parse_movie("Apollo 13 1995 1080p"),
+        ("apollo 13".into(), Some(1995))
+    );
+    // Audio channels after the year are not a day and a month.
+    assert_eq!(
+        parse_movie("Heat.1995.5.1.x264"),
+        ("heat".into(), Some(1995))
+    );
+    assert_eq!(
+        parse_movie("Heat 1995 7 1 Atmos"),
+        ("heat".into(), Some(1995))
+    );
+    assert_eq!(
+        parse_movie("Heat.1995.2.0.AAC"),
+        ("heat".into(), Some(1995))
+    );
+}
+
+#[test]
+fn test_is_episode() {
+    assert!(is_episode("The.Wire.S01E02.720p.mkv"));
+    assert!(is_episode("the wire s1e2"));
+    assert!(!is_episode("Se7en 1995"));
+    assert!(!is_episode("s01 e02"));
 }
 
 #[test]
@@ -141,6 +249,18 @@ fn test_tokenize_filename_simple() {
     assert_eq!(tokens, vec!["american", "psycho"]);
 }
 
+#[test]
+fn test_tokenize_filename_matches_index() {
+    let mut tags = Vec::new();
+    let names = ["Schindler's.List", "Don't.Look.Up", "Crouching Tiger, Hidden Dragon", "Amélie"];
+    for name in &names {
+        imdb::tokenize::text_to_tags(name, &mut tags);
+        assert_eq!(tokenize_filename(name), tags);
+    }
+    // Unlike the tags, the words of a name keep its stop words.
+    assert_eq!(tokenize_filename("The.Matrix"), vec!["the", "matrix"]);
+}
+
 #[test]
 fn test_tokenize_filename_parens_square() {
     let tokens = tokenize_filename("American.Psycho.(2000).[1080p]");
@@ -186,3 +306,29 @@ fn test_year_within_scope() {
         ("night of the living dead".into(), Some(1968))
     )
 }
+
+#[test]
+fn test_apostrophes_and_commas() {
+    use imdb::{Imdb, Title, TitleKind};
+
+    // The apostrophes and commas split the words like the index does.
+    let schindler = parse_movie("Schindler's.List.1993.1080p.mkv");
+    assert_eq!(schindler, ("schindler list".into(), Some(1993)));
+    let tiger = parse_movie("Crouching Tiger, Hidden Dragon (2000).mkv");
+    assert_eq!(tiger, ("crouching tiger hidden dragon".into(), Some(2000)));
+
+    let imdb = Imdb::from_titles(vec![
+        Title::new(108052, "Schindler's List", 1993, TitleKind::Movie, 1200000),
+        Title::new(190332, "Crouching Tiger, Hidden Dragon", 2000, TitleKind::Movie, 260000),
+    ]);
+    let id = |(name, year): (String, Option<i32>)| imdb.lookup(&name, year).map(|t| t.id());
+    assert_eq!(id(schindler), Some(108052));
+    assert_eq!(id(tiger), Some(190332));
+}
+
+#[test]
+fn test_parse_resolution() {
+    assert_eq!(parse_resolution("Heat.1995.1080p.BluRay.x264.mkv"), Some(1080));
+    assert_eq!(parse_resolution("Heat (1995) [2160p].mkv"), Some(2160));
+    assert_eq!(parse_resolution("Heat (1995).mkv"), None);
+}
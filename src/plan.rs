@@ -0,0 +1,334 @@
+use std::cmp::Reverse;
+use std::fs::{self, File as FsFile};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+use budget::Budget;
+use container::Owner;
+use events::Listener;
+use fault;
+use rename::{format_base, stale_companions, Category, Cleaner, Naming, Rename, Renames};
+use scan::{Confidence, ScanEntry};
+use util::{plan_id, PlanFilter};
+use vfs::File;
+
+/// What an operation of the plan does to its file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Move the file to its organized path.
+    Rename,
+    /// Leave the file in place and link to it from its organized path.
+    Link,
+    /// Remove the file, which belongs to no movie.
+    Remove,
+}
+
+/// An operation of the plan, with the identifier shown next to it in the preview.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Operation {
+    pub id: String,
+    pub action: Action,
+    pub from: PathBuf,
+    /// Organized path of the file, `None` for the removals.
+    pub to: Option<PathBuf>,
+    /// Category of the file, `None` for the removals.
+    pub category: Option<Category>,
+    /// The operation cannot be applied, such as on a read-only library.
+    pub impossible: bool,
+}
+
+impl Operation {
+    pub fn rename(rename: &Rename, action: Action, impossible: bool) -> Operation {
+        Operation {
+            id: rename.id(),
+            action,
+            from: rename.orig().to_owned(),
+            to: Some(rename.renamed().to_owned()),
+            category: Some(rename.category),
+            impossible,
+        }
+    }
+
+    pub fn remove(path: &Path, impossible: bool) -> Operation {
+        Operation {
+            id: plan_id("rm", &[path]),
+            action: Action::Remove,
+            from: path.to_owned(),
+            to: None,
+            category: None,
+            impossible,
+        }
+    }
+}
+
+/// A movie of the plan, with its match and the operations organizing it.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlannedEntry {
+    pub file: PathBuf,
+    pub imdb_id: String,
+    pub title: String,
+    pub year: i32,
+    pub confidence: Confidence,
+    pub operations: Vec<Operation>,
+    /// Companion files left in place because they are over the budget.
+    pub over_budget: Vec<PathBuf>,
+    /// Renames of the companions named after another movie, applied when asked.
+    pub stale: Vec<Operation>,
+}
+
+impl PlannedEntry {
+    pub fn new(entry: &ScanEntry, renames: &Renames, action: Action, impossible: bool) -> Self {
+        PlannedEntry {
+            file: entry.movie.path().to_owned(),
+            imdb_id: format!("tt{:07}", entry.title.id()),
+            title: entry.title.display_title().to_string(),
+            year: entry.title.year(),
+            confidence: entry.confidence,
+            operations: renames
+                .iter()
+                .map(|r| Operation::rename(r, action, impossible))
+                .collect(),
+            over_budget: vec![],
+            stale: vec![],
+        }
+    }
+}
+
+/// Everything a run does to the library: the movies organized and the files removed.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub entries: Vec<PlannedEntry>,
+    /// Files belonging to no movie, removed by the cleanup.
+    pub removals: Vec<Operation>,
+}
+
+impl Plan {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = FsFile::create(path)?;
+        serde_json::to_writer_pretty(file, self)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+}
+
+/// The renames planned for a movie, along with their operations in the plan.
+pub struct Planned {
+    pub renames: Renames,
+    /// Renames of the companions named after another movie, empty when building links.
+    pub stale: Renames,
+    pub entry: PlannedEntry,
+}
+
+/// Builds the plan of each movie: its renames within the budget and the filter.
+pub struct Planner<'p> {
+    naming: Naming,
+    action: Action,
+    budget: Option<&'p Budget>,
+    filter: Option<&'p PlanFilter>,
+}
+
+impl<'p> Planner<'p> {
+    pub fn new(naming: Naming) -> Planner<'p> {
+        Planner {
+            naming,
+            action: Action::Rename,
+            budget: None,
+            filter: None,
+        }
+    }
+
+    /// Link to the movies instead of renaming them.
+    pub fn link(mut self, link: bool) -> Self {
+        self.action = if link { Action::Link } else { Action::Rename };
+        self
+    }
+
+    pub fn budget(mut self, budget: &'p Budget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    pub fn filter(mut self, filter: &'p PlanFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Plan the renames of the movie into the root. The renames are marked impossible when the
+    /// root is read-only.
+    pub fn entry<'i>(&self, entry: &ScanEntry<'i>, root: &Path, read_only: bool) -> Planned {
+        let mut renames = Renames::new(root, entry, self.naming);
+        let over_budget = match self.budget {
+            Some(budget) => renames.enforce_budget(&format_base(entry), budget),
+            None => vec![],
+        };
+        let mut stale = match self.action {
+            Action::Link => Renames::default(),
+            _ => stale_companions(entry),
+        };
+        if let Some(filter) = self.filter {
+            renames.filter(filter);
+            stale.filter(filter);
+        }
+
+        let mut planned = PlannedEntry::new(entry, &renames, self.action, read_only);
+        planned.over_budget = over_budget.iter().map(|r| r.orig().to_owned()).collect();
+        planned.stale = stale
+            .iter()
+            .map(|r| Operation::rename(r, Action::Rename, read_only))
+            .collect();
+        Planned {
+            renames,
+            stale,
+            entry: planned,
+        }
+    }
+}
+
+/// Where a plan is applied, the local file system or a remote host.
+pub trait Target {
+    /// Tell how the file changed since the library was scanned, see `File::drift`.
+    fn drift(&self, file: &File) -> io::Result<Option<&'static str>>;
+
+    fn apply(&self, renames: &Renames, listener: &mut dyn Listener) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path, listener: &mut dyn Listener) -> io::Result<()>;
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Read the content of a file, such as a `.nfo` file.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+}
+
+/// The local file system, where the movies are renamed or linked to.
+pub struct Local {
+    owner: Option<Owner>,
+    link: bool,
+}
+
+impl Local {
+    pub fn new(owner: Option<Owner>) -> Local {
+        Local { owner, link: false }
+    }
+
+    /// Link to the movies instead of renaming them, see `Renames::link`.
+    pub fn link(mut self, link: bool) -> Self {
+        self.link = link;
+        self
+    }
+}
+
+impl Target for Local {
+    fn drift(&self, file: &File) -> io::Result<Option<&'static str>> {
+        Ok(file.drift())
+    }
+
+    fn apply(&self, renames: &Renames, listener: &mut dyn Listener) -> io::Result<()> {
+        if self.link {
+            renames.link(self.owner)
+        } else {
+            renames.apply(self.owner, listener)
+        }
+    }
+
+    fn remove_file(&self, path: &Path, listener: &mut dyn Listener) -> io::Result<()> {
+        fault::remove_file(path, listener)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+}
+
+/// Apply the renames, unless one of their files changed since the scan. The file that changed is
+/// returned with how it changed, and nothing is renamed.
+pub fn apply<'r>(
+    target: &dyn Target,
+    renames: &'r Renames,
+    listener: &mut dyn Listener,
+) -> io::Result<Option<(&'r Path, &'static str)>> {
+    for item in renames.iter() {
+        if let Some(reason) = target.drift(&item.orig)? {
+            return Ok(Some((item.orig(), reason)));
+        }
+    }
+    target.apply(renames, listener)?;
+    Ok(None)
+}
+
+/// The files of the library that belong to no movie, removed by the cleanup.
+pub fn unused_files(root: &File, cleaner: &Cleaner) -> Vec<File> {
+    root.descendants()
+        .filter(|file| file.is_file() && !cleaner.is_marked(file))
+        .collect()
+}
+
+/// Remove the file, unless it changed since the scan. How it changed is returned then.
+pub fn remove(
+    target: &dyn Target,
+    file: &File,
+    listener: &mut dyn Listener,
+) -> io::Result<Option<&'static str>> {
+    if let Some(reason) = target.drift(file)? {
+        return Ok(Some(reason));
+    }
+    target.remove_file(file.path(), listener)?;
+    Ok(None)
+}
+
+/// Remove the directories of the library left empty, the deepest first so that the directories
+/// holding only empty directories are removed too.
+pub fn remove_empty_dirs(target: &dyn Target, root: &File) {
+    let mut dirs: Vec<File> = root.descendants().filter(|file| file.is_dir()).collect();
+    dirs.sort_by_key(|dir| Reverse(dir.path().components().count()));
+    for dir in dirs {
+        let _ = target.remove_dir(dir.path());
+    }
+}
+
+#[test]
+fn test_plan() {
+    use test_util::{listing, scan, test_index, GB};
+
+    let imdb = test_index();
+    let root = listing("/movies", &[("Heat.1995.mkv", GB), ("Heat.1995.srt", 1000)]);
+    let entries = scan(&root, &imdb);
+    let renames = Renames::new("/movies", &entries[0], Naming::Default);
+
+    let plan = Plan {
+        entries: vec![PlannedEntry::new(&entries[0], &renames, Action::Rename, false)],
+        removals: vec![Operation::remove(Path::new("/movies/junk.txt"), false)],
+    };
+    let planned = &plan.entries[0];
+    assert_eq!(planned.imdb_id, "tt0113277");
+    assert_eq!(planned.operations.len(), 2);
+    assert_eq!(planned.operations[0].id, renames.iter().next().unwrap().id());
+    assert_eq!(
+        planned.operations[0].to,
+        Some(PathBuf::from("/movies/Heat (1995)/Heat (1995).mkv"))
+    );
+
+    let json = serde_json::to_string(&plan).unwrap();
+    assert!(json.contains(r#""action":"rename""#));
+    assert!(json.contains(r#""category":"subtitle""#));
+    assert!(json.contains(r#""confidence":"normal""#));
+    assert_eq!(serde_json::from_str::<Plan>(&json).unwrap(), plan);
+
+    let planned = Planner::new(Naming::Default).entry(&entries[0], Path::new("/movies"), false);
+    assert_eq!(planned.entry, plan.entries[0]);
+
+    // The renames left out by the filter are not planned.
+    let subtitle = renames.iter().nth(1).unwrap().id();
+    let filter = PlanFilter::new(None, Some(&subtitle));
+    let planned = Planner::new(Naming::Default)
+        .link(true)
+        .filter(&filter)
+        .entry(&entries[0], Path::new("/movies"), false);
+    assert_eq!(planned.renames.len(), 1);
+    assert_eq!(planned.entry.operations[0].action, Action::Link);
+}
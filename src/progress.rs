@@ -0,0 +1,221 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use yansi::Paint;
+
+use events::{Event, Listener};
+use imdb::{Phase, Progress};
+
+const MB: u64 = 1024 * 1024;
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Format a number of seconds as `m:ss`, or `h:mm:ss` past an hour.
+fn format_eta(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Show the progress of the dataset downloads, of the index creation and of the files copied on
+/// a single line that is updated in place.
+pub struct TermProgress {
+    phase: Option<Phase>,
+    last_mb: Option<u64>,
+    dirty: bool,
+    /// File being copied or copied last, when its copy started and the bytes copied so far.
+    copying: Option<(PathBuf, Instant, u64)>,
+    plan_total: u64,
+    plan_copied: u64,
+}
+
+impl TermProgress {
+    pub fn new() -> TermProgress {
+        TermProgress {
+            phase: None,
+            last_mb: None,
+            dirty: false,
+            copying: None,
+            plan_total: 0,
+            plan_copied: 0,
+        }
+    }
+
+    /// Set the bytes the plan may copy to another device, shown next to the progress of each file.
+    pub fn set_plan_total(&mut self, bytes: u64) {
+        self.plan_total = bytes;
+        self.plan_copied = 0;
+        self.copying = None;
+    }
+
+    fn update(&mut self, line: &str) {
+        // Pad the line to erase the end of a longer previous line.
+        print!("\r{:<60}", line);
+        let _ = io::stdout().flush();
+        self.dirty = true;
+    }
+
+    /// Terminate the line being updated, if any.
+    pub fn finish(&mut self) {
+        if self.dirty {
+            println!();
+            self.dirty = false;
+        }
+    }
+}
+
+impl Progress for TermProgress {
+    fn download(&mut self, file: &str, bytes: u64, total: Option<u64>) {
+        // Only redraw the line when another megabyte was downloaded.
+        let mb = bytes / MB;
+        if self.last_mb == Some(mb) && Some(bytes) != total {
+            return;
+        }
+        self.last_mb = Some(mb);
+
+        let line = match total {
+            Some(total) => format!("Downloading {}: {} / {} MB", file, mb, total / MB),
+            None => format!("Downloading {}: {} MB", file, mb),
+        };
+        self.update(&line);
+        if Some(bytes) == total {
+            self.last_mb = None;
+            self.finish();
+        }
+    }
+
+    fn phase(&mut self, phase: Phase) {
+        self.finish();
+        self.phase = Some(phase);
+        self.update(&format!("{}...", phase));
+    }
+
+    fn rows(&mut self, rows: u64) {
+        if let Some(phase) = self.phase {
+            self.update(&format!("{}... {} rows", phase, rows));
+        }
+    }
+
+    fn rebuild(&mut self, reason: &str) {
+        self.finish();
+        println!("Creating the index again: {}", reason);
+    }
+}
+
+impl Listener for TermProgress {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Copied { file, bytes, total } => self.copied(&file, bytes, total),
+            Event::ProbeTimedOut { movie, timeout } => {
+                self.finish();
+                println!(
+                    "{} probing {} timed out after {}s, it is matched without its probe.",
+                    Paint::yellow("warning:"),
+                    movie.file_name().unwrap_or_default().to_string_lossy(),
+                    timeout.as_secs()
+                );
+            }
+            Event::Retrying {
+                op,
+                path,
+                error,
+                attempt,
+                delay,
+            } => {
+                self.finish();
+                let ms = delay.as_secs() * 1000 + u64::from(delay.subsec_millis());
+                println!(
+                    "=> Could not {} {}: {}, retrying in {}ms (retry {})",
+                    op,
+                    path.display(),
+                    error,
+                    ms,
+                    attempt
+                );
+            }
+            Event::Matched { .. } | Event::Renamed { .. } => {}
+        }
+    }
+}
+
+impl TermProgress {
+    /// Show the progress of the file copied to another device. The bytes of a file count towards
+    /// the plan once the next file is copied, a copy starting over replaces them.
+    fn copied(&mut self, file: &Path, bytes: u64, total: u64) {
+        let started = match self.copying {
+            Some((ref copying, started, ref mut copied)) if copying == file && bytes >= *copied => {
+                *copied = bytes;
+                started
+            }
+            _ => {
+                if let Some((ref copying, _, copied)) = self.copying {
+                    if copying != file {
+                        self.plan_copied += copied;
+                    }
+                }
+                self.last_mb = None;
+                let started = Instant::now();
+                self.copying = Some((file.to_owned(), started, bytes));
+                started
+            }
+        };
+        let mb = bytes / MB;
+        if self.last_mb == Some(mb) && bytes != total {
+            return;
+        }
+        self.last_mb = Some(mb);
+
+        let elapsed = started.elapsed();
+        let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+        let rate = if secs > 0.0 { bytes as f64 / secs } else { 0.0 };
+        let eta = if rate > 0.0 {
+            format_eta(((total - bytes) as f64 / rate) as u64)
+        } else {
+            "?".to_string()
+        };
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        let mut line = format!(
+            "Copying {}: {} / {} MB, {:.1} MB/s, ETA {}",
+            name,
+            mb,
+            total / MB,
+            rate / MB as f64,
+            eta
+        );
+        if self.plan_total > 0 {
+            line += &format!(
+                " | plan {:.1} / {:.1} GB",
+                (self.plan_copied + bytes) as f64 / GB,
+                self.plan_total as f64 / GB
+            );
+        }
+        self.update(&line);
+
+        if bytes == total {
+            self.last_mb = None;
+            self.finish();
+        }
+    }
+}
+
+#[test]
+fn test_format_eta() {
+    assert_eq!(format_eta(42), "0:42");
+    assert_eq!(format_eta(125), "2:05");
+    assert_eq!(format_eta(3725), "1:02:05");
+}
+
+#[test]
+fn test_copy_started_over() {
+    let mut progress = TermProgress::new();
+    progress.set_plan_total(300);
+    progress.copied(Path::new("Heat.mkv"), 0, 100);
+    progress.copied(Path::new("Heat.mkv"), 100, 100);
+    // The copy failed once done and was retried, the file only counts once.
+    progress.copied(Path::new("Heat.mkv"), 0, 100);
+    progress.copied(Path::new("Heat.mkv"), 100, 100);
+    progress.copied(Path::new("Ronin.mkv"), 50, 200);
+    assert_eq!(progress.plan_copied, 100);
+}
@@ -0,0 +1,273 @@
+use std::fmt;
+use std::path::Path;
+
+use failure::Error;
+use ffprobe::{self, MediaInfo};
+use yansi::Paint;
+
+use rename::language_code;
+use scan::ScanEntry;
+use stats::format_bit_rate;
+
+/// Frame rates of film, PAL and NTSC video.
+const STANDARD_FRAME_RATES: &[f64] = &[
+    24000.0 / 1001.0,
+    24.0,
+    25.0,
+    30000.0 / 1001.0,
+    30.0,
+    50.0,
+    60000.0 / 1001.0,
+    60.0,
+];
+/// Difference in frames per second under which a frame rate is standard.
+const FRAME_RATE_TOLERANCE: f64 = 0.01;
+
+/// Properties of a movie file worth re-encoding it for.
+#[derive(Debug, PartialEq)]
+pub enum Flag {
+    /// The video is interlaced, with the field order reported by ffprobe.
+    Interlaced { field_order: String },
+    /// The frame rate is not one of the film, PAL or NTSC frame rates.
+    FrameRate { fps: f64 },
+    /// The frame rate varies, with the average frame rate.
+    VariableFrameRate { fps: f64 },
+    /// None of the audio streams is in the language expected, with the languages of the streams.
+    ForeignAudio { languages: Vec<String> },
+}
+
+impl fmt::Display for Flag {
+    fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Flag::Interlaced { field_order } => write!(w, "interlaced ({})", field_order),
+            Flag::FrameRate { fps } => write!(w, "non-standard frame rate of {:.3} fps", fps),
+            Flag::VariableFrameRate { fps } => {
+                write!(w, "variable frame rate, {:.3} fps on average", fps)
+            }
+            Flag::ForeignAudio { languages } => {
+                write!(w, "foreign audio ({})", languages.join(", "))
+            }
+        }
+    }
+}
+
+/// Tell if two languages are the same, written as ISO 639-1 or 639-2 codes or as English names.
+fn same_language(a: &str, b: &str) -> bool {
+    match (language_code(a), language_code(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a.eq_ignore_ascii_case(b),
+    }
+}
+
+/// Describe the audio streams of the movie, such as `5.1 Atmos eng, 2.0 fre`.
+pub fn format_audio(info: &MediaInfo) -> String {
+    let streams: Vec<String> = info
+        .audio
+        .iter()
+        .map(|audio| match audio.language() {
+            Some(language) => format!("{} {}", audio.audio_channels(), language),
+            None => audio.audio_channels(),
+        })
+        .collect();
+    streams.join(", ")
+}
+
+/// Describe the first video stream of the movie, such as `720x576 16:9, 25.000 fps PAL,
+/// interlaced, 4.5 Mb/s`.
+pub fn format_video(info: &MediaInfo) -> Option<String> {
+    let video = info.video.first()?;
+    let mut desc = format!("{}x{}", video.width, video.height);
+    if let Some(ratio) = video.aspect_ratio() {
+        desc += &format!(" {}", format_aspect_ratio(ratio));
+    }
+    if let Some(fps) = video.frame_rate() {
+        desc += &format!(", {:.3} fps", fps);
+        if let Some(standard) = video.standard() {
+            desc += &format!(" {}", standard);
+        }
+    }
+    if video.is_variable_frame_rate() {
+        desc += ", variable frame rate";
+    }
+    if video.is_interlaced() {
+        desc += ", interlaced";
+    }
+    if let Some(bit_rate) = info.video_bit_rate() {
+        desc += &format!(", {}", format_bit_rate(bit_rate));
+    }
+    Some(desc)
+}
+
+/// Write the aspect ratio the way it is usually written, `16:9` and `4:3` or `2.39:1`.
+fn format_aspect_ratio(ratio: f64) -> String {
+    if (ratio - 16.0 / 9.0).abs() < 0.01 {
+        "16:9".to_string()
+    } else if (ratio - 4.0 / 3.0).abs() < 0.01 {
+        "4:3".to_string()
+    } else {
+        format!("{:.2}:1", ratio)
+    }
+}
+
+/// Get the flags of the first video stream of the movie, and of its audio streams when a language
+/// is expected. Movies whose audio streams have no language are not flagged.
+pub fn flags(info: &MediaInfo, audio_language: Option<&str>) -> Vec<Flag> {
+    let mut flags = vec![];
+    if let Some(expected) = audio_language {
+        let languages = info.audio_languages();
+        if !languages.is_empty() && !languages.iter().any(|l| same_language(l, expected)) {
+            flags.push(Flag::ForeignAudio {
+                languages: languages.iter().map(|l| l.to_string()).collect(),
+            });
+        }
+    }
+    let video = match info.video.first() {
+        Some(video) => video,
+        None => return flags,
+    };
+    if video.is_interlaced() {
+        flags.push(Flag::Interlaced {
+            field_order: video.field_order.clone().unwrap_or_default(),
+        });
+    }
+    if let (true, Some(fps)) = (video.is_variable_frame_rate(), video.frame_rate()) {
+        flags.push(Flag::VariableFrameRate { fps });
+    } else if let Some(fps) = video.frame_rate() {
+        if !STANDARD_FRAME_RATES
+            .iter()
+            .any(|rate| (rate - fps).abs() < FRAME_RATE_TOLERANCE)
+        {
+            flags.push(Flag::FrameRate { fps });
+        }
+    }
+    flags
+}
+
+/// Probe every movie with ffprobe and report those with flags, so that they can be queued for
+/// re-encoding or replaced. Movies ffprobe cannot read are reported as warnings.
+pub fn run<'i>(
+    root_path: &Path,
+    entries: &[ScanEntry<'i>],
+    audio_language: Option<&str>,
+) -> Result<(), Error> {
+    let mut flagged = 0;
+
+    for entry in entries.iter() {
+        let path = entry.movie.path();
+        let name = path.strip_prefix(root_path).unwrap_or(path).display();
+        let info = match ffprobe::scan(path) {
+            Ok(info) => info,
+            Err(err) => {
+                println!("{} {}: {}", Paint::yellow("warning:"), name, err);
+                continue;
+            }
+        };
+        let flags = flags(&info, audio_language);
+        if flags.is_empty() {
+            continue;
+        }
+        flagged += 1;
+        println!("{}", Paint::yellow(name));
+        if let Some(video) = format_video(&info) {
+            println!("\tVideo: {}", video);
+        }
+        if !info.audio.is_empty() {
+            println!("\tAudio: {}", format_audio(&info));
+        }
+        for flag in flags.iter() {
+            println!("\t{}", flag);
+        }
+    }
+
+    println!("{} of {} movies are flagged.", flagged, entries.len());
+    Ok(())
+}
+
+#[test]
+fn test_flags() {
+    let json = r#"{
+        "streams": [
+            {
+                "index": 0,
+                "codec_type": "video",
+                "codec_name": "mpeg2video",
+                "width": 720,
+                "height": 480,
+                "field_order": "bb",
+                "avg_frame_rate": "15/1",
+                "tags": {}
+            }
+        ]
+    }"#;
+    let info = ffprobe::parse_output(json).unwrap();
+    assert_eq!(
+        flags(&info, None),
+        vec![
+            Flag::Interlaced {
+                field_order: "bb".to_string()
+            },
+            Flag::FrameRate { fps: 15.0 },
+        ]
+    );
+
+    assert_eq!(
+        format_video(&info),
+        Some("720x480 1.50:1, 15.000 fps, interlaced".to_string())
+    );
+
+    let json = json.replace("\"bb\"", "\"progressive\"").replace("15/1", "24000/1001");
+    assert!(flags(&ffprobe::parse_output(&json).unwrap(), None).is_empty());
+
+    let json = json.replace(
+        "\"24000/1001\"",
+        "\"24000/1001\", \"r_frame_rate\": \"120/1\", \"bit_rate\": \"4500000\"",
+    );
+    let info = ffprobe::parse_output(&json).unwrap();
+    assert_eq!(
+        flags(&info, None),
+        vec![Flag::VariableFrameRate {
+            fps: 24000.0 / 1001.0
+        }]
+    );
+    assert_eq!(
+        format_video(&info),
+        Some("720x480 1.50:1, 23.976 fps NTSC, variable frame rate, 4.5 Mb/s".to_string())
+    );
+}
+
+#[test]
+fn test_foreign_audio() {
+    let json = r#"{
+        "streams": [
+            {
+                "index": 0,
+                "codec_type": "audio",
+                "codec_name": "eac3",
+                "channels": 6,
+                "channel_layout": "5.1(side)",
+                "tags": {"language": "ita"}
+            },
+            {
+                "index": 1,
+                "codec_type": "audio",
+                "codec_name": "aac",
+                "channels": 2,
+                "channel_layout": "stereo",
+                "tags": {"language": "fre"}
+            }
+        ]
+    }"#;
+    let info = ffprobe::parse_output(json).unwrap();
+    assert_eq!(format_audio(&info), "5.1 ita, 2.0 fre");
+    assert_eq!(
+        flags(&info, Some("en")),
+        vec![Flag::ForeignAudio {
+            languages: vec!["ita".to_string(), "fre".to_string()]
+        }]
+    );
+    assert!(flags(&info, Some("french")).is_empty());
+    assert!(flags(&info, None).is_empty());
+
+    let json = json.replace("\"ita\"", "\"und\"").replace("\"fre\"", "\"und\"");
+    assert!(flags(&ffprobe::parse_output(&json).unwrap(), Some("en")).is_empty());
+}
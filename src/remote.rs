@@ -0,0 +1,194 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use events::Listener;
+use ignore::{IgnoreFile, IGNORE_FILE};
+use plan::Target;
+use rename::Renames;
+use vfs::{self, File, NodeKind, WalkOptions};
+
+/// Quote a string so that it is passed as a single argument by the remote shell.
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+fn path_arg(path: &Path) -> String {
+    shell_quote(&path.to_string_lossy())
+}
+
+/// A library located on another machine, reached with the `ssh` command.
+///
+/// Remote support is experimental. The remote host must have a POSIX shell, GNU `find` and
+/// `ffprobe` if probing is needed.
+pub struct Remote {
+    host: String,
+    /// Modification times of the walked files in seconds, to tell which ones changed since.
+    modified: RefCell<HashMap<PathBuf, u64>>,
+}
+
+impl Remote {
+    pub fn new(host: impl Into<String>) -> Remote {
+        Remote {
+            host: host.into(),
+            modified: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn ssh(&self, command: &str) -> io::Result<Output> {
+        let output = Command::new("ssh")
+            .arg("-o")
+            .arg("BatchMode=yes")
+            .arg(&self.host)
+            .arg(command)
+            .output()?;
+
+        if !output.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "remote command failed on {}: {}",
+                    self.host,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ));
+        }
+
+        Ok(output)
+    }
+
+    /// Read the ignore files of the library, each followed by its content.
+    fn ignore_files(&self, root: &Path) -> io::Result<Vec<IgnoreFile>> {
+        let output = self.ssh(&format!(
+            "find {} -type f -name {} -exec sh -c 'for f; do printf \"%s\\0\" \"$f\"; cat \"$f\"; \
+             printf \"\\0\"; done' sh {{}} +",
+            path_arg(root),
+            IGNORE_FILE
+        ))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        let mut parts = stdout.split('\0');
+        let mut ignore_files = vec![];
+        while let (Some(path), Some(content)) = (parts.next(), parts.next()) {
+            if let Some(dir) = Path::new(path).parent() {
+                ignore_files.push(IgnoreFile::parse(dir, content));
+            }
+        }
+        Ok(ignore_files)
+    }
+
+    /// List the library, leaving out what the options leave out of a local walk, such as the
+    /// files matched by the `.meroignore` files of the library.
+    pub fn walk(&self, root: &Path, options: &WalkOptions) -> io::Result<File> {
+        let ignore_files = self.ignore_files(root)?;
+        let output = self.ssh(&format!(
+            "find {} -printf '%y %s %T@ %p\\0'",
+            path_arg(root)
+        ))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        let mut modified = self.modified.borrow_mut();
+        let listing = stdout.split('\0').filter_map(|line| {
+            let mut parts = line.splitn(4, ' ');
+            let kind = match parts.next()? {
+                "f" => NodeKind::File,
+                "d" => NodeKind::Dir,
+                "l" => NodeKind::Symlink,
+                _ => NodeKind::Other,
+            };
+            let len = parts.next()?.parse().ok()?;
+            let seconds = parts.next()?.split('.').next()?.parse().ok();
+            let path = PathBuf::from(parts.next()?);
+            if let Some(seconds) = seconds {
+                modified.insert(path.clone(), seconds);
+            }
+            Some((path, kind, len))
+        });
+
+        Ok(vfs::from_listing_with(root, listing, options, ignore_files))
+    }
+}
+
+impl Target for Remote {
+    /// Tell how the file changed on the remote host since it was walked.
+    fn drift(&self, file: &File) -> io::Result<Option<&'static str>> {
+        if !file.is_file() {
+            return Ok(None);
+        }
+        let output = self.ssh(&format!(
+            "stat -c '%s %Y' {} 2>/dev/null || true",
+            path_arg(file.path())
+        ))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(parse_drift(file, self.modified.borrow().get(file.path()).cloned(), &stdout))
+    }
+
+    /// Move the files on the remote host, the progress of the moves is not reported.
+    fn apply(&self, renames: &Renames, _listener: &mut dyn Listener) -> io::Result<()> {
+        for item in renames.iter() {
+            let renamed = item.renamed();
+            let new_parent = renamed.parent().expect("renamed path has no parent");
+            self.ssh(&format!(
+                "mkdir -p {} && mv -n {} {}",
+                path_arg(new_parent),
+                path_arg(item.orig()),
+                path_arg(renamed)
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path, _listener: &mut dyn Listener) -> io::Result<()> {
+        self.ssh(&format!("rm -f {}", path_arg(path)))?;
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.ssh(&format!("rmdir {}", path_arg(path)))?;
+        Ok(())
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        Ok(self.ssh(&format!("cat {}", path_arg(path)))?.stdout)
+    }
+}
+
+/// Compare the output of `stat -c '%s %Y'` with the walked file, empty when it disappeared.
+fn parse_drift(file: &File, modified: Option<u64>, stat: &str) -> Option<&'static str> {
+    let mut parts = stat.split_whitespace();
+    let len = match parts.next().and_then(|len| len.parse::<u64>().ok()) {
+        Some(len) => len,
+        None => return Some("disappeared"),
+    };
+    let seconds = parts.next().and_then(|seconds| seconds.parse::<u64>().ok());
+    if len != file.len() {
+        Some("changed size")
+    } else if modified.map_or(false, |modified| seconds != Some(modified)) {
+        Some("was modified")
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_parse_drift() {
+    let root = vfs::from_listing(
+        "/movies",
+        vec![(PathBuf::from("/movies/Heat.1995.mkv"), NodeKind::File, 5)],
+    );
+    let file = root.descendants().next().unwrap();
+
+    assert_eq!(parse_drift(&file, Some(1000), "5 1000\n"), None);
+    assert_eq!(parse_drift(&file, None, "5 1200\n"), None);
+    assert_eq!(parse_drift(&file, Some(1000), ""), Some("disappeared"));
+    assert_eq!(parse_drift(&file, Some(1000), "6 1000\n"), Some("changed size"));
+    assert_eq!(parse_drift(&file, Some(1000), "5 1200\n"), Some("was modified"));
+}
+
+#[test]
+fn test_shell_quote() {
+    assert_eq!(shell_quote("The Matrix (1999)"), "'The Matrix (1999)'");
+    assert_eq!(shell_quote("Schindler's List"), "'Schindler'\\''s List'");
+}
@@ -1,5 +1,5 @@
-use std::collections::HashSet;
-use std::fs::{self, DirBuilder};
+use std::collections::{HashMap, HashSet};
+use std::fs::DirBuilder;
 use std::io;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
@@ -7,20 +7,57 @@ use std::path::{Path, PathBuf};
 use same_file::is_same_file;
 use same_file::Handle;
 
-use scan::ScanEntry;
-use util::PathExt;
+use budget::Budget;
+use container::Owner;
+use events::{Event, Listener};
+use fault;
+use manifest::{quick_hash, MANIFEST_NAME};
+use scan::{FileExt, ScanEntry};
+use util::{filter_path, is_cross_device, plan_id, PathExt, PlanFilter};
 use vfs::File;
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Movie,
+    Image,
+    Subtitle,
+    Audio,
+    Nfo,
+}
+
+/// Naming convention used for the organized files.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Naming {
+    /// Images keep their original names, such as `poster.jpg`.
+    Default,
+    /// Images are prefixed with the movie's name, such as `Heat (1995)-poster.jpg`, and backdrops
+    /// are called fanart.
+    Kodi,
+}
+
+impl Naming {
+    pub fn parse(text: &str) -> Option<Naming> {
+        match text {
+            "default" => Some(Naming::Default),
+            "kodi" => Some(Naming::Kodi),
+            _ => None,
+        }
+    }
+}
+
 pub struct Rename {
     pub orig: File,
     pub renamed: PathBuf,
+    pub category: Category,
 }
 
 impl Rename {
-    fn new(orig: &File, renamed: PathBuf) -> Rename {
+    fn new(orig: &File, renamed: PathBuf, category: Category) -> Rename {
         Rename {
             orig: orig.clone(),
             renamed,
+            category,
         }
     }
 
@@ -38,73 +75,374 @@ impl Rename {
     pub fn renamed(&self) -> &Path {
         &self.renamed
     }
+
+    /// Stable identifier of the rename, see `plan_id`.
+    pub fn id(&self) -> String {
+        plan_id("mv", &[self.orig(), self.renamed()])
+    }
+}
+
+#[cfg(unix)]
+fn symlink_file(orig: &Path, link: &Path) -> io::Result<()> {
+    ::std::os::unix::fs::symlink(orig, link)
 }
 
-fn format_base<'i, 'e>(entry: &'e ScanEntry<'i>) -> String {
-    format!("{} ({})", entry.title.primary_title(), entry.title.year(),)
+#[cfg(windows)]
+fn symlink_file(orig: &Path, link: &Path) -> io::Result<()> {
+    // Symbolic links require developer mode or administrator rights on Windows, fall back to a
+    // hard link which works without them as long as both paths are on the same volume.
+    ::std::os::windows::fs::symlink_file(orig, link).or_else(|_| ::std::fs::hard_link(orig, link))
+}
+
+pub fn format_base<'i, 'e>(entry: &'e ScanEntry<'i>) -> String {
+    format!("{} ({})", entry.title.display_title(), entry.title.year(),)
 }
 
 fn format_movie<'i, 'e>(entry: &'e ScanEntry<'i>) -> String {
     format!(
         "{} ({}).{}",
-        entry.title.primary_title(),
+        entry.title.display_title(),
         entry.title.year(),
         entry.movie.extension().unwrap(),
     )
 }
 
 fn format_subtitle<'i, 'e>(entry: &'e ScanEntry<'i>, file: &File) -> String {
-    // Remove the common part between the movie's stem and the subtitle's name.
-    let suffix = file.name().trim_left_matches(entry.movie.stem());
+    // Remove the common part between the movie's stem and the subtitle's name. Subtitles found in
+    // a subtitles folder have unrelated names, only their language and extension are kept.
+    let suffix = if file.name().starts_with(entry.movie.stem()) {
+        file.name().trim_left_matches(entry.movie.stem()).to_string()
+    } else {
+        subtitle_suffix(file.name())
+    };
     format!(
         "{} ({}){}",
-        entry.title.primary_title(),
+        entry.title.display_title(),
         entry.title.year(),
         suffix
     )
 }
 
-fn movie<'i, 'e>(root_path: &Path, entry: &'e ScanEntry<'i>) -> Vec<Rename> {
+/// Get the language code of a word of a subtitle's name, such as `en` for `English` or `eng`.
+pub fn language_code(word: &str) -> Option<&'static str> {
+    Some(match word.to_lowercase().as_str() {
+        "en" | "eng" | "english" => "en",
+        "fr" | "fre" | "fra" | "french" => "fr",
+        "es" | "spa" | "spanish" => "es",
+        "de" | "ger" | "deu" | "german" => "de",
+        "it" | "ita" | "italian" => "it",
+        "pt" | "por" | "portuguese" => "pt",
+        "nl" | "dut" | "nld" | "dutch" => "nl",
+        _ => return None,
+    })
+}
+
+/// Identify the language and flavor of a subtitle from its name: its language code, whether it
+/// is forced or for the hearing impaired, and its extension.
+fn subtitle_flavor(entry: &ScanEntry, file: &File) -> (Option<&'static str>, bool, bool, String) {
+    // The movie's stem may contain words that look like languages.
+    let name = if file.name().starts_with(entry.movie.stem()) {
+        &file.name()[entry.movie.stem().len()..]
+    } else {
+        file.name()
+    };
+    let stem = name.rsplitn(2, '.').nth(1).unwrap_or("");
+    let words: Vec<&str> = stem
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    let language = words.iter().filter_map(|w| language_code(w)).next();
+    let forced = words.iter().any(|w| w.eq_ignore_ascii_case("forced"));
+    let sdh = words
+        .iter()
+        .any(|w| w.eq_ignore_ascii_case("sdh") || w.eq_ignore_ascii_case("hi"));
+    let ext = file.extension().unwrap_or("").to_lowercase();
+    (language, forced, sdh, ext)
+}
+
+/// Tell if the file is the `.sub` half of a VobSub subtitle, which follows its `.idx`.
+fn follows_idx(file: &File) -> bool {
+    file.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("sub"))
+        && file.vobsub_pair().is_some()
+}
+
+/// Give a name the extension of the other half of a VobSub subtitle.
+fn pair_name(name: &str, pair: &File) -> String {
+    let stem = name.rsplitn(2, '.').nth(1).unwrap_or(name);
+    format!("{}.{}", stem, pair.extension().unwrap_or(""))
+}
+
+/// Tell if a subtitle stream of the same language and flavor is muxed in the movie file.
+fn is_muxed(entry: &ScanEntry, flavor: &(Option<&'static str>, bool, bool, String)) -> bool {
+    let (language, forced, sdh, _) = *flavor;
+    language.is_some() && entry.muxed_subtitles.iter().any(|muxed| {
+        muxed.language.as_ref().and_then(|l| language_code(l)) == language
+            && muxed.forced == forced
+            && muxed.sdh == sdh
+    })
+}
+
+/// Tell if the two subtitles have the same contents. Files that cannot be read, such as those of
+/// a remote machine, are different.
+fn same_contents(a: &File, b: &File) -> bool {
+    a.len() == b.len() && match (quick_hash(a.path()), quick_hash(b.path())) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Split the subtitles of the movie into the ones to keep and the near duplicates of a kept one,
+/// which are left to the cleanup. Subtitles of the same known language and flavor whose sizes are
+/// within 2% of each other are near duplicates, the largest one is kept as it is the most
+/// complete. Subtitles of an unknown language are only duplicates when their contents are the
+/// same, as they may be in different languages. Subtitles of the language and flavor of a stream
+/// muxed in the movie file are redundant and are left to the cleanup too. The `.sub` of a VobSub
+/// subtitle goes wherever its `.idx` goes.
+pub fn dedup_subtitles<'i>(entry: &ScanEntry<'i>) -> (Vec<File>, Vec<File>) {
+    let mut subtitles: Vec<File> = entry
+        .subtitles
+        .iter()
+        .filter(|f| !follows_idx(f))
+        .cloned()
+        .collect();
+    subtitles.sort_by_key(|f| ::std::cmp::Reverse(f.len()));
+
+    let mut kept: Vec<File> = vec![];
+    let mut duplicates = vec![];
+    for file in subtitles {
+        let pair = file.vobsub_pair();
+        let flavor = subtitle_flavor(entry, &file);
+        let is_duplicate = is_muxed(entry, &flavor) || kept.iter().any(|other| {
+            let close = other.len().saturating_sub(file.len()) <= other.len() / 50;
+            (flavor.0.is_some() && subtitle_flavor(entry, other) == flavor && close)
+                || same_contents(other, &file)
+        });
+        let files = if is_duplicate {
+            &mut duplicates
+        } else {
+            &mut kept
+        };
+        files.push(file);
+        files.extend(pair);
+    }
+    (kept, duplicates)
+}
+
+/// Name the image with the naming preset. The images sharing a name after the first one are
+/// numbered from 1 as Kodi does, such as `Heat (1995)-fanart1.jpg`.
+fn format_image<'i, 'e>(
+    entry: &'e ScanEntry<'i>,
+    file: &File,
+    naming: Naming,
+    num: usize,
+) -> String {
+    match naming {
+        Naming::Default if num == 0 => file.name().to_string(),
+        Naming::Default => match file.extension() {
+            Some(ext) => format!("{}{}.{}", file.stem(), num, ext),
+            None => format!("{}{}", file.stem(), num),
+        },
+        Naming::Kodi => {
+            // Images may already be prefixed and numbered by a previous run.
+            let stem = file.stem().trim_right_matches(|c: char| c.is_ascii_digit());
+            let kind = if stem.ends_with("backdrop") || stem.ends_with("fanart") {
+                "fanart"
+            } else {
+                "poster"
+            };
+            let num = if num > 0 { num.to_string() } else { String::new() };
+            match file.extension() {
+                Some(ext) => format!("{}-{}{}.{}", format_base(entry), kind, num, ext),
+                None => format!("{}-{}{}", format_base(entry), kind, num),
+            }
+        }
+    }
+}
+
+fn movie<'i, 'e>(root_path: &Path, entry: &'e ScanEntry<'i>, naming: Naming) -> Vec<Rename> {
     let dir_path = root_path.join_filtered(&format_base(entry));
 
     let mut renames = vec![Rename::new(
         &entry.movie,
         dir_path.join_filtered(&format_movie(entry)),
+        Category::Movie,
     )];
 
-    // images
-    renames.extend(
-        entry
-            .images
-            .iter()
-            .map(|f| Rename::new(f, dir_path.join(f.name()))),
-    );
+    // images, the ones sharing a name are numbered
+    let mut images: Vec<&File> = entry.images.iter().collect();
+    images.sort_by(|a, b| a.name().cmp(b.name()));
+    let mut names: HashSet<String> = HashSet::new();
+    for file in images {
+        let mut num = 0;
+        let mut name = format_image(entry, file, naming, num);
+        while names.contains(&name) {
+            num += 1;
+            name = format_image(entry, file, naming, num);
+        }
+        renames.push(Rename::new(
+            file,
+            dir_path.join_filtered(&name),
+            Category::Image,
+        ));
+        names.insert(name);
+    }
 
-    // subtitles
-    // TODO: handle languages and duplicates
-    renames.extend(
-        entry
-            .subtitles
-            .iter()
-            .map(|f| Rename::new(f, dir_path.join_filtered(&format_subtitle(entry, f)))),
-    );
+    // audio companions keep their names, such as the tracks of a concert film's album
+    renames.extend(entry.audio.iter().map(|f| {
+        Rename::new(f, dir_path.join_filtered(f.name()), Category::Audio)
+    }));
+
+    // subtitles, the ones still sharing a name after removing the duplicates are numbered, both
+    // halves of a VobSub subtitle get the same number
+    for file in dedup_subtitles(entry).0.iter().filter(|f| !follows_idx(f)) {
+        let pair = file.vobsub_pair();
+        let taken = |name: &String| {
+            names.contains(name)
+                || pair.as_ref().map_or(false, |p| names.contains(&pair_name(name, p)))
+        };
+        let mut name = format_subtitle(entry, file);
+        let mut num = 2;
+        while taken(&name) {
+            let suffix = &format_subtitle(entry, file)[format_base(entry).len()..];
+            name = format!("{}.{}{}", format_base(entry), num, suffix);
+            num += 1;
+        }
+        if let Some(ref pair) = pair {
+            let pair_name = pair_name(&name, pair);
+            renames.push(Rename::new(
+                pair,
+                dir_path.join_filtered(&pair_name),
+                Category::Subtitle,
+            ));
+            names.insert(pair_name);
+        }
+        renames.push(Rename::new(
+            file,
+            dir_path.join_filtered(&name),
+            Category::Subtitle,
+        ));
+        names.insert(name);
+    }
 
     renames
 }
 
+/// Extract the language and extension of a subtitle's name, such as `.en.srt`.
+fn subtitle_suffix(name: &str) -> String {
+    let mut parts = name.rsplitn(3, '.');
+    let ext = parts.next().unwrap_or("");
+    match (parts.next(), parts.next()) {
+        (Some(lang), Some(_)) if lang.len() <= 3 && lang.chars().all(char::is_alphabetic) => {
+            format!(".{}.{}", lang, ext)
+        }
+        _ => format!(".{}", ext),
+    }
+}
+
+/// Kinds of artwork named after the movie file by the media centers, such as `Heat-thumb.jpg`. An
+/// image named only after its kind, such as `folder.jpg`, belongs to the folder.
+const ARTWORK_KINDS: &[&str] = &[
+    "poster", "fanart", "backdrop", "thumb", "banner", "landscape", "clearart", "clearlogo",
+    "logo", "disc", "discart", "folder", "cover",
+];
+
+/// Extract the kind and extension of an image named after a movie file, such as `-thumb.jpg`, or
+/// only its extension when it has no kind, such as `.tbn`. Returns `None` for the artwork of the
+/// folder.
+fn image_suffix(name: &str) -> Option<String> {
+    let (stem, ext) = name.split_at(name.rfind('.')?);
+    let kind_stem = stem.trim_right_matches(|c: char| c.is_ascii_digit());
+    let is_kind = |text: &str| ARTWORK_KINDS.contains(&text.to_lowercase().as_str());
+    if is_kind(kind_stem) {
+        return None;
+    }
+    match kind_stem.rfind('-') {
+        Some(dash) if is_kind(&kind_stem[dash + 1..]) => Some(format!("{}{}", &stem[dash..], ext)),
+        _ => Some(ext.to_string()),
+    }
+}
+
+fn is_artwork(file: &File) -> bool {
+    file.extension().map_or(false, |ext| {
+        ["jpg", "jpeg", "png", "tbn"].iter().any(|e| ext.eq_ignore_ascii_case(e))
+    })
+}
+
+/// Find the subtitles, artwork and `.nfo` files left in an organized movie folder that no longer
+/// match the movie's name, such as the companions of a movie file that was replaced by a better
+/// copy. The renames give them the name of the current movie file. The artwork of the folder and
+/// `movie.nfo` are not named after a movie file, they are left out.
+pub fn stale_companions<'i>(entry: &ScanEntry<'i>) -> Renames {
+    let mut diff: Vec<Rename> = vec![];
+
+    let folder = match entry.movie.parent() {
+        Some(folder) => folder,
+        None => return Renames { diff },
+    };
+    if folder.name() != filter_path(&format_base(entry)) {
+        return Renames { diff };
+    }
+
+    for sibling in folder.children() {
+        if !sibling.is_file()
+            || entry.subtitles.contains(&sibling)
+            || entry.images.contains(&sibling)
+            || follows_idx(&sibling)
+        {
+            continue;
+        }
+        let is_nfo = sibling.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("nfo"));
+        let (suffix, category) = if sibling.is_subtitle() {
+            (subtitle_suffix(sibling.name()), Category::Subtitle)
+        } else if is_nfo && sibling.stem() != "movie" {
+            (".nfo".to_string(), Category::Nfo)
+        } else if is_artwork(&sibling) {
+            match image_suffix(sibling.name()) {
+                Some(suffix) => (suffix, Category::Image),
+                None => continue,
+            }
+        } else {
+            continue;
+        };
+        let name = format!("{}{}", format_base(entry), suffix);
+        // The .sub of a VobSub subtitle is renamed along with its .idx.
+        let mut renames = vec![(sibling.clone(), folder.path().join_filtered(&name))];
+        if let Some(pair) = sibling.vobsub_pair() {
+            let renamed = folder.path().join_filtered(&pair_name(&name, &pair));
+            renames.push((pair, renamed));
+        }
+        // Never overwrite an existing companion or another stale one.
+        let taken = |renamed: &PathBuf| {
+            folder.children().any(|f| f.path() == renamed)
+                || diff.iter().any(|r| r.renamed == *renamed)
+        };
+        if renames.iter().any(|&(_, ref renamed)| taken(renamed)) {
+            continue;
+        }
+        for (file, renamed) in renames {
+            diff.push(Rename::new(&file, renamed, category));
+        }
+    }
+
+    Renames { diff }
+}
+
+#[derive(Default)]
 pub struct Renames {
     diff: Vec<Rename>,
 }
 
 impl Renames {
-    pub fn new<'i>(root_path: impl AsRef<Path>, entry: &ScanEntry<'i>) -> Renames {
-        let renames = movie(root_path.as_ref(), &entry);
+    pub fn new<'i>(root_path: impl AsRef<Path>, entry: &ScanEntry<'i>, naming: Naming) -> Renames {
+        let renames = movie(root_path.as_ref(), &entry, naming);
         Renames {
             diff: renames.into_iter().filter(|r| r.different()).collect(),
         }
     }
 
-    pub fn apply(&self) -> io::Result<()> {
+    /// Rename the files, the files renamed to another device are copied. The progress of the
+    /// copies and the files renamed are reported to the listener.
+    pub fn apply(&self, owner: Option<Owner>, listener: &mut dyn Listener) -> io::Result<()> {
         for item in self.diff.iter() {
             let renamed = item.renamed();
             let new_parent = renamed.parent().expect("renamed path has no parent");
@@ -119,12 +457,94 @@ impl Renames {
             //     println!("here tho");
             //     fs::rename(old_parent.path(), new_parent)?;
             // } else {
-            DirBuilder::new().recursive(true).create(new_parent)?;
-            fs::rename(item.orig(), renamed)?;
+            fault::create_dir_all(new_parent, listener)?;
+            match fault::rename(item.orig(), renamed, listener) {
+                Ok(()) => {}
+                // Files cannot be renamed to another device, copy them instead.
+                Err(ref err) if is_cross_device(err) => {
+                    fault::copy(item.orig(), renamed, listener)?;
+                    fault::remove_file(item.orig(), listener)?;
+                }
+                Err(err) => return Err(err),
+            }
+            if let Some(owner) = owner {
+                owner.apply(new_parent)?;
+                owner.apply(renamed)?;
+            }
+            listener.event(Event::Renamed {
+                from: item.orig().to_owned(),
+                to: renamed.to_owned(),
+            });
             // }
         }
         Ok(())
     }
+
+    /// Find a file of the renames that changed on disk since the library was scanned, with how it
+    /// changed, see `File::drift`. The renames must not be applied to files that are no longer
+    /// the ones reviewed.
+    pub fn drifted(&self) -> Option<(&Path, &'static str)> {
+        self.diff
+            .iter()
+            .filter_map(|item| item.orig.drift().map(|reason| (item.orig(), reason)))
+            .next()
+    }
+
+    /// Remove the companion files that go over the budget of their category, the files removed
+    /// are returned. The movie itself is always kept.
+    pub fn enforce_budget(&mut self, name: &str, budget: &Budget) -> Vec<Rename> {
+        if budget.is_exempt(name) {
+            return vec![];
+        }
+
+        let mut totals: HashMap<Category, u64> = HashMap::new();
+        let mut kept = Vec::with_capacity(self.diff.len());
+        let mut skipped = vec![];
+
+        for item in self.diff.drain(..) {
+            let within = match budget.limit(item.category) {
+                Some(limit) if item.category != Category::Movie => {
+                    let total = totals.entry(item.category).or_insert(0);
+                    *total += item.orig.len();
+                    *total <= limit
+                }
+                _ => true,
+            };
+            if within {
+                kept.push(item);
+            } else {
+                skipped.push(item);
+            }
+        }
+
+        self.diff = kept;
+        skipped
+    }
+
+    /// Only keep the renames allowed by the filter, the others are returned.
+    pub fn filter(&mut self, filter: &PlanFilter) -> Vec<Rename> {
+        let (kept, skipped) = self.diff.drain(..).partition(|r| filter.allows(&r.id()));
+        self.diff = kept;
+        skipped
+    }
+
+    /// Create links to the original files at the renamed paths, the original files are not moved.
+    pub fn link(&self, owner: Option<Owner>) -> io::Result<()> {
+        for item in self.diff.iter() {
+            let renamed = item.renamed();
+            // The link was created by a previous run.
+            if renamed.symlink_metadata().is_ok() {
+                continue;
+            }
+            let new_parent = renamed.parent().expect("renamed path has no parent");
+            DirBuilder::new().recursive(true).create(new_parent)?;
+            symlink_file(item.orig(), renamed)?;
+            if let Some(owner) = owner {
+                owner.apply(new_parent)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Deref for Renames {
@@ -150,7 +570,17 @@ impl Cleaner {
     pub fn mark<'i>(&mut self, entry: &ScanEntry<'i>) {
         self.marked_files.insert(entry.movie.clone());
         self.marked_files.extend(entry.images.iter().cloned());
-        self.marked_files.extend(entry.subtitles.iter().cloned());
+        self.marked_files.extend(entry.audio.iter().cloned());
+        self.marked_files.extend(dedup_subtitles(entry).0);
+        if let Some(siblings) = entry.movie.siblings() {
+            self.marked_files
+                .extend(siblings.filter(|f| f.name() == MANIFEST_NAME));
+        }
+    }
+
+    #[inline]
+    pub fn mark_file(&mut self, file: &File) {
+        self.marked_files.insert(file.clone());
     }
 
     #[inline]
@@ -158,3 +588,210 @@ impl Cleaner {
         self.marked_files.contains(file)
     }
 }
+
+#[test]
+fn test_subtitle_suffix() {
+    assert_eq!(subtitle_suffix("Heat.1995.720p.en.srt"), ".en.srt");
+    assert_eq!(subtitle_suffix("Heat.1995.720p.srt"), ".srt");
+    assert_eq!(subtitle_suffix("en.srt"), ".srt");
+}
+
+#[test]
+fn test_image_suffix() {
+    assert_eq!(image_suffix("Heat.1995.720p-thumb.jpg").unwrap(), "-thumb.jpg");
+    assert_eq!(image_suffix("Heat.1995.720p-fanart2.jpg").unwrap(), "-fanart2.jpg");
+    assert_eq!(image_suffix("Heat.1995.720p.tbn").unwrap(), ".tbn");
+    assert_eq!(image_suffix("folder.jpg"), None);
+    assert_eq!(image_suffix("Poster1.png"), None);
+}
+
+#[test]
+fn test_stale_companions() {
+    use test_util::{listing, scan, test_index, GB};
+
+    let imdb = test_index();
+    let root = listing(
+        "/movies",
+        &[
+            ("Heat (1995)/", 0),
+            ("Heat (1995)/Heat (1995).mkv", GB),
+            ("Heat (1995)/Heat (1995).srt", 1),
+            ("Heat (1995)/poster.jpg", 1),
+            ("Heat (1995)/folder.jpg", 1),
+            ("Heat (1995)/movie.nfo", 1),
+            ("Heat (1995)/Heat.1995.720p.en.srt", 1),
+            ("Heat (1995)/Heat.1995.720p.nfo", 1),
+            ("Heat (1995)/Heat.1995.720p-thumb.jpg", 1),
+            ("Heat (1995)/Heat.1995.720p.tbn", 1),
+        ],
+    );
+    let entries = scan(&root, &imdb);
+
+    let stale = stale_companions(&entries[0]);
+    let mut names: Vec<(&str, String, Category)> = stale
+        .iter()
+        .map(|r| {
+            let renamed = r.renamed().file_name().unwrap().to_string_lossy().into_owned();
+            (r.orig.name(), renamed, r.category)
+        }).collect();
+    names.sort_by(|a, b| a.0.cmp(b.0));
+    assert_eq!(
+        names,
+        vec![
+            ("Heat.1995.720p-thumb.jpg", "Heat (1995)-thumb.jpg".to_string(), Category::Image),
+            ("Heat.1995.720p.en.srt", "Heat (1995).en.srt".to_string(), Category::Subtitle),
+            ("Heat.1995.720p.nfo", "Heat (1995).nfo".to_string(), Category::Nfo),
+            ("Heat.1995.720p.tbn", "Heat (1995).tbn".to_string(), Category::Image),
+        ]
+    );
+}
+
+#[test]
+fn test_dedup_subtitles() {
+    use scan::MuxedSubtitle;
+    use test_util::{listing, scan, test_index, GB};
+
+    let imdb = test_index();
+    let root = listing(
+        "/movies",
+        &[
+            ("Heat.1995/", 0),
+            ("Heat.1995/Heat.1995.mkv", GB),
+            ("Heat.1995/Heat.1995.en.srt", 1000),
+            ("Heat.1995/Subs/", 0),
+            ("Heat.1995/Subs/2_English.srt", 1010),
+            ("Heat.1995/Subs/eng.srt", 3000),
+            ("Heat.1995/Subs/French.srt", 1000),
+        ],
+    );
+
+    let mut entries = scan(&root, &imdb);
+    let (kept, duplicates) = dedup_subtitles(&entries[0]);
+    assert_eq!(kept.len(), 3);
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].name(), "Heat.1995.en.srt");
+
+    let renames = Renames::new("/movies", &entries[0], Naming::Default);
+    let mut names: Vec<String> = renames
+        .iter()
+        .filter(|r| r.category == Category::Subtitle)
+        .map(|r| r.renamed().file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec!["Heat (1995).2.srt", "Heat (1995).3.srt", "Heat (1995).srt"]
+    );
+
+    // The French subtitles are muxed in the movie, only a forced English stream is.
+    let muxed = |language: &str, forced| MuxedSubtitle {
+        language: Some(language.to_string()),
+        forced,
+        sdh: false,
+    };
+    entries[0].muxed_subtitles = vec![muxed("fre", false), muxed("eng", true)];
+    let (kept, duplicates) = dedup_subtitles(&entries[0]);
+    assert_eq!(kept.len(), 2);
+    assert_eq!(duplicates.len(), 2);
+    assert!(duplicates.iter().any(|f| f.name() == "French.srt"));
+}
+
+#[test]
+fn test_dedup_unknown_language() {
+    use std::{env, fs, process};
+
+    use test_util::{scan, test_index};
+    use vfs;
+
+    let imdb = test_index();
+    let root_path = env::temp_dir().join(format!("mero-dedup-{}", process::id()));
+    let dir = root_path.join("Heat.1995");
+    fs::create_dir_all(dir.join("Subs")).unwrap();
+    fs::write(dir.join("Heat.1995.mkv"), vec![0; 4096]).unwrap();
+    // Subtitles of unknown languages with the same size are different subtitles, unless their
+    // contents are the same.
+    fs::write(dir.join("Subs/1.srt"), b"1\nHello\n").unwrap();
+    fs::write(dir.join("Subs/2.srt"), b"1\nHola!\n").unwrap();
+    fs::write(dir.join("Subs/3.srt"), b"1\nHello\n").unwrap();
+
+    let root = vfs::walk(&root_path).unwrap();
+    let entries = scan(&root, &imdb);
+    let (kept, duplicates) = dedup_subtitles(&entries[0]);
+    assert_eq!(kept.len(), 2);
+    assert_eq!(duplicates.len(), 1);
+    assert!(kept.iter().any(|f| f.name() == "2.srt"));
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_kodi_images() {
+    use test_util::{listing, scan, test_index, GB};
+
+    let imdb = test_index();
+    let root = listing(
+        "/movies",
+        &[
+            ("Heat.1995/", 0),
+            ("Heat.1995/Heat.1995.mkv", GB),
+            ("Heat.1995/poster.jpg", 1000),
+            ("Heat.1995/Heat-poster.jpg", 1000),
+            ("Heat.1995/backdrop.jpg", 1000),
+        ],
+    );
+
+    let entries = scan(&root, &imdb);
+    let renames = Renames::new("/movies", &entries[0], Naming::Kodi);
+    let mut names: Vec<(&str, String)> = renames
+        .iter()
+        .filter(|r| r.category == Category::Image)
+        .map(|r| {
+            let renamed = r.renamed().file_name().unwrap().to_string_lossy().into_owned();
+            (r.orig.name(), renamed)
+        }).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            ("Heat-poster.jpg", "Heat (1995)-poster.jpg".to_string()),
+            ("backdrop.jpg", "Heat (1995)-fanart.jpg".to_string()),
+            ("poster.jpg", "Heat (1995)-poster1.jpg".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_vobsub_pairs() {
+    use test_util::{listing, scan, test_index, GB};
+
+    let imdb = test_index();
+    let root = listing(
+        "/movies",
+        &[
+            ("Heat.1995/", 0),
+            ("Heat.1995/Heat.1995.mkv", GB),
+            ("Heat.1995/Heat.1995.srt", 1000),
+            ("Heat.1995/Subs/", 0),
+            ("Heat.1995/Subs/vobsub.idx", 2000),
+            ("Heat.1995/Subs/vobsub.sub", 900000),
+            ("Heat.1995/Subs/English.idx", 5000),
+            ("Heat.1995/Subs/English.sub", 800000),
+        ],
+    );
+
+    let entries = scan(&root, &imdb);
+    assert_eq!(entries[0].subtitles.len(), 5);
+
+    let renames = Renames::new("/movies", &entries[0], Naming::Default);
+    let names: Vec<(&str, String)> = renames
+        .iter()
+        .filter(|r| r.category == Category::Subtitle)
+        .map(|r| {
+            let renamed = r.renamed().file_name().unwrap().to_string_lossy().into_owned();
+            (r.orig.name(), renamed)
+        }).collect();
+    assert!(names.contains(&("English.idx", "Heat (1995).idx".to_string())));
+    assert!(names.contains(&("English.sub", "Heat (1995).sub".to_string())));
+    assert!(names.contains(&("vobsub.idx", "Heat (1995).2.idx".to_string())));
+    assert!(names.contains(&("vobsub.sub", "Heat (1995).2.sub".to_string())));
+}
@@ -0,0 +1,158 @@
+use std::path::{Path, PathBuf};
+
+use failure::Error;
+
+use imdb::{Genre, Title, TitleKind};
+
+/// Kinds of titles that can be sent to a library root of their own.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Route {
+    /// Titles with the Documentary genre.
+    Documentary,
+    /// Stand-up comedy specials. IMDB has no genre for them, they are told apart as the videos and
+    /// TV movies whose only genre is Comedy.
+    StandUp,
+    /// Titles suitable for children according to their certification, or to their genres when
+    /// their certification is unknown.
+    Family,
+}
+
+/// Tell if the certification is given to titles suitable for children, such as `G` and `PG` in
+/// the US, `U` in the UK and France or `0` and `6` in Germany.
+fn family_certification(certification: &str) -> bool {
+    match certification {
+        "G" | "PG" | "U" | "0" | "6" | "TP" => true,
+        _ => false,
+    }
+}
+
+impl Route {
+    fn parse(text: &str) -> Option<Route> {
+        match text {
+            "documentary" => Some(Route::Documentary),
+            "standup" | "stand-up" => Some(Route::StandUp),
+            "family" | "kids" => Some(Route::Family),
+            _ => None,
+        }
+    }
+
+    fn accepts(self, title: &Title, certification: Option<&str>) -> bool {
+        match self {
+            Route::Documentary => title.has_genre(Genre::Documentary),
+            Route::StandUp => {
+                title.genres() == [Genre::Comedy]
+                    && (title.kind() == TitleKind::Video || title.kind() == TitleKind::TvMovie)
+            }
+            Route::Family => match certification {
+                Some(certification) => family_certification(certification),
+                None => {
+                    title.has_genre(Genre::Family)
+                        && ![Genre::Horror, Genre::Thriller, Genre::Crime, Genre::War]
+                            .iter()
+                            .any(|&genre| title.has_genre(genre))
+                }
+            },
+        }
+    }
+}
+
+/// Library roots the titles are sent to instead of the main root, according to their genres or
+/// certifications.
+pub struct Routes {
+    roots: Vec<(Route, PathBuf)>,
+}
+
+impl Routes {
+    pub fn new() -> Routes {
+        Routes { roots: vec![] }
+    }
+
+    /// Add a route written as `route=path`, such as `documentary=/mnt/documentaries`. The routes
+    /// are tried in the order they were added.
+    pub fn add(&mut self, text: &str) -> Result<(), Error> {
+        let mut parts = text.splitn(2, '=');
+        let route = match parts.next().and_then(Route::parse) {
+            Some(route) => route,
+            None => bail!("unknown route: {}", text),
+        };
+        match parts.next() {
+            Some(path) if !path.is_empty() => self.roots.push((route, PathBuf::from(path))),
+            _ => bail!("route has no path: {}", text),
+        }
+        Ok(())
+    }
+
+    /// Make sure the roots of the routes are local directories, they are not created.
+    pub fn check_roots(&self) -> Result<(), Error> {
+        for &(_, ref path) in self.roots.iter() {
+            if !path.is_dir() {
+                bail!("route root {} is not a directory", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the library roots of the routes, in the order they were added.
+    pub fn roots(&self) -> impl Iterator<Item = &Path> {
+        self.roots.iter().map(|&(_, ref path)| path.as_path())
+    }
+
+    /// Tell if the certification of the title can change its root: the family route is reached,
+    /// no route before it accepts the title.
+    pub fn needs_certification(&self, title: &Title) -> bool {
+        for &(route, _) in self.roots.iter() {
+            if route == Route::Family {
+                return true;
+            }
+            if route.accepts(title, None) {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Get the library root of the title, `default` unless a route accepts it. The certification
+    /// of the title is used by the family route when known.
+    pub fn root<'a>(
+        &'a self,
+        title: &Title,
+        certification: Option<&str>,
+        default: &'a Path,
+    ) -> &'a Path {
+        self.roots
+            .iter()
+            .find(|&&(route, _)| route.accepts(title, certification))
+            .map(|&(_, ref path)| path.as_path())
+            .unwrap_or(default)
+    }
+}
+
+#[test]
+fn test_routes() {
+    let documentary = Title::new(1, "Man on Wire", 2008, TitleKind::Movie, 60000)
+        .with_genres(&[Genre::Documentary]);
+    let special = Title::new(2, "Killing Them Softly", 2000, TitleKind::Video, 5000)
+        .with_genres(&[Genre::Comedy]);
+    let movie = Title::new(3, "Heat", 1995, TitleKind::Movie, 500000);
+
+    let mut routes = Routes::new();
+    routes.add("documentary=/docs").unwrap();
+    routes.add("standup=/standup").unwrap();
+    assert!(routes.add("anime=/anime").is_err());
+    assert!(routes.add("documentary").is_err());
+
+    let default = Path::new("/movies");
+    assert_eq!(routes.root(&documentary, None, default), Path::new("/docs"));
+    assert_eq!(routes.root(&special, None, default), Path::new("/standup"));
+    assert_eq!(routes.root(&movie, None, default), default);
+    assert!(!routes.needs_certification(&movie));
+
+    let cartoon = Title::new(4, "Toy Story", 1995, TitleKind::Movie, 900000)
+        .with_genres(&[Genre::Animation, Genre::Family]);
+    routes.add("family=/kids").unwrap();
+    assert!(routes.needs_certification(&movie));
+    assert!(!routes.needs_certification(&documentary));
+    assert_eq!(routes.root(&cartoon, None, default), Path::new("/kids"));
+    assert_eq!(routes.root(&cartoon, Some("R"), default), default);
+    assert_eq!(routes.root(&movie, Some("PG"), default), Path::new("/kids"));
+}
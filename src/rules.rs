@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use failure::Error;
+
+/// Same answers given to a question for the movies of a folder before it is offered as a rule.
+const REPEATS: usize = 3;
+
+/// Questions asked while applying the renames whose answers can be saved as rules.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Question {
+    /// Apply or skip the renames of a low confidence match.
+    LowConfidence,
+    /// Rename, delete or keep the stale companions of a movie.
+    Stale,
+}
+
+impl Question {
+    fn name(self) -> &'static str {
+        match self {
+            Question::LowConfidence => "low-confidence",
+            Question::Stale => "stale",
+        }
+    }
+
+    fn parse(text: &str) -> Option<Question> {
+        match text {
+            "low-confidence" => Some(Question::LowConfidence),
+            "stale" => Some(Question::Stale),
+            _ => None,
+        }
+    }
+}
+
+struct Rule {
+    question: Question,
+    answer: String,
+    folder: String,
+}
+
+/// Answers saved in the rules file, one rule per line written as `<question> <answer> <folder>`,
+/// such as `low-confidence skip /movies/Camera`. The folder is the first folder of the movie's
+/// path within the library joined to the root of the library, the root itself for the movies at
+/// its root, so that the rules of a library do not apply to the others.
+pub struct Rules {
+    path: PathBuf,
+    rules: Vec<Rule>,
+    answers: HashMap<(Question, String, String), usize>,
+}
+
+impl Rules {
+    /// Load the rules file, a missing file has no rules.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Rules, Error> {
+        let path = path.into();
+        let mut rules = vec![];
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        for (num, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(3, ' ');
+            match (parts.next().and_then(Question::parse), parts.next(), parts.next()) {
+                (Some(question), Some(answer), Some(folder)) => rules.push(Rule {
+                    question,
+                    answer: answer.to_string(),
+                    folder: folder.to_string(),
+                }),
+                _ => bail!("invalid rule on line {} of {}", num + 1, path.display()),
+            }
+        }
+
+        Ok(Rules {
+            path,
+            rules,
+            answers: HashMap::new(),
+        })
+    }
+
+    /// Get the saved answer to the question for the movies of the folder.
+    pub fn answer(&self, question: Question, folder: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|r| r.question == question && r.folder == folder)
+            .map(|r| r.answer.as_str())
+    }
+
+    /// Record an answer given by the user. Returns true when the same answer was given enough
+    /// times for the movies of the folder that it should be offered as a rule.
+    pub fn record(&mut self, question: Question, folder: &str, answer: &str) -> bool {
+        let count = self
+            .answers
+            .entry((question, folder.to_string(), answer.to_string()))
+            .or_insert(0);
+        *count += 1;
+        *count == REPEATS
+    }
+
+    /// Save the answer as a rule, appended to the rules file.
+    pub fn add(&mut self, question: Question, folder: &str, answer: &str) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{} {} {}", question.name(), answer, folder)?;
+        self.rules.push(Rule {
+            question,
+            answer: answer.to_string(),
+            folder: folder.to_string(),
+        });
+        Ok(())
+    }
+}
+
+/// Get the first folder of the path within the library joined to the canonical root of the
+/// library, the key of the rules.
+pub fn rule_folder(path: &Path, root_path: &Path) -> String {
+    let relative = path.strip_prefix(root_path).unwrap_or(path);
+    let mut components = relative.components();
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => root_path.join(first).to_string_lossy().into_owned(),
+        _ => root_path.to_string_lossy().into_owned(),
+    }
+}
+
+#[test]
+fn test_rules() {
+    use std::env;
+    use std::process;
+
+    let path = env::temp_dir().join(format!("mero-rules-{}", process::id()));
+    fs::write(&path, "# saved answers\nstale keep Downloads\n").unwrap();
+
+    let mut rules = Rules::load(&path).unwrap();
+    assert_eq!(rules.answer(Question::Stale, "Downloads"), Some("keep"));
+    assert_eq!(rules.answer(Question::LowConfidence, "Camera"), None);
+
+    assert!(!rules.record(Question::LowConfidence, "Camera", "skip"));
+    assert!(!rules.record(Question::LowConfidence, "Camera", "skip"));
+    assert!(rules.record(Question::LowConfidence, "Camera", "skip"));
+    rules.add(Question::LowConfidence, "Camera", "skip").unwrap();
+
+    let rules = Rules::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(rules.answer(Question::LowConfidence, "Camera"), Some("skip"));
+
+    let root = Path::new("/movies");
+    assert_eq!(rule_folder(Path::new("/movies/Camera/a/b.mkv"), root), "/movies/Camera");
+    assert_eq!(rule_folder(Path::new("/movies/b.mkv"), root), "/movies");
+    // The same folder of another library has its own rules.
+    let other = Path::new("/kids");
+    assert_eq!(rule_folder(Path::new("/kids/Camera/a/b.mkv"), other), "/kids/Camera");
+}
@@ -1,9 +1,16 @@
 use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::rc::Rc;
 
 use failure::Error;
+use ffprobe::{self, MediaInfo, ProbeCache};
 
-use imdb::{Imdb, Title};
+use imdb::tokenize::tag_splitter;
+use events::{Event, Listener};
+use imdb::{parse_id, Imdb, Title};
+use overrides::Overrides;
 use parse::{parse_movie, tokenize_filename};
+use plan::{Local, Target};
 use vfs::File;
 
 lazy_static! {
@@ -27,6 +34,15 @@ lazy_static! {
         "usf",
         "smi",
     };
+    static ref AUDIO_EXT: HashSet<&'static str> = hashset!{
+        "flac",
+        "cue",
+        "log",
+        "ape",
+        "wav",
+        "m4a",
+        "mp3",
+    };
     static ref DIRECTORY_FLAG: HashSet<&'static str> = hashset!{
         "extras",
         "features",
@@ -42,10 +58,23 @@ lazy_static! {
 }
 
 const FILE_MIN_SIZE: u64 = 650 * 1024 * 1024; // 650MB
+/// Folders with this many movie files hold unrelated movies, such as a flat download folder.
+const SHARED_FOLDER_MOVIES: usize = 3;
+
+/// Whether a file name has the extension of a subtitle, such as a path inside an archive.
+pub fn is_subtitle_name(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| SUBTITLE_EXT.contains(ext))
+        .unwrap_or(false)
+}
 
 pub trait FileExt {
     fn is_video(&self) -> bool;
     fn is_subtitle(&self) -> bool;
+    fn is_audio(&self) -> bool;
+    fn vobsub_pair(&self) -> Option<File>;
 }
 
 impl FileExt for File {
@@ -62,21 +91,183 @@ impl FileExt for File {
             .map(|ext| SUBTITLE_EXT.contains(ext))
             .unwrap_or(false)
     }
+
+    /// Tell if the file is an audio companion, such as the `.flac` and `.cue` of a concert film.
+    fn is_audio(&self) -> bool {
+        self.is_file() && self
+            .extension()
+            .map(|ext| AUDIO_EXT.contains(ext.to_lowercase().as_str()))
+            .unwrap_or(false)
+    }
+
+    /// Get the other half of a VobSub subtitle: the `.sub` of an `.idx` or the `.idx` of a `.sub`
+    /// with the same stem. The two files only work together and are renamed as one.
+    fn vobsub_pair(&self) -> Option<File> {
+        let other = match self.extension().map(|ext| ext.to_lowercase()) {
+            Some(ref ext) if ext == "idx" => "sub",
+            Some(ref ext) if ext == "sub" => "idx",
+            _ => return None,
+        };
+        self.siblings()?.find(|f| {
+            f.is_file()
+                && f.stem() == self.stem()
+                && f.extension().map_or(false, |ext| ext.eq_ignore_ascii_case(other))
+        })
+    }
+}
+
+/// How much the match for a movie file can be trusted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    /// The parent folder's name resolves to the same title as the file name.
+    High,
+    /// The parent folder's name does not tell us anything.
+    Normal,
+    /// The parent folder's name resolves to a different title, the match should be reviewed.
+    Low,
+}
+
+/// How the title of a movie was found.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MatchedBy {
+    /// The override saved for the movie file.
+    Override,
+    /// An IMDB id in the name of the movie file or in a `.nfo` file next to it.
+    ImdbId,
+    /// The name of the movie file. When the runtimes are checked, the title is the best of the
+    /// candidates whose runtime agrees with the duration of the file, and the flag tells if one
+    /// did. It is `false` when the file could not be probed, and `None` when the runtimes are not
+    /// checked.
+    Name { runtime_agrees: Option<bool> },
 }
 
 #[derive(Debug)]
 pub struct ScanEntry<'e> {
     pub movie: File,
     pub title: &'e Title,
+    pub confidence: Confidence,
+    pub matched_by: MatchedBy,
     pub images: Vec<File>,
     pub subtitles: Vec<File>,
+    /// Audio companions kept with the movie, only scanned when asked.
+    pub audio: Vec<File>,
+    /// The movie is in a folder shared with unrelated movies, whose images, `movie.nfo` and name
+    /// were ignored.
+    pub shared_folder: bool,
+    /// The subtitle streams muxed in the movie file, only probed when asked.
+    pub muxed_subtitles: Vec<MuxedSubtitle>,
+    /// The container of the movie file does not match its extension, only probed when asked.
+    pub container_mismatch: Option<ContainerMismatch>,
+}
+
+/// A movie file whose content is not what its extension says.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContainerMismatch {
+    /// The container is another format, such as `matroska` for an `.avi` file.
+    Format(String),
+    /// ffprobe could not read the file, it may not be a movie at all.
+    Unreadable,
+}
+
+/// A subtitle stream muxed in a movie file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MuxedSubtitle {
+    /// Language of the stream such as `eng`, `None` when undetermined.
+    pub language: Option<String>,
+    pub forced: bool,
+    pub sdh: bool,
+}
+
+/// Find an IMDB id such as `tt0133093` in the movie's file name or in a `.nfo` file next to it.
+/// The `.nfo` file is read through the target holding the library.
+pub fn find_imdb_id(movie_file: &File, target: &dyn Target) -> Option<u32> {
+    let tokens = tokenize_filename(movie_file.stem());
+    if let Some(id) = tokens.iter().filter_map(|t| parse_id(t)).next() {
+        return Some(id);
+    }
+
+    for entry in movie_file.siblings()? {
+        let is_nfo = entry.is_file() && entry
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("nfo"))
+            .unwrap_or(false);
+        // A movie.nfo belongs to the folder, only trust it when the folder has a single movie.
+        let is_folder_nfo = entry.stem() == "movie"
+            && !movie_file
+                .siblings()
+                .map(|mut siblings| siblings.any(|f| f.is_video()))
+                .unwrap_or(false);
+        if is_nfo && (entry.stem() == movie_file.stem() || is_folder_nfo) {
+            if let Ok(content) = target.read(entry.path()) {
+                let content = String::from_utf8_lossy(&content);
+                if let Some(id) = content.split(tag_splitter).filter_map(parse_id).next() {
+                    return Some(id);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Best matches whose runtime is compared to the duration of the movie file.
+pub const RUNTIME_CANDIDATES: usize = 3;
+/// Difference between the runtime of a title and the duration of a file, relative to the runtime,
+/// under which they agree. Director's cuts run a few minutes longer than the theatrical cuts.
+const RUNTIME_TOLERANCE: f64 = 0.25;
+/// Difference in minutes under which the runtime and the duration always agree.
+const RUNTIME_MIN_TOLERANCE: f64 = 10.0;
+
+/// Tell if the runtime of the title agrees with the duration of a file in seconds. Titles without
+/// a runtime agree with any duration.
+pub fn runtime_matches(title: &Title, duration: f64) -> bool {
+    if title.runtime() == 0 {
+        return true;
+    }
+    let runtime = f64::from(title.runtime());
+    let tolerance = (runtime * RUNTIME_TOLERANCE).max(RUNTIME_MIN_TOLERANCE);
+    (runtime - duration / 60.0).abs() <= tolerance
+}
+
+/// Pick the best of the candidates whose runtime agrees with the duration, or the best candidate
+/// when none does. The flag tells if the runtime of the title picked agrees.
+fn pick_by_runtime<'t>(
+    candidates: &[&'t Title],
+    duration: Option<f64>,
+) -> Option<(&'t Title, bool)> {
+    let best = *candidates.first()?;
+    let duration = match duration {
+        Some(duration) => duration,
+        None => return Some((best, true)),
+    };
+    match candidates.iter().find(|title| runtime_matches(title, duration)) {
+        Some(title) => Some((title, true)),
+        None => Some((best, false)),
+    }
 }
 
 pub struct Scanner<'i> {
     root: File,
     imdb: &'i Imdb,
+    overrides: Overrides,
+    check_runtime: bool,
+    probe_subtitles: bool,
+    check_container: bool,
+    keep_audio: bool,
+    listener: Box<dyn Listener + 'i>,
+    probe_cache: Option<&'i ProbeCache>,
+    /// Host holding the library, whose ffprobe probes the movie files through `ssh`.
+    remote: Option<String>,
+    /// Where the files of the library are read, the local file system when not set.
+    target: Option<&'i dyn Target>,
+    /// The movie file probed last and its probe, shared by the checks of the movie.
+    last_probe: Option<(File, Option<Rc<MediaInfo>>)>,
+    /// The movie files ffprobe failed to read.
+    unreadable: HashSet<File>,
     is_flagged_cache: HashMap<File, bool>,
     is_movie_cache: HashMap<File, bool>,
+    is_shared_cache: HashMap<File, bool>,
 }
 
 impl<'i> Scanner<'i> {
@@ -84,8 +275,165 @@ impl<'i> Scanner<'i> {
         Scanner {
             root: root.clone(),
             imdb,
+            overrides: Overrides::default(),
+            check_runtime: false,
+            probe_subtitles: false,
+            check_container: false,
+            keep_audio: false,
+            listener: Box::new(()),
+            probe_cache: None,
+            remote: None,
+            target: None,
+            last_probe: None,
+            unreadable: HashSet::new(),
             is_flagged_cache: HashMap::new(),
             is_movie_cache: HashMap::new(),
+            is_shared_cache: HashMap::new(),
+        }
+    }
+
+    /// Trust the IMDB ids of the movie files identified before over the matching of their names.
+    pub fn overrides(mut self, overrides: Overrides) -> Scanner<'i> {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Probe the duration of the movie files matched by name with ffprobe, and prefer the matches
+    /// whose runtime agrees with it. Matches whose runtime disagrees get a low confidence.
+    pub fn check_runtime(mut self, check_runtime: bool) -> Scanner<'i> {
+        self.check_runtime = check_runtime;
+        self
+    }
+
+    /// Probe the subtitle streams muxed in the movie files with ffprobe.
+    pub fn probe_subtitles(mut self, probe_subtitles: bool) -> Scanner<'i> {
+        self.probe_subtitles = probe_subtitles;
+        self
+    }
+
+    /// Probe the container of the movie files with ffprobe, and flag the files whose container
+    /// does not match their extension or that ffprobe cannot read.
+    pub fn check_container(mut self, check_container: bool) -> Scanner<'i> {
+        self.check_container = check_container;
+        self
+    }
+
+    /// Keep the audio files next to the movies with them, such as the `.flac` and `.cue` files of
+    /// concert films, instead of leaving them to the cleanup.
+    pub fn keep_audio(mut self, keep_audio: bool) -> Scanner<'i> {
+        self.keep_audio = keep_audio;
+        self
+    }
+
+    /// Report the movies matched and the probes that failed to the listener.
+    pub fn listener(mut self, listener: impl Listener + 'i) -> Scanner<'i> {
+        self.listener = Box::new(listener);
+        self
+    }
+
+    /// Keep the probes of the movie files in the cache, and only probe the files that changed
+    /// since they were cached.
+    pub fn probe_cache(mut self, probe_cache: &'i ProbeCache) -> Scanner<'i> {
+        self.probe_cache = Some(probe_cache);
+        self
+    }
+
+    /// Probe the movie files with the ffprobe of the host holding the library, reached with `ssh`.
+    /// The probes of a remote library are not cached, its files cannot be checked for changes.
+    pub fn remote(mut self, host: impl Into<String>) -> Scanner<'i> {
+        self.remote = Some(host.into());
+        self
+    }
+
+    /// Read the files of the library, such as the `.nfo` files, through the target holding it.
+    pub fn target(mut self, target: &'i dyn Target) -> Scanner<'i> {
+        self.target = Some(target);
+        self
+    }
+
+    /// Probe the movie file with ffprobe.
+    fn probe(&mut self, movie_file: &File) -> Option<Rc<MediaInfo>> {
+        if let Some((ref file, ref info)) = self.last_probe {
+            if file == movie_file {
+                return info.clone();
+            }
+        }
+        // A probe killed after the timeout only skips this movie, such as a corrupt file or a
+        // network share that hangs, the other movies are still probed.
+        let result = match (&self.remote, self.probe_cache) {
+            (Some(host), _) => ffprobe::scan_remote(host, movie_file.path()),
+            (None, Some(cache)) => cache.probe(movie_file.path(), |path| ffprobe::scan(path)),
+            (None, None) => ffprobe::scan(movie_file.path()),
+        };
+        let info = match result {
+            Ok(info) => Some(Rc::new(info)),
+            Err(ffprobe::Error::Timeout(timeout)) => {
+                self.listener.event(Event::ProbeTimedOut {
+                    movie: movie_file.path().to_owned(),
+                    timeout,
+                });
+                None
+            }
+            Err(ffprobe::Error::ProbeFailed { .. }) => {
+                self.unreadable.insert(movie_file.clone());
+                None
+            }
+            Err(_) => None,
+        };
+        self.last_probe = Some((movie_file.clone(), info.clone()));
+        info
+    }
+
+    fn muxed_subtitles(&mut self, movie_file: &File) -> Vec<MuxedSubtitle> {
+        if !self.probe_subtitles {
+            return vec![];
+        }
+        let info = match self.probe(movie_file) {
+            Some(info) => info,
+            None => return vec![],
+        };
+        info.subtitle
+            .iter()
+            .map(|stream| MuxedSubtitle {
+                language: stream.language().map(|s| s.to_string()),
+                forced: stream.is_forced(),
+                sdh: stream.is_sdh(),
+            })
+            .collect()
+    }
+
+    fn container_mismatch(&mut self, movie_file: &File) -> Option<ContainerMismatch> {
+        if !self.check_container {
+            return None;
+        }
+        match self.probe(movie_file) {
+            Some(info) => match info.matches_extension(movie_file.extension()?) {
+                Some(false) => info.format_name.clone().map(ContainerMismatch::Format),
+                _ => None,
+            },
+            None if self.unreadable.contains(movie_file) => Some(ContainerMismatch::Unreadable),
+            None => None,
+        }
+    }
+
+    /// Find the match for the name, and tell if its runtime agrees with the movie file.
+    fn lookup(
+        &mut self,
+        movie_file: &File,
+        name: &str,
+        year: Option<i32>,
+    ) -> Option<(&'i Title, bool)> {
+        if !self.check_runtime {
+            return self.imdb.lookup(name, year).map(|title| (title, true));
+        }
+        let candidates = self.imdb.lookup_n(name, year, RUNTIME_CANDIDATES);
+        if candidates.is_empty() {
+            return None;
+        }
+        // A movie that could not be probed has no runtime to agree with, its match is doubtful.
+        match self.probe(movie_file) {
+            Some(info) => pick_by_runtime(&candidates, info.duration),
+            None => Some((candidates[0], false)),
         }
     }
 
@@ -107,7 +455,7 @@ impl<'i> Scanner<'i> {
                         .map(|p| self.is_flagged_dir(&p))
                         .unwrap_or(false);
                     let has_token = tokens.iter().any(|t| FILE_FLAG.contains(t.as_str()));
-                    let is_small = file.metadata().len() <= FILE_MIN_SIZE;
+                    let is_small = file.len() <= FILE_MIN_SIZE;
 
                     (parent_flagged && (has_token || is_small)) || (has_token && is_small)
                 };
@@ -118,33 +466,147 @@ impl<'i> Scanner<'i> {
         }
     }
 
+    /// Tell if the folder of the movie holds enough movies to be a folder of unrelated movies, in
+    /// which case the files next to the movie tell nothing about it.
+    fn is_shared_folder(&mut self, movie_file: &File) -> bool {
+        let dir = match movie_file.parent() {
+            Some(dir) => dir,
+            None => return false,
+        };
+        if let Some(&shared) = self.is_shared_cache.get(&dir) {
+            return shared;
+        }
+        let movies = dir
+            .children()
+            .filter(|f| self.is_movie_file(f))
+            .take(SHARED_FOLDER_MOVIES)
+            .count();
+        let shared = movies >= SHARED_FOLDER_MOVIES;
+        self.is_shared_cache.insert(dir, shared);
+        shared
+    }
+
     pub fn scan_root(&mut self) -> Result<Vec<ScanEntry<'i>>, Error> {
         let mut scan_entries = vec![];
 
         for entry in self.root.descendants() {
             if self.is_movie_file(&entry) {
                 let stem = entry.stem();
+                // The images and the folder's name of a shared folder belong to no movie.
+                let shared_folder = self.is_shared_folder(&entry);
+                let (images, audio) = if shared_folder {
+                    (vec![], vec![])
+                } else {
+                    (self.scan_images(&entry), self.scan_audio(&entry))
+                };
+
+                // A known IMDB id bypasses the fuzzy matching.
+                let id = match self.overrides.get(entry.path()) {
+                    Some(id) => Some((id, MatchedBy::Override)),
+                    None => match self.target {
+                        Some(target) => find_imdb_id(&entry, target),
+                        None => find_imdb_id(&entry, &Local::new(None)),
+                    }.map(|id| (id, MatchedBy::ImdbId)),
+                };
+                let found = id.and_then(|(id, by)| self.imdb.by_id(id).map(|title| (title, by)));
+                if let Some((title, matched_by)) = found {
+                    let scan_entry = ScanEntry {
+                        movie: entry.clone(),
+                        title,
+                        confidence: Confidence::High,
+                        matched_by,
+                        images,
+                        subtitles: self.scan_subtitles(&entry, stem),
+                        audio,
+                        shared_folder,
+                        muxed_subtitles: self.muxed_subtitles(&entry),
+                        container_mismatch: self.container_mismatch(&entry),
+                    };
+                    self.found(&mut scan_entries, scan_entry);
+                    continue;
+                }
+
                 let (name, year) = parse_movie(stem);
-                if let Some(title) = self.imdb.lookup(&name, year) {
-                    scan_entries.push(ScanEntry {
+                if let Some((title, runtime_agrees)) = self.lookup(&entry, &name, year) {
+                    let confidence = if !runtime_agrees {
+                        Confidence::Low
+                    } else if shared_folder {
+                        Confidence::Normal
+                    } else {
+                        self.parent_confidence(&entry, title, year)
+                    };
+                    let runtime_agrees = if self.check_runtime {
+                        Some(runtime_agrees)
+                    } else {
+                        None
+                    };
+                    let scan_entry = ScanEntry {
                         movie: entry.clone(),
                         title,
-                        images: self.scan_images(&entry),
+                        confidence,
+                        matched_by: MatchedBy::Name { runtime_agrees },
+                        images,
                         subtitles: self.scan_subtitles(&entry, stem),
-                    });
+                        audio,
+                        shared_folder,
+                        muxed_subtitles: self.muxed_subtitles(&entry),
+                        container_mismatch: self.container_mismatch(&entry),
+                    };
+                    self.found(&mut scan_entries, scan_entry);
                 }
             }
         }
         Ok(scan_entries)
     }
 
+    fn found(&mut self, scan_entries: &mut Vec<ScanEntry<'i>>, entry: ScanEntry<'i>) {
+        self.listener.event(Event::Matched {
+            movie: entry.movie.path().to_owned(),
+            imdb_id: format!("tt{:07}", entry.title.id()),
+            title: entry.title.display_title().to_string(),
+            year: entry.title.year(),
+            confidence: entry.confidence,
+        });
+        scan_entries.push(entry);
+    }
+
+    /// Cross-check the match against the name of the movie file's parent folder.
+    fn parent_confidence(&self, movie_file: &File, title: &Title, year: Option<i32>) -> Confidence {
+        let parent = match movie_file.parent() {
+            Some(ref parent) if *parent != self.root => parent.clone(),
+            _ => return Confidence::Normal,
+        };
+
+        let (parent_name, parent_year) = parse_movie(parent.name());
+        if let (Some(year), Some(parent_year)) = (year, parent_year) {
+            if year != parent_year {
+                return Confidence::Low;
+            }
+        }
+
+        match self.imdb.lookup(&parent_name, parent_year.or(year)) {
+            Some(parent_title) if parent_title == title => Confidence::High,
+            Some(_) => Confidence::Low,
+            None => Confidence::Normal,
+        }
+    }
+
     fn scan_images(&self, movie_file: &File) -> Vec<File> {
         let mut images = Vec::new();
         if let Some(siblings) = movie_file.siblings() {
             for entry in siblings {
-                if entry.name() == "backdrop.jpg" {
+                if !entry.name().ends_with(".jpg") {
+                    continue;
+                }
+                // the images numbered when several of a kind share a name, such as `poster1.jpg`
+                let stem = entry.name()[..entry.name().len() - 4]
+                    .trim_right_matches(|c: char| c.is_ascii_digit());
+                if stem == "backdrop" {
+                    images.push(entry);
+                } else if stem == "poster" {
                     images.push(entry);
-                } else if entry.name() == "poster.jpg" {
+                } else if stem.ends_with("-poster") || stem.ends_with("-fanart") {
+                    // images named with the kodi naming preset
                     images.push(entry);
                 }
             }
@@ -152,6 +614,16 @@ impl<'i> Scanner<'i> {
         images
     }
 
+    fn scan_audio(&self, movie_file: &File) -> Vec<File> {
+        if !self.keep_audio {
+            return vec![];
+        }
+        match movie_file.siblings() {
+            Some(siblings) => siblings.filter(FileExt::is_audio).collect(),
+            None => vec![],
+        }
+    }
+
     fn scan_subtitles(&mut self, movie_file: &File, movie_stem: &str) -> Vec<File> {
         let mut subtitles = Vec::new();
         let mut movies_in_folder = 0;
@@ -183,6 +655,175 @@ impl<'i> Scanner<'i> {
             }
         }
 
+        // A VobSub subtitle is kept whole even when only one half matched the movie's stem.
+        let pairs: Vec<File> = subtitles
+            .iter()
+            .filter_map(|f| f.vobsub_pair())
+            .filter(|pair| !subtitles.contains(pair))
+            .collect();
+        subtitles.extend(pairs);
+
         subtitles
     }
 }
+
+#[test]
+fn test_scan_root() {
+    use test_util::{listing, scan, test_index, GB};
+
+    let imdb = test_index();
+    let root = listing(
+        "/movies",
+        &[
+            ("Heat.1995.1080p/", 0),
+            ("Heat.1995.1080p/Heat.1995.1080p.mkv", GB),
+            ("Heat.1995.1080p/Heat.1995.1080p.srt", 1),
+            ("Heat.1995.1080p/poster.jpg", 1),
+            ("The.Matrix.1999.mkv", GB),
+            ("sample.mkv", 1),
+        ],
+    );
+
+    let mut entries = scan(&root, &imdb);
+    entries.sort_by_key(|e| e.title.id());
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].title.id(), 113277);
+    assert_eq!(entries[0].confidence, Confidence::High);
+    assert_eq!(entries[0].images.len(), 1);
+    assert_eq!(entries[0].subtitles.len(), 1);
+    assert_eq!(entries[1].title.id(), 133093);
+    assert_eq!(entries[1].confidence, Confidence::Normal);
+    assert_eq!(entries[1].matched_by, MatchedBy::Name { runtime_agrees: None });
+
+    // The override wins over the name of the file.
+    let mut overrides = Overrides::default();
+    overrides.insert("/movies/The.Matrix.1999.mkv", "tt0113277");
+    let mut entries = Scanner::new(&root, &imdb).overrides(overrides).scan_root().unwrap();
+    entries.sort_by_key(|e| e.movie.path().to_owned());
+    assert_eq!(entries[1].title.id(), 113277);
+    assert_eq!(entries[1].matched_by, MatchedBy::Override);
+}
+
+#[test]
+fn test_shared_folder() {
+    use test_util::{listing, scan, test_index, GB};
+
+    let imdb = test_index();
+    let root = listing(
+        "/movies",
+        &[
+            ("Downloads/", 0),
+            ("Downloads/Heat.1995.mkv", GB),
+            ("Downloads/Heat.1995.srt", 1),
+            ("Downloads/The.Matrix.1999.mkv", GB),
+            ("Downloads/Inception.2010.mkv", GB),
+            ("Downloads/poster.jpg", 1),
+        ],
+    );
+
+    let entries = scan(&root, &imdb);
+    assert_eq!(entries.len(), 3);
+    for entry in entries.iter() {
+        assert!(entry.shared_folder);
+        assert!(entry.images.is_empty());
+        assert_eq!(entry.confidence, Confidence::Normal);
+    }
+    let heat = entries.iter().find(|e| e.title.id() == 113277).unwrap();
+    assert_eq!(heat.subtitles.len(), 1);
+}
+
+#[test]
+fn test_pick_by_runtime() {
+    use imdb::TitleKind;
+
+    let original = Title::new(1, "Solaris", 1972, TitleKind::Movie, 90000).with_runtime(167);
+    let remake = Title::new(2, "Solaris", 2002, TitleKind::Movie, 90000).with_runtime(99);
+    let short = Title::new(3, "Solaris", 2010, TitleKind::Short, 100).with_runtime(12);
+    let unknown = Title::new(4, "Solaris", 2015, TitleKind::Movie, 100);
+
+    assert!(runtime_matches(&original, 180.0 * 60.0));
+    assert!(!runtime_matches(&original, 99.0 * 60.0));
+    assert!(runtime_matches(&short, 20.0 * 60.0));
+    assert!(runtime_matches(&unknown, 5.0 * 60.0));
+
+    let candidates = [&remake, &original, &short];
+    assert_eq!(pick_by_runtime(&candidates, None), Some((&remake, true)));
+    assert_eq!(pick_by_runtime(&candidates, Some(165.0 * 60.0)), Some((&original, true)));
+    assert_eq!(pick_by_runtime(&candidates, Some(15.0 * 60.0)), Some((&short, true)));
+    assert_eq!(pick_by_runtime(&candidates, Some(60.0 * 60.0)), Some((&remake, false)));
+    assert_eq!(pick_by_runtime(&[], Some(60.0 * 60.0)), None);
+}
+
+#[test]
+fn test_keep_audio() {
+    use imdb::TitleKind;
+    use test_util::{listing, scan, GB};
+
+    let imdb = Imdb::from_titles(vec![Title::new(
+        1234567,
+        "Stop Making Sense",
+        1984,
+        TitleKind::Movie,
+        40000,
+    )]);
+    let root = listing(
+        "/movies",
+        &[
+            ("Stop.Making.Sense.1984/", 0),
+            ("Stop.Making.Sense.1984/Stop.Making.Sense.1984.mkv", GB),
+            ("Stop.Making.Sense.1984/01 - Psycho Killer.flac", 1),
+            ("Stop.Making.Sense.1984/Stop Making Sense.cue", 1),
+            ("Stop.Making.Sense.1984/notes.txt", 1),
+        ],
+    );
+
+    let entries = scan(&root, &imdb);
+    assert!(entries[0].audio.is_empty());
+
+    let entries = Scanner::new(&root, &imdb).keep_audio(true).scan_root().unwrap();
+    let mut names: Vec<&str> = entries[0].audio.iter().map(File::name).collect();
+    names.sort();
+    assert_eq!(names, vec!["01 - Psycho Killer.flac", "Stop Making Sense.cue"]);
+}
+
+#[test]
+fn test_find_imdb_id_through_target() {
+    use std::io;
+
+    use rename::Renames;
+    use test_util::listing;
+
+    /// A library on another machine, only its `.nfo` file can be read.
+    struct Nfo;
+
+    impl Target for Nfo {
+        fn drift(&self, _file: &File) -> io::Result<Option<&'static str>> {
+            Ok(None)
+        }
+
+        fn apply(&self, _renames: &Renames, _listener: &mut dyn Listener) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn remove_file(&self, _path: &Path, _listener: &mut dyn Listener) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn remove_dir(&self, _path: &Path) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+            match path.to_str() {
+                // Latin-1 text, which is not valid UTF-8.
+                Some("/movies/Heat/Heat.nfo") => Ok(b"Heat \xe9t\xe9 tt0113277".to_vec()),
+                _ => Err(io::ErrorKind::NotFound.into()),
+            }
+        }
+    }
+
+    let root = listing("/movies", &[("Heat/", 0), ("Heat/Heat.mkv", 1), ("Heat/Heat.nfo", 1)]);
+    let movie = root.descendants().find(|f| f.name() == "Heat.mkv").unwrap();
+    assert_eq!(find_imdb_id(&movie, &Nfo), Some(113277));
+    assert_eq!(find_imdb_id(&movie, &Local::new(None)), None);
+}
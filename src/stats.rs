@@ -0,0 +1,91 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Resources used by a run: the time spent in each phase, the bytes moved and the peak memory.
+pub struct RunStats {
+    phases: Vec<(&'static str, Duration)>,
+    bytes_moved: u64,
+}
+
+impl RunStats {
+    pub fn new() -> RunStats {
+        RunStats {
+            phases: vec![],
+            bytes_moved: 0,
+        }
+    }
+
+    /// Run the phase and record the time it took. The time of phases run more than once adds up.
+    pub fn time<T>(&mut self, phase: &'static str, func: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = func();
+        self.add_time(phase, start.elapsed());
+        result
+    }
+
+    pub fn add_time(&mut self, phase: &'static str, elapsed: Duration) {
+        match self.phases.iter_mut().find(|&&mut (name, _)| name == phase) {
+            Some(&mut (_, ref mut total)) => *total += elapsed,
+            None => self.phases.push((phase, elapsed)),
+        }
+    }
+
+    pub fn add_bytes_moved(&mut self, bytes: u64) {
+        self.bytes_moved += bytes;
+    }
+}
+
+/// Peak resident memory of the process in bytes, only available on Linux.
+#[cfg(target_os = "linux")]
+fn peak_memory() -> Option<u64> {
+    let status = ::std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peak_memory() -> Option<u64> {
+    None
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Write a bit rate in megabits per second, such as `8.2 Mb/s`.
+pub fn format_bit_rate(bit_rate: u64) -> String {
+    format!("{:.1} Mb/s", bit_rate as f64 / 1_000_000.0)
+}
+
+impl fmt::Display for RunStats {
+    fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
+        for &(phase, elapsed) in self.phases.iter() {
+            let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+            write!(w, "{} {:.2}s, ", phase, secs)?;
+        }
+        write!(w, "{} moved", format_bytes(self.bytes_moved))?;
+        if let Some(peak) = peak_memory() {
+            write!(w, ", peak memory {}", format_bytes(peak))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_format_bytes() {
+    assert_eq!(format_bytes(512), "512 B");
+    assert_eq!(format_bytes(1536), "1.5 KB");
+    assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GB");
+}
@@ -0,0 +1,44 @@
+//! Fixtures shared by the tests: a small index of well known movies built in memory, and the
+//! libraries scanned against it listed in memory instead of walked.
+
+use std::path::Path;
+
+use imdb::{Imdb, Title, TitleKind};
+
+use scan::{ScanEntry, Scanner};
+use vfs::{self, File, NodeKind};
+
+pub const GB: u64 = 1 << 30;
+
+/// Heat (1995), the title most tests match.
+pub fn heat() -> Title {
+    Title::new(113277, "Heat", 1995, TitleKind::Movie, 500000)
+}
+
+/// An index of Heat (1995), The Matrix (1999) and Inception (2010).
+pub fn test_index() -> Imdb {
+    Imdb::from_titles(vec![
+        heat(),
+        Title::new(133093, "The Matrix", 1999, TitleKind::Movie, 1700000),
+        Title::new(1375666, "Inception", 2010, TitleKind::Movie, 2000000),
+    ])
+}
+
+/// List a library in memory. The paths of the files and their lengths are relative to the root,
+/// the paths ending with `/` are directories.
+pub fn listing(root: &str, files: &[(&str, u64)]) -> File {
+    let listing = files.iter().map(|&(path, len)| {
+        let kind = if path.ends_with('/') {
+            NodeKind::Dir
+        } else {
+            NodeKind::File
+        };
+        (Path::new(root).join(path.trim_right_matches('/')), kind, len)
+    });
+    vfs::from_listing(root, listing)
+}
+
+/// Scan the library with the default options.
+pub fn scan<'i>(root: &File, imdb: &'i Imdb) -> Vec<ScanEntry<'i>> {
+    Scanner::new(root, imdb).scan_root().unwrap()
+}
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+use failure::Error;
+use reqwest::{Client, Response};
+use serde_json;
+
+const API_URL: &str = "https://api.themoviedb.org/3";
+const IMAGE_URL: &str = "https://image.tmdb.org/t/p/original";
+
+#[derive(Deserialize)]
+struct FindResults {
+    movie_results: Vec<Movie>,
+}
+
+/// A movie found on TMDB from its IMDB id.
+#[derive(Deserialize)]
+pub struct Movie {
+    pub id: u64,
+    pub poster_path: Option<String>,
+    pub backdrop_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseDates {
+    results: Vec<CountryReleases>,
+}
+
+#[derive(Deserialize)]
+struct CountryReleases {
+    iso_3166_1: String,
+    release_dates: Vec<Release>,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    certification: String,
+}
+
+/// Client of the TMDB API, which finds the images and the certifications of a movie from its IMDB
+/// id.
+pub struct Tmdb {
+    client: Client,
+    api_key: String,
+}
+
+impl Tmdb {
+    pub fn new(api_key: impl Into<String>) -> Tmdb {
+        Tmdb {
+            client: Client::new(),
+            api_key: api_key.into(),
+        }
+    }
+
+    pub fn find(&self, imdb_id: u32) -> Result<Option<Movie>, Error> {
+        let url = format!(
+            "{}/find/tt{:07}?api_key={}&external_source=imdb_id",
+            API_URL, imdb_id, self.api_key
+        );
+        let resp = self.get(&url)?;
+        let results: FindResults = serde_json::from_reader(resp)?;
+        Ok(results.movie_results.into_iter().next())
+    }
+
+    /// Get the certification of the movie in the country, such as `PG-13` in `US`.
+    pub fn certification(&self, imdb_id: u32, country: &str) -> Result<Option<String>, Error> {
+        let movie = match self.find(imdb_id)? {
+            Some(movie) => movie,
+            None => return Ok(None),
+        };
+        let url = format!(
+            "{}/movie/{}/release_dates?api_key={}",
+            API_URL, movie.id, self.api_key
+        );
+        let resp = self.get(&url)?;
+        let dates: ReleaseDates = serde_json::from_reader(resp)?;
+        Ok(dates
+            .results
+            .into_iter()
+            .filter(|r| r.iso_3166_1 == country)
+            .flat_map(|r| r.release_dates)
+            .map(|r| r.certification)
+            .find(|c| !c.is_empty()))
+    }
+
+    /// Download the image to a temporary file renamed over the destination once complete.
+    pub fn download_image(&self, image_path: &str, dest: &Path) -> Result<(), Error> {
+        let url = format!("{}{}", IMAGE_URL, image_path);
+        let mut resp = self.get(&url)?;
+        let tmp_path = dest.with_extension("part");
+        let result = File::create(&tmp_path)
+            .and_then(|mut file| io::copy(&mut resp, &mut file))
+            .and_then(|_| fs::rename(&tmp_path, dest));
+        if let Err(err) = result {
+            // The partial download is not left next to the movie.
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    /// Send a GET request. The API key is in the query string, it is removed from the URL of
+    /// the errors, which are printed.
+    fn get(&self, url: &str) -> Result<Response, Error> {
+        self.client
+            .get(url)
+            .send()
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|err| format_err!("{}", self.redact(&err.to_string())))
+    }
+
+    fn redact(&self, text: &str) -> String {
+        if self.api_key.is_empty() {
+            return text.to_string();
+        }
+        text.replace(&self.api_key, "<api key>")
+    }
+}
+
+/// Certifications found on TMDB before, so that a title is only looked up once. The file is a
+/// JSON object of the country and of the IMDB ids to their certification in the country, `null`
+/// when TMDB has none.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Certifications {
+    country: String,
+    certifications: BTreeMap<String, Option<String>>,
+}
+
+impl Certifications {
+    /// Load the certifications of the country, a missing file or the certifications of another
+    /// country have none.
+    pub fn load(path: impl AsRef<Path>, country: &str) -> Result<Certifications, Error> {
+        let path = path.as_ref();
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut cached = Certifications::default();
+        if !content.is_empty() {
+            cached = serde_json::from_str(&content).map_err(|err| {
+                format_err!("invalid certifications in {}: {}", path.display(), err)
+            })?;
+        }
+        if cached.country != country {
+            cached = Certifications {
+                country: country.to_string(),
+                certifications: BTreeMap::new(),
+            };
+        }
+        Ok(cached)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+
+    /// Look up the certification of the title on TMDB, unless it was found before.
+    pub fn fetch(&mut self, tmdb: &Tmdb, imdb_id: u32) -> Result<(), Error> {
+        let key = format!("tt{:07}", imdb_id);
+        if !self.certifications.contains_key(&key) {
+            let certification = tmdb.certification(imdb_id, &self.country)?;
+            self.certifications.insert(key, certification);
+        }
+        Ok(())
+    }
+
+    /// Get the certification of the title found before.
+    pub fn get(&self, imdb_id: u32) -> Option<&str> {
+        let key = format!("tt{:07}", imdb_id);
+        self.certifications.get(&key).and_then(|c| c.as_ref()).map(|c| c.as_str())
+    }
+}
+
+#[test]
+fn test_redact() {
+    let tmdb = Tmdb::new("secret");
+    assert_eq!(
+        tmdb.redact("error for https://api.themoviedb.org/3/find/tt0113277?api_key=secret"),
+        "error for https://api.themoviedb.org/3/find/tt0113277?api_key=<api key>"
+    );
+    assert_eq!(Tmdb::new("").redact("timed out"), "timed out");
+}
+
+#[test]
+fn test_certifications() {
+    use std::process;
+
+    let path = ::std::env::temp_dir().join(format!("mero-certifications-{}.json", process::id()));
+    let mut certifications = Certifications::load(&path, "US").unwrap();
+    assert_eq!(certifications.get(113277), None);
+
+    let key = |id: u32| format!("tt{:07}", id);
+    certifications.certifications.insert(key(113277), Some("R".into()));
+    certifications.certifications.insert(key(114709), None);
+    certifications.save(&path).unwrap();
+
+    let certifications = Certifications::load(&path, "US").unwrap();
+    assert_eq!(certifications.get(113277), Some("R"));
+    assert_eq!(certifications.get(114709), None);
+    assert!(certifications.certifications.contains_key(&key(114709)));
+    let certifications = Certifications::load(&path, "FR").unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(certifications.get(113277), None);
+}
@@ -1,6 +1,10 @@
 use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
 pub fn filter_path(source: &str) -> String {
     let mut dest = String::with_capacity(source.len());
@@ -31,3 +35,144 @@ pub fn format_runtime(runtime: i32) -> String {
     let minutes = runtime % 60;
     format!("{}h {:02}m", hours, minutes)
 }
+
+/// Tell if the error was caused by renaming a file to another device.
+pub fn is_cross_device(err: &io::Error) -> bool {
+    // EXDEV on Unix systems and ERROR_NOT_SAME_DEVICE on Windows.
+    if cfg!(windows) {
+        err.raw_os_error() == Some(17)
+    } else {
+        err.raw_os_error() == Some(18)
+    }
+}
+
+/// Tell if both paths are located on the same device.
+#[cfg(unix)]
+pub fn same_device(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(a.metadata()?.dev() == b.metadata()?.dev())
+}
+
+/// Tell if both paths are located on the same device.
+#[cfg(not(unix))]
+pub fn same_device(a: &Path, b: &Path) -> io::Result<bool> {
+    // Compare the prefix of the paths, such as the drive letter on Windows.
+    Ok(a.components().next() == b.components().next())
+}
+
+/// Get the free space of the file system containing the path using `df`.
+pub fn free_space(path: &Path) -> Option<u64> {
+    let output = Command::new("df").arg("-Pk").arg(path).output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().nth(1)?;
+    let available: u64 = line.split_whitespace().nth(3)?.parse().ok()?;
+    Some(available * 1024)
+}
+
+/// Check that files can be created in the directory by creating and removing an empty file. Both
+/// read-only mounts and directories the user cannot write to fail.
+pub fn check_writable(dir: &Path) -> io::Result<()> {
+    let probe = dir.join(".merovingian-probe");
+    fs::write(&probe, b"")?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
+/// 64-bit FNV-1a hash, which unlike the hasher of the standard library never changes between
+/// releases, so that the hashes can be saved.
+pub struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Fnv1a {
+    fn default() -> Fnv1a {
+        Fnv1a::new()
+    }
+}
+
+/// Derive a stable identifier from the operation and the paths it involves, so that an operation
+/// planned by two runs has the same identifier.
+pub fn plan_id(op: &str, paths: &[&Path]) -> String {
+    let mut hash = Fnv1a::new();
+    hash.feed(op.as_bytes());
+    hash.feed(&[0]);
+    for path in paths {
+        hash.feed(path.to_string_lossy().as_bytes());
+        hash.feed(&[0]);
+    }
+    format!("{:012x}", hash.finish() >> 16)
+}
+
+/// Derive a stable key from the root of a library and the host holding it, so that the state kept
+/// between runs, such as the cleanup list, is kept apart for each library.
+pub fn library_key(host: Option<&str>, root: &Path) -> String {
+    let mut hash = Fnv1a::new();
+    hash.feed(host.unwrap_or("").as_bytes());
+    hash.feed(&[0]);
+    hash.feed(root.to_string_lossy().as_bytes());
+    format!("{:012x}", hash.finish() >> 16)
+}
+
+/// Selection of the planned operations to apply, by identifier.
+pub struct PlanFilter {
+    only: Option<HashSet<String>>,
+    exclude: HashSet<String>,
+}
+
+impl PlanFilter {
+    /// Parse comma separated lists of identifiers, all the operations are allowed without lists.
+    pub fn new(only: Option<&str>, exclude: Option<&str>) -> PlanFilter {
+        let parse = |text: &str| -> HashSet<String> {
+            text.split(',')
+                .map(|id| id.trim().to_lowercase())
+                .filter(|id| !id.is_empty())
+                .collect()
+        };
+        PlanFilter {
+            only: only.map(&parse),
+            exclude: exclude.map(&parse).unwrap_or_default(),
+        }
+    }
+
+    pub fn allows(&self, id: &str) -> bool {
+        self.only.as_ref().map(|only| only.contains(id)).unwrap_or(true)
+            && !self.exclude.contains(id)
+    }
+}
+
+#[test]
+fn test_plan_id() {
+    let a = plan_id("mv", &[Path::new("/a/b.mkv"), Path::new("/c/d.mkv")]);
+    assert_eq!(a.len(), 12);
+    assert_eq!(a, plan_id("mv", &[Path::new("/a/b.mkv"), Path::new("/c/d.mkv")]));
+    assert_ne!(a, plan_id("mv", &[Path::new("/a/b.mkv/c"), Path::new("d.mkv")]));
+    assert_ne!(a, plan_id("rm", &[Path::new("/a/b.mkv"), Path::new("/c/d.mkv")]));
+
+    let filter = PlanFilter::new(Some(&format!("{},abc", a)), Some("abc"));
+    assert!(filter.allows(&a));
+    assert!(!filter.allows("abc"));
+    assert!(PlanFilter::new(None, None).allows("abc"));
+}
+
+#[test]
+fn test_library_key() {
+    let key = library_key(None, Path::new("/movies"));
+    assert_eq!(key, library_key(None, Path::new("/movies")));
+    assert_ne!(key, library_key(None, Path::new("/movies/kids")));
+    assert_ne!(key, library_key(Some("nas"), Path::new("/movies")));
+}
@@ -0,0 +1,45 @@
+use std::path::Path;
+
+use failure::Error;
+use ffprobe::{self, Ffmpeg};
+use yansi::Paint;
+
+use scan::ScanEntry;
+
+/// Decode errors shown for each movie, the others are counted.
+const SHOWN_ERRORS: usize = 5;
+
+/// Decode every movie with ffmpeg and report those with decode errors, such as broken rips, so
+/// that they can be replaced before they are organized. Movies ffmpeg cannot open are reported as
+/// broken too.
+pub fn run<'i>(root_path: &Path, entries: &[ScanEntry<'i>]) -> Result<(), Error> {
+    let ffmpeg = Ffmpeg::default();
+    let mut broken = 0;
+
+    for entry in entries.iter() {
+        let path = entry.movie.path();
+        let name = path.strip_prefix(root_path).unwrap_or(path).display();
+        println!("Verifying {}...", name);
+        let errors = match ffmpeg.verify(path) {
+            Ok(errors) => errors,
+            Err(ffprobe::Error::NotFound(program)) => bail!(
+                "{} not found. Install ffmpeg or set FFMPEG_PATH to its path.",
+                program.display()
+            ),
+            Err(err) => vec![err.to_string()],
+        };
+        if errors.is_empty() {
+            continue;
+        }
+        broken += 1;
+        for error in errors.iter().take(SHOWN_ERRORS) {
+            println!("\t{}", Paint::red(error));
+        }
+        if errors.len() > SHOWN_ERRORS {
+            println!("\t... and {} more errors", errors.len() - SHOWN_ERRORS);
+        }
+    }
+
+    println!("{} of {} movies have decode errors.", broken, entries.len());
+    Ok(())
+}
@@ -1,13 +1,45 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::fs::Metadata;
+use std::fs::{self, DirEntry, Metadata, ReadDir};
 use std::hash::{Hash, Hasher};
 use std::io;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use same_file::Handle;
+
+use ignore::{IgnoreFile, IGNORE_FILE};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    File,
+    Dir,
+    /// A symbolic link that was not followed.
+    Symlink,
+    Other,
+}
+
+impl NodeKind {
+    fn from_metadata(metadata: &Metadata) -> NodeKind {
+        if metadata.file_type().is_symlink() {
+            NodeKind::Symlink
+        } else if metadata.is_dir() {
+            NodeKind::Dir
+        } else if metadata.is_file() {
+            NodeKind::File
+        } else {
+            NodeKind::Other
+        }
+    }
+}
 
 struct Node {
     path: PathBuf,
-    metadata: Metadata,
+    kind: NodeKind,
+    len: u64,
+    /// Modification time of the node, unknown for the nodes of a listing.
+    modified: Option<SystemTime>,
     parent: Option<NodeId>,
     children: Vec<NodeId>,
 }
@@ -74,18 +106,48 @@ impl File {
     }
 
     #[inline]
-    pub fn metadata(&self) -> &Metadata {
-        &self.get().metadata
+    pub fn kind(&self) -> NodeKind {
+        self.get().kind
+    }
+
+    /// Size of the file in bytes.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.get().len
+    }
+
+    /// Tell how the file changed on disk since it was walked, if it did: it disappeared, or its
+    /// size or modification time changed. Directories never drift, their content does.
+    pub fn drift(&self) -> Option<&'static str> {
+        if !self.is_file() {
+            return None;
+        }
+        let metadata = match fs::metadata(self.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => return Some("disappeared"),
+        };
+        if metadata.len() != self.len() {
+            Some("changed size")
+        } else if self.get().modified.map_or(false, |m| metadata.modified().ok() != Some(m)) {
+            Some("was modified")
+        } else {
+            None
+        }
     }
 
     #[inline]
     pub fn is_dir(&self) -> bool {
-        self.metadata().is_dir()
+        self.kind() == NodeKind::Dir
     }
 
     #[inline]
     pub fn is_file(&self) -> bool {
-        self.metadata().is_file()
+        self.kind() == NodeKind::File
+    }
+
+    #[inline]
+    pub fn is_symlink(&self) -> bool {
+        self.kind() == NodeKind::Symlink
     }
 
     #[inline]
@@ -219,7 +281,7 @@ impl Iterator for DescendantsIter {
     fn next(&mut self) -> Option<File> {
         if let Some(id) = self.queue.pop() {
             let node = self.arena.at(id);
-            if node.metadata.is_dir() {
+            if node.kind == NodeKind::Dir {
                 self.queue.extend(node.children.iter().rev().cloned());
             }
             return Some(File {
@@ -231,47 +293,704 @@ impl Iterator for DescendantsIter {
     }
 }
 
+/// Average time of a call under which the file system is fast enough, even over a network.
+const SLOW_AVERAGE: Duration = Duration::from_millis(20);
+/// Calls needed before the average time of the calls tells anything.
+const SLOW_MIN_CALLS: u32 = 50;
+
+/// Time spent listing the folders and reading the metadata of the files during a walk.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct WalkTiming {
+    pub calls: u32,
+    pub total: Duration,
+}
+
+impl WalkTiming {
+    fn record(&mut self, start: Instant) {
+        self.calls += 1;
+        self.total += start.elapsed();
+    }
+
+    /// Average time of a call in milliseconds.
+    pub fn average_ms(&self) -> f64 {
+        let secs = self.total.as_secs() as f64 + f64::from(self.total.subsec_nanos()) / 1e9;
+        secs * 1000.0 / f64::from(self.calls.max(1))
+    }
+
+    /// Tell if the calls were slow enough to be answered by a network share over a slow link.
+    pub fn is_slow(&self) -> bool {
+        self.calls >= SLOW_MIN_CALLS && self.total / self.calls >= SLOW_AVERAGE
+    }
+}
+
+/// What the walk does with the symbolic links it meets.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Walk the target of the links as if it was at the path of the link, such as the folders of
+    /// a NAS linked into a library. Broken links and links to a folder being walked, which would
+    /// never end, are recorded instead.
+    Follow,
+    /// Leave the links out of the tree.
+    Skip,
+    /// Keep the links in the tree as `NodeKind::Symlink`, without walking their target.
+    Record,
+}
+
+impl SymlinkPolicy {
+    pub fn parse(text: &str) -> Option<SymlinkPolicy> {
+        match text {
+            "follow" => Some(SymlinkPolicy::Follow),
+            "skip" => Some(SymlinkPolicy::Skip),
+            "record" => Some(SymlinkPolicy::Record),
+            _ => None,
+        }
+    }
+}
+
+/// Name of the file marking a folder whose content is not media, as Kodi and Jellyfin do.
+pub const NOMEDIA: &str = ".nomedia";
+
+/// How to walk a folder. The symbolic links are recorded and every file is walked by default,
+/// except the ones left out by the `.meroignore` files.
+#[derive(Clone, Debug)]
+pub struct WalkOptions {
+    symlinks: SymlinkPolicy,
+    skip_hidden: bool,
+    skip_nomedia: bool,
+    ignore_files: bool,
+}
+
+impl Default for WalkOptions {
+    fn default() -> WalkOptions {
+        WalkOptions {
+            symlinks: SymlinkPolicy::Record,
+            skip_hidden: false,
+            skip_nomedia: false,
+            ignore_files: true,
+        }
+    }
+}
+
+impl WalkOptions {
+    pub fn symlinks(mut self, symlinks: SymlinkPolicy) -> WalkOptions {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Leave the files and folders whose name starts with a dot out of the tree, such as
+    /// `.DS_Store` or `.git`.
+    pub fn skip_hidden(mut self, skip_hidden: bool) -> WalkOptions {
+        self.skip_hidden = skip_hidden;
+        self
+    }
+
+    /// Leave the folders containing a `.nomedia` file out of the tree, with their content. The
+    /// root is walked even if it contains one.
+    pub fn skip_nomedia(mut self, skip_nomedia: bool) -> WalkOptions {
+        self.skip_nomedia = skip_nomedia;
+        self
+    }
+
+    /// Leave out the files and folders matched by the `.meroignore` files of the root and of the
+    /// folders walked. The ignore files themselves are always left out.
+    pub fn ignore_files(mut self, ignore_files: bool) -> WalkOptions {
+        self.ignore_files = ignore_files;
+        self
+    }
+}
+
 pub fn walk(root: impl AsRef<Path>) -> io::Result<File> {
+    walk_timed(root).map(|(root, _)| root)
+}
+
+/// Like `walk`, and measure how long the file system took to answer.
+pub fn walk_timed(root: impl AsRef<Path>) -> io::Result<(File, WalkTiming)> {
+    walk_with(root, &WalkOptions::default())
+}
+
+/// Like `walk_timed`, with the options.
+pub fn walk_with(root: impl AsRef<Path>, options: &WalkOptions) -> io::Result<(File, WalkTiming)> {
     let root = root.as_ref();
     let mut arena = Arena(Vec::new());
 
+    let metadata = root.metadata()?;
     let node = Node {
         path: root.to_owned(),
-        metadata: root.metadata()?,
+        kind: NodeKind::from_metadata(&metadata),
+        len: metadata.len(),
+        modified: metadata.modified().ok(),
         parent: None,
         children: vec![],
     };
 
     let id = arena.add(node);
 
-    walk_rec(root, &mut arena, id)?;
+    // The folders holding the entries of the stream, by depth.
+    let mut parents = vec![id];
+    let mut stream = stream(root, options)?;
+    for entry in &mut stream {
+        let entry = entry?;
+        parents.truncate(entry.depth + 1);
+        let parent_id = parents[entry.depth];
+        let is_dir = entry.kind == NodeKind::Dir;
+        let child_id = arena.add(Node {
+            path: entry.path,
+            kind: entry.kind,
+            len: entry.len,
+            modified: entry.modified,
+            parent: Some(parent_id),
+            children: vec![],
+        });
+        arena.at_mut(parent_id).children.push(child_id);
+        if is_dir {
+            parents.push(child_id);
+        }
+    }
 
-    Ok(File {
+    let root = File {
         id: id,
         arena: Rc::new(arena),
-    })
+    };
+    Ok((root, stream.timing()))
 }
 
-fn walk_rec(parent_path: &Path, arena: &mut Arena, parent_id: NodeId) -> io::Result<()> {
-    for entry in parent_path.read_dir()? {
-        let entry = entry?;
+/// A file or folder found by a streaming walk.
+#[derive(Clone, Debug)]
+pub struct WalkEntry {
+    pub path: PathBuf,
+    pub kind: NodeKind,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    /// Number of folders between the root and the entry, 0 for the children of the root.
+    pub depth: usize,
+}
+
+/// Walk the folder like `walk_with`, one entry at a time instead of building the whole tree. The
+/// entries come depth first, a folder before its content, and each folder is read once its entry
+/// is reached. Only the folders being read are kept in memory, which bounds the memory used by
+/// the walk of a very large library. The scan still needs the whole tree, the companions of a
+/// movie are found among its siblings and the cleanup lists every file, so only the passes that
+/// look at each file once, such as the forecast, use the stream.
+pub fn stream(root: impl AsRef<Path>, options: &WalkOptions) -> io::Result<WalkStream> {
+    let mut stream = WalkStream {
+        options: options.clone(),
+        timing: WalkTiming::default(),
+        open: vec![],
+    };
+    stream.open(root.as_ref())?;
+    Ok(stream)
+}
+
+pub struct WalkStream {
+    options: WalkOptions,
+    timing: WalkTiming,
+    /// The folders being read, from the root.
+    open: Vec<OpenDir>,
+}
+
+struct OpenDir {
+    entries: ReadDir,
+    /// The folder, to tell the links leading back to it apart. Only kept when the links are
+    /// followed.
+    handle: Option<Handle>,
+    ignore: Option<IgnoreFile>,
+}
+
+impl WalkStream {
+    /// Time spent listing the folders and reading the metadata of the files so far.
+    pub fn timing(&self) -> WalkTiming {
+        self.timing
+    }
+
+    fn open(&mut self, path: &Path) -> io::Result<()> {
+        let handle = if self.options.symlinks == SymlinkPolicy::Follow {
+            Some(Handle::from_path(path)?)
+        } else {
+            None
+        };
+        let ignore = if self.options.ignore_files {
+            IgnoreFile::load(path)?
+        } else {
+            None
+        };
+
+        let start = Instant::now();
+        let entries = path.read_dir()?;
+        self.timing.record(start);
+
+        self.open.push(OpenDir {
+            entries,
+            handle,
+            ignore,
+        });
+        Ok(())
+    }
+
+    /// Read the metadata of the entry, `None` when the options leave it out.
+    fn read(&mut self, entry: DirEntry) -> io::Result<Option<(PathBuf, Metadata)>> {
+        let name = entry.file_name();
+        if self.options.skip_hidden && name.to_string_lossy().starts_with('.') {
+            return Ok(None);
+        }
+        if name == IGNORE_FILE {
+            return Ok(None);
+        }
         let path = entry.path();
+        let start = Instant::now();
+        let mut metadata = entry.metadata()?;
+        self.timing.record(start);
+
+        if metadata.file_type().is_symlink() {
+            match self.options.symlinks {
+                SymlinkPolicy::Skip => return Ok(None),
+                SymlinkPolicy::Record => {}
+                SymlinkPolicy::Follow => {
+                    let start = Instant::now();
+                    let target = fs::metadata(&path);
+                    self.timing.record(start);
+                    if let Ok(target) = target {
+                        if !target.is_dir() || !self.is_ancestor(&path) {
+                            metadata = target;
+                        }
+                    }
+                }
+            }
+        }
+
+        let is_dir = metadata.is_dir() && !metadata.file_type().is_symlink();
+        if self.is_ignored(&path, is_dir) {
+            return Ok(None);
+        }
+        if is_dir && self.options.skip_nomedia {
+            let start = Instant::now();
+            let nomedia = path.join(NOMEDIA).symlink_metadata().is_ok();
+            self.timing.record(start);
+            if nomedia {
+                return Ok(None);
+            }
+        }
+        Ok(Some((path, metadata)))
+    }
+
+    /// Tell if the ignore files leave the file out, the deepest one matching it decides.
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.open
+            .iter()
+            .rev()
+            .filter_map(|dir| dir.ignore.as_ref()?.matched(path, is_dir))
+            .next()
+            .unwrap_or(false)
+    }
+
+    /// Tell if the folder the link leads to is being walked.
+    fn is_ancestor(&self, link: &Path) -> bool {
+        match Handle::from_path(link) {
+            Ok(handle) => self.open.iter().any(|dir| dir.handle.as_ref() == Some(&handle)),
+            // A folder that cannot be opened is not walked either.
+            Err(_) => true,
+        }
+    }
+}
+
+impl Iterator for WalkStream {
+    type Item = io::Result<WalkEntry>;
+
+    fn next(&mut self) -> Option<io::Result<WalkEntry>> {
+        loop {
+            let depth = self.open.len().checked_sub(1)?;
+            let entry = match self.open[depth].entries.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(err)) => return Some(Err(err)),
+                None => {
+                    self.open.pop();
+                    continue;
+                }
+            };
+            let (path, metadata) = match self.read(entry) {
+                Ok(Some(read)) => read,
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            };
+            let kind = NodeKind::from_metadata(&metadata);
+            if kind == NodeKind::Dir {
+                if let Err(err) = self.open(&path) {
+                    return Some(Err(err));
+                }
+            }
+            return Some(Ok(WalkEntry {
+                path,
+                kind,
+                len: metadata.len(),
+                modified: metadata.modified().ok(),
+                depth,
+            }));
+        }
+    }
+}
+
+/// Build a tree from a listing of the root and its descendants, such as one obtained from a
+/// remote machine.
+///
+/// Entries whose parent is not part of the listing are ignored.
+pub fn from_listing<I>(root: impl AsRef<Path>, listing: I) -> File
+where
+    I: IntoIterator<Item = (PathBuf, NodeKind, u64)>,
+{
+    from_listing_with(root, listing, &WalkOptions::default(), vec![])
+}
+
+/// Like `from_listing`, leaving out of the tree the entries the options leave out of a walk. The
+/// ignore files are the ones found in the folders of the listing, which cannot be read from it.
+/// The links of a listing cannot be followed, they are recorded unless they are skipped.
+pub fn from_listing_with<I>(
+    root: impl AsRef<Path>,
+    listing: I,
+    options: &WalkOptions,
+    mut ignore_files: Vec<IgnoreFile>,
+) -> File
+where
+    I: IntoIterator<Item = (PathBuf, NodeKind, u64)>,
+{
+    let root = root.as_ref();
+    let listing: Vec<_> = listing.into_iter().collect();
+    // The deepest ignore file matching an entry decides.
+    if !options.ignore_files {
+        ignore_files.clear();
+    }
+    ignore_files.sort_by_key(|ignore| ::std::cmp::Reverse(ignore.base().components().count()));
+    let is_ignored = |path: &Path, is_dir| {
+        ignore_files
+            .iter()
+            .filter_map(|ignore| ignore.matched(path, is_dir))
+            .next()
+            .unwrap_or(false)
+    };
+    // The folders containing a `.nomedia` file, the root is walked either way.
+    let nomedia: HashSet<PathBuf> = listing
+        .iter()
+        .filter(|&&(ref path, _, _)| path.file_name() == Some(NOMEDIA.as_ref()))
+        .filter(|_| options.skip_nomedia)
+        .filter_map(|&(ref path, _, _)| path.parent().map(Path::to_owned))
+        .collect();
+    let mut arena = Arena(Vec::new());
+    let mut ids = HashMap::new();
+
+    let root_id = arena.add(Node {
+        path: root.to_owned(),
+        kind: NodeKind::Dir,
+        len: 0,
+        modified: None,
+        parent: None,
+        children: vec![],
+    });
+    ids.insert(root.to_owned(), root_id);
+
+    for (path, kind, len) in listing {
+        if path == root {
+            continue;
+        }
+        // The descendants of the folders left out have no parent in the tree and are left out too.
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if (options.skip_hidden && name.starts_with('.'))
+            || name == IGNORE_FILE
+            || (kind == NodeKind::Symlink && options.symlinks == SymlinkPolicy::Skip)
+            || (kind == NodeKind::Dir && nomedia.contains(&path))
+            || is_ignored(&path, kind == NodeKind::Dir)
+        {
+            continue;
+        }
 
-        let node = Node {
+        let parent_id = match path.parent().and_then(|parent| ids.get(parent)) {
+            Some(&parent_id) => parent_id,
+            None => continue,
+        };
+
+        let id = arena.add(Node {
             path: path.clone(),
-            metadata: entry.metadata()?,
+            kind,
+            len,
+            modified: None,
             parent: Some(parent_id),
             children: vec![],
-        };
-
-        let is_dir = node.metadata.is_dir();
-        let id = arena.add(node);
+        });
         arena.at_mut(parent_id).children.push(id);
 
-        if is_dir {
-            walk_rec(&path, arena, id)?;
+        if kind == NodeKind::Dir {
+            ids.insert(path, id);
         }
     }
 
-    Ok(())
+    File {
+        id: root_id,
+        arena: Rc::new(arena),
+    }
+}
+
+#[test]
+fn test_walk_timing() {
+    let fast = WalkTiming {
+        calls: 1000,
+        total: Duration::from_millis(500),
+    };
+    assert!(!fast.is_slow());
+    assert_eq!(fast.average_ms(), 0.5);
+
+    let slow = WalkTiming {
+        calls: 100,
+        total: Duration::from_secs(3),
+    };
+    assert!(slow.is_slow());
+
+    // A few slow calls, such as a disk waking up, tell nothing.
+    let few = WalkTiming {
+        calls: 10,
+        total: Duration::from_secs(3),
+    };
+    assert!(!few.is_slow());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_walk_symlinks() {
+    use std::env;
+    use std::os::unix::fs::symlink;
+
+    let dir = env::temp_dir().join(format!("vfs-symlinks-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("movies")).unwrap();
+    fs::create_dir_all(dir.join("nas/Heat (1995)")).unwrap();
+    fs::write(dir.join("nas/Heat (1995)/Heat (1995).mkv"), b"movie").unwrap();
+    symlink(dir.join("nas/Heat (1995)"), dir.join("movies/Heat (1995)")).unwrap();
+    symlink(dir.join("movies"), dir.join("movies/loop")).unwrap();
+    symlink(dir.join("missing.mkv"), dir.join("movies/broken.mkv")).unwrap();
+
+    let names = |root: &File, kind: NodeKind| {
+        let mut names: Vec<_> = root
+            .descendants()
+            .filter(|file| file.kind() == kind)
+            .map(|file| file.path().strip_prefix(root.path()).unwrap().to_owned())
+            .collect();
+        names.sort();
+        names
+    };
+    let walk = |symlinks| {
+        let options = WalkOptions::default().symlinks(symlinks);
+        walk_with(dir.join("movies"), &options).unwrap().0
+    };
+
+    let root = walk(SymlinkPolicy::Record);
+    assert!(names(&root, NodeKind::File).is_empty());
+    assert_eq!(names(&root, NodeKind::Symlink).len(), 3);
+
+    let root = walk(SymlinkPolicy::Skip);
+    assert_eq!(root.descendants().count(), 0);
+
+    // The loop back to the root and the broken link are recorded instead of followed.
+    let root = walk(SymlinkPolicy::Follow);
+    assert_eq!(
+        names(&root, NodeKind::File),
+        vec![Path::new("Heat (1995)/Heat (1995).mkv")]
+    );
+    assert_eq!(
+        names(&root, NodeKind::Symlink),
+        vec![Path::new("broken.mkv"), Path::new("loop")]
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_walk_hidden() {
+    use std::env;
+
+    let dir = env::temp_dir().join(format!("vfs-hidden-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("Heat (1995)")).unwrap();
+    fs::create_dir_all(dir.join(".trash")).unwrap();
+    fs::create_dir_all(dir.join("Home Videos/2018")).unwrap();
+    fs::write(dir.join("Heat (1995)/Heat (1995).mkv"), b"movie").unwrap();
+    fs::write(dir.join("Heat (1995)/.DS_Store"), b"").unwrap();
+    fs::write(dir.join(".trash/Ronin (1998).mkv"), b"movie").unwrap();
+    fs::write(dir.join("Home Videos/.nomedia"), b"").unwrap();
+    fs::write(dir.join("Home Videos/2018/Birthday.mkv"), b"movie").unwrap();
+
+    let names = |options: WalkOptions| {
+        let root = walk_with(&dir, &options).unwrap().0;
+        let mut names: Vec<_> = root
+            .descendants()
+            .map(|file| file.path().strip_prefix(&dir).unwrap().to_owned())
+            .collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(names(WalkOptions::default()).len(), 9);
+    assert_eq!(
+        names(WalkOptions::default().skip_hidden(true).skip_nomedia(true)),
+        vec![Path::new("Heat (1995)"), Path::new("Heat (1995)/Heat (1995).mkv")]
+    );
+    // The marker is found even when the hidden files are skipped.
+    assert_eq!(
+        names(WalkOptions::default().skip_nomedia(true)),
+        vec![
+            Path::new(".trash"),
+            Path::new(".trash/Ronin (1998).mkv"),
+            Path::new("Heat (1995)"),
+            Path::new("Heat (1995)/.DS_Store"),
+            Path::new("Heat (1995)/Heat (1995).mkv"),
+        ]
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_listing_hidden() {
+    let listing = vec![
+        (PathBuf::from("/movies/Heat (1995)"), NodeKind::Dir, 0),
+        (PathBuf::from("/movies/Heat (1995)/Heat (1995).mkv"), NodeKind::File, 5),
+        (PathBuf::from("/movies/Heat (1995)/.DS_Store"), NodeKind::File, 0),
+        (PathBuf::from("/movies/.trash"), NodeKind::Dir, 0),
+        (PathBuf::from("/movies/.trash/Ronin (1998).mkv"), NodeKind::File, 5),
+        (PathBuf::from("/movies/Home Videos"), NodeKind::Dir, 0),
+        (PathBuf::from("/movies/Home Videos/2018"), NodeKind::Dir, 0),
+        (PathBuf::from("/movies/Home Videos/2018/Birthday.mkv"), NodeKind::File, 5),
+        (PathBuf::from("/movies/Home Videos/.nomedia"), NodeKind::File, 0),
+        (PathBuf::from("/movies/Ronin.mkv"), NodeKind::Symlink, 0),
+    ];
+    let names = |options: WalkOptions| {
+        let root = from_listing_with("/movies", listing.clone(), &options, vec![]);
+        let mut names: Vec<_> = root.descendants().map(|file| file.path().to_owned()).collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(names(WalkOptions::default()).len(), 10);
+    let options = WalkOptions::default()
+        .skip_hidden(true)
+        .skip_nomedia(true)
+        .symlinks(SymlinkPolicy::Skip);
+    assert_eq!(
+        names(options),
+        vec![
+            Path::new("/movies/Heat (1995)"),
+            Path::new("/movies/Heat (1995)/Heat (1995).mkv"),
+        ]
+    );
+}
+
+#[test]
+fn test_walk_ignore_files() {
+    use std::env;
+
+    let dir = env::temp_dir().join(format!("vfs-ignore-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("Heat (1995)")).unwrap();
+    fs::create_dir_all(dir.join("Home Videos")).unwrap();
+    fs::write(dir.join(".meroignore"), "Home Videos/\n*.iso\n").unwrap();
+    fs::write(dir.join("Heat (1995)/.meroignore"), "!Heat.1995.iso\n").unwrap();
+    fs::write(dir.join("Heat (1995)/Heat.1995.mkv"), b"movie").unwrap();
+    fs::write(dir.join("Heat (1995)/Heat.1995.iso"), b"movie").unwrap();
+    fs::write(dir.join("Ronin.1998.iso"), b"movie").unwrap();
+    fs::write(dir.join("Home Videos/Birthday.mkv"), b"movie").unwrap();
+
+    let names = |options: WalkOptions| {
+        let root = walk_with(&dir, &options).unwrap().0;
+        let mut names: Vec<_> = root
+            .descendants()
+            .map(|file| file.path().strip_prefix(&dir).unwrap().to_owned())
+            .collect();
+        names.sort();
+        names
+    };
+
+    // The ignore file of the movie folder includes its image again.
+    assert_eq!(
+        names(WalkOptions::default()),
+        vec![
+            Path::new("Heat (1995)"),
+            Path::new("Heat (1995)/Heat.1995.iso"),
+            Path::new("Heat (1995)/Heat.1995.mkv"),
+        ]
+    );
+    assert_eq!(names(WalkOptions::default().ignore_files(false)).len(), 6);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_listing_ignore_files() {
+    let listing = vec![
+        (PathBuf::from("/movies/.meroignore"), NodeKind::File, 20),
+        (PathBuf::from("/movies/Heat (1995)"), NodeKind::Dir, 0),
+        (PathBuf::from("/movies/Heat (1995)/.meroignore"), NodeKind::File, 15),
+        (PathBuf::from("/movies/Heat (1995)/Heat.1995.mkv"), NodeKind::File, 5),
+        (PathBuf::from("/movies/Heat (1995)/Heat.1995.iso"), NodeKind::File, 5),
+        (PathBuf::from("/movies/Ronin.1998.iso"), NodeKind::File, 5),
+        (PathBuf::from("/movies/Home Videos"), NodeKind::Dir, 0),
+        (PathBuf::from("/movies/Home Videos/Birthday.mkv"), NodeKind::File, 5),
+    ];
+    let ignore_files = || {
+        vec![
+            IgnoreFile::parse("/movies/Heat (1995)", "!Heat.1995.iso\n"),
+            IgnoreFile::parse("/movies", "Home Videos/\n*.iso\n"),
+        ]
+    };
+    let names = |options: WalkOptions| {
+        let root = from_listing_with("/movies", listing.clone(), &options, ignore_files());
+        let mut names: Vec<_> = root.descendants().map(|file| file.path().to_owned()).collect();
+        names.sort();
+        names
+    };
+
+    // The ignore files are left out of the tree, so that they are never removed.
+    assert_eq!(
+        names(WalkOptions::default()),
+        vec![
+            Path::new("/movies/Heat (1995)"),
+            Path::new("/movies/Heat (1995)/Heat.1995.iso"),
+            Path::new("/movies/Heat (1995)/Heat.1995.mkv"),
+        ]
+    );
+    assert_eq!(names(WalkOptions::default().ignore_files(false)).len(), 6);
+}
+
+#[test]
+fn test_stream() {
+    use std::env;
+
+    let dir = env::temp_dir().join(format!("vfs-stream-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("Heat (1995)/Subs")).unwrap();
+    fs::write(dir.join("Heat (1995)/Heat (1995).mkv"), b"movie").unwrap();
+    fs::write(dir.join("Heat (1995)/Subs/English.srt"), b"subs").unwrap();
+    fs::write(dir.join("Ronin.1998.mkv"), b"movie").unwrap();
+
+    let mut entries: Vec<_> = stream(&dir, &WalkOptions::default())
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let path = entry.path.strip_prefix(&dir).unwrap().to_owned();
+            (path, entry.kind, entry.depth)
+        })
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        entries,
+        vec![
+            (PathBuf::from("Heat (1995)"), NodeKind::Dir, 0),
+            (PathBuf::from("Heat (1995)/Heat (1995).mkv"), NodeKind::File, 1),
+            (PathBuf::from("Heat (1995)/Subs"), NodeKind::Dir, 1),
+            (PathBuf::from("Heat (1995)/Subs/English.srt"), NodeKind::File, 2),
+            (PathBuf::from("Ronin.1998.mkv"), NodeKind::File, 0),
+        ]
+    );
+
+    // The tree built from the stream holds the same entries.
+    let root = walk(&dir).unwrap();
+    let heat = root.children().find(|file| file.name() == "Heat (1995)").unwrap();
+    assert_eq!(heat.descendants().count(), 3);
+    assert_eq!(root.descendants().count(), 5);
+
+    fs::remove_dir_all(&dir).unwrap();
 }
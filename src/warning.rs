@@ -0,0 +1,112 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+use parse::is_episode;
+use scan::{FileExt, ScanEntry};
+use vfs::File;
+
+/// Folder structures that are not handled well and should be fixed by hand.
+#[derive(Debug)]
+pub enum Warning {
+    /// A movie's folder is inside the folder of another movie.
+    NestedMovieFolder { outer: PathBuf, inner: PathBuf },
+    /// A folder contains more than one movie.
+    MultipleMovies { folder: PathBuf, count: usize },
+    /// A folder contains many unrelated movies, the files next to them were not used to match them.
+    SharedFolder { folder: PathBuf, count: usize },
+    /// A folder contains both TV episodes and movies.
+    MixedTvAndMovies { folder: PathBuf },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::NestedMovieFolder { outer, inner } => write!(
+                w,
+                "movie folder {} is inside movie folder {}",
+                inner.display(),
+                outer.display()
+            ),
+            Warning::MultipleMovies { folder, count } => {
+                write!(w, "folder {} contains {} movies", folder.display(), count)
+            }
+            Warning::SharedFolder { folder, count } => write!(
+                w,
+                "folder {} contains {} unrelated movies, its images and name were ignored",
+                folder.display(),
+                count
+            ),
+            Warning::MixedTvAndMovies { folder } => write!(
+                w,
+                "folder {} contains both TV episodes and movies",
+                folder.display()
+            ),
+        }
+    }
+}
+
+/// Look for suspicious folder structures among the scanned movies, paths are relative to the root.
+pub fn check<'i>(root: &File, entries: &[ScanEntry<'i>]) -> Vec<Warning> {
+    let mut warnings = vec![];
+    let rel = |file: &File| {
+        file.path()
+            .strip_prefix(root.path())
+            .unwrap_or(file.path())
+            .to_owned()
+    };
+
+    // Folders containing movies, the root itself is not a movie folder.
+    let mut movie_folders: HashMap<File, usize> = HashMap::new();
+    let mut shared_folders: HashSet<File> = HashSet::new();
+    for entry in entries {
+        if let Some(parent) = entry.movie.parent() {
+            if parent != *root {
+                if entry.shared_folder {
+                    shared_folders.insert(parent.clone());
+                }
+                *movie_folders.entry(parent).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut folders: Vec<_> = movie_folders.iter().collect();
+    folders.sort_by(|a, b| a.0.path().cmp(b.0.path()));
+
+    for &(folder, &count) in folders.iter() {
+        if shared_folders.contains(folder) {
+            warnings.push(Warning::SharedFolder {
+                folder: rel(folder),
+                count,
+            });
+        } else if count > 1 {
+            warnings.push(Warning::MultipleMovies {
+                folder: rel(folder),
+                count,
+            });
+        }
+
+        let mut ancestor = folder.parent();
+        while let Some(dir) = ancestor {
+            if movie_folders.contains_key(&dir) {
+                warnings.push(Warning::NestedMovieFolder {
+                    outer: rel(&dir),
+                    inner: rel(folder),
+                });
+                break;
+            }
+            ancestor = dir.parent();
+        }
+
+        if folder
+            .children()
+            .any(|child| child.is_video() && is_episode(child.stem()))
+        {
+            warnings.push(Warning::MixedTvAndMovies {
+                folder: rel(folder),
+            });
+        }
+    }
+
+    warnings
+}